@@ -0,0 +1,55 @@
+// The menu ENTER opens on the selected command, so that what can be done with it doesn't have to
+// be remembered key by key. It's modal: while it's open, the keys only move in it, pick an action
+// or close it.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    FocusPane,
+    Rerun,
+    Kill,
+    Skip,
+    Unskip,
+    Edit,
+    ViewLog,
+    RestartRun,
+}
+
+impl MenuAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MenuAction::FocusPane => "Focus pane",
+            MenuAction::Rerun => "Re-run",
+            MenuAction::Kill => "Kill",
+            MenuAction::Skip => "Skip",
+            MenuAction::Unskip => "Unskip",
+            MenuAction::Edit => "Edit this command",
+            MenuAction::ViewLog => "View log",
+            MenuAction::RestartRun => "Restart the run",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActionMenu {
+    pub actions: Vec<MenuAction>, // only those that apply to the command
+    pub selected: usize,
+}
+
+impl ActionMenu {
+    pub fn new(actions: Vec<MenuAction>) -> Self {
+        ActionMenu { actions, selected: 0 }
+    }
+    pub fn move_down(&mut self) {
+        if !self.actions.is_empty() {
+            self.selected = (self.selected + 1) % self.actions.len();
+        }
+    }
+    pub fn move_up(&mut self) {
+        if !self.actions.is_empty() {
+            self.selected = (self.selected + self.actions.len() - 1) % self.actions.len();
+        }
+    }
+    pub fn selected_action(&self) -> Option<MenuAction> {
+        self.actions.get(self.selected).copied()
+    }
+}
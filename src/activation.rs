@@ -0,0 +1,30 @@
+use std::path::Path;
+
+// Per-command environment helpers (eg. "pytest" { python_venv ".venv"; }) expanded to the
+// activation boilerplate of the shell the command runs in
+
+fn shell_name(shell: &str) -> &str {
+    Path::new(shell).file_name().and_then(|f| f.to_str()).unwrap_or(shell)
+}
+
+// Prefixed to the command line, eg. `. ".venv/bin/activate" && pytest`
+pub fn python_venv_prefix(shell: &str, venv: &str) -> Option<String> {
+    match shell_name(shell) {
+        "bash" | "zsh" | "sh" | "dash" | "ksh" => Some(format!(". {:?} && ", format!("{}/bin/activate", venv))),
+        "fish" => Some(format!("source {:?}; and ", format!("{}/bin/activate.fish", venv))),
+        "nu" => Some(format!("overlay use {:?}; ", format!("{}/bin/activate.nu", venv))),
+        _ => None,
+    }
+}
+
+// Uses nvm (nvm.fish for fish) to switch the node version
+pub fn node_version_prefix(shell: &str, node_version: &str) -> Option<String> {
+    match shell_name(shell) {
+        "bash" | "zsh" | "sh" | "dash" | "ksh" => Some(format!(
+            r#". "${{NVM_DIR:-$HOME/.nvm}}/nvm.sh" && nvm use {:?} > /dev/null && "#,
+            node_version
+        )),
+        "fish" => Some(format!("nvm use {:?} > /dev/null; and ", node_version)),
+        _ => None,
+    }
+}
@@ -0,0 +1,76 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::Path;
+use zellij_tile::prelude::run_command_with_env_variables_and_cwd;
+
+// Commands declaring the paths they depend on (eg. "cargo test" { paths "src/**" "Cargo.toml"; })
+// are skipped when none of them changed since a git base, a lightweight monorepo "affected" mode.
+// The changed paths arrive as a RunCommandResult with this context before every run.
+pub const AFFECTED_PATHS_CONTEXT: &str = "affected_paths";
+
+pub const DEFAULT_AFFECTED_BASE: &str = "HEAD";
+
+// The files changed since the base (committed or not) and the untracked ones, relative to the
+// folder of the commands
+pub fn request_changed_paths(base: &str, folder: &Path, current_run_index: usize) {
+    let mut context = BTreeMap::new();
+    context.insert(AFFECTED_PATHS_CONTEXT.to_owned(), String::new());
+    context.insert("current_run_index".to_owned(), current_run_index.to_string());
+    let command = [
+        "sh",
+        "-c",
+        r#"git diff --name-only --relative "$0" && git ls-files --others --exclude-standard"#,
+        base,
+    ];
+    run_command_with_env_variables_and_cwd(&command, BTreeMap::new(), folder.to_path_buf(), context);
+}
+
+pub fn parse_changed_paths(stdout: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+// Whether one of the changed paths matches one of the patterns: globs (* within a directory, **
+// across them) or directories (eg. src for everything under it)
+pub fn is_affected(patterns: &[String], changed_paths: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        match glob_to_regex(pattern) {
+            Some(glob) => changed_paths.iter().any(|path| glob.is_match(path) || path.starts_with(&format!("{}/", pattern))),
+            None => false,
+        }
+    })
+}
+
+fn glob_to_regex(pattern: &str) -> Option<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    // **/ is also no directory at all
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    match Regex::new(&regex) {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            eprintln!("Invalid paths pattern {}: {}", pattern, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::Command;
+
+// A request/response protocol for tools driving zlaunch (eg. agents or editor extensions).
+//
+// Requests are pipe messages named "zlaunch:api" with a JSON payload, from the CLI:
+//
+//   zellij pipe --plugin zlaunch --name zlaunch:api -- '{"version": 1, "id": "1", "method": "list_commands"}'
+//
+// or from a plugin. The response goes back to the CLI pipe, or to the plugin as a
+// "zlaunch:api_response" message:
+//
+//   {"version": 1, "id": "1", "result": ...}
+//   {"version": 1, "id": "1", "error": {"code": "unknown_method", "message": "..."}}
+//
+// Methods:
+//   describe                          the protocol version and methods
+//   status                            the run status, as broadcast in "zlaunch:status"
+//   list_commands                     every command with its state
+//   get_command  {"index": 0}         one command in detail
+//   action       {"action": "..."}    restart, pause, resume, stop_on_failure, suspend
+//   action       {"action": "...", "index": 0}
+//                                     skip, babysit or rerun the command
+//   add_command  {"command_line": "cargo test"}
+//
+// Like the status format, fields are only ever added, anything else bumps API_VERSION.
+pub const API_VERSION: u32 = 1;
+pub const API_PIPE_NAME: &str = "zlaunch:api";
+pub const API_RESPONSE_PIPE_NAME: &str = "zlaunch:api_response";
+pub const METHODS: &[&str] = &["describe", "status", "list_commands", "get_command", "action", "add_command"];
+pub const RUN_ACTIONS: &[&str] = &["restart", "pause", "resume", "stop_on_failure", "suspend"];
+pub const COMMAND_ACTIONS: &[&str] = &["skip", "babysit", "rerun"];
+
+#[derive(Deserialize, Debug)]
+pub struct ApiRequest {
+    pub version: Option<u32>,
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError { code, message: message.into() }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiResponse {
+    pub version: u32,
+    pub id: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+impl ApiResponse {
+    pub fn new(id: Option<Value>, result: Result<Value, ApiError>) -> Self {
+        match result {
+            Ok(result) => ApiResponse { version: API_VERSION, id, result: Some(result), error: None },
+            Err(error) => ApiResponse { version: API_VERSION, id, result: None, error: Some(error) },
+        }
+    }
+}
+
+pub fn parse_request(payload: Option<&str>) -> Result<ApiRequest, ApiError> {
+    let payload = payload.ok_or_else(|| ApiError::new("invalid_request", "missing payload"))?;
+    let request: ApiRequest = serde_json::from_str(payload)
+        .map_err(|e| ApiError::new("invalid_request", e.to_string()))?;
+    match request.version {
+        Some(version) if version > API_VERSION => Err(ApiError::new(
+            "unsupported_version",
+            format!("version {} is not supported, the latest is {}", version, API_VERSION),
+        )),
+        _ => Ok(request),
+    }
+}
+
+pub fn describe() -> Value {
+    json!({
+        "version": API_VERSION,
+        "methods": METHODS,
+        "run_actions": RUN_ACTIONS,
+        "command_actions": COMMAND_ACTIONS,
+    })
+}
+
+pub fn command_state(command: &Command) -> &'static str {
+    if command.is_running() {
+        "running"
+    } else if command.succeeded() {
+        "succeeded"
+    } else if command.exited {
+        "failed"
+    } else if command.skipped {
+        "skipped"
+    } else {
+        "pending"
+    }
+}
+
+pub fn command_summary(index: usize, command: &Command) -> Value {
+    json!({
+        "index": index,
+        "command_line": command.command_line,
+        "stage": command.stage,
+        "state": command_state(command),
+        "exit_code": command.exit_status,
+        "attempts": command.attempts,
+    })
+}
+
+pub fn command_detail(index: usize, command: &Command) -> Value {
+    let mut detail = command_summary(index, command);
+    if let Value::Object(fields) = &mut detail {
+        fields.insert("duration_ms".to_owned(), json!(command.duration().map(|d| d.as_millis() as u64)));
+        fields.insert("babysitting".to_owned(), json!(command.babysitting));
+        fields.insert("locked".to_owned(), json!(command.options.locked));
+        fields.insert("budget_ms".to_owned(), json!(command.options.budget.map(|b| b.as_millis() as u64)));
+        fields.insert("over_budget".to_owned(), json!(command.is_over_budget()));
+        fields.insert("log_file".to_owned(), json!(command.log_file));
+        fields.insert("captured_output".to_owned(), json!(command.captured_output));
+        fields.insert("output_tail".to_owned(), json!(command.output_tail));
+        fields.insert("spawn_failure".to_owned(), json!(command.spawn_failure));
+        fields.insert("hook_failure".to_owned(), json!(command.hook_failure));
+        fields.insert("refused".to_owned(), json!(command.refused));
+    }
+    detail
+}
+
+pub fn index_param(params: &Value, command_count: usize) -> Result<usize, ApiError> {
+    let index = params.get("index")
+        .and_then(|i| i.as_u64())
+        .ok_or_else(|| ApiError::new("invalid_params", "expected an \"index\""))? as usize;
+    if index >= command_count {
+        return Err(ApiError::new("not_found", format!("there is no command at index {}", index)));
+    }
+    Ok(index)
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::time::Instant;
+
+// Older entries are dropped above this, eg. in long running watch sessions
+const MAX_AUDIT_ENTRIES: usize = 1000;
+
+// Everything zlaunch did during the current run, so that a run that behaved unexpectedly can be
+// accounted for
+#[derive(Debug, Clone)]
+pub enum AuditEvent {
+    RunStarted(usize), // run index
+    Spawned(usize, String), // command index, command line
+    Exited(usize, Option<i32>), // command index, exit code
+    Skipped(usize, String), // command index, reason
+    SpawnFailed(usize, String), // command index, reason
+    Killed(usize),
+    Retried(usize),
+    PaneClosed(usize),
+    Action(String), // something the user did
+}
+
+// What a command was doing at some moment of a run, replayed from the audit trail
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayedStatus {
+    Pending,
+    Running(Instant), // since
+    Exited(Option<i32>),
+    Skipped,
+    SpawnFailed,
+    Killed,
+    PaneClosed,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub at: Instant,
+    pub event: AuditEvent,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditTrail {
+    entries: Vec<AuditEntry>,
+}
+
+impl AuditTrail {
+    pub fn record(&mut self, event: AuditEvent) {
+        self.entries.push(AuditEntry { at: Instant::now(), event });
+        if self.entries.len() > MAX_AUDIT_ENTRIES {
+            let excess = self.entries.len() - MAX_AUDIT_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+    // The entries since the current run started, including the actions that led to it (eg. a
+    // restart and the commands it killed)
+    pub fn current_run_entries(&self) -> &[AuditEntry] {
+        let run_start = match self.entries.iter().rposition(|e| matches!(e.event, AuditEvent::RunStarted(_))) {
+            Some(run_start) => run_start,
+            None => return &self.entries,
+        };
+        let mut first_entry = run_start;
+        while first_entry > 0 && matches!(self.entries[first_entry - 1].event, AuditEvent::Action(_) | AuditEvent::Killed(_)) {
+            first_entry -= 1;
+        }
+        &self.entries[first_entry..]
+    }
+}
+
+// The status of every command once these entries happened
+pub fn replay(entries: &[AuditEntry], command_count: usize) -> Vec<ReplayedStatus> {
+    let mut statuses = vec![ReplayedStatus::Pending; command_count];
+    for entry in entries {
+        let (command_index, status) = match &entry.event {
+            AuditEvent::Spawned(command_index, _) | AuditEvent::Retried(command_index) => (*command_index, ReplayedStatus::Running(entry.at)),
+            AuditEvent::Exited(command_index, exit_code) => (*command_index, ReplayedStatus::Exited(*exit_code)),
+            AuditEvent::Skipped(command_index, _) => (*command_index, ReplayedStatus::Skipped),
+            AuditEvent::SpawnFailed(command_index, _) => (*command_index, ReplayedStatus::SpawnFailed),
+            AuditEvent::Killed(command_index) => (*command_index, ReplayedStatus::Killed),
+            AuditEvent::PaneClosed(command_index) => (*command_index, ReplayedStatus::PaneClosed),
+            AuditEvent::RunStarted(_) | AuditEvent::Action(_) => continue,
+        };
+        if let Some(replayed_status) = statuses.get_mut(command_index) {
+            *replayed_status = status;
+        }
+    }
+    statuses
+}
+
+// One launch of a command during the run, and how it ended (if it did)
+#[derive(Debug, Clone, Copy)]
+pub struct Attempt {
+    pub started_at: Instant,
+    pub end: Option<(Instant, ReplayedStatus)>,
+}
+
+pub fn attempts(entries: &[AuditEntry], command_index: usize) -> Vec<Attempt> {
+    let mut attempts: Vec<Attempt> = vec![];
+    for entry in entries {
+        let replayed = replay(std::slice::from_ref(entry), command_index + 1);
+        match replayed[command_index] {
+            ReplayedStatus::Running(started_at) => attempts.push(Attempt { started_at, end: None }),
+            ReplayedStatus::Pending | ReplayedStatus::Skipped => {}
+            end => {
+                if let Some(attempt) = attempts.last_mut().filter(|a| a.end.is_none()) {
+                    attempt.end = Some((entry.at, end));
+                }
+            }
+        }
+    }
+    attempts
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditEvent::RunStarted(run_index) => write!(f, "run #{} started", run_index),
+            AuditEvent::Spawned(command_index, command_line) => write!(f, "spawned #{}: {}", command_index + 1, command_line),
+            AuditEvent::Exited(command_index, Some(exit_code)) => write!(f, "#{} exited with {}", command_index + 1, exit_code),
+            AuditEvent::Exited(command_index, None) => write!(f, "#{} exited", command_index + 1),
+            AuditEvent::Skipped(command_index, reason) => write!(f, "skipped #{} ({})", command_index + 1, reason),
+            AuditEvent::SpawnFailed(command_index, reason) => write!(f, "#{} failed to spawn ({})", command_index + 1, reason),
+            AuditEvent::Killed(command_index) => write!(f, "killed #{}", command_index + 1),
+            AuditEvent::Retried(command_index) => write!(f, "re-ran #{}", command_index + 1),
+            AuditEvent::PaneClosed(command_index) => write!(f, "pane of #{} closed", command_index + 1),
+            AuditEvent::Action(action) => write!(f, "user: {}", action),
+        }
+    }
+}
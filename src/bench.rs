@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crate::format::format_duration;
+use crate::report::ReportRow;
+
+// The timings of a command that was re-run in bench mode
+#[derive(Debug, Clone, Copy)]
+pub struct BenchStats {
+    pub runs: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    pub stddev: Duration,
+}
+
+impl BenchStats {
+    pub fn from_samples(samples: &[Duration]) -> Option<Self> {
+        let min = *samples.iter().min()?;
+        let max = *samples.iter().max()?;
+        let secs: Vec<f64> = samples.iter().map(|s| s.as_secs_f64()).collect();
+        let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+        let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / secs.len() as f64;
+        Some(BenchStats {
+            runs: samples.len(),
+            min,
+            mean: Duration::from_secs_f64(mean),
+            max,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+}
+
+// Appended to the Markdown report
+pub fn markdown_bench_table(rows: &[ReportRow], precision: usize) -> String {
+    let mut table = String::new();
+    table.push_str("| Command | Runs | Min | Mean | Max | Std dev |\n");
+    table.push_str("| --- | --- | --- | --- | --- | --- |\n");
+    for row in rows {
+        if let Some(stats) = BenchStats::from_samples(&row.bench_samples) {
+            table.push_str(&format!(
+                "| `{}` | {} | {} | {} | {} | {} |\n",
+                row.command_line.replace('|', "\\|").replace('`', "'"),
+                stats.runs,
+                format_duration(stats.min, precision),
+                format_duration(stats.mean, precision),
+                format_duration(stats.max, precision),
+                format_duration(stats.stddev, precision),
+            ));
+        }
+    }
+    table
+}
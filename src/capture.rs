@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zellij_tile::prelude::CommandToRun;
+
+pub const FAILURES_FOLDER: &str = ".zlaunch/failures";
+pub const LOGS_FOLDER: &str = ".zlaunch/logs";
+
+// Wraps a command so that everything it prints is also written to `log_path` (a path on the
+// host), while preserving its exit code so the pane still reports it correctly.
+pub fn wrap_with_capture(command_to_run: CommandToRun, log_path: &Path) -> CommandToRun {
+    let script = r#"log="$1"; shift; { "$@" 2>&1; echo $? > "$log.status"; } | tee "$log"; status=$(cat "$log.status"); rm -f "$log.status"; exit "$status""#;
+    let mut args = vec![
+        "-c".to_owned(),
+        script.to_owned(),
+        "zlaunch-capture".to_owned(),
+        log_path.to_string_lossy().to_string(),
+        command_to_run.path.to_string_lossy().to_string(),
+    ];
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("sh", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
+
+// The end of a capture that's still being written, at most `max_bytes` of it. Lines are cut
+// at carriage returns (eg. progress bars), only what's left showing is kept.
+pub fn read_end(path: &Path, max_bytes: u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(max_bytes)))?;
+    let mut end = vec![];
+    file.read_to_end(&mut end)?;
+    let end = String::from_utf8_lossy(&end);
+    let lines: Vec<&str> = end
+        .lines()
+        .map(|line| line.rsplit('\r').find(|segment| !segment.is_empty()).unwrap_or(""))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+// A file name derived from the command line, safe to use on any filesystem
+pub fn file_name_for_command(command_index: usize, command_line: &str) -> String {
+    format!("{}-{}.log", command_index, slug(command_line))
+}
+
+fn slug(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(40)
+        .collect()
+}
+
+pub fn failure_output_path(command_index: usize, command_line: &str) -> PathBuf {
+    PathBuf::from(FAILURES_FOLDER).join(file_name_for_command(command_index, command_line))
+}
+
+// Where the output of a failed command is kept once its pane is closed, by its name (or command
+// line) rather than its position, so that the next run doesn't overwrite it
+pub fn failure_dump_path(name: Option<&str>, command_line: &str) -> PathBuf {
+    PathBuf::from(FAILURES_FOLDER).join(format!("{}.log", slug(name.unwrap_or(command_line))))
+}
+
+// Unlike failure output, logs are kept for every run, so they are prefixed with the time the
+// command started (seconds since the unix epoch)
+pub fn log_file_path(command_index: usize, command_line: &str) -> PathBuf {
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    PathBuf::from(LOGS_FOLDER).join(format!("{}-{}", started_at, file_name_for_command(command_index, command_line)))
+}
+
+// Wraps a command so that its stdout (only) is also written to `output_path` (a path on the
+// host), for later commands to use. Like wrap_with_capture, the exit code is preserved.
+pub fn wrap_with_stdout_capture(command_to_run: CommandToRun, output_path: &Path) -> CommandToRun {
+    let script = r#"out="$1"; shift; { "$@"; echo $? > "$out.status"; } | tee "$out"; status=$(cat "$out.status"); rm -f "$out.status"; exit "$status""#;
+    let mut args = vec![
+        "-c".to_owned(),
+        script.to_owned(),
+        "zlaunch-output".to_owned(),
+        output_path.to_string_lossy().to_string(),
+        command_to_run.path.to_string_lossy().to_string(),
+    ];
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("sh", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
+
+// Wraps a command so that it reads its stdin from `input_path` (a path on the host)
+pub fn wrap_with_stdin(command_to_run: CommandToRun, input_path: &Path) -> CommandToRun {
+    let mut args = vec![
+        "-c".to_owned(),
+        r#"input="$1"; shift; exec "$@" < "$input""#.to_owned(),
+        "zlaunch-stdin".to_owned(),
+        input_path.to_string_lossy().to_string(),
+        command_to_run.path.to_string_lossy().to_string(),
+    ];
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("sh", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
@@ -0,0 +1,232 @@
+use std::path::Path;
+
+// Command lines chaining commands with || (a fallback, eg. cargo nextest run || cargo test) or ;
+// (always run the next one). The parts are run one after the other by a small script that writes
+// which of them ran and how they exited, so that the outcome can be told apart from a plain run:
+// "cargo test" succeeding as the fallback of "cargo nextest run" isn't the same as the latter
+// succeeding. Command lines with && or | are left to the shell as a whole: in
+// cargo build && cargo test || cargo check, || binds the && before it.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainOperator {
+    Fallback, // ||, the part only runs if the previous one failed
+    Sequence, // ;, the part runs whatever the previous one did
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainPart {
+    pub command_line: String,
+    pub operator: Option<ChainOperator>, // how it's chained to the previous part, None for the first
+}
+
+// A part that ran, in the order they ran
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BranchResult {
+    pub part: usize,
+    pub exit_code: i32,
+}
+
+// Words that start or end a compound command, whose ; and || are its own: for i in 1 2; do
+// echo $i; done or { a; b; } || c are left to the shell as a whole
+const COMPOUND_WORDS: &[&str] = &["for", "while", "until", "if", "case", "select", "function", "{", "}"];
+
+// The parts of the command line, a single one if nothing is chained. Operators in quotes,
+// parentheses or escaped with a backslash are part of a command, and so are those of command
+// lines with compound commands, && or pipes.
+pub fn split(command_line: &str) -> Vec<ChainPart> {
+    if unquoted_words(command_line).iter().any(|w| COMPOUND_WORDS.contains(&w.as_str())) {
+        return unsplit(command_line);
+    }
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut operator = None;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = command_line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => {
+                current.push(c);
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+                continue;
+            }
+            (q, Some(open)) if q == open => quote = None,
+            (_, Some(_)) => {}
+            ('\'', None) | ('"', None) => quote = Some(c),
+            ('(', None) => depth += 1,
+            (')', None) => depth -= 1,
+            ('|', None) if depth == 0 && chars.peek() == Some(&'|') => {
+                chars.next();
+                parts.push(ChainPart { command_line: current.trim().to_owned(), operator });
+                current.clear();
+                operator = Some(ChainOperator::Fallback);
+                continue;
+            }
+            ('|', None) if depth == 0 => return unsplit(command_line),
+            ('&', None) if depth == 0 && chars.peek() == Some(&'&') => return unsplit(command_line),
+            // ;; only ends a case branch
+            (';', None) if depth == 0 && chars.peek() != Some(&';') => {
+                parts.push(ChainPart { command_line: current.trim().to_owned(), operator });
+                current.clear();
+                operator = Some(ChainOperator::Sequence);
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(ChainPart { command_line: current.trim().to_owned(), operator });
+    // a trailing ; doesn't chain anything
+    parts.retain(|p| !p.command_line.is_empty());
+    parts
+}
+
+fn unsplit(command_line: &str) -> Vec<ChainPart> {
+    let command_line = command_line.trim();
+    if command_line.is_empty() {
+        return vec![];
+    }
+    vec![ChainPart { command_line: command_line.to_owned(), operator: None }]
+}
+
+// The words outside of quotes, separated by blanks and operators
+fn unquoted_words(command_line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = command_line.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => {
+                // an escaped character is never an operator, nor one of the words
+                word.push('\\');
+                word.extend(chars.next());
+            }
+            (q, Some(open)) if q == open => quote = None,
+            (_, Some(_)) => {}
+            ('\'', None) | ('"', None) => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (c, None) if c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')') => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+// The script is POSIX, other shells run the command line as it is
+pub fn is_supported_shell(shell: &str) -> bool {
+    let shell_name = Path::new(shell).file_name().and_then(|f| f.to_str()).unwrap_or(shell);
+    matches!(shell_name, "bash" | "zsh" | "sh" | "dash" | "ksh")
+}
+
+// Runs the parts like the shell would, writing "<part> <exit code>" to `results_path` (a path on
+// the host) for each one that ran. It exits with the exit code of the last part that ran.
+pub fn wrap(parts: &[ChainPart], results_path: &Path) -> String {
+    let results_path = format!("'{}'", results_path.to_string_lossy().replace('\'', "'\\''"));
+    let mut script = format!(": > {}\n", results_path);
+    for (i, part) in parts.iter().enumerate() {
+        let run_part = format!("{{ {}\n}}; zlaunch_status=$?; echo \"{} $zlaunch_status\" >> {}\n", part.command_line, i, results_path);
+        match part.operator {
+            Some(ChainOperator::Fallback) => script.push_str(&format!("if [ \"$zlaunch_status\" -ne 0 ]; then\n{}fi\n", run_part)),
+            _ => script.push_str(&run_part),
+        }
+    }
+    script.push_str("exit \"$zlaunch_status\"");
+    script
+}
+
+pub fn parse_results(results: &str) -> Vec<BranchResult> {
+    results
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let part = fields.next()?.parse().ok()?;
+            let exit_code = fields.next()?.parse().ok()?;
+            Some(BranchResult { part, exit_code })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_lines(parts: &[ChainPart]) -> Vec<&str> {
+        parts.iter().map(|p| p.command_line.as_str()).collect()
+    }
+
+    #[test]
+    fn splits_fallbacks_and_sequences() {
+        let parts = split("cargo nextest run || cargo test; echo done");
+        assert_eq!(command_lines(&parts), vec!["cargo nextest run", "cargo test", "echo done"]);
+        assert_eq!(parts[0].operator, None);
+        assert_eq!(parts[1].operator, Some(ChainOperator::Fallback));
+        assert_eq!(parts[2].operator, Some(ChainOperator::Sequence));
+    }
+
+    #[test]
+    fn leaves_and_and_pipes_to_the_shell() {
+        assert_eq!(command_lines(&split("cargo build && cargo test | tee out")), vec!["cargo build && cargo test | tee out"]);
+    }
+
+    #[test]
+    fn leaves_lines_mixing_and_and_with_fallbacks_to_the_shell() {
+        // || and && have the same precedence, splitting at || would change what runs
+        assert_eq!(command_lines(&split("true || false && echo C")), vec!["true || false && echo C"]);
+        assert_eq!(command_lines(&split("a && b || c && d")), vec!["a && b || c && d"]);
+        assert_eq!(command_lines(&split("make; cargo test | tee out")), vec!["make; cargo test | tee out"]);
+        assert_eq!(command_lines(&split("a || (b && c)")), vec!["a", "(b && c)"]);
+        assert_eq!(command_lines(&split("a 2>&1 || b")), vec!["a 2>&1", "b"]);
+    }
+
+    #[test]
+    fn ignores_operators_in_quotes_parentheses_and_escapes() {
+        assert_eq!(command_lines(&split("echo 'a; b' \"c || d\"")), vec!["echo 'a; b' \"c || d\""]);
+        assert_eq!(command_lines(&split("(a; b) || c")), vec!["(a; b)", "c"]);
+        assert_eq!(command_lines(&split(r"echo a\; b")), vec![r"echo a\; b"]);
+    }
+
+    #[test]
+    fn ignores_a_trailing_semicolon() {
+        assert_eq!(command_lines(&split("cargo test;")), vec!["cargo test"]);
+    }
+
+    #[test]
+    fn leaves_loops_to_the_shell() {
+        assert_eq!(command_lines(&split("for i in 1 2; do echo $i; done")), vec!["for i in 1 2; do echo $i; done"]);
+        assert_eq!(command_lines(&split("while true; do sleep 1; done")), vec!["while true; do sleep 1; done"]);
+        assert_eq!(command_lines(&split("until make; do sleep 1; done")), vec!["until make; do sleep 1; done"]);
+    }
+
+    #[test]
+    fn leaves_conditionals_to_the_shell() {
+        assert_eq!(command_lines(&split("if make; then echo ok; fi || echo failed")), vec!["if make; then echo ok; fi || echo failed"]);
+        assert_eq!(command_lines(&split("case $1 in a) echo a;; b) echo b;; esac")), vec!["case $1 in a) echo a;; b) echo b;; esac"]);
+    }
+
+    #[test]
+    fn leaves_brace_groups_to_the_shell() {
+        assert_eq!(command_lines(&split("{ a; b; } || c")), vec!["{ a; b; } || c"]);
+    }
+
+    #[test]
+    fn quoted_keywords_are_not_compound_commands() {
+        assert_eq!(command_lines(&split("echo 'for'; echo \"{\"")), vec!["echo 'for'", "echo \"{\""]);
+    }
+
+    #[test]
+    fn empty_command_lines_have_no_parts() {
+        assert!(split("  ").is_empty());
+    }
+}
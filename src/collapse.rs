@@ -0,0 +1,79 @@
+use kdl::KdlDocument;
+use regex::Regex;
+
+const ANSI_ESCAPE_PATTERN: &str = r"\x1b\[[0-9;?]*[A-Za-z]|\x1b\][^\x07]*\x07";
+
+// Parses the collapse_patterns configuration, either KDL nodes (one pattern per node, eg.
+// r"^Downloading") or one pattern per line
+pub fn parse_patterns(configuration: Option<&String>) -> Vec<Regex> {
+    let configuration = match configuration {
+        Some(configuration) => configuration,
+        None => return vec![],
+    };
+    let patterns: Vec<String> = match configuration.parse::<KdlDocument>() {
+        Ok(doc) => doc.nodes().iter().map(|n| n.name().value().to_owned()).collect(),
+        Err(_) => configuration.lines().map(|l| l.trim().to_owned()).filter(|l| !l.is_empty()).collect(),
+    };
+    patterns
+        .iter()
+        .filter_map(|pattern| match Regex::new(pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Invalid collapse pattern {}: {}", pattern, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Removes the noise from captured output:
+// - lines redrawn with carriage returns (eg. progress bars) only keep their final state
+// - consecutive lines matching one of the patterns are collapsed into the last of them
+pub fn collapse_noise(output: &str, patterns: &[Regex]) -> String {
+    let mut collapsed: Vec<String> = vec![];
+    let mut noise_run: Option<(String, usize)> = None; // last noise line and how many were seen
+    for line in output.lines() {
+        let line = line.trim_end_matches('\r');
+        let line = line.rsplit('\r').next().unwrap_or(line);
+        if patterns.iter().any(|p| p.is_match(line)) {
+            let count = noise_run.as_ref().map(|(_, count)| *count).unwrap_or(0);
+            noise_run = Some((line.to_owned(), count + 1));
+            continue;
+        }
+        flush_noise_run(&mut collapsed, noise_run.take());
+        collapsed.push(line.to_owned());
+    }
+    flush_noise_run(&mut collapsed, noise_run.take());
+    let mut collapsed = collapsed.join("\n");
+    if output.ends_with('\n') {
+        collapsed.push('\n');
+    }
+    collapsed
+}
+
+fn flush_noise_run(collapsed: &mut Vec<String>, noise_run: Option<(String, usize)>) {
+    if let Some((last_line, count)) = noise_run {
+        if count > 1 {
+            collapsed.push(format!("[... {} similar lines collapsed]", count - 1));
+        }
+        collapsed.push(last_line);
+    }
+}
+
+// The last non-empty lines of the output, without escape sequences so that they can be rendered
+// in the plugin
+pub fn tail(output: &str, line_count: usize) -> Vec<String> {
+    if line_count == 0 {
+        return vec![];
+    }
+    let ansi_escape = Regex::new(ANSI_ESCAPE_PATTERN).expect("valid ansi escape pattern");
+    let mut tail: Vec<String> = output
+        .lines()
+        .rev()
+        .map(|line| ansi_escape.replace_all(line, "").trim_end().to_owned())
+        .filter(|line| !line.is_empty())
+        .take(line_count)
+        .collect();
+    tail.reverse();
+    tail
+}
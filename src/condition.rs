@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+// A condition evaluated right before a command is launched, eg. when="file_exists(Cargo.lock)"
+// Commands whose condition isn't met are skipped
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    FileExists(PathBuf), // relative to the folder the commands run in
+    PrevSuccess,         // all the commands of the previous stage succeeded
+    PrevFailure,         // a command of the previous stage failed
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn from_str(condition: &str) -> Option<Self> {
+        let condition = condition.trim();
+        if let Some(negated) = condition.strip_prefix('!') {
+            return Condition::from_str(negated).map(|c| Condition::Not(Box::new(c)));
+        }
+        match condition {
+            "prev_success" => Some(Condition::PrevSuccess),
+            "prev_failure" => Some(Condition::PrevFailure),
+            _ => {
+                let path = condition.strip_prefix("file_exists(")?.strip_suffix(')')?.trim();
+                let path = path.trim_matches(|c| c == '"' || c == '\'');
+                if path.is_empty() {
+                    None
+                } else {
+                    Some(Condition::FileExists(PathBuf::from(path)))
+                }
+            }
+        }
+    }
+    // previous_stage_succeeded is None for the first stage
+    pub fn is_met(&self, folder: &Path, previous_stage_succeeded: Option<bool>) -> bool {
+        match self {
+            Condition::FileExists(path) => Path::new("/host").join(folder).join(path).exists(),
+            Condition::PrevSuccess => previous_stage_succeeded.unwrap_or(true),
+            Condition::PrevFailure => previous_stage_succeeded.map(|s| !s).unwrap_or(false),
+            Condition::Not(condition) => !condition.is_met(folder, previous_stage_succeeded),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Condition::FileExists(path) => write!(f, "file_exists({})", path.display()),
+            Condition::PrevSuccess => write!(f, "prev_success"),
+            Condition::PrevFailure => write!(f, "prev_failure"),
+            Condition::Not(condition) => write!(f, "!{}", condition),
+        }
+    }
+}
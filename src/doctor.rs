@@ -0,0 +1,66 @@
+use regex::Regex;
+
+use crate::shell_words;
+
+// Recognizes common failures in the output of a command (or its exit code when the output wasn't
+// captured) and suggests a fix, sometimes with a command that applies it (run with d). What the
+// output says ends up in these commands, so it's quoted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub message: String,
+    pub remediation: Option<String>,
+}
+
+impl Hint {
+    fn new(message: String, remediation: Option<String>) -> Self {
+        Hint { message, remediation }
+    }
+}
+
+fn first_capture(pattern: &str, output: &str) -> Option<String> {
+    let regex = Regex::new(pattern).ok()?;
+    regex.captures(output).and_then(|c| c.get(1)).map(|m| m.as_str().to_owned())
+}
+
+pub fn diagnose(exit_code: Option<i32>, output: &str) -> Option<Hint> {
+    // bash/sh: "foo: command not found", zsh: "command not found: foo", fish: "Unknown command: foo"
+    let missing_binary = first_capture(r"(?m)([^\s:]+): (?:command )?not found$", output)
+        .or_else(|| first_capture(r"(?:command not found|Unknown command): (\S+)", output));
+    if let Some(binary) = missing_binary {
+        return Some(Hint::new(format!("{} is not installed, or not in the PATH of the shell", binary), None));
+    }
+    if let Some(port) = first_capture(r"(?i)address already in use\D*(\d{2,5})", output)
+        .or_else(|| first_capture(r"(?i)port (\d{2,5}) is already in use", output))
+        .or_else(|| first_capture(r"(?i)EADDRINUSE\D*(\d{2,5})", output))
+    {
+        return Some(Hint::new(
+            format!("Port {} is taken, is an old instance still running?", port),
+            Some(format!("lsof -ti tcp:{} | xargs kill", port)),
+        ));
+    }
+    if Regex::new(r"(?i)address already in use|EADDRINUSE").map(|r| r.is_match(output)).unwrap_or(false) {
+        return Some(Hint::new("A port is taken, is an old instance still running?".to_owned(), None));
+    }
+    if let Some(path) = first_capture(r"(?m)(\S+): Permission denied", output) {
+        let path = path.trim_end_matches(':').to_owned();
+        return Some(Hint::new(format!("{} is not executable (or not readable)", path), Some(format!("chmod +x {}", shell_words::quote(&path)))));
+    }
+    if let Some(module) = first_capture(r"No module named '([^'.]+)", output) {
+        return Some(Hint::new(format!("The python module {} is missing", module), Some(format!("pip install {}", shell_words::quote(&module)))));
+    }
+    if output.contains("Cannot find module") {
+        return Some(Hint::new("A node module is missing, were the dependencies installed?".to_owned(), Some("npm install".to_owned())));
+    }
+    if output.contains("could not find `Cargo.toml`") {
+        return Some(Hint::new("No Cargo.toml here, check the folder option".to_owned(), None));
+    }
+    if output.contains("No space left on device") {
+        return Some(Hint::new("The disk is full".to_owned(), None));
+    }
+    // without output to go on, the shell's exit codes are the best we have
+    match exit_code {
+        Some(127) => Some(Hint::new("The command was not found, is it installed and in the PATH?".to_owned(), None)),
+        Some(126) => Some(Hint::new("The command is not executable".to_owned(), None)),
+        _ => None,
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use zellij_tile::prelude::CommandToRun;
+
+use crate::storage;
+
+// The variables of a dotenv file (eg. env_file ".env"), relative to the host folder. Lines are
+// KEY=value, optionally prefixed with export. Single quoted values are taken as is, double quoted
+// ones understand \n, \" and \\, and all of them end at a " #" comment.
+pub fn load(env_file: &str) -> Vec<(String, String)> {
+    match fs::read_to_string(storage::host_path(env_file)) {
+        Ok(contents) => parse(&contents),
+        Err(e) => {
+            eprintln!("Failed to read env file {}: {}", env_file, e);
+            vec![]
+        }
+    }
+}
+
+fn parse(contents: &str) -> Vec<(String, String)> {
+    let mut variables = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if is_valid_key(key.trim()) => (key.trim(), value.trim()),
+            _ => {
+                eprintln!("Ignoring line {} of the env file, expected KEY=value", i + 1);
+                continue;
+            }
+        };
+        let value = quoted_value(value)
+            .unwrap_or_else(|| value.split(" #").next().unwrap_or_default().trim_end().to_owned());
+        variables.push((key.to_owned(), value));
+    }
+    variables
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.starts_with(|c: char| c.is_ascii_digit()) && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// The value between its quotes, if nothing but a comment follows them
+fn quoted_value(value: &str) -> Option<String> {
+    let quote = value.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let mut chars = value.char_indices().skip(1);
+    let mut closing = None;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            c if c == quote => {
+                closing = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let closing = closing?;
+    let rest = value[closing + 1..].trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return None;
+    }
+    let quoted = &value[1..closing];
+    Some(if quote == '"' { unescape(quoted) } else { quoted.to_owned() })
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some(escaped @ ('"' | '\\'))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Wraps a command so that it runs with the variables set, through env(1)
+pub fn wrap_with_env(command_to_run: CommandToRun, variables: &[(String, String)]) -> CommandToRun {
+    if variables.is_empty() {
+        return command_to_run;
+    }
+    let mut args: Vec<String> = variables.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+    args.push(command_to_run.path.to_string_lossy().to_string());
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("env", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(line: &str) -> Option<(String, String)> {
+        parse(line).into_iter().next()
+    }
+
+    fn value(line: &str) -> String {
+        variable(line).map(|(_, value)| value).unwrap_or_default()
+    }
+
+    #[test]
+    fn parses_keys_with_or_without_export() {
+        assert_eq!(variable("DATABASE_URL=postgres://localhost"), Some(("DATABASE_URL".to_owned(), "postgres://localhost".to_owned())));
+        assert_eq!(variable("export RUST_LOG = debug"), Some(("RUST_LOG".to_owned(), "debug".to_owned())));
+    }
+
+    #[test]
+    fn takes_single_quoted_values_as_they_are() {
+        assert_eq!(value(r"GREETING='hello \n $USER'"), r"hello \n $USER");
+    }
+
+    #[test]
+    fn unescapes_double_quoted_values() {
+        assert_eq!(value(r#"GREETING="say \"hi\"\nand \\ bye""#), "say \"hi\"\nand \\ bye");
+    }
+
+    #[test]
+    fn ends_values_at_a_comment() {
+        assert_eq!(value("NAME=zlaunch # the name"), "zlaunch");
+        assert_eq!(value("NAME=a#b"), "a#b");
+        assert_eq!(value(r#"NAME="a b" # note"#), "a b");
+        assert_eq!(value("NAME='a # b' # note"), "a # b");
+        assert_eq!(value(r#"NAME="a \" # b"#), r#""a \""#);
+    }
+
+    #[test]
+    fn skips_comments_blank_lines_and_invalid_keys() {
+        let variables = parse("# a comment\n\n1KEY=a\nMY-KEY=b\nNO_VALUE\nKEY=c\n");
+        assert_eq!(variables, vec![("KEY".to_owned(), "c".to_owned())]);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, HashMap};
+use zellij_tile::prelude::CommandToRun;
+
+use crate::readiness::ReadyWhen;
+use crate::Command;
+
+// The location written in exported layouts unless configured (plugin_location), to be adjusted
+// to wherever zlaunch is installed
+pub const DEFAULT_PLUGIN_LOCATION: &str = "file:~/.config/zellij/plugins/zlaunch.wasm";
+
+// Serializes the commands back to the KDL format of the commands configuration, including
+// their attributes and stages
+pub fn commands_to_kdl(commands: &[Command], stage_names: &HashMap<usize, String>) -> String {
+    let mut kdl = String::new();
+    let mut i = 0;
+    while i < commands.len() {
+        let stage = commands[i].stage;
+        let stage_len = commands[i..].iter().take_while(|c| c.stage == stage).count();
+        let stage_name = stage_names.get(&stage);
+        if stage_len > 1 || stage_name.is_some() {
+            match stage_name {
+                Some(stage_name) => kdl.push_str(&format!("stage {:?} {{\n", stage_name)),
+                None => kdl.push_str("stage {\n"),
+            }
+            for command in &commands[i..i + stage_len] {
+                kdl.push_str(&format!("    {}\n", command_to_kdl_node(command)));
+            }
+            kdl.push_str("}\n");
+        } else {
+            kdl.push_str(&format!("{}\n", command_to_kdl_node(&commands[i])));
+        }
+        i += stage_len;
+    }
+    kdl
+}
+
+fn command_to_kdl_node(command: &Command) -> String {
+    let mut node = format!("{:?}", command.command_line);
+    if let Some(on_retrigger) = command.options.on_retrigger {
+        node.push_str(&format!(" on_retrigger={:?}", on_retrigger.as_str()));
+    }
+    if let Some(when) = &command.options.when {
+        node.push_str(&format!(" when={:?}", when.to_string()));
+    }
+    if let Some(name) = &command.options.name {
+        node.push_str(&format!(" name={:?}", name));
+    }
+    if let Some(desc) = &command.options.desc {
+        node.push_str(&format!(" desc={:?}", desc));
+    }
+    if !command.options.tags.is_empty() {
+        node.push_str(&format!(" tags={:?}", command.options.tags.join(" ")));
+    }
+    if let Some(before) = &command.options.before {
+        node.push_str(&format!(" before={:?}", before));
+    }
+    if let Some(after) = &command.options.after {
+        node.push_str(&format!(" after={:?}", after));
+    }
+    if command.options.capture_output {
+        node.push_str(" capture_output=true");
+    }
+    if let Some(stdin_from) = &command.options.stdin_from {
+        node.push_str(&format!(" stdin_from={:?}", stdin_from));
+    }
+    if command.options.locked {
+        node.push_str(" locked=true");
+    }
+    if let Some(success_codes) = &command.options.success_codes {
+        let success_codes: Vec<String> = success_codes.iter().map(|c| c.to_string()).collect();
+        node.push_str(&format!(" success_codes={:?}", success_codes.join(" ")));
+    }
+    if let Some(budget) = command.options.budget {
+        node.push_str(&format!(" budget={:?}", format!("{}ms", budget.as_millis())));
+    }
+    if command.options.warmup {
+        node.push_str(" warmup=true");
+    }
+    if command.options.heavy {
+        node.push_str(" heavy=true");
+    }
+    if command.options.allow_failure {
+        node.push_str(" allow_failure=true");
+    }
+    if command.options.independent {
+        node.push_str(" independent=true");
+    }
+    if command.options.background {
+        node.push_str(" background=true");
+    }
+    if command.options.interactive {
+        node.push_str(" interactive=true");
+    }
+    if let Some(host) = &command.options.host {
+        node.push_str(&format!(" host={:?}", host));
+    }
+    match &command.options.ready_when {
+        Some(ReadyWhen::Port(port)) => node.push_str(&format!(" ready_when_port={}", port)),
+        Some(ReadyWhen::Command(ready_when_cmd)) => node.push_str(&format!(" ready_when_cmd={:?}", ready_when_cmd)),
+        None => {}
+    }
+    if let Some(allow_recursion) = command.options.allow_recursion {
+        node.push_str(&format!(" allow_recursion={}", allow_recursion));
+    }
+    let mut children = vec![];
+    if !command.options.path_prepend.is_empty() {
+        let directories: Vec<String> = command.options.path_prepend.iter().map(|d| format!("{:?}", d)).collect();
+        children.push(format!("path_prepend {};", directories.join(" ")));
+    }
+    if !command.options.paths.is_empty() {
+        let paths: Vec<String> = command.options.paths.iter().map(|p| format!("{:?}", p)).collect();
+        children.push(format!("paths {};", paths.join(" ")));
+    }
+    if let Some(script) = &command.options.script {
+        children.push(format!("script {:?};", script));
+    }
+    for input in &command.options.input {
+        if input.delay.is_zero() {
+            children.push(format!("input {:?};", input.text));
+        } else {
+            children.push(format!("input {:?} delay={:?};", input.text, format!("{}ms", input.delay.as_millis())));
+        }
+    }
+    if let Some(python_venv) = &command.options.python_venv {
+        children.push(format!("python_venv {:?};", python_venv));
+    }
+    if let Some(node_version) = &command.options.node_version {
+        children.push(format!("node_version {:?};", node_version));
+    }
+    if !children.is_empty() {
+        node.push_str(&format!(" {{ {} }}", children.join(" ")));
+    }
+    if let Some(exec) = command.options.exec {
+        node.push_str(&format!(" exec={}", exec));
+    }
+    if let Some(quiet) = command.options.quiet {
+        node.push_str(&format!(" quiet={}", quiet));
+    }
+    node
+}
+
+// A layout that can be pasted into a zellij layout file, launching zlaunch with the current
+// commands and the rest of its configuration
+pub fn layout_snippet(commands: &[Command], stage_names: &HashMap<usize, String>, configuration: &BTreeMap<String, String>, plugin_location: &str) -> String {
+    let mut snippet = String::new();
+    snippet.push_str("layout {\n");
+    snippet.push_str(&format!("    pane {{\n        plugin location={:?} {{\n", plugin_location));
+    snippet.push_str(&plugin_configuration(commands, stage_names, configuration, "            "));
+    snippet.push_str("        }\n    }\n}\n");
+    snippet
+}
+
+// A keybinding for the zellij configuration, launching (or focusing) zlaunch with the current
+// commands and the rest of its configuration, eg. bind "Alt l"
+pub fn keybinding_snippet(commands: &[Command], stage_names: &HashMap<usize, String>, configuration: &BTreeMap<String, String>, plugin_location: &str, key: &str) -> String {
+    let mut snippet = String::new();
+    snippet.push_str("keybinds {\n    shared {\n");
+    snippet.push_str(&format!("        bind {:?} {{\n", key));
+    snippet.push_str(&format!("            LaunchOrFocusPlugin {:?} {{\n", plugin_location));
+    snippet.push_str("                floating true\n");
+    snippet.push_str(&plugin_configuration(commands, stage_names, configuration, "                "));
+    snippet.push_str("            }\n        }\n    }\n}\n");
+    snippet
+}
+
+fn plugin_configuration(commands: &[Command], stage_names: &HashMap<usize, String>, configuration: &BTreeMap<String, String>, indent: &str) -> String {
+    let mut plugin_configuration = format!("{}commands {:?}\n", indent, commands_to_kdl(commands, stage_names));
+    for (key, value) in configuration {
+        // the current commands are exported in place of the configured ones (or the profile's)
+        if key != "commands" && key != "profile" {
+            plugin_configuration.push_str(&format!("{}{} {:?}\n", indent, key, value));
+        }
+    }
+    plugin_configuration
+}
+
+// A layout running the commands in regular panes, without zlaunch, eg. to turn a run that works
+// into a permanent project layout. Commands of the same stage are split side by side.
+// commands_to_run are the commands as they would be launched (shell or exec, cwd).
+pub fn commands_layout(commands: &[Command], commands_to_run: &[CommandToRun]) -> String {
+    let mut layout = String::new();
+    layout.push_str("layout {\n    tab name=\"zlaunch\" {\n");
+    let mut i = 0;
+    while i < commands.len() {
+        let stage = commands[i].stage;
+        let stage_len = commands[i..].iter().take_while(|c| c.stage == stage).count();
+        let indent = if stage_len > 1 { "            " } else { "        " };
+        if stage_len > 1 {
+            layout.push_str("        pane split_direction=\"vertical\" {\n");
+        }
+        for (command, command_to_run) in commands[i..i + stage_len].iter().zip(&commands_to_run[i..i + stage_len]) {
+            let mut pane = format!("{}pane command={:?} name={:?}", indent, command_to_run.path.to_string_lossy(), command.command_line);
+            if let Some(cwd) = &command_to_run.cwd {
+                pane.push_str(&format!(" cwd={:?}", cwd.to_string_lossy()));
+            }
+            if command_to_run.args.is_empty() {
+                layout.push_str(&format!("{}\n", pane));
+            } else {
+                let args: Vec<String> = command_to_run.args.iter().map(|a| format!("{:?}", a)).collect();
+                layout.push_str(&format!("{} {{\n{}    args {}\n{}}}\n", pane, indent, args.join(" "), indent));
+            }
+        }
+        if stage_len > 1 {
+            layout.push_str("        }\n");
+        }
+        i += stage_len;
+    }
+    layout.push_str("    }\n}\n");
+    layout
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+pub const DEFAULT_DURATION_PRECISION: usize = 1;
+
+// Formats a duration for display, eg. "350ms", "4.2s", "1m 23.4s" or "2h 3m 4s"
+// precision is the number of decimal places shown for seconds under an hour
+pub fn format_duration(duration: Duration, precision: usize) -> String {
+    let total_secs = duration.as_secs();
+    if total_secs >= 3600 {
+        let hours = total_secs / 3600;
+        let minutes = (total_secs % 3600) / 60;
+        let seconds = total_secs % 60;
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if total_secs >= 60 {
+        let minutes = total_secs / 60;
+        let seconds = truncate(duration.as_secs_f64() - (minutes * 60) as f64, precision);
+        format!("{}m {:.*}s", minutes, precision, seconds)
+    } else if total_secs == 0 && precision > 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.*}s", precision, truncate(duration.as_secs_f64(), precision))
+    }
+}
+
+// A rough duration for estimates, rounded up to the largest unit, eg. "2m" or "1h 5m"
+pub fn format_estimate(duration: Duration) -> String {
+    let total_secs = duration.as_secs();
+    if total_secs >= 3600 {
+        let minutes = total_secs.div_ceil(60);
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else if total_secs >= 60 {
+        format!("{}m", total_secs.div_ceil(60))
+    } else {
+        format!("{}s", total_secs.max(1))
+    }
+}
+
+// truncate rather than round so that we never display eg. "60.0s" for 59.96 seconds
+fn truncate(value: f64, precision: usize) -> f64 {
+    let factor = 10_f64.powi(precision as i32);
+    (value * factor).floor() / factor
+}
+
+// Formats a time (milliseconds since the unix epoch) as "HH:MM:SS UTC"
+pub fn format_time_of_day(ms_since_epoch: u64) -> String {
+    let secs_of_day = (ms_since_epoch / 1000) % 86400;
+    format!("{:02}:{:02}:{:02} UTC", secs_of_day / 3600, (secs_of_day % 3600) / 60, secs_of_day % 60)
+}
+
+// Parses durations like "500ms", "30s", "1.5m", "1h" or "1m30s", plain numbers being seconds
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    if let Ok(secs) = duration.parse::<f64>() {
+        // negative, too large, inf or NaN
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, unit_and_rest) = rest.split_at(number_len);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = unit_and_rest.find(|c: char| c.is_ascii_digit()).unwrap_or(unit_and_rest.len());
+        let (unit, remaining) = unit_and_rest.split_at(unit_len);
+        let secs = match unit.trim() {
+            "ms" => number / 1000.0,
+            "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => return None,
+        };
+        total = total.checked_add(Duration::try_from_secs_f64(secs).ok()?)?;
+        rest = remaining;
+    }
+    Some(total)
+}
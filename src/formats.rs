@@ -0,0 +1,129 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// TOML and YAML versions of the configuration, for teams that keep their task definitions in
+// those formats. They're mapped to the KDL one, eg. in YAML:
+// commands:
+//   - cargo build
+//   - stage: checks
+//     commands:
+//       - cargo clippy
+//       - cmd: cargo test
+//         success_codes: "0 1"
+
+// Looked for (in this order) when there's no .zlaunch.kdl, relative to the host folder
+pub const PROJECT_FILES: &[&str] = &["zlaunch.toml", ".zlaunch.toml", "zlaunch.yaml", ".zlaunch.yaml", "zlaunch.yml", ".zlaunch.yml"];
+
+// Command attributes that are child nodes in KDL, see CommandOptions::from_kdl_node
+const CHILD_ATTRIBUTES: &[&str] = &["path_prepend", "python_venv", "node_version", "script", "input", "paths"];
+
+// The commands of a TOML or YAML payload as KDL, None if it's neither. A YAML payload is either
+// a list of commands or a mapping with a commands list, so that plain text (which is valid YAML)
+// isn't mistaken for it.
+pub fn commands_to_kdl(payload: &str) -> Option<String> {
+    let document = toml::from_str::<Value>(payload).ok()
+        .or_else(|| serde_yaml::from_str::<Value>(payload).ok())?;
+    let commands = match &document {
+        Value::Array(commands) => commands,
+        Value::Object(document) => document.get("commands")?.as_array()?,
+        _ => return None,
+    };
+    Some(command_list_to_kdl(commands, ""))
+}
+
+// The top level keys of a TOML or YAML project file as configuration, like the nodes of
+// .zlaunch.kdl
+pub fn load_configuration(file_name: &str, contents: &str) -> Option<BTreeMap<String, String>> {
+    let document = if file_name.ends_with(".toml") {
+        toml::from_str::<Value>(contents).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_str::<Value>(contents).map_err(|e| e.to_string())
+    };
+    let document = match document {
+        Ok(Value::Object(document)) => document,
+        Ok(_) => {
+            eprintln!("Failed to parse {}: expected a mapping of configuration keys", file_name);
+            return None;
+        }
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", file_name, e);
+            return None;
+        }
+    };
+    let mut configuration = BTreeMap::new();
+    for (key, value) in document {
+        let value = match (key.as_str(), value) {
+            ("commands", Value::Array(commands)) => command_list_to_kdl(&commands, ""),
+            (_, Value::String(value)) => value,
+            (_, value @ Value::Bool(_)) | (_, value @ Value::Number(_)) => value.to_string(),
+            (key, _) => {
+                eprintln!("Ignoring {} in {}: only commands can be a list or a mapping", key, file_name);
+                continue;
+            }
+        };
+        configuration.insert(key, value);
+    }
+    Some(configuration)
+}
+
+fn command_list_to_kdl(commands: &[Value], indent: &str) -> String {
+    let mut kdl = String::new();
+    for command in commands {
+        match command {
+            Value::String(command_line) => kdl.push_str(&format!("{}{:?}\n", indent, command_line)),
+            Value::Object(command) if command.contains_key("commands") => {
+                let stage_commands = command.get("commands").and_then(|c| c.as_array()).map(|c| c.as_slice()).unwrap_or_default();
+                match command.get("stage").and_then(|s| s.as_str()) {
+                    Some(stage_name) => kdl.push_str(&format!("{}stage {:?} {{\n", indent, stage_name)),
+                    None => kdl.push_str(&format!("{}stage {{\n", indent)),
+                }
+                kdl.push_str(&command_list_to_kdl(stage_commands, &format!("{}    ", indent)));
+                kdl.push_str(&format!("{}}}\n", indent));
+            }
+            Value::Object(command) => {
+                let command_line = match command.get("cmd").or_else(|| command.get("command")).and_then(|c| c.as_str()) {
+                    Some(command_line) => command_line,
+                    None => {
+                        eprintln!("Ignoring a command without cmd: {}", Value::Object(command.clone()));
+                        continue;
+                    }
+                };
+                let mut node = format!("{}{:?}", indent, command_line);
+                let mut children = vec![];
+                for (attribute, value) in command.iter().filter(|(a, _)| *a != "cmd" && *a != "command") {
+                    let value = match value {
+                        Value::String(value) => format!("{:?}", value),
+                        Value::Bool(_) | Value::Number(_) => value.to_string(),
+                        // eg. tags = ["docker", "slow"] or path_prepend = ["./node_modules/.bin"]
+                        Value::Array(values) => {
+                            let values: Vec<String> = values.iter()
+                                .map(|v| v.as_str().map(|v| v.to_owned()).unwrap_or_else(|| v.to_string()))
+                                .collect();
+                            if CHILD_ATTRIBUTES.contains(&attribute.as_str()) {
+                                let values: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+                                children.push(format!("{} {};", attribute, values.join(" ")));
+                                continue;
+                            }
+                            format!("{:?}", values.join(" "))
+                        }
+                        _ => {
+                            eprintln!("Ignoring {} of {}: unsupported value", attribute, command_line);
+                            continue;
+                        }
+                    };
+                    if CHILD_ATTRIBUTES.contains(&attribute.as_str()) {
+                        children.push(format!("{} {};", attribute, value));
+                    } else {
+                        node.push_str(&format!(" {}={}", attribute, value));
+                    }
+                }
+                if !children.is_empty() {
+                    node.push_str(&format!(" {{ {} }}", children.join(" ")));
+                }
+                kdl.push_str(&format!("{}\n", node));
+            }
+            _ => eprintln!("Ignoring a command that is neither a command line nor a mapping: {}", command),
+        }
+    }
+    kdl
+}
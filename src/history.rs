@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::storage;
+use crate::Command;
+
+// Previous runs are kept in the host folder so that we can show whether the commands are
+// getting slower over time
+pub const HISTORY_FILE: &str = "/host/.zlaunch-history.json";
+pub const MAX_RUNS_IN_HISTORY: usize = 100;
+
+// Durations within this ratio of the average are not considered a trend
+const TREND_TOLERANCE: f64 = 0.1;
+
+// How many of the latest durations of a command its estimate is the average of
+const ESTIMATE_SAMPLE_RUNS: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RunRecord {
+    pub finished_at_ms: u64, // since the unix epoch
+    pub duration_ms: u64,
+    pub commands: Vec<CommandRecord>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>, // eg. branch=feature-x, to compare runs by
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct CommandRecord {
+    pub command_line: String,
+    pub duration_ms: Option<u64>,
+    pub exit_status: Option<i32>,
+    #[serde(default)]
+    pub succeeded: Option<bool>, // None in records from before success codes were configurable
+    #[serde(default)]
+    pub warmup: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trend {
+    Slower,
+    Faster,
+    Steady,
+}
+
+impl CommandRecord {
+    pub fn from_command(command: &Command) -> Self {
+        let duration_ms = match (command.start_time, command.end_time) {
+            (Some(start_time), Some(end_time)) => Some(end_time.duration_since(start_time).as_millis() as u64),
+            _ => None,
+        };
+        CommandRecord {
+            command_line: command.command_line.clone(),
+            duration_ms,
+            exit_status: command.exit_status,
+            succeeded: command.exit_status.map(|_| command.succeeded()),
+            warmup: command.options.warmup,
+        }
+    }
+    pub fn succeeded(&self) -> bool {
+        self.succeeded.unwrap_or(self.exit_status == Some(0))
+    }
+    pub fn failed(&self) -> bool {
+        self.exit_status.is_some() && !self.succeeded()
+    }
+}
+
+impl RunRecord {
+    pub fn successful_command_count(&self) -> usize {
+        self.commands.iter().filter(|c| c.succeeded()).count()
+    }
+    pub fn failed_command_count(&self) -> usize {
+        self.commands.iter().filter(|c| c.failed()).count()
+    }
+}
+
+pub fn load_history() -> Vec<RunRecord> {
+    match fs::read_to_string(HISTORY_FILE) {
+        Ok(history) => serde_json::from_str(&history).unwrap_or_else(|e| {
+            eprintln!("Failed to parse run history: {}", e);
+            vec![]
+        }),
+        Err(_) => vec![], // no runs yet
+    }
+}
+
+// Appends the run to the history in memory, dropping the oldest runs above MAX_RUNS_IN_HISTORY
+pub fn append_run(history: &mut Vec<RunRecord>, run: RunRecord) {
+    history.push(run);
+    if history.len() > MAX_RUNS_IN_HISTORY {
+        let excess = history.len() - MAX_RUNS_IN_HISTORY;
+        history.drain(..excess);
+    }
+}
+
+// Appends the run to the history file, dropping the oldest runs above MAX_RUNS_IN_HISTORY. The
+// file is read again first, so that runs other instances recorded in the meantime are kept.
+pub fn record_run(history: &mut Vec<RunRecord>, run: RunRecord) {
+    let recorded_history = load_history();
+    if !recorded_history.is_empty() {
+        *history = recorded_history;
+    }
+    append_run(history, run);
+    let result = serde_json::to_string(history)
+        .map_err(|e| e.to_string())
+        .and_then(|history| storage::write_atomic(Path::new(HISTORY_FILE), history).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("Failed to write run history: {}", e);
+    }
+}
+
+// The successful durations of this command across the history, oldest first. Warm-up runs are
+// not representative, so they are left out.
+pub fn command_durations(history: &[RunRecord], command_line: &str) -> Vec<u64> {
+    history
+        .iter()
+        .filter_map(|run| run.commands.iter().find(|c| c.command_line == command_line))
+        .filter(|c| c.succeeded() && !c.warmup)
+        .filter_map(|c| c.duration_ms)
+        .collect()
+}
+
+// What a command usually takes: the average of its latest successful durations
+pub fn expected_duration(history: &[RunRecord], command_line: &str) -> Option<Duration> {
+    let durations = command_durations(history, command_line);
+    let recent = &durations[durations.len().saturating_sub(ESTIMATE_SAMPLE_RUNS)..];
+    if recent.is_empty() {
+        return None;
+    }
+    Some(Duration::from_millis(recent.iter().sum::<u64>() / recent.len() as u64))
+}
+
+// Compares the latest duration to the average of the ones before it
+pub fn trend(durations: &[u64]) -> Option<Trend> {
+    let (latest, previous) = durations.split_last()?;
+    if previous.is_empty() {
+        return None;
+    }
+    let average = previous.iter().sum::<u64>() as f64 / previous.len() as f64;
+    let latest = *latest as f64;
+    if latest > average * (1.0 + TREND_TOLERANCE) {
+        Some(Trend::Slower)
+    } else if latest < average * (1.0 - TREND_TOLERANCE) {
+        Some(Trend::Faster)
+    } else {
+        Some(Trend::Steady)
+    }
+}
+
+// Parses labels like "branch=feature-x os=linux"
+pub fn parse_labels(labels: &str) -> BTreeMap<String, String> {
+    labels
+        .split_whitespace()
+        .filter_map(|label| {
+            let (key, value) = label.split_once('=')?;
+            Some((key.trim().to_owned(), value.trim().to_owned()))
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+// Every distinct label of the history, sorted
+pub fn labels_in_history(history: &[RunRecord]) -> Vec<(String, String)> {
+    let mut labels: Vec<(String, String)> = history
+        .iter()
+        .flat_map(|run| run.labels.iter().map(|(k, v)| (k.clone(), v.clone())))
+        .collect();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+// The runs that have this label, or all of them without a filter
+pub fn filter_by_label(history: &[RunRecord], label: Option<&(String, String)>) -> Vec<RunRecord> {
+    history
+        .iter()
+        .filter(|run| label.map(|(key, value)| run.labels.get(key) == Some(value)).unwrap_or(true))
+        .cloned()
+        .collect()
+}
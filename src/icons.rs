@@ -0,0 +1,74 @@
+use kdl::KdlDocument;
+
+// The prefixes of the commands in the list and in the titles of their panes, one per state
+#[derive(Debug, Clone)]
+pub struct StatusIcons {
+    pub success: String,
+    pub failure: String,
+    pub running: String,
+    pub pending: String,
+    pub skipped: String,
+}
+
+impl Default for StatusIcons {
+    fn default() -> Self {
+        StatusIcons {
+            success: "✔".to_owned(),
+            failure: "✘".to_owned(),
+            running: "▶".to_owned(),
+            pending: "⏳".to_owned(),
+            skipped: "⏭".to_owned(),
+        }
+    }
+}
+
+impl StatusIcons {
+    // For terminals and fonts without these glyphs
+    pub fn ascii() -> Self {
+        StatusIcons {
+            success: "[OK]".to_owned(),
+            failure: "[FAIL]".to_owned(),
+            running: "[RUN]".to_owned(),
+            pending: "[WAIT]".to_owned(),
+            skipped: "[SKIP]".to_owned(),
+        }
+    }
+    // Either the name of a set (glyphs or ascii) or states overriding those of the glyphs, eg.
+    // status_icons {
+    //     success "OK"
+    //     failure "!!"
+    // }
+    pub fn from_configuration(status_icons: &str) -> Self {
+        match status_icons.trim() {
+            "glyphs" => return StatusIcons::default(),
+            "ascii" => return StatusIcons::ascii(),
+            _ => {}
+        }
+        let mut parsed = StatusIcons::default();
+        let doc = match status_icons.parse::<KdlDocument>() {
+            Ok(doc) => doc,
+            Err(e) => {
+                eprintln!("Failed to parse status_icons: {}", e);
+                return parsed;
+            }
+        };
+        for node in doc.nodes() {
+            let icon = match node.entries().first().and_then(|e| e.value().as_string()) {
+                Some(icon) => icon.to_owned(),
+                None => {
+                    eprintln!("Invalid icon for status: {}", node.name().value());
+                    continue;
+                }
+            };
+            match node.name().value() {
+                "success" => parsed.success = icon,
+                "failure" => parsed.failure = icon,
+                "running" => parsed.running = icon,
+                "pending" => parsed.pending = icon,
+                "skipped" => parsed.skipped = icon,
+                status => eprintln!("Unknown status for status_icons: {}", status),
+            }
+        }
+        parsed
+    }
+}
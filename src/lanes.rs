@@ -0,0 +1,44 @@
+use std::time::{Duration, Instant};
+
+// The time a command occupied a concurrency slot, relative to the start of the run
+#[derive(Debug, Clone)]
+pub struct LaneSegment {
+    pub command_index: usize,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+// Assigns the commands that ran (or are running) to as few lanes as possible, so that each lane
+// is a concurrency slot. Commands are (command_index, start_time, end_time), end_time being None
+// for commands that are still running.
+pub fn assign_lanes(commands: &[(usize, Instant, Option<Instant>)], now: Instant) -> Vec<Vec<LaneSegment>> {
+    let run_start = match commands.iter().map(|(_, start, _)| *start).min() {
+        Some(run_start) => run_start,
+        None => return vec![],
+    };
+    let mut segments: Vec<LaneSegment> = commands
+        .iter()
+        .map(|(command_index, start, end)| LaneSegment {
+            command_index: *command_index,
+            start: start.duration_since(run_start),
+            end: end.unwrap_or(now).duration_since(run_start),
+        })
+        .collect();
+    segments.sort_by_key(|s| s.start);
+    let mut lanes: Vec<Vec<LaneSegment>> = vec![];
+    for segment in segments {
+        let free_lane = lanes
+            .iter_mut()
+            .find(|lane| lane.last().map(|last| last.end <= segment.start).unwrap_or(true));
+        match free_lane {
+            Some(lane) => lane.push(segment),
+            None => lanes.push(vec![segment]),
+        }
+    }
+    lanes
+}
+
+// The segment of the lane at this point in time, if any
+pub fn segment_at(lane: &[LaneSegment], at: Duration) -> Option<&LaneSegment> {
+    lane.iter().find(|s| s.start <= at && at < s.end.max(s.start + Duration::from_millis(1)))
+}
@@ -1,11 +1,75 @@
-use kdl::KdlDocument;
-use std::time::Instant;
-use std::path::PathBuf;
-use std::fs::{self, File};
-use std::io::prelude::*;
+mod action_menu;
+mod activation;
+mod affected;
+mod api;
+mod audit;
+mod bench;
+mod capture;
+mod chain;
+mod collapse;
+mod condition;
+mod doctor;
+mod env_file;
+mod export;
+mod format;
+mod formats;
+mod history;
+mod icons;
+mod lanes;
+mod matrix;
+mod palette;
+mod placeholders;
+mod process;
+mod readiness;
+mod recursion;
+mod report;
+mod run_status;
+mod run_tmp;
+mod schema;
+mod scheduler;
+mod share;
+mod shell_words;
+mod shell_history;
+mod sibling;
+mod snapshot;
+mod status;
+mod stopwatch;
+mod templates;
+mod storage;
+mod tags;
+mod theme;
+mod wizard;
+mod worker;
+
+use kdl::{KdlDocument, KdlError, KdlNode};
+use regex::Regex;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::fs;
 use zellij_tile::prelude::*;
 
-use std::collections::{HashMap, BTreeMap};
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use action_menu::{ActionMenu, MenuAction};
+use audit::{AuditEvent, AuditTrail, ReplayedStatus};
+use condition::Condition;
+use format::{format_duration, format_estimate, parse_duration, DEFAULT_DURATION_PRECISION};
+use history::{CommandRecord, RunRecord, Trend};
+use icons::StatusIcons;
+use palette::{OneOff, Palette};
+use snapshot::{now_ms, RunSnapshot, CommandSnapshot};
+use status::{CommandFinishedMessage, CommandStartedMessage, RunFinishedMessage, RunState, StatusMessage, StatusReport};
+use stopwatch::Stopwatch;
+use readiness::ReadyWhen;
+use scheduler::AfterExit;
+use share::SharedRun;
+use sibling::SiblingPolicy;
+use tags::TagFilter;
+use wizard::Wizard;
+use worker::ReportFollowUp;
+use theme::Theme;
 
 #[derive(Default)]
 struct State {
@@ -19,20 +83,650 @@ struct State {
     selected_index: Option<usize>,
     paused: bool,
     stop_on_failure: bool,
-    panes_to_run_on_completion: HashMap<String, Option<PaneId>>,
+    focus_follow: bool, // the pane of the running command is focused as the run goes
+    panes_to_run_on_completion: Vec<CompletionPane>,
+    run_completion_panes_on: RunEndOutcome,
+    show_descriptions: DescriptionMode,
+    tabs: Vec<TabInfo>,
+    first_failure_reported: bool,
+    observer: bool,
+    observed_plugin_id: Option<u32>,
+    failure_screenshots: bool,
+    dump_failed_panes: bool, // the output of failed commands is kept when their panes are closed
+    failure_tail_lines: usize, // lines of a failed command's output shown under it
+    live_tail: bool, // the bottom half of the pane shows the output of the selected or running command
+    live_tail_lines: Vec<String>,
+    log_output: bool, // every command's output is kept in a log file
+    host_folder: PathBuf,
+    duration_precision: usize,
+    watch: bool,
+    watch_ignore: Vec<String>,
+    on_retrigger: RetriggerPolicy,
+    rerun_queued: bool,
+    last_watch_trigger: Option<Instant>,
+    watch_idle_period: Option<Duration>, // runs wait for the files to stop changing for this long
+    last_deferred_change: Option<Instant>, // a change waiting for the idle period
+    spinner_frame: usize,
+    theme: Theme,
+    minimal_mode: bool,
+    plain_ui: bool, // the list as prefixed lines of text, eg. [OK 3s] cargo build
+    accessible: bool, // status is conveyed by symbols and text, never by color alone
+    status_icons: StatusIcons,
+    list_status_icons: bool, // the commands in the list are prefixed with their status icon, not only their panes
+    restored_from_persisted_state: bool,
+    run_ended: bool, // the state of a run is only persisted until it ends, see persist_state
+    should_verify_restored_panes: bool,
+    teardown_commands: Vec<String>,
+    teardown_ran_for_run: Option<usize>,
+    setup_commands: Vec<String>,
+    setup_running: bool, // the commands of the run wait for the setup commands
+    affected_base: String, // commands with paths are skipped unless one of them changed since this git revision
+    changed_paths: Option<Vec<String>>, // since affected_base, None if unknown (every command runs)
+    checking_affected_paths: bool, // the commands of the run wait for the changed paths
+    timer_armed: bool,
+    history: Vec<RunRecord>,
+    history_recorded_for_run: Option<usize>,
+    pipe_run_status: bool, // a CLI pipe that sent commands is answered with the run status once it ends
+    pipes_awaiting_run_status: Vec<String>,
+    previous_results: HashMap<String, bool>, // whether each command line succeeded in the previous run
+    show_history: bool,
+    labels: BTreeMap<String, String>, // attached to the runs recorded in the history
+    history_label_filter: Option<(String, String)>,
+    audit_trail: AuditTrail,
+    show_audit_trail: bool,
+    summary_screen: bool, // show the summary when the run ends rather than closing
+    summary_pane: bool, // open a pane with the report when the run ends, it outlives us
+    show_summary: bool,
+    show_details: bool, // the detail view of the selected command
+    show_keybindings: bool, // the keybindings overlay, toggled with ?
+    sort_mode: SortMode,
+    marking: bool, // moving the selection marks commands for bulk actions, toggled with V
+    marked: BTreeSet<usize>,
+    hide_succeeded: bool, // succeeded commands are collapsed into one line, toggled with c
+    confirm_restart: bool, // ask before ENTER restarts a run that isn't over
+    rerun_in_same_pane: bool, // a restart re-runs exited commands in their pane rather than a new one
+    restart_prompt: bool, // waiting for the answer to "Restart run?"
+    resume_prompt: bool, // a run interrupted eg. by a session restart was restored, resume it?
+    action_menu: Option<ActionMenu>, // opened with ENTER on the selected command
+    waiting_to_start: bool, // start_paused, the first run only starts with ENTER
+    shell_history_file: String,
+    report_file: PathBuf, // relative to the host folder
+    plugin_location: String, // where zlaunch is installed, for the exported layouts and keybindings
+    install_key: String, // eg. Alt l, bound to launch zlaunch in the keybinding written with L
+    report_on_run_end: bool,
+    babysit_interval: Duration, // between the attempts of a babysat command
+    capacity_hint: Option<usize>, // how many commands the machine runs at once comfortably
+    allow_recursion: bool, // run commands that start zellij or zlaunch again
+    spawn_timeout: Duration, // how long a command pane has to open
+    termination_grace_period: Duration, // between the ctrl-c sent to a running command and closing its pane
+    terminating_panes: Vec<(u32, Instant)>, // panes sent a ctrl-c, and when to close them
+    close_after_termination: bool, // the run is over, we close ourselves once the panes are closed
+    pending_worker_jobs: usize, // sent to the worker, which hasn't answered yet
+    close_after_worker_jobs: bool, // the run is over, we close ourselves once the worker is done
+    start_at: Option<Instant>, // the first run is delayed until then
+    loop_mode: Option<LoopMode>,
+    bench_runs: Option<usize>, // every command is run this many times, for its timing statistics
+    loop_iteration: usize, // starting at 1
+    failed_loop_iterations: usize,
+    first_failed_loop_iteration: Option<usize>,
+    repeat_every: Option<Duration>,
+    next_repeat_at: Option<Instant>,
+    stopwatch: Stopwatch, // the active time of the current run
+    import_candidates: Option<Vec<String>>, // the import screen is shown while this is Some
+    import_selection: HashSet<usize>,
+    import_cursor: usize,
+    wizard: Option<Wizard>, // the first run wizard is shown while this is Some
+    quick_add_input: Option<String>, // the command being typed after pressing a
+    palette: Option<Palette>, // the command palette is shown while this is Some
+    shell_history_for_palette: bool, // the shell history being read is offered in the palette
+    one_offs: Vec<OneOff>, // the commands run from the palette, outside of the run
+    edit_error: Option<String>, // why the last edit of the commands wasn't applied
+    config_error: Option<ConfigError>, // the configured commands look like KDL but don't parse, nothing runs
+    write_project_file_after_import: bool,
+    stage_names: HashMap<usize, String>,
+    config_file: Option<PathBuf>, // relative to the host folder
+    suspended: bool, // the running commands are stopped, not just the run
+    collapse_patterns: Vec<Regex>,
+    hook_commands: HashMap<String, Vec<String>>, // eg. on_failure, on_success, stage:checks or tag:docker
+    hooks_ran_for_run: HashMap<String, usize>,
+    profiles: BTreeMap<String, String>, // name -> commands
+    profile_options: BTreeMap<String, BTreeMap<String, String>>, // name -> configuration overrides
+    base_configuration: BTreeMap<String, String>, // the configuration before profile overrides
+    active_profile: Option<String>,
+    on_success_run_profile: Option<String>,
+    pending_capability_requests: Vec<Capability>,
+    capabilities: HashSet<Capability>,
+    denied_capabilities: HashSet<Capability>,
+    permissions_screen_dismissed: bool, // the user chose to go on without the denied permissions
+    on_sibling_instance: SiblingPolicy,
+    tag_filter: TagFilter, // which commands run by their tags
+    run_order: scheduler::RunOrder, // independent commands can run fastest (or slowest) first
+    configured_tag_filter: TagFilter, // run_tags and skip_tags, before T changed it
+    run_tag_filter: TagFilter, // tag_filter as the run started with it, T only changes the next run
+    start_after_sibling_check: bool, // the first run waits to know whether a sibling already runs it
+    sibling_check_until: Option<Instant>, // we asked the other instances, until then they can answer
+    sibling_plugin_id: Option<u32>, // the instance already running our commands
+    page: usize,
+    page_size: Option<usize>, // None when all the commands fit in the pane
+    exec: bool, // run the commands directly rather than through the shell
+    quiet: bool, // command panes open in the background and are only shown if they fail
+    fully_quiet: bool, // quiet="full": every command is quiet and its output is logged to a file
+    open_in_new_tab: bool, // command panes are moved to a dedicated tab
+    stack_panes: bool, // only one command pane is shown at a time, cycled with v
+    tile_failed_panes: bool, // failed panes are tiled in their own tab rather than floating on top of each other
+    slow_command_factor: f64, // commands running this many times longer than usual are flagged, 0 to never flag them
+    auto_resize: bool, // our pane is resized to fit the command list
+    time_travel: Option<usize>, // the audit entry of the current run being replayed
+    last_auto_resize: Option<(Resize, usize, usize)>, // the last resize requested, for the rows wanted and the rows we had
+    visible_stack_member: Option<usize>, // the command whose pane is shown when stacking
+    command_tab_requested: bool, // the dedicated tab is being created
+    panes_waiting_for_command_tab: Vec<PaneId>,
+    shell_args: Vec<String>, // "{cmd}" stands for the command line
+    env_file: Option<String>, // relative to the host folder, eg. ".env"
+    env_variables: Vec<(String, String)>, // passed to every command, read from the env_file
 }
 
+const COMMAND_TAB_NAME: &str = "zlaunch";
+const FAILURES_TAB_NAME: &str = "zlaunch failures";
+// Pipes controlling the run from keybindings of the zellij configuration, so that they work
+// whichever pane is focused, eg.
+// bind "Alt p" { MessagePlugin "file:/path/to/zlaunch.wasm" { name "zlaunch_toggle_pause"; } }
+// or from the command line: zellij pipe --name zlaunch_rerun_failed
+const TOGGLE_PAUSE_PIPE_NAME: &str = "zlaunch_toggle_pause";
+const RESTART_PIPE_NAME: &str = "zlaunch_restart";
+const RERUN_FAILED_PIPE_NAME: &str = "zlaunch_rerun_failed";
+const COMMAND_HOOK_CONTEXT: &str = "command_hook"; // before or after, for the panes of the per-command hooks
+const RERUN_PANE_CONTEXT: &str = "rerun_pane"; // the pane a before hook re-runs its command in once it passes
+const SETUP_CONTEXT: &str = "setup";
+const FINAL_STATE_FILE: &str = "/data/final-state.json"; // in the plugin's data folder
+const EXPORTED_COMMANDS_FILE: &str = ".zlaunch/commands.kdl"; // relative to the host folder
+const EXPORTED_LAYOUT_FILE: &str = ".zlaunch/layout.kdl"; // relative to the host folder
+const COMMANDS_LAYOUT_FILE: &str = ".zlaunch/commands-layout.kdl"; // relative to the host folder
+const INSTALL_LAYOUT_FILE: &str = ".zlaunch/install/layout.kdl"; // relative to the host folder
+const INSTALL_KEYBINDS_FILE: &str = ".zlaunch/install/keybinds.kdl"; // relative to the host folder
+const DEFAULT_INSTALL_KEY: &str = "Alt l";
+const EDIT_BUFFER_FOLDER: &str = ".zlaunch"; // relative to the host folder
+const EDIT_BUFFER_HEADER: &str = "// One quoted command per line, commands in a stage run at once, eg.\n// \"cargo build\"\n// stage \"checks\" {\n//     \"cargo test\" success_codes=\"0 1\"\n// }\n";
+const LOCKED_COMMAND_STAGE: usize = usize::MAX; // while renumbering the stages after an edit
+
+const DEFAULT_BABYSIT_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_SPAWN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(3);
+const DEFAULT_SLOW_COMMAND_FACTOR: f64 = 2.0;
+const SPINNER_FRAMES: [&str; 8] = ["⣾", "⣽", "⣻", "⢿", "⡿", "⣟", "⣯", "⣷"];
+const PROGRESS_BAR_MAX_WIDTH: usize = 30;
+const PROGRESS_CIRCLE_FRAMES: [&str; 5] = ["○", "◔", "◑", "◕", "●"];
+const MINIMAL_MODE_MAX_ROWS: usize = 3; // panes this small are always rendered in minimal mode
+const LIVE_TAIL_MIN_ROWS: usize = 12; // below this, the live tail would leave too little for the list
+const LIVE_TAIL_MAX_BYTES: u64 = 64 * 1024; // of the capture, read on every tick
+const LIVE_TAIL_MAX_LINES: usize = 200;
+const LANES_MAX_WIDTH: usize = 40;
+const AUTO_RESIZE_SLACK: usize = 4; // extra rows tolerated before shrinking the pane
+const PAGINATION_RESERVED_ROWS: usize = 13; // title, progress bar, page summary, status and help
+// (keys, description) as listed in the keybindings overlay
+const KEYBINDINGS: &[(&str, &str)] = &[
+    ("↓↑", "Select a command"),
+    ("PgDn/PgUp", "Next/previous page"),
+    ("TAB", "Focus the pane of the selected command"),
+    ("D", "Details of the selected command"),
+    ("r", "Re-run the selected command (details)"),
+    ("Ctrl c", "Kill the selected command (details)"),
+    ("s", "Skip/unskip the selected command"),
+    ("Ctrl ↓↑", "Move the selected command (if not started)"),
+    ("y", "Clone the selected command"),
+    ("b", "Babysit the selected command"),
+    ("d", "Run the suggested fix of the selected command"),
+    ("V", "Mark commands: ↓↑/SPACE to mark, then r, Ctrl c, s or DEL"),
+    ("ENTER", "Actions of the selected command (or restart the run)"),
+    ("SPACE", "Pause after the running command"),
+    ("z", "Suspend/resume the running commands"),
+    ("x", "Abort the run, killing the running commands"),
+    ("f", "Stop on failure"),
+    ("F", "Focus the pane of the running command as the run goes"),
+    ("c", "Collapse the succeeded commands"),
+    ("O", "Sort by definition, failures or duration"),
+    ("T", "Change which tags run (from the next run)"),
+    ("v", "Show the next command pane (stack_panes)"),
+    ("←/→", "Step through a finished run"),
+    ("a", "Add a command"),
+    ("e", "Edit the commands"),
+    ("o", "Open the configuration file"),
+    ("n", "Next profile"),
+    ("i", "Import commands from the shell history"),
+    ("p", "Run a single command on its own, outside of the run"),
+    ("h", "History"),
+    ("g", "Filter the history by label"),
+    ("t", "Audit trail"),
+    ("w", "Save the commands to the project file"),
+    ("m", "Write the report"),
+    ("l", "Export a layout with zlaunch"),
+    ("k", "Export a layout of the commands"),
+    ("L", "Write a layout and keybinding launching zlaunch for the project"),
+    ("S", "Share the run"),
+    ("?", "Keybindings"),
+];
+
 register_plugin!(State);
+register_worker!(worker::ZlaunchWorker, zlaunch_worker, ZLAUNCH_WORKER);
+
+// What to do when a watch trigger fires while a command is still running
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum RetriggerPolicy {
+    #[default]
+    Queue,
+    Restart,
+    Skip,
+}
+
+impl RetriggerPolicy {
+    pub fn from_str(policy: &str) -> Option<Self> {
+        match policy {
+            "queue" => Some(RetriggerPolicy::Queue),
+            "restart" => Some(RetriggerPolicy::Restart),
+            "skip" => Some(RetriggerPolicy::Skip),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetriggerPolicy::Queue => "queue",
+            RetriggerPolicy::Restart => "restart",
+            RetriggerPolicy::Skip => "skip",
+        }
+    }
+}
+
+// Chains runs automatically, eg. to flush out flaky tests
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LoopMode {
+    Times(usize), // failed iterations don't stop the loop
+    UntilFailure,
+}
+
+impl LoopMode {
+    pub fn from_str(mode: &str) -> Option<Self> {
+        match mode {
+            "until-failure" => Some(LoopMode::UntilFailure),
+            times => times.parse().ok().filter(|t| *t > 0).map(LoopMode::Times),
+        }
+    }
+}
+
+// The order the commands are listed in, cycled with O. The selection stays on the same command
+// when the order changes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum SortMode {
+    #[default]
+    Definition,
+    FailuresFirst,
+    SlowestFirst,
+}
+
+impl SortMode {
+    pub fn next(&self) -> Self {
+        match self {
+            SortMode::Definition => SortMode::FailuresFirst,
+            SortMode::FailuresFirst => SortMode::SlowestFirst,
+            SortMode::SlowestFirst => SortMode::Definition,
+        }
+    }
+    pub fn description(&self) -> &'static str {
+        match self {
+            SortMode::Definition => "definition order",
+            SortMode::FailuresFirst => "failures first",
+            SortMode::SlowestFirst => "slowest first",
+        }
+    }
+}
+
+// Points in the run at which completion panes can be triggered
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LifecycleEvent {
+    RunStart,
+    FirstFailure,
+    StageEnd, // commands outside of a stage node are each their own stage
+    RunEnd,
+}
+
+impl LifecycleEvent {
+    pub fn from_str(event: &str) -> Option<Self> {
+        match event {
+            "run_start" => Some(LifecycleEvent::RunStart),
+            "first_failure" => Some(LifecycleEvent::FirstFailure),
+            "stage_end" => Some(LifecycleEvent::StageEnd),
+            "run_end" => Some(LifecycleEvent::RunEnd),
+            _ => None,
+        }
+    }
+}
+
+// Which run endings fire the run_end completion panes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum RunEndOutcome {
+    #[default]
+    Success,
+    Failure,
+    Always,
+}
+
+impl RunEndOutcome {
+    pub fn from_str(outcome: &str) -> Option<Self> {
+        match outcome {
+            "success" => Some(RunEndOutcome::Success),
+            "failure" => Some(RunEndOutcome::Failure),
+            "always" => Some(RunEndOutcome::Always),
+            _ => None,
+        }
+    }
+    pub fn includes(&self, succeeded: bool) -> bool {
+        match self {
+            RunEndOutcome::Success => succeeded,
+            RunEndOutcome::Failure => !succeeded,
+            RunEndOutcome::Always => true,
+        }
+    }
+}
+
+// When the desc of a command is shown under it in the list
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum DescriptionMode {
+    #[default]
+    Always,
+    Selected,
+}
+
+impl DescriptionMode {
+    pub fn from_str(mode: &str) -> Option<Self> {
+        match mode {
+            "always" => Some(DescriptionMode::Always),
+            "selected" => Some(DescriptionMode::Selected),
+            _ => None,
+        }
+    }
+}
+
+// Permissions are requested in groups, one after the other, so that we know which features to
+// disable when only some of them are granted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Capability {
+    RunCommands,
+    OpenFiles, // the editor, opening the config file and exporting layouts
+    Messaging, // state broadcasts, pipes and linked profiles
+}
+
+impl Capability {
+    pub fn all() -> Vec<Capability> {
+        vec![Capability::RunCommands, Capability::OpenFiles, Capability::Messaging]
+    }
+    pub fn permissions(&self) -> Vec<PermissionType> {
+        match self {
+            Capability::RunCommands => vec![
+                PermissionType::ReadApplicationState,
+                PermissionType::ChangeApplicationState,
+                PermissionType::RunCommands,
+            ],
+            Capability::OpenFiles => vec![PermissionType::OpenFiles],
+            Capability::Messaging => vec![
+                PermissionType::MessageAndLaunchOtherPlugins,
+                PermissionType::ReadCliPipes,
+            ],
+        }
+    }
+    pub fn description(&self) -> &'static str {
+        match self {
+            Capability::RunCommands => "running commands",
+            Capability::OpenFiles => "editing (e, o, l)",
+            Capability::Messaging => "pipes and observers",
+        }
+    }
+}
+
+// How the panes to run on completion are found
+#[derive(Debug, Clone)]
+enum CompletionTarget {
+    Title(String),
+    TitleRegex(Regex),
+    Pane(PaneId),
+    Tab(String), // all the panes in the tab with this name or position (starting at 1)
+}
+
+impl CompletionTarget {
+    // eg. "my pane title", regex "^cargo", terminal 3, plugin 2 or tab "backend"
+    pub fn from_kdl_node(node: &KdlNode) -> Option<Self> {
+        let name = node.name().value().trim();
+        let argument = node.entries().iter().find(|e| e.name().is_none()).map(|e| e.value());
+        match (name, argument) {
+            ("regex", Some(pattern)) => {
+                let pattern = pattern.as_string()?;
+                match Regex::new(pattern) {
+                    Ok(regex) => Some(CompletionTarget::TitleRegex(regex)),
+                    Err(e) => {
+                        eprintln!("Invalid completion pane regex {}: {}", pattern, e);
+                        None
+                    }
+                }
+            }
+            ("terminal", Some(id)) => id.as_i64().map(|id| CompletionTarget::Pane(PaneId::Terminal(id as u32))),
+            ("plugin", Some(id)) => id.as_i64().map(|id| CompletionTarget::Pane(PaneId::Plugin(id as u32))),
+            ("tab", Some(tab)) => tab.as_string().map(|t| t.to_owned())
+                .or_else(|| tab.as_i64().map(|t| t.to_string()))
+                .map(CompletionTarget::Tab),
+            (title, _) => Some(CompletionTarget::Title(title.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CompletionPane {
+    target: CompletionTarget,
+    pane_ids: Vec<PaneId>,
+    triggers: Vec<LifecycleEvent>,
+}
+
+// How the commands are launched, taken out of State so that a command can be launched while it's
+// borrowed mutably
+#[derive(Debug, Clone)]
+struct LaunchSettings {
+    shell_invocation: Vec<String>,
+    exec: bool,
+    quiet: bool,
+    fully_quiet: bool,
+    open_in_new_tab: bool,
+    stack_panes: bool,
+    folder: String,
+    host_folder: PathBuf,
+    run_tmp_dir: PathBuf, // relative to the host folder
+    env_variables: Vec<(String, String)>, // from the env_file
+}
+
+// What a command is launched with besides its options, worked out right before it's launched
+#[derive(Debug, Clone)]
+struct LaunchInputs {
+    command_line: String, // with the placeholders resolved
+    output_file: Option<PathBuf>, // where its stdout is kept, relative to the host folder
+    stdin_file: Option<PathBuf>, // where its stdin comes from, relative to the host folder
+    script_file: Option<PathBuf>, // where its script is written, relative to the host folder
+    chain_results_file: Option<PathBuf>, // where the parts of a || or ; chain that ran are written, relative to the host folder
+}
+
+// Characters written to the pane of a command once it opened, eg. to answer a confirmation
+#[derive(Debug, Clone)]
+struct ScriptedInput {
+    text: String,
+    delay: Duration, // after the previous input, or after the pane opened
+}
+
+// Per-command configuration, preserved when a command is reset
+#[derive(Debug, Clone, Default)]
+struct CommandOptions {
+    on_retrigger: Option<RetriggerPolicy>,
+    when: Option<Condition>,
+    locked: bool, // can't be edited, skipped or removed at runtime
+    success_codes: Option<Vec<i32>>, // exit codes considered successful, only 0 if None
+    exec: Option<bool>, // overrides the global exec mode
+    quiet: Option<bool>, // overrides the global quiet mode
+    budget: Option<Duration>, // commands taking longer are marked as over budget, but not stopped
+    warmup: bool, // eg. cache priming, its durations are left out of the statistics
+    heavy: bool, // resource hungry, we warn when several would run at once
+    allow_failure: bool, // advisory (eg. a lint step), its failure doesn't fail the run
+    independent: bool, // doesn't need the commands before it, the order option can move it
+    allow_recursion: Option<bool>, // overrides the global allow_recursion
+    path_prepend: Vec<String>, // directories put in front of PATH, relative ones to the folder
+    python_venv: Option<String>, // activated before the command
+    node_version: Option<String>, // switched to with nvm before the command
+    background: bool, // a service (eg. a dev server), the run doesn't wait for it to exit
+    ready_when: Option<ReadyWhen>, // later stages wait until the background command is up
+    name: Option<String>, // how later commands refer to this one in placeholders
+    capture_output: bool, // keep its stdout for later commands ({{output:name}} or stdin_from)
+    stdin_from: Option<String>, // the name of a capture_output command whose output is our stdin
+    tags: Vec<String>, // eg. docker, for the hooks run when all the commands with a tag are done
+    script: Option<String>, // run by the shell instead of the command line, which is then a label
+    before: Option<String>, // run in a hidden pane first, the command only runs if it succeeds
+    after: Option<String>, // run in a hidden pane once the command exits, its failure fails the command
+    desc: Option<String>, // what the command does, shown under it in the list
+    interactive: bool, // waits for the user (eg. git rebase -i), opened focused and never quiet
+    input: Vec<ScriptedInput>, // written to its pane in order once it opens
+    paths: Vec<String>, // globs or directories, the command is skipped when none of them changed
+    host: Option<String>, // eg. user@server, the command is run there through ssh
+    matrix: Vec<(String, String)>, // the parameter values of a command expanded from a matrix
+}
+
+impl CommandOptions {
+    pub fn from_kdl_node(node: &KdlNode) -> Self {
+        CommandOptions {
+            on_retrigger: node.get("on_retrigger").and_then(|e| e.value().as_string()).and_then(RetriggerPolicy::from_str),
+            when: node.get("when").and_then(|e| e.value().as_string()).and_then(|when| {
+                let condition = Condition::from_str(when);
+                if condition.is_none() {
+                    eprintln!("Invalid condition: {}", when);
+                }
+                condition
+            }),
+            locked: node.get("locked").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            success_codes: node.get("success_codes").and_then(|e| e.value().as_string()).map(|codes| {
+                codes.split_whitespace().filter_map(|code| code.parse().ok()).collect()
+            }),
+            exec: node.get("exec").and_then(|e| e.value().as_bool()),
+            quiet: node.get("quiet").and_then(|e| e.value().as_bool()),
+            warmup: node.get("warmup").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            heavy: node.get("heavy").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            allow_failure: node.get("allow_failure").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            independent: node.get("independent").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            background: node.get("background").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            name: node.get("name").and_then(|e| e.value().as_string()).map(|n| n.to_owned()),
+            capture_output: node.get("capture_output").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            tags: node.get("tags").and_then(|e| e.value().as_string())
+                .map(|tags| tags.split_whitespace().map(|t| t.to_owned()).collect())
+                .unwrap_or_default(),
+            stdin_from: node.get("stdin_from").and_then(|e| e.value().as_string()).map(|n| n.to_owned()),
+            ready_when: node.get("ready_when_port").and_then(|e| e.value().as_i64())
+                .and_then(|port| std::convert::TryFrom::try_from(port).ok())
+                .map(ReadyWhen::Port)
+                .or_else(|| node.get("ready_when_cmd").and_then(|e| e.value().as_string()).map(|c| ReadyWhen::Command(c.to_owned()))),
+            allow_recursion: node.get("allow_recursion").and_then(|e| e.value().as_bool()),
+            // eg. "npm test" { path_prepend "./node_modules/.bin" ".venv/bin"; }
+            path_prepend: node.children()
+                .map(|children| children.get_args("path_prepend").iter().filter_map(|a| a.as_string()).map(|a| a.to_owned()).collect())
+                .unwrap_or_default(),
+            // eg. "cargo test" { paths "src/**" "Cargo.toml"; }
+            paths: node.children()
+                .map(|children| children.get_args("paths").iter().filter_map(|a| a.as_string()).map(|a| a.to_owned()).collect())
+                .unwrap_or_default(),
+            // eg. "migrate db" { script r#"
+            //     set -e
+            //     ./wait-for-db.sh
+            //     diesel migration run
+            // "#; }
+            // eg. "cargo test" before="docker start test-db" after="docker stop test-db"
+            before: node.get("before").and_then(|e| e.value().as_string())
+                .or_else(|| node.children().and_then(|c| c.get_arg("before")).and_then(|a| a.as_string()))
+                .map(|b| b.to_owned()),
+            after: node.get("after").and_then(|e| e.value().as_string())
+                .or_else(|| node.children().and_then(|c| c.get_arg("after")).and_then(|a| a.as_string()))
+                .map(|a| a.to_owned()),
+            desc: node.get("desc").and_then(|e| e.value().as_string()).map(|d| d.to_owned()),
+            interactive: node.get("interactive").and_then(|e| e.value().as_bool()).unwrap_or(false),
+            host: node.get("host").and_then(|e| e.value().as_string()).map(|h| h.to_owned()),
+            matrix: vec![], // set once expanded, see commands_from_node
+            // eg. "npx prisma migrate reset" { input "y\n"; input "yes\n" delay="2s"; }
+            input: node.children()
+                .map(|children| {
+                    children.nodes().iter()
+                        .filter(|n| n.name().value() == "input")
+                        .flat_map(|n| {
+                            let delay = n.get("delay").and_then(|e| e.value().as_string()).and_then(|delay| {
+                                let duration = parse_duration(delay);
+                                if duration.is_none() {
+                                    eprintln!("Invalid input delay: {}", delay);
+                                }
+                                duration
+                            });
+                            // the delay is before the first of the node's inputs
+                            n.entries().iter()
+                                .filter(|e| e.name().is_none())
+                                .filter_map(|e| e.value().as_string())
+                                .enumerate()
+                                .map(move |(i, text)| ScriptedInput {
+                                    text: text.to_owned(),
+                                    delay: if i == 0 { delay.unwrap_or_default() } else { Duration::ZERO },
+                                })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            script: node.children().and_then(|c| c.get_arg("script")).and_then(|a| a.as_string()).map(|a| a.to_owned()),
+            python_venv: node.children().and_then(|c| c.get_arg("python_venv")).and_then(|a| a.as_string()).map(|a| a.to_owned()),
+            node_version: node.children().and_then(|c| c.get_arg("node_version")).map(|a| a.as_string().map(|a| a.to_owned()).unwrap_or_else(|| a.to_string())),
+            budget: node.get("budget").and_then(|e| e.value().as_string()).and_then(|budget| {
+                let duration = parse_duration(budget);
+                if duration.is_none() {
+                    eprintln!("Invalid budget: {}", budget);
+                }
+                duration
+            }),
+        }
+    }
+}
 
 #[derive(Debug)]
 struct Command {
     command_line: String,
+    options: CommandOptions,
     start_time: Option<Instant>,
     end_time: Option<Instant>,
     pane_id: Option<PaneId>,
     exit_status: Option<i32>,
     exited: bool,
     pane_closed_by_user: bool,
+    pane_lost: bool, // the pane was gone when restoring persisted state
+    captured_output: Option<PathBuf>, // relative to the host folder
+    log_file: Option<PathBuf>, // relative to the host folder
+    output_tail: Vec<String>, // the last lines a failed command printed
+    hint: Option<doctor::Hint>, // a suggested fix for a recognizable failure
+    launch_inputs: Option<LaunchInputs>,
+    attempts: usize, // how many times the command was launched in the current run
+    babysitting: bool, // re-run automatically until it succeeds
+    next_attempt: Option<Instant>, // when a failed babysat command is re-run
+    refused: Option<&'static str>, // why the command was skipped rather than run
+    spawned_at: Option<Instant>, // when we asked for its pane, until the pane opens
+    spawn_failure: Option<String>, // the pane never opened
+    bench_samples: Vec<Duration>, // the durations of the bench mode runs so far
+    stopped: bool, // a background command we killed when the run ended
+    aborted: bool, // killed or never started because the run was aborted with x
+    ready: bool, // the readiness check of the background command passed
+    probing: bool, // a readiness check is in flight
+    stage: usize, // commands in the same stage run concurrently, stages run in order
+    skipped: bool,
+    skipped_by_user: bool, // unlike a skip due to a condition, this is kept across runs
+    before_hook_passed: bool,
+    after_hook_done: bool,
+    hook_pane_id: Option<u32>, // the pane of its before or after hook while the hook runs
+    hook_failure: Option<&'static str>, // before or after, the hook that failed the command
+    slow_warned: bool, // the on_slow hook ran for this attempt
+    chain_results: Vec<chain::BranchResult>, // the parts of its || or ; chain that ran, in order
+    scheduled_input: Vec<(Instant, String)>, // its input yet to be written, by when it's due
+    kept_pane: bool, // its pane is the one of the previous run, where it's re-run (see restart_run)
+    launch_position: Option<usize>, // when it's launched in the run, by definition order if None (see apply_run_order)
 }
 
 impl Command {
@@ -41,28 +735,203 @@ impl Command {
     {
         Command {
             command_line: command_line.to_string(),
+            options: CommandOptions::default(),
             start_time: None,
             end_time: None,
             pane_id: None,
             exit_status: None,
             exited: false,
             pane_closed_by_user: false,
+            pane_lost: false,
+            captured_output: None,
+            log_file: None,
+            output_tail: vec![],
+            hint: None,
+            launch_inputs: None,
+            attempts: 0,
+            babysitting: false,
+            next_attempt: None,
+            refused: None,
+            spawned_at: None,
+            spawn_failure: None,
+            bench_samples: vec![],
+            stopped: false,
+            aborted: false,
+            ready: false,
+            probing: false,
+            stage: 0,
+            skipped: false,
+            skipped_by_user: false,
+            before_hook_passed: false,
+            after_hook_done: false,
+            hook_pane_id: None,
+            hook_failure: None,
+            slow_warned: false,
+            chain_results: vec![],
+            scheduled_input: vec![],
+            kept_pane: false,
+            launch_position: None,
         }
     }
+    pub fn with_options(command_line: &str, options: CommandOptions) -> Self {
+        let mut command = Command::new(command_line);
+        command.options = options;
+        command
+    }
     pub fn reset(&mut self) {
-        *self = Self::new(&self.command_line);
+        let stage = self.stage;
+        let skipped_by_user = self.skipped_by_user;
+        let attempts = self.attempts;
+        let babysitting = self.babysitting;
+        let launch_position = self.launch_position;
+        *self = Self::with_options(&self.command_line, self.options.clone());
+        self.stage = stage;
+        self.skipped_by_user = skipped_by_user;
+        self.attempts = attempts;
+        self.babysitting = babysitting;
+        self.launch_position = launch_position;
+    }
+    // Whether its pane can run it again in the next run: the pane runs the same command line with
+    // the same wrappers, which can't refer to the files of the run it was opened in (the temp
+    // folder is looked up when the command starts, see run_tmp::wrap_with_run_tmp_dir)
+    pub fn can_rerun_in_place(&self) -> bool {
+        if self.is_running() || self.options.before.is_some() {
+            return false;
+        }
+        match &self.launch_inputs {
+            Some(l) => l.command_line == self.command_line
+                && l.output_file.is_none()
+                && l.stdin_file.is_none()
+                && l.script_file.is_none()
+                && l.chain_results_file.is_none(),
+            None => false,
+        }
+    }
+    // interactive commands always get a visible pane, the user has to answer them
+    pub fn is_quiet(&self, quiet: bool, fully_quiet: bool) -> bool {
+        !self.options.interactive && (fully_quiet || self.options.quiet.unwrap_or(quiet))
+    }
+    pub fn is_running(&self) -> bool {
+        self.start_time.is_some() && self.end_time.is_none()
+    }
+    pub fn is_success_code(&self, exit_code: i32) -> bool {
+        match &self.options.success_codes {
+            Some(success_codes) => success_codes.contains(&exit_code),
+            None => exit_code == 0,
+        }
+    }
+    pub fn succeeded(&self) -> bool {
+        self.exit_status.map(|e| self.is_success_code(e)).unwrap_or(false)
+    }
+    // failed, but with allow_failure, so it doesn't count against the run
+    pub fn is_allowed_failure(&self) -> bool {
+        self.options.allow_failure && self.exited && !self.succeeded()
+    }
+    // how long the command ran, or has been running for
+    pub fn duration(&self) -> Option<Duration> {
+        self.start_time.map(|start_time| self.end_time.unwrap_or_else(Instant::now).duration_since(start_time))
+    }
+    // background commands without a readiness check are up as soon as they're launched
+    pub fn is_ready(&self) -> bool {
+        self.ready || self.options.ready_when.is_none()
+    }
+    // as far as its stage is concerned, background commands are done once they're up
+    pub fn is_done(&self) -> bool {
+        self.exited || self.pane_closed_by_user || self.skipped || self.aborted || self.options.background && self.is_ready()
+    }
+    // The title of its pane, so that floating panes can be told apart, eg. ✔ cargo test (12s)
+    pub fn pane_title(&self, duration_precision: usize, icons: &StatusIcons) -> String {
+        let duration = self.duration().map(|d| format_duration(d, duration_precision)).unwrap_or_default();
+        match self.exit_status {
+            // whole seconds, it's updated every second
+            _ if self.is_running() => format!("{} {} ({})", icons.running, self.command_line, self.duration().map(|d| format_duration(d, 0)).unwrap_or_default()),
+            Some(exit_status) if self.is_success_code(exit_status) => format!("{} {} ({})", icons.success, self.command_line, duration),
+            Some(exit_status) => format!("{} {} (exit code {}, {})", icons.failure, self.command_line, exit_status, duration),
+            None => format!("{} {} ({})", icons.failure, self.command_line, duration),
+        }
+    }
+    pub fn status_icon<'a>(&self, icons: &'a StatusIcons) -> &'a str {
+        if self.is_running() {
+            &icons.running
+        } else if self.succeeded() {
+            &icons.success
+        } else if self.skipped || self.stopped {
+            &icons.skipped
+        } else if self.exited || self.aborted || self.pane_lost || self.pane_closed_by_user || self.spawn_failure.is_some() {
+            &icons.failure
+        } else {
+            &icons.pending
+        }
+    }
+    // The status as text alone, eg. [OK 3s] or [FAIL 1], for the plain UI
+    pub fn plain_status(&self, duration_precision: usize) -> String {
+        let duration = self.duration().map(|d| format!(" {}", format_duration(d, duration_precision))).unwrap_or_default();
+        if self.is_running() && self.options.background {
+            if self.is_ready() { format!("[UP{}]", duration) } else { "[STARTING]".to_owned() }
+        } else if self.is_running() {
+            format!("[RUNNING{}]", duration)
+        } else if self.succeeded() {
+            format!("[OK{}]", duration)
+        } else if self.skipped {
+            "[SKIPPED]".to_owned()
+        } else if self.stopped {
+            "[STOPPED]".to_owned()
+        } else if self.exited {
+            let exit_status = self.exit_status.map(|e| format!(" {}", e)).unwrap_or_default();
+            let kind = if self.options.allow_failure { "ALLOWED FAIL" } else { "FAIL" };
+            format!("[{}{}]", kind, exit_status)
+        } else if self.aborted {
+            "[ABORTED]".to_owned()
+        } else if self.pane_lost || self.pane_closed_by_user || self.spawn_failure.is_some() {
+            "[FAIL]".to_owned()
+        } else {
+            "[PENDING]".to_owned()
+        }
+    }
+    pub fn status(&self) -> scheduler::CommandStatus {
+        scheduler::CommandStatus {
+            stage: self.stage,
+            exited: self.exited,
+            succeeded: self.succeeded(),
+            skipped: self.skipped,
+            aborted: self.aborted,
+            allowed_failure: self.is_allowed_failure(),
+            background: self.options.background,
+            done: self.is_done(),
+            babysitting: self.babysitting,
+        }
+    }
+    pub fn is_over_budget(&self) -> bool {
+        match (self.options.budget, self.duration()) {
+            (Some(budget), Some(duration)) => duration > budget,
+            _ => false,
+        }
     }
 }
 
 impl ZellijPlugin for State {
     fn load(&mut self, configuration: BTreeMap<String, String>) {
         self.userspace_configuration = configuration;
-        request_permission(&[
-            PermissionType::ReadApplicationState,
-            PermissionType::ChangeApplicationState,
-            PermissionType::RunCommands,
-            PermissionType::OpenFiles
-        ]);
+        storage::set_instance_id(get_plugin_ids().plugin_id);
+        self.observer = self.userspace_configuration.get("observer").map(|s| s == "true").unwrap_or(false);
+        if self.observer {
+            // observers only mirror the state broadcast by another instance, they never run anything
+            self.parse_other_configuration();
+            request_permission(&[
+                PermissionType::MessageAndLaunchOtherPlugins,
+                PermissionType::ReadCliPipes,
+            ]);
+            subscribe(&[
+                EventType::PermissionRequestResult,
+                EventType::Key,
+                EventType::Timer,
+            ]);
+            set_timeout(1.0);
+            return;
+        }
+        self.load_project_file();
+        self.pending_capability_requests = Capability::all();
+        self.request_next_capability();
         subscribe(&[
             EventType::PermissionRequestResult,
             EventType::CommandPaneOpened,
@@ -74,38 +943,134 @@ impl ZellijPlugin for State {
             EventType::Timer,
             EventType::PaneClosed,
             EventType::PaneUpdate,
+            EventType::TabUpdate,
+            EventType::BeforeClose,
+            EventType::RunCommandResult,
+            EventType::CustomMessage,
         ]);
+        self.parse_profiles_from_configuration();
         self.parse_commands_from_configuration();
         self.parse_panes_to_run_on_completion_from_configuration();
         self.parse_other_configuration();
-        set_timeout(1.0); // used for indicating the elapsed time
+        self.restore_persisted_state();
+        // eg. to tweak the list (skip, reorder, add commands) before anything runs
+        self.waiting_to_start = !self.restored_from_persisted_state
+            && self.userspace_configuration.get("start_paused").map(|s| s == "true").unwrap_or(false);
+        self.start_at = self.userspace_configuration.get("start_delay_secs")
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|s| *s > 0.0 && !self.waiting_to_start)
+            .map(|s| Instant::now() + Duration::from_secs_f64(s));
+        let wizard_enabled = self.userspace_configuration.get("first_run_wizard").map(|s| s != "false").unwrap_or(true);
+        let has_configured_commands = self.userspace_configuration.contains_key("commands") || self.userspace_configuration.contains_key("template");
+        if wizard_enabled && self.commands_to_run.is_empty() && self.active_profile.is_none() && !has_configured_commands {
+            self.wizard = Some(Wizard::new());
+        }
+        self.history = history::load_history();
+        if let Err(e) = fs::create_dir_all(PathBuf::from("/host").join(process::PIDS_FOLDER)) {
+            eprintln!("Failed to create pids folder: {}", e);
+        }
+        if self.watch {
+            subscribe(&[
+                EventType::FileSystemCreate,
+                EventType::FileSystemUpdate,
+                EventType::FileSystemDelete,
+            ]);
+            watch_filesystem();
+        }
+        self.arm_timer_if_needed();
     }
     fn update(&mut self, event: Event) -> bool {
+        if self.observer {
+            return self.update_observer(event);
+        }
         let mut should_render = false;
+        let is_timer = matches!(event, Event::Timer(_));
         match event {
             Event::PaneUpdate(panes) => {
+                if self.should_verify_restored_panes {
+                    self.should_verify_restored_panes = false;
+                    should_render = self.verify_restored_panes(&panes);
+                }
                 self.log_pane_ids_as_needed(panes);
             }
+            Event::TabUpdate(tabs) => {
+                self.tabs = tabs;
+                self.move_panes_waiting_for_command_tab();
+            }
             Event::Timer(_elapsed) => {
-                set_timeout(1.0);
+                self.timer_armed = false;
+                self.spinner_frame = self.spinner_frame.wrapping_add(1);
+                self.finish_sibling_check_if_unanswered();
+                self.refresh_live_tail();
+                self.trigger_deferred_watch_run();
+                self.retry_babysat_commands();
+                self.start_scheduled_runs();
+                self.fail_commands_that_did_not_spawn();
+                self.probe_starting_services();
+                self.update_running_pane_titles();
+                self.write_scheduled_input();
+                self.warn_about_slow_commands();
+                self.close_terminated_panes();
                 should_render = true;
             }
             Event::PermissionRequestResult(result) => {
-                if result == PermissionStatus::Granted && self.running_command_index == None {
-                    self.current_run_index += 1;
-                    self.run_next_command();
+                if !self.pending_capability_requests.is_empty() {
+                    let capability = self.pending_capability_requests.remove(0);
+                    if result == PermissionStatus::Granted {
+                        self.capabilities.insert(capability);
+                    } else {
+                        self.denied_capabilities.insert(capability);
+                    }
+                    if capability == Capability::RunCommands && result == PermissionStatus::Granted && self.running_command_index == None && !self.restored_from_persisted_state && self.wizard.is_none() && self.start_at.is_none() && !self.waiting_to_start {
+                        if self.on_sibling_instance == SiblingPolicy::Ignore {
+                            self.start_run();
+                        } else {
+                            // siblings are asked through pipes, once that's allowed too
+                            self.start_after_sibling_check = true;
+                        }
+                    }
+                    if capability == Capability::Messaging && self.start_after_sibling_check {
+                        if result == PermissionStatus::Granted {
+                            self.query_siblings();
+                        } else {
+                            self.start_after_sibling_check = false;
+                            self.start_run();
+                        }
+                    }
+                    self.request_next_capability();
                 }
                 should_render = true;
             }
+            Event::CommandPaneOpened(terminal_pane_id, context) if context.contains_key(COMMAND_HOOK_CONTEXT) => {
+                self.handle_command_hook_opened(terminal_pane_id, context);
+            }
+            Event::CommandPaneOpened(terminal_pane_id, context) | Event::CommandPaneReRun(terminal_pane_id, context) if context.contains_key(palette::ONE_OFF_CONTEXT) => {
+                should_render = self.handle_one_off_opened(terminal_pane_id, context);
+            }
+            Event::CommandPaneExited(_terminal_pane_id, exit_code, context) if context.contains_key(palette::ONE_OFF_CONTEXT) => {
+                should_render = self.handle_one_off_exited(exit_code, context);
+            }
             Event::CommandPaneOpened(terminal_pane_id, context) => {
                 should_render = self.handle_command_pane_opened(terminal_pane_id, context);
+                // input without a delay is written right away
+                self.write_scheduled_input();
+            }
+            Event::CommandPaneExited(terminal_pane_id, exit_code, context) if context.contains_key(COMMAND_HOOK_CONTEXT) => {
+                self.handle_command_hook_exited(terminal_pane_id, exit_code, context);
+                should_render = true;
             }
-            Event::CommandPaneExited(_terminal_pane_id, exit_code, context) => {
+            Event::CommandPaneExited(terminal_pane_id, exit_code, mut context) => {
+                self.adopt_kept_pane(terminal_pane_id, &mut context);
                 self.handle_command_pane_exited(exit_code, context);
+                if self.focus_follow {
+                    self.focus_running_command();
+                }
                 should_render = true;
             }
-            Event::CommandPaneReRun(terminal_pane_id, context) => {
+            Event::CommandPaneReRun(terminal_pane_id, mut context) => {
+                self.adopt_kept_pane(terminal_pane_id, &mut context);
                 should_render = self.handle_command_pane_opened(terminal_pane_id, context);
+                self.write_scheduled_input();
             }
             Event::EditPaneOpened(terminal_pane_id, context) => {
                 if context.get("edit_pane_marker").is_some() {
@@ -121,487 +1086,5571 @@ impl ZellijPlugin for State {
             Event::PaneClosed(pane_id) => {
                 should_render = self.handle_pane_closed(pane_id);
             }
+            Event::RunCommandResult(exit_code, _stdout, _stderr, context) if context.contains_key(readiness::READINESS_PROBE_CONTEXT) => {
+                should_render = self.handle_readiness_probe(exit_code, context);
+            }
+            Event::RunCommandResult(exit_code, stdout, stderr, context) if context.contains_key(affected::AFFECTED_PATHS_CONTEXT) => {
+                should_render = self.handle_changed_paths(exit_code, stdout, stderr, context);
+            }
+            Event::RunCommandResult(exit_code, _stdout, stderr, context) if context.contains_key(SETUP_CONTEXT) => {
+                should_render = self.handle_setup_result(exit_code, stderr, context);
+            }
+            Event::RunCommandResult(exit_code, stdout, stderr, context) if context.contains_key(shell_history::SHELL_HISTORY_CONTEXT) => {
+                let for_palette = context.get(shell_history::SHELL_HISTORY_CONTEXT).map(|p| p == shell_history::FOR_PALETTE).unwrap_or(false);
+                should_render = self.handle_shell_history(exit_code, stdout, stderr, for_palette);
+            }
+            Event::CustomMessage(message, payload) => {
+                should_render = self.handle_worker_message(&message, &payload);
+            }
+            Event::FileSystemCreate(paths) | Event::FileSystemUpdate(paths) | Event::FileSystemDelete(paths) => {
+                should_render = self.handle_filesystem_change(paths);
+            }
+            Event::BeforeClose => {
+                self.run_teardown();
+            }
             Event::Key(key) => {
-                if key.bare_key == BareKey::Down && key.has_no_modifiers() {
+                if self.wizard.is_some() {
+                    should_render = self.handle_wizard_key(key);
+                } else if self.shows_permissions_screen() {
+                    should_render = self.handle_permissions_screen_key(key);
+                } else if self.quick_add_input.is_some() {
+                    should_render = self.handle_quick_add_key(key);
+                } else if self.import_candidates.is_some() {
+                    should_render = self.handle_import_key(key);
+                } else if self.palette.is_some() {
+                    should_render = self.handle_palette_key(key);
+                } else if self.restart_prompt {
+                    should_render = self.handle_restart_prompt_key(key);
+                } else if self.resume_prompt {
+                    should_render = self.handle_resume_prompt_key(key);
+                } else if self.action_menu.is_some() {
+                    should_render = self.handle_action_menu_key(key);
+                } else if self.show_keybindings {
+                    if matches!(key.bare_key, BareKey::Esc | BareKey::Char('?')) {
+                        self.show_keybindings = false;
+                        should_render = true;
+                    }
+                } else if key.bare_key == BareKey::Char('?') {
+                    self.show_keybindings = true;
+                    should_render = true;
+                } else if self.marking {
+                    should_render = self.handle_marking_key(key);
+                } else if key.bare_key == BareKey::Char('V') {
+                    self.marking = true;
+                    self.marked.extend(self.selected_index);
+                    should_render = true;
+                } else if self.show_details && self.selected_index.is_some() {
+                    should_render = self.handle_details_key(key);
+                } else if key.bare_key == BareKey::Char('D') && self.selected_index.is_some() {
+                    self.show_details = true;
+                    should_render = true;
+                } else if matches!(key.bare_key, BareKey::Left | BareKey::Right) && key.has_no_modifiers() {
+                    should_render = self.step_time_travel(key.bare_key == BareKey::Right);
+                } else if key.bare_key == BareKey::Esc && key.has_no_modifiers() && self.time_travel.is_some() {
+                    self.time_travel = None;
+                    should_render = true;
+                } else if matches!(key.bare_key, BareKey::Down | BareKey::Up) && key.has_modifiers(&[KeyModifier::Ctrl]) {
+                    should_render = self.move_selected_command(key.bare_key == BareKey::Down);
+                } else if key.bare_key == BareKey::Down && key.has_no_modifiers() {
                     self.move_selection_down();
                     should_render = true;
                 } else if key.bare_key == BareKey::Up && key.has_no_modifiers() {
                     self.move_selection_up();
                     should_render = true;
+                } else if key.bare_key == BareKey::PageDown && key.has_no_modifiers() {
+                    should_render = self.move_page(1);
+                } else if key.bare_key == BareKey::PageUp && key.has_no_modifiers() {
+                    should_render = self.move_page(-1);
                 } else if key.bare_key == BareKey::Tab && key.has_no_modifiers() {
                     self.focus_selected_terminal();
                     should_render = true;
                 } else if key.bare_key == BareKey::Enter && key.has_no_modifiers() {
-                    self.restart_run();
+                    if self.waiting_to_start {
+                        self.waiting_to_start = false;
+                        self.audit_trail.record(AuditEvent::Action("start".to_owned()));
+                        // otherwise, the run starts once the permission is granted
+                        if self.capabilities.contains(&Capability::RunCommands) {
+                            self.start_run();
+                        }
+                    } else if self.selected_index.is_some() {
+                        self.open_action_menu();
+                    } else {
+                        self.request_restart();
+                    }
+                    should_render = true;
+                } else if (key.bare_key == BareKey::Char('z') || (key.bare_key == BareKey::Char(' ') && self.suspended)) && key.has_no_modifiers() {
+                    // SPACE also resumes a suspended run
+                    self.toggle_suspend();
                     should_render = true;
                 } else if key.bare_key == BareKey::Char(' ') && key.has_no_modifiers() {
                     self.paused = !self.paused;
+                    self.audit_trail.record(AuditEvent::Action(if self.paused { "pause" } else { "unpause" }.to_owned()));
                     if !self.paused && !self.all_commands_exited() {
                         self.run_next_command();
                     }
                     should_render = true;
+                } else if key.bare_key == BareKey::Char('T') {
+                    self.cycle_tag_filter();
+                    should_render = true;
+                } else if key.bare_key == BareKey::Char('O') {
+                    self.sort_mode = self.sort_mode.next();
+                    should_render = true;
+                } else if key.bare_key == BareKey::Char('x') && key.has_no_modifiers() {
+                    should_render = self.abort_run();
+                } else if key.bare_key == BareKey::Char('c') && key.has_no_modifiers() {
+                    self.toggle_hide_succeeded();
+                    should_render = true;
                 } else if key.bare_key == BareKey::Char('f') && key.has_no_modifiers() {
                     self.stop_on_failure = !self.stop_on_failure;
+                    self.audit_trail.record(AuditEvent::Action(format!("stop on failure {}", if self.stop_on_failure { "on" } else { "off" })));
                     if !self.stop_on_failure && !self.all_commands_exited() {
                         self.run_next_command();
                     }
                     should_render = true;
+                } else if key.bare_key == BareKey::Char('F') {
+                    self.focus_follow = !self.focus_follow;
+                    self.audit_trail.record(AuditEvent::Action(format!("focus follow {}", if self.focus_follow { "on" } else { "off" })));
+                    if self.focus_follow {
+                        self.focus_running_command();
+                    }
+                    should_render = true;
                 } else if key.bare_key == BareKey::Char('e') && key.has_no_modifiers() {
                     self.open_editor();
                     should_render = true;
+                } else if key.bare_key == BareKey::Char('a') && key.has_no_modifiers() {
+                    self.quick_add_input = Some(String::new());
+                    should_render = true;
+                } else if key.bare_key == BareKey::Char('p') && key.has_no_modifiers() {
+                    self.open_palette();
+                    should_render = true;
+                } else if key.bare_key == BareKey::Char('n') && key.has_no_modifiers() {
+                    should_render = self.cycle_profile();
+                } else if key.bare_key == BareKey::Char('y') && key.has_no_modifiers() {
+                    should_render = self.clone_selected_command();
+                } else if key.bare_key == BareKey::Char('s') && key.has_no_modifiers() {
+                    should_render = self.toggle_skip_selected_command();
+                } else if key.bare_key == BareKey::Char('v') && key.has_no_modifiers() && self.stack_panes {
+                    should_render = self.cycle_stack();
+                } else if key.bare_key == BareKey::Char('d') && key.has_no_modifiers() {
+                    should_render = self.run_remediation();
+                } else if key.bare_key == BareKey::Char('b') && key.has_no_modifiers() {
+                    should_render = self.toggle_babysit_selected_command();
+                } else if key.bare_key == BareKey::Char('l') && key.has_no_modifiers() {
+                    self.export_layout();
+                } else if key.bare_key == BareKey::Char('S') {
+                    self.share_run();
+                } else if key.bare_key == BareKey::Char('L') {
+                    self.write_install_files();
+                } else if key.bare_key == BareKey::Char('k') && key.has_no_modifiers() {
+                    self.export_commands_layout();
+                } else if key.bare_key == BareKey::Char('o') && key.has_no_modifiers() {
+                    self.open_config_file();
+                } else if key.bare_key == BareKey::Char('h') && key.has_no_modifiers() {
+                    self.show_history = !self.show_history;
+                    self.show_audit_trail = false;
+                    should_render = true;
+                } else if key.bare_key == BareKey::Esc && key.has_no_modifiers() && self.show_summary {
+                    self.show_summary = false;
+                    should_render = true;
+                } else if key.bare_key == BareKey::Char('i') && key.has_no_modifiers() {
+                    if self.capabilities.contains(&Capability::RunCommands) {
+                        shell_history::request_shell_history(&self.shell_history_file, "");
+                    }
+                } else if key.bare_key == BareKey::Char('w') && key.has_no_modifiers() {
+                    self.save_commands_to_project_file();
+                } else if key.bare_key == BareKey::Char('m') && key.has_no_modifiers() {
+                    self.write_report(ReportFollowUp::OpenFile);
+                } else if key.bare_key == BareKey::Char('g') && key.has_no_modifiers() && self.show_history {
+                    self.cycle_history_label_filter();
+                    should_render = true;
+                } else if key.bare_key == BareKey::Char('t') && key.has_no_modifiers() {
+                    self.show_audit_trail = !self.show_audit_trail;
+                    self.show_history = false;
+                    should_render = true;
                 }
             }
             _ => (),
         };
+        self.sync_stopwatch();
+        self.arm_timer_if_needed();
+        if should_render && !is_timer {
+            // eg. another command was selected
+            self.refresh_live_tail();
+            self.broadcast_state();
+            self.broadcast_status(None);
+            self.persist_state();
+        }
+        should_render
+    }
+
+    fn pipe(&mut self, pipe_message: PipeMessage) -> bool {
+        let mut should_render = false;
+        match pipe_message.name.as_str() {
+            "zlaunch_state" if self.observer => {
+                should_render = self.handle_state_broadcast(pipe_message.source, pipe_message.payload);
+            }
+            sibling::SIBLING_QUERY_PIPE_NAME if !self.observer => {
+                self.answer_sibling_query(pipe_message.source, pipe_message.payload);
+            }
+            sibling::SIBLING_HERE_PIPE_NAME if !self.observer => {
+                should_render = self.handle_sibling_here(pipe_message.source, pipe_message.payload);
+            }
+            sibling::TAKE_OVER_PIPE_NAME if !self.observer => {
+                should_render = self.handle_take_over(pipe_message.source, pipe_message.payload);
+            }
+            "zlaunch_state_request" if !self.observer => {
+                self.broadcast_state();
+            }
+            status::STATUS_REQUEST_PIPE_NAME if !self.observer => {
+                if let PipeSource::Plugin(plugin_id) = pipe_message.source {
+                    self.broadcast_status(Some(plugin_id));
+                }
+            }
+            // eg. zellij pipe --name zlaunch::status | jq -r .state
+            status::STATUS_QUERY_PIPE_NAME if !self.observer => {
+                let report = StatusReport::new(get_plugin_ids().plugin_id, self.run_state(), self.current_run_index, &self.commands_to_run);
+                match serde_json::to_string(&report) {
+                    Ok(report) => respond_to_pipe(&pipe_message.source, status::STATUS_PIPE_NAME, &report),
+                    Err(e) => eprintln!("Failed to serialize status: {}", e),
+                }
+            }
+            "zlaunch_label" if !self.observer => {
+                should_render = self.handle_label_request(pipe_message.payload);
+            }
+            "zlaunch_run_profile" if !self.observer => {
+                should_render = self.handle_run_profile_request(pipe_message.payload);
+            }
+            "schema" => {
+                match serde_json::to_string_pretty(&schema::schema()) {
+                    Ok(schema) => respond_to_pipe(&pipe_message.source, "zlaunch_schema", &schema),
+                    Err(e) => eprintln!("Failed to serialize schema: {}", e),
+                }
+            }
+            // see api.rs for the protocol
+            api::API_PIPE_NAME if !self.observer => {
+                should_render = self.handle_api_request(&pipe_message.source, pipe_message.payload.as_deref());
+            }
+            // eg. zellij pipe --name zlaunch_import -- "zlaunch1:eyJ2..."
+            "zlaunch_import" if !self.observer => {
+                if let Some(payload) = pipe_message.payload {
+                    should_render = self.import_shared_run(&payload);
+                }
+            }
+            TOGGLE_PAUSE_PIPE_NAME | RESTART_PIPE_NAME | RERUN_FAILED_PIPE_NAME if !self.observer => {
+                should_render = self.handle_keybinding_pipe(&pipe_message.name);
+            }
+            "zlaunch_commands" if !self.observer => {
+                should_render = self.handle_commands_payload(&pipe_message.source, pipe_message.payload);
+            }
+            // eg. zellij pipe --plugin zlaunch -- "cargo build\ncargo test", an unnamed message
+            // from the CLI gets a generated name
+            _ if !self.observer && matches!(pipe_message.source, PipeSource::Cli(_)) && pipe_message.is_private && is_generated_pipe_name(&pipe_message.name) => {
+                should_render = self.handle_commands_payload(&pipe_message.source, pipe_message.payload);
+            }
+            _ => {}
+        }
+        if !self.observer {
+            self.arm_timer_if_needed();
+        }
         should_render
     }
 
     fn render(&mut self, rows: usize, cols: usize) {
+        // these take every key, so they're shown even in minimal mode
+        if self.wizard.is_some() {
+            self.render_wizard(rows, cols);
+            return;
+        }
+        if self.config_error.is_some() {
+            self.render_config_error(rows, cols);
+            return;
+        }
+        if self.shows_permissions_screen() {
+            self.render_permissions_screen(rows, cols);
+            return;
+        }
+        if !self.plain_ui && (self.minimal_mode || rows <= MINIMAL_MODE_MAX_ROWS) {
+            self.render_minimal(rows, cols);
+            return;
+        }
+        if self.import_candidates.is_some() {
+            self.render_import(rows, cols);
+            return;
+        }
+        if self.palette.is_some() {
+            self.render_palette(rows, cols);
+            return;
+        }
+        if self.show_keybindings {
+            self.render_keybindings(rows, cols);
+            return;
+        }
+        if self.show_history {
+            self.render_history(rows, cols);
+            return;
+        }
+        if self.show_audit_trail {
+            self.render_audit_trail(rows, cols);
+            return;
+        }
+        if self.time_travel.is_some() {
+            self.render_time_travel(rows, cols);
+            return;
+        }
+        if self.show_details && self.selected_index.is_some() {
+            self.render_details(rows, cols);
+            return;
+        }
+        if self.show_summary {
+            self.render_summary(rows, cols);
+            return;
+        }
+        if self.plain_ui {
+            self.render_plain(rows, cols);
+            return;
+        }
+        // the list makes do with the top half
+        let (rows, live_tail_rows) = if self.live_tail && rows >= LIVE_TAIL_MIN_ROWS {
+            (rows - rows / 2, rows / 2)
+        } else {
+            (rows, 0)
+        };
+        if self.auto_resize {
+            self.fit_pane_to_commands(rows);
+        }
+        self.update_pagination(rows);
         let title = self.render_title(rows, cols);
         let mut list = vec![];
-        for (i, command) in self.commands_to_run.iter().enumerate() {
-            let is_running = command.start_time.is_some() && command.end_time.is_none();
+        let mut services = vec![];
+        let mut previous_stage = None;
+        let render_order = self.render_order();
+        let visible_commands: Vec<usize> = render_order[self.visible_commands()].to_vec();
+        if self.sort_mode != SortMode::Definition {
+            let text = format!("Sorted: {} (O - change)", self.sort_mode.description());
+            let text_len = text.chars().count();
+            list.push(NestedListItem::new(text).color_range(self.theme.running, 0..7).color_range(self.theme.key_hint, text_len - 11..text_len - 10));
+        }
+        if !self.tag_filter.is_empty() {
+            let next_run = if self.tag_filter != self.run_tag_filter { " from the next run" } else { "" };
+            let text = format!("Tags: {}{} (T - change)", self.tag_filter.description(), next_run);
+            let text_len = text.chars().count();
+            list.push(NestedListItem::new(text).color_range(self.theme.running, 0..5).color_range(self.theme.key_hint, text_len - 11..text_len - 10));
+        }
+        let hidden_count = visible_commands.iter().filter(|i| self.is_hidden(&self.commands_to_run[**i])).count();
+        if hidden_count > 0 {
+            let text = format!("{} succeeded (c - show)", hidden_count);
+            let hidden_count_len = hidden_count.to_string().len();
+            list.push(NestedListItem::new(text).color_range(self.theme.success, 0..hidden_count_len + 10).color_range(self.theme.key_hint, hidden_count_len + 12..hidden_count_len + 13));
+        }
+        for i in visible_commands {
+            let command = &self.commands_to_run[i];
+            if self.is_hidden(command) {
+                continue;
+            }
+            let is_marked = self.marked.contains(&i);
+            if command.options.background {
+                // services are listed on their own, under the other commands
+                let is_selected = Some(i) == self.selected_index;
+                services.append(&mut self.render_command(command, command.is_running(), is_selected, is_marked, 1));
+                continue;
+            }
+            // stages are only grouped in the order they're defined in
+            let stage_name = self.stage_names.get(&command.stage).filter(|_| self.sort_mode == SortMode::Definition);
+            if let (true, Some(stage_name)) = (previous_stage != Some(command.stage), stage_name) {
+                list.push(self.render_stage_header(command.stage, stage_name));
+            }
+            previous_stage = Some(command.stage);
+            let is_running = command.is_running();
             let is_selected = Some(i) == self.selected_index;
-            list.append(&mut self.render_command(command, is_running, is_selected));
+            let indent = if stage_name.is_some() { 1 } else { 0 };
+            list.append(&mut self.render_command(command, is_running, is_selected, is_marked, indent));
+        }
+        if !services.is_empty() {
+            list.push(NestedListItem::new("Services"));
+            list.append(&mut services);
+        }
+        if !self.one_offs.is_empty() {
+            list.push(NestedListItem::new("One-off"));
+            list.extend(self.one_offs.iter().map(|o| self.render_one_off(o)));
         }
         print_text_with_coordinates(title, 1, 1, None, None);
-        print_nested_list_with_coordinates(list, 0, 3, Some(cols), None);
+        print_text_with_coordinates(self.render_progress_bar(cols), 1, 2, None, None);
+        let list_y_coords = match self.render_page_summary() {
+            Some(page_summary) => {
+                print_text_with_coordinates(page_summary, 1, 3, None, None);
+                4
+            }
+            None => 3,
+        };
+        print_nested_list_with_coordinates(list, 0, list_y_coords, Some(cols), None);
         self.render_status(rows, cols);
         self.render_help(rows, cols);
+        self.render_lanes(rows, cols);
+        if live_tail_rows > 0 {
+            self.render_live_tail(rows, live_tail_rows, cols);
+        }
     }
 }
 
-impl State {
-    fn kill_all_commands(&mut self) {
-        for command in self.commands_to_run.iter_mut() {
-            if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
-                close_terminal_pane(pane_id);
+// Commands are in kdl format, eg.
+// "cargo build"
+// stage "checks" {
+//     "cargo clippy"
+//     "cargo test" success_codes="0 1"
+// }
+fn commands_from_kdl(doc: &KdlDocument) -> (Vec<Command>, HashMap<usize, String>) {
+    let mut commands = vec![];
+    let mut stage_names = HashMap::new();
+    for (stage, node) in doc.nodes().iter().enumerate() {
+        match node.children().filter(|_| node.name().value() == "stage") {
+            Some(stage_commands) => {
+                if let Some(stage_name) = node.entries().iter().find(|e| e.name().is_none()).and_then(|e| e.value().as_string()) {
+                    stage_names.insert(stage, stage_name.to_owned());
+                }
+                // eg. stage "deploy" host="deploy@prod" { ... }, unless a command has its own
+                let stage_host = node.get("host").and_then(|e| e.value().as_string());
+                for child in stage_commands.nodes() {
+                    for mut command in commands_from_node(child) {
+                        if command.options.host.is_none() {
+                            command.options.host = stage_host.map(|h| h.to_owned());
+                        }
+                        command.stage = stage;
+                        commands.push(command);
+                    }
+                }
+            }
+            None => {
+                for mut command in commands_from_node(node) {
+                    command.stage = stage;
+                    commands.push(command);
+                }
             }
         }
     }
-    fn handle_editor_closed(&mut self) {
-        match fs::read_to_string("/host/.editing-commands") {
-            Ok(new_commands) => {
-                self.kill_all_commands();
-                self.commands_to_run = new_commands.trim().split('\n').map(|c| Command::new(c)).collect();
-                self.running_command_index = None;
-                self.current_run_index += 1;
-                self.run_next_command();
-                let _ = std::fs::remove_file("/host/.editing-commands");
-            },
-            Err(e) => {
-                eprintln!("Failed to read commands: {}", e);
-            }
+    (commands, stage_names)
+}
+
+// A single command, or one per combination of its matrix (see matrix.rs). Like the jobs of a CI
+// matrix, they're in the same stage, so they run at once.
+fn commands_from_node(node: &KdlNode) -> Vec<Command> {
+    let command_line = node.name().value().trim();
+    let options = CommandOptions::from_kdl_node(node);
+    let matrix = matrix::parse_matrix(node);
+    if matrix.is_empty() {
+        return vec![Command::with_options(command_line, options)];
+    }
+    matrix::combinations(&matrix)
+        .into_iter()
+        .map(|combination| {
+            let mut options = options.clone();
+            options.desc = options.desc.map(|desc| matrix::substitute(&desc, &combination));
+            options.script = options.script.map(|script| matrix::substitute(&script, &combination));
+            // names have to stay unique for placeholders and stdin_from to refer to a single command
+            options.name = options.name.map(|name| {
+                let values: Vec<&str> = combination.iter().map(|(_, value)| value.as_str()).collect();
+                format!("{}-{}", name, values.join("-"))
+            });
+            let command_line = matrix::substitute(command_line, &combination);
+            options.matrix = combination;
+            Command::with_options(&command_line, options)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct ConfigError {
+    description: String, // eg. line 3, column 14: Expected a node name
+    line_text: String, // the line the error is on
+    column: usize, // starting at 1
+    source: String, // the whole configuration, reopened in the editor to be fixed
+}
+
+impl ConfigError {
+    pub fn from_kdl_error(source: &str, error: &KdlError) -> Self {
+        let offset = error.span.offset().min(source.len());
+        let before = source.get(..offset).unwrap_or(source);
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        let line_text = source[line_start..].lines().next().unwrap_or("").to_owned();
+        let mut description = format!("line {}, column {}: {}", line, column, error);
+        if let Some(help) = error.help {
+            description.push_str(&format!(" ({})", help));
         }
+        ConfigError { description, line_text, column, source: source.to_owned() }
     }
-    fn open_editor(&mut self) {
-        let stringified_commands: Vec<String> = self.commands_to_run.iter().map(|c| c.command_line.to_string()).collect();
-        let stringified_commands = stringified_commands.join("\n");
-        match File::create("/host/.editing-commands").and_then(|mut file| file.write_all(stringified_commands.as_bytes())) {
-            Ok(_) => {
+}
+
+// Whether commands that don't parse as KDL were meant to be KDL (quoted command lines or stages)
+// rather than separated by &&, in which case splitting them would run something else entirely
+fn looks_like_kdl(commands: &str) -> bool {
+    let commands = commands.trim_start();
+    commands.starts_with('"') || commands.starts_with("r#\"") || commands.starts_with("stage") || commands.contains('{')
+}
+
+// An unquoted command line is valid KDL (eg. cargo test is the node "cargo" with the argument
+// "test"), but is certainly not what was meant
+fn find_unquoted_command(doc: &KdlDocument) -> Option<String> {
+    for node in doc.nodes() {
+        let is_stage = node.name().value() == "stage" && node.children().is_some();
+        let commands = match node.children() {
+            Some(children) if is_stage => children.nodes().iter().collect(),
+            _ => vec![node],
+        };
+        for command in commands {
+            if command.entries().iter().any(|e| e.name().is_none()) {
+                return Some(command.to_string().trim().to_owned());
+            }
+        }
+    }
+    None
+}
+
+// Command lines configured either as KDL nodes (one per command) or separated by &&, used for
+// the commands that aren't part of the run itself (eg. teardown)
+fn parse_command_lines(configuration: Option<&String>) -> Vec<String> {
+    match configuration {
+        Some(commands) => match commands.parse::<KdlDocument>() {
+            Ok(doc) => doc.nodes().iter().map(|node| node.name().value().trim().to_owned()).collect(),
+            Err(_) => commands.split("&&").map(|command| command.trim().to_owned()).collect(),
+        },
+        None => vec![],
+    }
+}
+
+// Hooks run when a stage or all the commands with a tag are done, eg.
+// stage "checks" "notify-send 'checks done'"
+// tag "docker" "docker compose down"
+// keyed like the other hooks, as stage:checks and tag:docker. Stages are referred to by name or
+// by position, starting at 1.
+fn parse_completion_hooks(configuration: Option<&String>) -> HashMap<String, Vec<String>> {
+    let mut hooks: HashMap<String, Vec<String>> = HashMap::new();
+    let doc = match configuration.map(|c| c.parse::<KdlDocument>()) {
+        Some(Ok(doc)) => doc,
+        Some(Err(e)) => {
+            eprintln!("Failed to parse completion_hooks: {}", e);
+            return hooks;
+        }
+        None => return hooks,
+    };
+    for node in doc.nodes() {
+        let kind = node.name().value();
+        let mut arguments = node.entries().iter().filter(|e| e.name().is_none()).map(|e| e.value());
+        let target = arguments.next().and_then(|t| t.as_string().map(|t| t.to_owned()).or_else(|| t.as_i64().map(|t| t.to_string())));
+        match (kind, target) {
+            ("stage", Some(target)) | ("tag", Some(target)) => {
+                let commands = arguments.filter_map(|a| a.as_string()).map(|a| a.to_owned());
+                hooks.entry(format!("{}:{}", kind, target)).or_default().extend(commands);
+            }
+            _ => eprintln!("Invalid completion hook: {}", node),
+        }
+    }
+    hooks
+}
+
+// zellij names an unnamed message with a UUID, eg. 0b2e9a4c-7f3d-4c55-9a8e-2f1d6c3b7e10. Other
+// names are either ours (handled on their own) or meant for another plugin, or typos.
+fn is_generated_pipe_name(name: &str) -> bool {
+    name.len() == 36 && name.char_indices().all(|(i, c)| match i {
+        8 | 13 | 18 | 23 => c == '-',
+        _ => c.is_ascii_hexdigit(),
+    })
+}
+
+// Events for plugins following the run, see status.rs for their format
+fn broadcast_event<T: Serialize>(pipe_name: &str, event: &T) {
+    match serde_json::to_string(event) {
+        Ok(payload) => pipe_message_to_plugin(MessageToPlugin::new(pipe_name).with_payload(payload)),
+        Err(e) => eprintln!("Failed to serialize {}: {}", pipe_name, e),
+    }
+}
+
+// Replies to a pipe message either on the CLI that sent it or to the plugin that sent it
+fn respond_to_pipe(source: &PipeSource, message_name: &str, payload: &str) {
+    match source {
+        PipeSource::Cli(pipe_id) => {
+            cli_pipe_output(pipe_id, payload);
+            unblock_cli_pipe_input(pipe_id);
+        }
+        PipeSource::Plugin(plugin_id) => {
+            pipe_message_to_plugin(
+                MessageToPlugin::new(message_name)
+                    .with_destination_plugin_id(*plugin_id)
+                    .with_payload(payload)
+            );
+        }
+        PipeSource::Keybind => {}
+    }
+}
+
+impl State {
+    fn kill_all_commands(&mut self) {
+        self.release_pipes_awaiting_run_status(&run_status::status_marker(false, None));
+        let mut panes = vec![];
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
+                panes.push((pane_id, command.is_running()));
+                self.audit_trail.record(AuditEvent::Killed(i));
+            }
+        }
+        self.terminate_panes(panes);
+    }
+    // Running commands get a ctrl-c and the grace period to shut down cleanly (eg. docker compose
+    // stopping its containers) before their pane is closed, the other panes are closed right away
+    fn terminate_panes(&mut self, panes: Vec<(u32, bool)>) {
+        for (pane_id, is_running) in panes {
+            if is_running && !self.termination_grace_period.is_zero() {
+                write_chars_to_pane_id("\u{3}", PaneId::Terminal(pane_id));
+                self.terminating_panes.push((pane_id, Instant::now() + self.termination_grace_period));
+            } else {
+                close_terminal_pane(pane_id);
+            }
+        }
+    }
+    fn close_terminated_panes(&mut self) {
+        let now = Instant::now();
+        self.terminating_panes.retain(|(pane_id, close_at)| {
+            if *close_at <= now {
+                close_terminal_pane(*pane_id);
+                return false;
+            }
+            true
+        });
+        if self.close_after_termination && self.terminating_panes.is_empty() {
+            self.close_once_worker_is_done();
+        }
+    }
+    // What the worker is still writing would be lost with us
+    fn close_once_worker_is_done(&mut self) {
+        if self.pending_worker_jobs > 0 {
+            self.close_after_worker_jobs = true;
+            return;
+        }
+        self.persist_final_state();
+        run_tmp::remove_run_tmp_dir(&run_tmp::run_tmp_dir(self.current_run_index));
+        run_tmp::forget_current_run_tmp_dir();
+        close_self();
+    }
+    fn handle_worker_message(&mut self, message: &str, payload: &str) -> bool {
+        if message != worker::REPORT_WRITTEN_MESSAGE && message != worker::RUN_RECORDED_MESSAGE {
+            return false;
+        }
+        self.pending_worker_jobs = self.pending_worker_jobs.saturating_sub(1);
+        match serde_json::from_str::<worker::JobDone>(payload) {
+            Ok(worker::JobDone { error: Some(e), .. }) => eprintln!("Failed to {}: {}", if message == worker::REPORT_WRITTEN_MESSAGE { "write report" } else { "write run history" }, e),
+            Ok(worker::JobDone { then: Some(ReportFollowUp::OpenFile), .. }) => {
+                if self.capabilities.contains(&Capability::OpenFiles) {
+                    open_file_floating(FileToOpen::new(&self.report_file), None, BTreeMap::new());
+                }
+            }
+            Ok(worker::JobDone { then: Some(ReportFollowUp::SummaryPane), .. }) => self.open_report_pane(),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to parse the worker's answer: {}", e),
+        }
+        if self.close_after_worker_jobs && self.pending_worker_jobs == 0 {
+            self.close_after_worker_jobs = false;
+            self.close_once_worker_is_done();
+        }
+        false
+    }
+    // each instance edits its own copy of the commands
+    fn edit_buffer_path() -> PathBuf {
+        PathBuf::from(EDIT_BUFFER_FOLDER).join(format!("{}.kdl", storage::instance_file_name("editing-commands")))
+    }
+    // The edit buffer is a full KDL document, so stages and per-command options survive the
+    // edit. If it doesn't parse, nothing changes and the buffer is kept to be fixed with e.
+    fn handle_editor_closed(&mut self) {
+        let new_commands = match fs::read_to_string(storage::host_path(Self::edit_buffer_path())) {
+            Ok(new_commands) => new_commands,
+            Err(e) => {
+                eprintln!("Failed to read commands: {}", e);
+                return;
+            }
+        };
+        let doc = match new_commands.parse::<KdlDocument>() {
+            Ok(doc) => doc,
+            Err(e) => {
+                self.edit_error = Some(ConfigError::from_kdl_error(&new_commands, &e).description);
+                return;
+            }
+        };
+        if let Some(unquoted_command) = find_unquoted_command(&doc) {
+            self.edit_error = Some(format!("commands need to be quoted, eg. \"{}\"", unquoted_command));
+            return;
+        }
+        self.edit_error = None;
+        self.config_error = None;
+        self.audit_trail.record(AuditEvent::Action("edited the commands".to_owned()));
+        self.kill_all_commands();
+        // locked commands are not in the edited file, they're kept in their original position
+        let locked_commands: Vec<(usize, Command)> = self.commands_to_run.drain(..)
+            .enumerate()
+            .filter(|(_, c)| c.options.locked)
+            .collect();
+        let (commands, stage_names) = commands_from_kdl(&doc);
+        self.commands_to_run = commands;
+        self.stage_names = stage_names;
+        for (i, mut locked_command) in locked_commands {
+            locked_command.reset();
+            locked_command.stage = LOCKED_COMMAND_STAGE;
+            self.commands_to_run.insert(i.min(self.commands_to_run.len()), locked_command);
+        }
+        self.renumber_stages();
+        self.running_command_index = None;
+        self.start_run();
+        let _ = std::fs::remove_file(storage::host_path(Self::edit_buffer_path()));
+    }
+    // Stages are numbered in order again, each locked command getting a stage of its own
+    fn renumber_stages(&mut self) {
+        let mut stage_names = HashMap::new();
+        let mut previous_stage = None;
+        let mut next_stage = 0;
+        for command in self.commands_to_run.iter_mut() {
+            let is_new_stage = command.stage == LOCKED_COMMAND_STAGE || previous_stage != Some(command.stage);
+            previous_stage = Some(command.stage);
+            if is_new_stage {
+                if let Some(stage_name) = self.stage_names.get(&command.stage) {
+                    stage_names.insert(next_stage, stage_name.clone());
+                }
+                next_stage += 1;
+            }
+            command.stage = next_stage - 1;
+        }
+        self.stage_names = stage_names;
+    }
+    fn request_next_capability(&self) {
+        if let Some(capability) = self.pending_capability_requests.first() {
+            request_permission(&capability.permissions());
+        }
+    }
+    fn open_editor(&mut self) {
+        if !self.capabilities.contains(&Capability::OpenFiles) {
+            return;
+        }
+        let edit_buffer_written = if self.edit_error.is_some() {
+            // reopen the edit that failed to parse, rather than losing it
+            Ok(())
+        } else if let Some(config_error) = &self.config_error {
+            // there are no commands to export, the configuration is fixed as it was written
+            let edit_buffer = format!("{}{}", EDIT_BUFFER_HEADER, config_error.source);
+            storage::write_atomic(&storage::host_path(Self::edit_buffer_path()), edit_buffer)
+        } else {
+            let unlocked_commands: Vec<Command> = self.commands_to_run.iter()
+                .filter(|c| !c.options.locked)
+                .map(|c| {
+                    let mut command = Command::with_options(&c.command_line, c.options.clone());
+                    command.stage = c.stage;
+                    command
+                })
+                .collect();
+            let edit_buffer = format!("{}{}", EDIT_BUFFER_HEADER, export::commands_to_kdl(&unlocked_commands, &self.stage_names));
+            storage::write_atomic(&storage::host_path(Self::edit_buffer_path()), edit_buffer)
+        };
+        match edit_buffer_written {
+            Ok(_) => {
                 let mut context = BTreeMap::new();
                 context.insert("edit_pane_marker".into(), String::new());
-                open_file_floating(FileToOpen::new(".editing-commands"), None, context);
+                open_file_floating(FileToOpen::new(Self::edit_buffer_path()), None, context);
             }
             Err(e) => {
                 eprintln!("Failed to write commands file: {}", e);
             }
         }
     }
+    // Opens the file the commands came from at the line of the selected command. Without a
+    // config_file, the commands of the layout are exported to a file so they can be seen in full
+    fn open_config_file(&self) {
+        if !self.capabilities.contains(&Capability::OpenFiles) {
+            return;
+        }
+        let (path, contents) = match &self.config_file {
+            Some(config_file) => {
+                match fs::read_to_string(Path::new("/host").join(config_file)) {
+                    Ok(contents) => (config_file.clone(), contents),
+                    Err(e) => {
+                        eprintln!("Failed to read config file: {}", e);
+                        return;
+                    }
+                }
+            }
+            None => {
+                let commands = self.userspace_configuration.get("commands").cloned().unwrap_or_default();
+                let contents = if commands.parse::<KdlDocument>().is_ok() {
+                    commands
+                } else if let Some(kdl) = formats::commands_to_kdl(&commands) {
+                    kdl
+                } else {
+                    commands.split("&&").map(|c| format!("{:?}", c.trim())).collect::<Vec<_>>().join("\n")
+                };
+                let path = PathBuf::from(EXPORTED_COMMANDS_FILE);
+                let written = storage::write_atomic(&storage::host_path(&path), &contents);
+                if let Err(e) = written {
+                    eprintln!("Failed to export commands: {}", e);
+                    return;
+                }
+                (path, contents)
+            }
+        };
+        let line_number = self.selected_index
+            .and_then(|i| self.commands_to_run.get(i))
+            .and_then(|command| contents.lines().position(|line| line.contains(&command.command_line)))
+            .map(|i| i + 1);
+        let mut file_to_open = FileToOpen::new(path);
+        if let Some(line_number) = line_number {
+            file_to_open = file_to_open.with_line_number(line_number);
+        }
+        open_file_floating(file_to_open, None, BTreeMap::new());
+    }
+    // Unlike pausing, this also stops the commands that are running until resumed
+    fn toggle_suspend(&mut self) {
+        self.suspended = !self.suspended;
+        self.audit_trail.record(AuditEvent::Action(if self.suspended { "suspend" } else { "resume" }.to_owned()));
+        let signal = if self.suspended { "STOP" } else { "CONT" };
+        for (i, command) in self.commands_to_run.iter().enumerate() {
+            if command.is_running() {
+                process::signal_command(i, signal);
+            }
+        }
+        self.paused = self.suspended;
+        if !self.paused && !self.all_commands_exited() {
+            self.run_next_command();
+        }
+    }
+    // Writes the current commands and configuration as a layout snippet and opens it, so that
+    // commands changed at runtime can be made permanent
+    fn export_layout(&self) {
+        if !self.capabilities.contains(&Capability::OpenFiles) {
+            return;
+        }
+        let snippet = export::layout_snippet(&self.commands_to_run, &self.stage_names, &self.userspace_configuration, &self.plugin_location);
+        let path = PathBuf::from(EXPORTED_LAYOUT_FILE);
+        match storage::write_atomic(&storage::host_path(&path), snippet) {
+            Ok(_) => open_file_floating(FileToOpen::new(path), None, BTreeMap::new()),
+            Err(e) => eprintln!("Failed to export layout: {}", e),
+        }
+    }
+    // Writes a layout and a keybinding snippet launching zlaunch with the current commands and
+    // configuration into the project, so that teammates can add them to their zellij setup
+    // rather than writing them by hand. The keybinding snippet (which explains both) is opened.
+    fn write_install_files(&self) {
+        if !self.capabilities.contains(&Capability::OpenFiles) {
+            return;
+        }
+        let layout = export::layout_snippet(&self.commands_to_run, &self.stage_names, &self.userspace_configuration, &self.plugin_location);
+        let keybinding = export::keybinding_snippet(&self.commands_to_run, &self.stage_names, &self.userspace_configuration, &self.plugin_location, &self.install_key);
+        let keybinding = format!(
+            "// Add this to your zellij configuration (eg. ~/.config/zellij/config.kdl) to launch\n\
+             // this project's commands with {} from its folder, or start a session with them:\n\
+             // zellij --layout {}\n{}",
+            self.install_key,
+            INSTALL_LAYOUT_FILE,
+            keybinding
+        );
+        let result = storage::write_atomic(&storage::host_path(INSTALL_LAYOUT_FILE), layout)
+            .and_then(|_| storage::write_atomic(&storage::host_path(INSTALL_KEYBINDS_FILE), keybinding));
+        match result {
+            Ok(_) => open_file_floating(FileToOpen::new(INSTALL_KEYBINDS_FILE), None, BTreeMap::new()),
+            Err(e) => eprintln!("Failed to write the install files: {}", e),
+        }
+    }
+    // Writes a layout running the current commands in regular panes and opens it
+    fn export_commands_layout(&self) {
+        if !self.capabilities.contains(&Capability::OpenFiles) {
+            return;
+        }
+        let launch_settings = self.launch_settings();
+        let commands_to_run: Vec<CommandToRun> = self.commands_to_run.iter()
+            .map(|c| Self::command_to_run(c, &launch_settings))
+            .collect();
+        let layout = export::commands_layout(&self.commands_to_run, &commands_to_run);
+        let path = PathBuf::from(COMMANDS_LAYOUT_FILE);
+        match storage::write_atomic(&storage::host_path(&path), layout) {
+            Ok(_) => open_file_floating(FileToOpen::new(path), None, BTreeMap::new()),
+            Err(e) => eprintln!("Failed to export commands layout: {}", e),
+        }
+    }
+    // Writes the current commands and options as a blob that can be pasted to a teammate, who
+    // imports it through the quick add input (a) or a zlaunch_import pipe message
+    fn share_run(&self) {
+        if !self.capabilities.contains(&Capability::OpenFiles) {
+            return;
+        }
+        let commands = export::commands_to_kdl(&self.commands_to_run, &self.stage_names);
+        let blob = SharedRun::new(commands, &self.userspace_configuration).encode();
+        let path = PathBuf::from(share::SHARE_FILE);
+        match storage::write_atomic(&storage::host_path(&path), format!("{}\n", blob)) {
+            Ok(_) => open_file_floating(FileToOpen::new(path), None, BTreeMap::new()),
+            Err(e) => eprintln!("Failed to write the shared run: {}", e),
+        }
+    }
+    // Replaces the commands and options with those of a shared run and starts it
+    fn import_shared_run(&mut self, blob: &str) -> bool {
+        let shared_run = match SharedRun::decode(blob) {
+            Ok(shared_run) => shared_run,
+            Err(e) => {
+                eprintln!("Failed to import the shared run: {}", e);
+                return false;
+            }
+        };
+        self.audit_trail.record(AuditEvent::Action("import shared run".to_owned()));
+        self.wizard = None;
+        self.kill_all_commands();
+        self.commands_to_run.clear();
+        self.stage_names.clear();
+        self.parse_commands(&shared_run.commands);
+        self.base_configuration.extend(shared_run.options.clone());
+        self.userspace_configuration.extend(shared_run.options);
+        self.parse_other_configuration();
+        self.running_command_index = None;
+        self.selected_index = None;
+        if self.capabilities.contains(&Capability::RunCommands) {
+            self.start_run();
+        }
+        true
+    }
+    // Replaces the commands with those of the profile and starts running them
+    fn load_profile(&mut self, profile: &str) {
+        let commands = match self.profiles.get(profile) {
+            Some(commands) => commands.clone(),
+            None => return,
+        };
+        self.audit_trail.record(AuditEvent::Action(format!("load profile {}", profile)));
+        self.kill_all_commands();
+        self.commands_to_run.clear();
+        self.stage_names.clear();
+        self.parse_commands(&commands);
+        self.active_profile = Some(profile.to_owned());
+        self.apply_profile_options();
+        self.parse_other_configuration();
+        if self.watch {
+            subscribe(&[
+                EventType::FileSystemCreate,
+                EventType::FileSystemUpdate,
+                EventType::FileSystemDelete,
+            ]);
+            watch_filesystem();
+        }
+        self.running_command_index = None;
+        self.selected_index = None;
+        self.start_run();
+    }
+    // Switches to the profile after the active one, in alphabetical order
+    fn cycle_profile(&mut self) -> bool {
+        let profile_names: Vec<String> = self.profiles.keys().cloned().collect();
+        let next_profile = match self.active_profile.as_ref().and_then(|a| profile_names.iter().position(|p| p == a)) {
+            Some(active_index) => profile_names.get((active_index + 1) % profile_names.len()),
+            None => profile_names.first(),
+        };
+        match next_profile.cloned() {
+            Some(next_profile) => {
+                self.load_profile(&next_profile);
+                true
+            }
+            None => false,
+        }
+    }
+    // See api.rs for the protocol
+    fn handle_api_request(&mut self, source: &PipeSource, payload: Option<&str>) -> bool {
+        let (id, result) = match api::parse_request(payload) {
+            Ok(request) => {
+                let result = self.dispatch_api_request(&request);
+                (request.id, result)
+            }
+            Err(error) => (None, Err(error)),
+        };
+        let changes_state = result.is_ok();
+        match serde_json::to_string(&api::ApiResponse::new(id, result)) {
+            Ok(response) => respond_to_pipe(source, api::API_RESPONSE_PIPE_NAME, &format!("{}\n", response)),
+            Err(e) => eprintln!("Failed to serialize api response: {}", e),
+        }
+        changes_state
+    }
+    fn dispatch_api_request(&mut self, request: &api::ApiRequest) -> Result<serde_json::Value, api::ApiError> {
+        let to_value = |value: serde_json::Result<serde_json::Value>| value.map_err(|e| api::ApiError::new("internal_error", e.to_string()));
+        match request.method.as_str() {
+            "describe" => Ok(api::describe()),
+            "status" => {
+                let status = StatusMessage::new(get_plugin_ids().plugin_id, self.run_state(), self.current_run_index, &self.commands_to_run);
+                to_value(serde_json::to_value(status))
+            }
+            "list_commands" => {
+                let commands: Vec<serde_json::Value> = self.commands_to_run.iter().enumerate().map(|(i, c)| api::command_summary(i, c)).collect();
+                Ok(serde_json::Value::Array(commands))
+            }
+            "get_command" => {
+                let index = api::index_param(&request.params, self.commands_to_run.len())?;
+                Ok(api::command_detail(index, &self.commands_to_run[index]))
+            }
+            "add_command" => {
+                let command_line = request.params.get("command_line")
+                    .and_then(|c| c.as_str())
+                    .filter(|c| !c.trim().is_empty())
+                    .ok_or_else(|| api::ApiError::new("invalid_params", "expected a \"command_line\""))?;
+                self.quick_add_command(command_line.trim());
+                let index = self.commands_to_run.len() - 1;
+                Ok(api::command_summary(index, &self.commands_to_run[index]))
+            }
+            "action" => {
+                let action = request.params.get("action")
+                    .and_then(|a| a.as_str())
+                    .ok_or_else(|| api::ApiError::new("invalid_params", "expected an \"action\""))?;
+                self.perform_api_action(action, &request.params)
+            }
+            method => Err(api::ApiError::new("unknown_method", format!("unknown method {}, expected one of {}", method, api::METHODS.join(", ")))),
+        }
+    }
+    fn perform_api_action(&mut self, action: &str, params: &serde_json::Value) -> Result<serde_json::Value, api::ApiError> {
+        self.audit_trail.record(AuditEvent::Action(format!("api: {}", action)));
+        match action {
+            "restart" => self.restart_run(),
+            "pause" | "resume" => {
+                self.paused = action == "pause";
+                if !self.paused && !self.all_commands_exited() {
+                    self.run_next_command();
+                }
+            }
+            "stop_on_failure" => {
+                self.stop_on_failure = params.get("enabled").and_then(|e| e.as_bool()).unwrap_or(!self.stop_on_failure);
+            }
+            "suspend" => self.toggle_suspend(),
+            "skip" | "babysit" | "rerun" => {
+                let index = api::index_param(params, self.commands_to_run.len())?;
+                // these act on the selected command, the selection is restored afterwards
+                let selected_index = self.selected_index.replace(index);
+                let performed = match action {
+                    "skip" => self.toggle_skip_selected_command(),
+                    "babysit" => self.toggle_babysit_selected_command(),
+                    _ => self.rerun_selected_command(),
+                };
+                self.selected_index = selected_index;
+                if !performed {
+                    return Err(api::ApiError::new("not_allowed", format!("can't {} the command at index {} now", action, index)));
+                }
+                return Ok(api::command_summary(index, &self.commands_to_run[index]));
+            }
+            action => {
+                let actions: Vec<&str> = api::RUN_ACTIONS.iter().chain(api::COMMAND_ACTIONS).copied().collect();
+                return Err(api::ApiError::new("unknown_action", format!("unknown action {}, expected one of {}", action, actions.join(", "))));
+            }
+        }
+        let status = StatusMessage::new(get_plugin_ids().plugin_id, self.run_state(), self.current_run_index, &self.commands_to_run);
+        serde_json::to_value(status).map_err(|e| api::ApiError::new("internal_error", e.to_string()))
+    }
+    // Unlike TAB, this re-runs the command even if its pane is still open
+    fn rerun_selected_command(&mut self) -> bool {
+        let selected_index = match self.selected_index {
+            Some(selected_index) => selected_index,
+            None => return false,
+        };
+        let current_run_index = self.current_run_index;
+        let launch_settings = self.launch_settings();
+        match self.commands_to_run.get_mut(selected_index) {
+            Some(command) if command.is_running() || command.skipped => false,
+            Some(command) => match command.pane_id {
+                Some(PaneId::Terminal(pane_id)) => {
+                    command.attempts += 1;
+                    Self::rerun_command_in_pane(command, selected_index, pane_id, current_run_index, &launch_settings);
+                    self.audit_trail.record(AuditEvent::Retried(selected_index));
+                    true
+                }
+                _ => {
+                    self.focus_selected_terminal();
+                    true
+                }
+            },
+            None => false,
+        }
+    }
+    // Replaces the commands with the ones in the payload and starts a new run with them. The
+    // payload is either KDL (like the commands option) or one command per line, the line breaks
+    // being actual ones or a literal \n as typed in a shell.
+    fn handle_commands_payload(&mut self, source: &PipeSource, payload: Option<String>) -> bool {
+        let payload = match payload.filter(|p| !p.trim().is_empty()) {
+            Some(payload) => payload,
+            None => return false,
+        };
+        let (commands, stage_names) = match payload.parse::<KdlDocument>() {
+            Ok(doc) if find_unquoted_command(&doc).is_none() => commands_from_kdl(&doc),
+            _ => {
+                let commands = payload.replace("\\n", "\n")
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .enumerate()
+                    .map(|(stage, line)| {
+                        let mut command = Command::new(line);
+                        command.stage = stage;
+                        command
+                    })
+                    .collect();
+                (commands, HashMap::new())
+            }
+        };
+        self.audit_trail.record(AuditEvent::Action(format!("{} commands received through a pipe", commands.len())));
+        let running = format!("Running {} commands\n", commands.len());
+        let deferred_pipe_id = match source {
+            PipeSource::Cli(pipe_id) if self.pipe_run_status => Some(pipe_id.clone()),
+            _ => None,
+        };
+        match &deferred_pipe_id {
+            Some(pipe_id) => cli_pipe_output(pipe_id, &running),
+            None => respond_to_pipe(source, "zlaunch_commands", &running),
+        }
+        // the commands came from elsewhere, no need to set them up
+        self.wizard = None;
+        self.kill_all_commands();
+        self.commands_to_run = commands;
+        self.stage_names = stage_names;
+        self.running_command_index = None;
+        self.selected_index = None;
+        if let Some(pipe_id) = deferred_pipe_id {
+            // kept open until the run ends (which might be right away), see write_run_status
+            block_cli_pipe_input(&pipe_id);
+            self.pipes_awaiting_run_status.push(pipe_id);
+        }
+        let run_index = self.current_run_index;
+        if self.capabilities.contains(&Capability::RunCommands) {
+            self.start_run();
+        }
+        if self.current_run_index == run_index {
+            // nothing runs, there's no status to wait for
+            self.release_pipes_awaiting_run_status(&run_status::status_marker(false, None));
+        }
+        true
+    }
+    fn handle_run_profile_request(&mut self, profile: Option<String>) -> bool {
+        let profile = match profile {
+            Some(profile) => profile.trim().to_owned(),
+            None => return false,
+        };
+        if self.active_profile.as_ref() == Some(&profile) {
+            self.restart_run();
+            true
+        } else if self.profiles.contains_key(&profile) {
+            self.load_profile(&profile);
+            true
+        } else {
+            false
+        }
+    }
+    fn handle_restart_prompt_key(&mut self, key: KeyWithModifier) -> bool {
+        match key.bare_key {
+            BareKey::Char('y') | BareKey::Enter => {
+                self.restart_prompt = false;
+                self.restart_from_key();
+            }
+            BareKey::Char('n') | BareKey::Esc => self.restart_prompt = false,
+            _ => return false,
+        }
+        true
+    }
+    fn request_restart(&mut self) {
+        if self.confirm_restart && !self.all_commands_exited() {
+            self.restart_prompt = true;
+        } else {
+            self.restart_from_key();
+        }
+    }
+    // Only the actions that apply to the selected command as it is now
+    fn open_action_menu(&mut self) {
+        let command = match self.selected_index.and_then(|i| self.commands_to_run.get(i)) {
+            Some(command) => command,
+            None => return,
+        };
+        let mut actions = vec![];
+        if command.pane_id.is_some() {
+            actions.push(MenuAction::FocusPane);
+        }
+        if command.is_running() {
+            actions.push(MenuAction::Kill);
+        } else if !command.skipped {
+            actions.push(MenuAction::Rerun);
+        }
+        if command.skipped {
+            actions.push(MenuAction::Unskip);
+        } else if !command.is_running() && !command.exited {
+            actions.push(MenuAction::Skip);
+        }
+        if self.capabilities.contains(&Capability::OpenFiles) {
+            actions.push(MenuAction::Edit);
+            if self.selected_log_file().is_some() {
+                actions.push(MenuAction::ViewLog);
+            }
+        }
+        actions.push(MenuAction::RestartRun);
+        self.action_menu = Some(ActionMenu::new(actions));
+    }
+    fn handle_action_menu_key(&mut self, key: KeyWithModifier) -> bool {
+        let action_menu = match self.action_menu.as_mut() {
+            Some(action_menu) => action_menu,
+            None => return false,
+        };
+        match key.bare_key {
+            BareKey::Esc => self.action_menu = None,
+            BareKey::Down if key.has_no_modifiers() => action_menu.move_down(),
+            BareKey::Up if key.has_no_modifiers() => action_menu.move_up(),
+            BareKey::Enter if key.has_no_modifiers() => {
+                if let Some(action) = self.action_menu.take().and_then(|m| m.selected_action()) {
+                    self.run_menu_action(action);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+    fn run_menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::FocusPane => self.focus_selected_terminal(),
+            MenuAction::Rerun => {
+                self.rerun_selected_command();
+            }
+            MenuAction::Kill => {
+                self.kill_selected_command();
+            }
+            MenuAction::Skip | MenuAction::Unskip => {
+                self.toggle_skip_selected_command();
+            }
+            MenuAction::Edit => self.open_config_file(),
+            MenuAction::ViewLog => {
+                if let Some(log_file) = self.selected_log_file() {
+                    open_file_floating(FileToOpen::new(log_file), None, BTreeMap::new());
+                }
+            }
+            MenuAction::RestartRun => self.request_restart(),
+        }
+    }
+    // the full output of the selected command, if it was logged or captured
+    fn selected_log_file(&self) -> Option<PathBuf> {
+        let command = self.commands_to_run.get(self.selected_index?)?;
+        command.log_file.clone().or_else(|| command.captured_output.clone())
+    }
+    fn handle_resume_prompt_key(&mut self, key: KeyWithModifier) -> bool {
+        match key.bare_key {
+            BareKey::Char('y') | BareKey::Enter => {
+                self.resume_prompt = false;
+                self.resume_run();
+            }
+            BareKey::Char('n') | BareKey::Esc => {
+                self.resume_prompt = false;
+                self.restart_from_key();
+            }
+            _ => return false,
+        }
+        true
+    }
+    // A new run in which the commands that succeeded in the interrupted one aren't run again, it
+    // goes on from the first stage with a pending or failed command
+    fn resume_run(&mut self) {
+        self.audit_trail.record(AuditEvent::Action("resume".to_owned()));
+        self.load_env_file();
+        self.running_command_index = None;
+        self.suspended = false;
+        for command in self.commands_to_run.iter_mut().filter(|c| !c.succeeded()) {
+            command.reset();
+        }
+        self.start_run();
+    }
+    fn restart_from_key(&mut self) {
+        self.audit_trail.record(AuditEvent::Action("restart".to_owned()));
+        self.reset_loop();
+        self.restart_run();
+    }
     fn restart_run(&mut self) {
+        self.release_pipes_awaiting_run_status(&run_status::status_marker(false, None));
+        // the env file might have changed since the last run
+        let previous_env_variables = self.env_variables.clone();
+        self.load_env_file();
         self.running_command_index = None;
-        for command in self.commands_to_run.iter_mut() {
+        self.suspended = false;
+        self.dump_failed_panes();
+        // the panes of the commands that exited are kept, to re-run them in place with their
+        // scrollback, unless they'd run differently (a new env or, once reordered, another pid file)
+        let keeps_panes = self.rerun_in_same_pane
+            && self.env_variables == previous_env_variables
+            && self.run_order == scheduler::RunOrder::Definition;
+        let mut panes = vec![];
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            if let Some(hook_pane_id) = command.hook_pane_id.take() {
+                panes.push((hook_pane_id, true));
+            }
             if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
+                if keeps_panes && command.can_rerun_in_place() {
+                    // the pane writes its output where it did in the previous run
+                    let (captured_output, log_file) = (command.captured_output.take(), command.log_file.take());
+                    command.reset();
+                    command.pane_id = Some(PaneId::Terminal(pane_id));
+                    command.kept_pane = true;
+                    command.captured_output = captured_output;
+                    command.log_file = log_file;
+                    continue;
+                }
+                panes.push((pane_id, command.is_running()));
+                if command.is_running() {
+                    self.audit_trail.record(AuditEvent::Killed(i));
+                }
+            }
+            command.reset();
+        }
+        self.terminate_panes(panes);
+        self.start_run();
+    }
+    fn start_run(&mut self) {
+        if self.config_error.is_some() {
+            // the commands couldn't be parsed, running none of them would end (and close) the run
+            return;
+        }
+        // scratch files are kept until the next run (or until we close), the commands of a finished
+        // run can be re-run with their script, stdin and captured output
+        run_tmp::remove_run_tmp_dir(&run_tmp::run_tmp_dir(self.current_run_index));
+        self.current_run_index += 1;
+        run_tmp::create_run_tmp_dir(&run_tmp::run_tmp_dir(self.current_run_index));
+        run_tmp::set_current_run_tmp_dir(&self.host_folder.join(run_tmp::run_tmp_dir(self.current_run_index)));
+        self.audit_trail.record(AuditEvent::RunStarted(self.current_run_index));
+        run_status::clear();
+        self.stopwatch.reset();
+        self.first_failure_reported = false;
+        self.run_ended = false;
+        self.run_tag_filter = self.tag_filter.clone();
+        self.show_summary = false;
+        self.time_travel = None;
+        self.previous_results = self.history.last()
+            .map(|run| run.commands.iter().filter(|c| c.exit_status.is_some()).map(|c| (c.command_line.clone(), c.succeeded())).collect())
+            .unwrap_or_default();
+        for command in self.commands_to_run.iter_mut() {
+            command.attempts = 0;
+            command.babysitting = false;
+        }
+        self.apply_run_order();
+        self.fire_lifecycle_event(LifecycleEvent::RunStart);
+        self.setup_running = false;
+        self.changed_paths = None;
+        self.checking_affected_paths = false;
+        if self.commands_to_run.iter().any(|c| !c.options.paths.is_empty()) {
+            // which of them are affected is only known once the changed paths arrive
+            self.checking_affected_paths = true;
+            affected::request_changed_paths(&self.affected_base, &PathBuf::from(&self.folder), self.current_run_index);
+            return;
+        }
+        self.start_run_commands();
+    }
+    fn start_run_commands(&mut self) {
+        if !self.setup_commands.is_empty() {
+            self.run_setup();
+            return;
+        }
+        self.run_next_command();
+    }
+    fn handle_changed_paths(&mut self, exit_code: Option<i32>, stdout: Vec<u8>, stderr: Vec<u8>, context: BTreeMap<String, String>) -> bool {
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        if current_run_index != Some(self.current_run_index) || !self.checking_affected_paths {
+            return false;
+        }
+        self.checking_affected_paths = false;
+        if exit_code == Some(0) {
+            self.changed_paths = Some(affected::parse_changed_paths(&stdout));
+        } else {
+            // eg. not a git repository, nothing is skipped rather than everything
+            eprintln!("Failed to list the paths changed since {}, running every command: {}", self.affected_base, String::from_utf8_lossy(&stderr));
+        }
+        self.start_run_commands();
+        true
+    }
+    // The setup commands run in order (stopping at the first failure) before the first command
+    // of every run, which only starts once they're done
+    fn run_setup(&mut self) {
+        self.setup_running = true;
+        let setup_script = self.setup_commands.join(" && ");
+        let mut context = BTreeMap::new();
+        context.insert(SETUP_CONTEXT.to_owned(), String::new());
+        context.insert("current_run_index".to_owned(), self.current_run_index.to_string());
+        let setup_command = process::shell_command(&self.shell_invocation(), &setup_script);
+        let mut argv = vec![setup_command.path.to_string_lossy().to_string()];
+        argv.extend(setup_command.args);
+        let argv: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+        run_command_with_env_variables_and_cwd(
+            &argv,
+            self.env_variables.iter().cloned().collect(),
+            PathBuf::from(&self.folder),
+            context,
+        );
+    }
+    fn handle_setup_result(&mut self, exit_code: Option<i32>, stderr: Vec<u8>, context: BTreeMap<String, String>) -> bool {
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        if current_run_index != Some(self.current_run_index) || !self.setup_running {
+            return false;
+        }
+        self.setup_running = false;
+        if exit_code == Some(0) {
+            self.run_next_command();
+            return true;
+        }
+        eprintln!("Setup failed with exit code {:?}: {}", exit_code, String::from_utf8_lossy(&stderr));
+        // none of the commands run, the run fails as is (and is torn down)
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            if !command.is_done() {
+                command.skipped = true;
+                command.refused = Some("setup failed");
+                self.audit_trail.record(AuditEvent::Skipped(i, "setup failed".to_owned()));
+            }
+        }
+        self.handle_run_failed();
+        self.start_queued_rerun();
+        true
+    }
+    fn fire_lifecycle_event(&self, event: LifecycleEvent) {
+        for completion_pane in &self.panes_to_run_on_completion {
+            if !completion_pane.triggers.contains(&event) {
+                continue;
+            }
+            for pane_id in &completion_pane.pane_ids {
+                match pane_id {
+                    PaneId::Terminal(terminal_pane_id) => rerun_command_pane(*terminal_pane_id),
+                    PaneId::Plugin(plugin_id) => reload_plugin_with_id(*plugin_id),
+                }
+            }
+        }
+    }
+    // The command that started last among the running ones, when focus follows the run
+    fn focus_running_command(&self) {
+        let running_command = self.commands_to_run.iter()
+            .filter(|c| c.is_running() && !c.is_quiet(self.quiet, self.fully_quiet))
+            // one waiting for the user keeps the focus
+            .max_by_key(|c| (c.options.interactive, c.start_time));
+        if let Some(PaneId::Terminal(pane_id)) = running_command.and_then(|c| c.pane_id) {
+            focus_terminal_pane(pane_id, true);
+        }
+    }
+    // Both the end of a successful run and the one of a failed (or halted) run go through here
+    fn fire_run_end(&self, succeeded: bool) {
+        if self.run_completion_panes_on.includes(succeeded) {
+            self.fire_lifecycle_event(LifecycleEvent::RunEnd);
+        }
+    }
+    fn render_status(&self, rows: usize, cols: usize) {
+        let y_coords = 6 + self.command_list_len();
+        let shell_text = self.shell.to_string();
+        let folder_text = self.folder.to_string();
+        let total_run_time = self.total_run_time();
+        let profile_text = match &self.active_profile {
+            Some(active_profile) => format!(" Profile: {}", active_profile),
+            None => String::new(),
+        };
+        let text = format!("Elapsed: {} Shell: {} Folder: {}{}", total_run_time, shell_text, folder_text, profile_text);
+        let total_run_time_len = total_run_time.chars().count();
+        let shell_len = shell_text.chars().count();
+        let text = Text::new(text)
+            .color_range(self.theme.running, 9..9 + total_run_time_len)
+            .color_range(self.theme.running, 17 + total_run_time_len..17 + total_run_time_len + shell_len)
+            .color_range(self.theme.running, 26 + total_run_time_len + shell_len..26 + total_run_time_len + shell_len + folder_text.chars().count());
+        let text = if self.active_profile.is_some() {
+            text.color_range(self.theme.running, 36 + total_run_time_len + shell_len + folder_text.chars().count()..)
+        } else {
+            text
+        };
+        print_text_with_coordinates(text, 1, y_coords, None, None);
+        if self.watch {
+            let watch_text = match (self.rerun_queued, self.last_deferred_change, self.watch_idle_period) {
+                (true, _, _) => "Watching for changes (re-run queued)".to_owned(),
+                (false, Some(_), Some(watch_idle_period)) => format!("Watching for changes (running after {}s without changes)", watch_idle_period.as_secs_f64()),
+                _ => "Watching for changes".to_owned(),
+            };
+            print_text_with_coordinates(Text::new(watch_text).color_range(self.theme.running, ..), 1, y_coords + 1, None, None);
+        } else if let Some(schedule_text) = self.loop_text().or_else(|| self.schedule_text()) {
+            print_text_with_coordinates(Text::new(schedule_text).color_range(self.theme.running, ..), 1, y_coords + 1, None, None);
+        }
+    }
+    fn schedule_text(&self) -> Option<String> {
+        let now = Instant::now();
+        if self.checking_affected_paths {
+            return Some(format!("Checking the paths changed since {}", self.affected_base));
+        }
+        if self.setup_running {
+            return Some("Running the setup commands".to_owned());
+        }
+        if let (true, Some(sibling_plugin_id)) = (self.waiting_to_start, self.sibling_plugin_id) {
+            return Some(format!("Another zlaunch instance (plugin {}) runs these commands, press ENTER to start anyway", sibling_plugin_id));
+        }
+        if self.waiting_to_start {
+            return Some("Press ENTER to start the run".to_owned());
+        }
+        if let Some(start_at) = self.start_at {
+            return Some(format!("Starting in {}", format_duration(start_at.saturating_duration_since(now), 0)));
+        }
+        let repeat_every = self.repeat_every?;
+        match self.next_repeat_at {
+            Some(next_repeat_at) => Some(format!(
+                "Running every {} (next in {})",
+                format_duration(repeat_every, 0),
+                format_duration(next_repeat_at.saturating_duration_since(now), 0)
+            )),
+            None => Some(format!("Running every {}", format_duration(repeat_every, 0))),
+        }
+    }
+    fn total_run_time(&self) -> String {
+        format_duration(self.total_run_duration(), self.duration_precision)
+    }
+    fn total_run_duration(&self) -> Duration {
+        self.stopwatch.elapsed()
+    }
+    // the run is active while any of its commands is running and it isn't suspended
+    fn sync_stopwatch(&mut self) {
+        let is_active = !self.suspended && self.commands_to_run.iter().any(|c| c.is_running());
+        self.stopwatch.set_running(is_active);
+    }
+    fn record_run_in_history(&mut self) {
+        if self.history_recorded_for_run == Some(self.current_run_index) {
+            return;
+        }
+        self.history_recorded_for_run = Some(self.current_run_index);
+        let run = RunRecord {
+            finished_at_ms: now_ms(),
+            duration_ms: self.total_run_duration().as_millis() as u64,
+            commands: self.commands_to_run.iter().map(CommandRecord::from_command).collect(),
+            labels: self.labels.clone(),
+        };
+        history::append_run(&mut self.history, run.clone());
+        if worker::post_to_worker(worker::RECORD_RUN_MESSAGE, &run) {
+            self.pending_worker_jobs += 1;
+        }
+        if self.report_on_run_end {
+            self.write_report(ReportFollowUp::Nothing);
+        }
+    }
+    // The report of the run in a pane of its own, so a copyable record of the run remains once
+    // the command panes (and we) are gone
+    fn open_summary_pane(&mut self) {
+        if self.capabilities.contains(&Capability::RunCommands) {
+            self.write_report(ReportFollowUp::SummaryPane);
+        }
+    }
+    fn open_report_pane(&self) {
+        let mut summary_command = CommandToRun::new_with_args("cat", vec![self.report_file.to_string_lossy().to_string()]);
+        summary_command.cwd = Some(self.host_folder.clone());
+        let mut context = BTreeMap::new();
+        context.insert("summary_pane".to_owned(), String::new());
+        open_command_pane_floating(summary_command, None, context);
+    }
+    // Written by the worker, `then` is done once it's written
+    fn write_report(&mut self, then: ReportFollowUp) {
+        let write_report = worker::WriteReport {
+            path: storage::host_path(&self.report_file),
+            rows: report::report_rows(&self.commands_to_run),
+            total_duration: self.total_run_duration(),
+            precision: self.duration_precision,
+            then,
+        };
+        if worker::post_to_worker(worker::WRITE_REPORT_MESSAGE, &write_report) {
+            self.pending_worker_jobs += 1;
+        }
+    }
+    // Cycles through filtering the history by each of its labels, and back to no filter
+    fn cycle_history_label_filter(&mut self) {
+        let labels = history::labels_in_history(&self.history);
+        let next_label = match &self.history_label_filter {
+            Some(current) => labels.iter().skip_while(|l| *l != current).nth(1).cloned(),
+            None => labels.first().cloned(),
+        };
+        self.history_label_filter = next_label;
+    }
+    fn handle_label_request(&mut self, labels: Option<String>) -> bool {
+        let labels = match labels {
+            Some(labels) => history::parse_labels(&labels),
+            None => return false,
+        };
+        for (key, value) in labels {
+            // "key=" removes the label
+            if value.is_empty() {
+                self.labels.remove(&key);
+            } else {
+                self.labels.insert(key, value);
+            }
+        }
+        true
+    }
+    fn render_history(&self, rows: usize, cols: usize) {
+        let history = history::filter_by_label(&self.history, self.history_label_filter.as_ref());
+        let title = match &self.history_label_filter {
+            Some((key, value)) => format!("Run history ({} runs with {}={})", history.len(), key, value),
+            None => format!("Run history ({} runs)", history.len()),
+        };
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..11), 1, 1, None, None);
+
+        let mut commands_table = Table::new().add_row(vec!["Command", "Last", "Average", "Trend"]);
+        for command in &self.commands_to_run {
+            let durations = history::command_durations(&history, &command.command_line);
+            let last = durations.last()
+                .map(|d| format_duration(Duration::from_millis(*d), self.duration_precision))
+                .unwrap_or_else(|| "-".to_owned());
+            let average = if durations.is_empty() {
+                "-".to_owned()
+            } else {
+                let average = durations.iter().sum::<u64>() / durations.len() as u64;
+                format_duration(Duration::from_millis(average), self.duration_precision)
+            };
+            let trend = match history::trend(&durations) {
+                Some(Trend::Slower) => Text::new("↑ slower").color_range(self.theme.failure, ..),
+                Some(Trend::Faster) => Text::new("↓ faster").color_range(self.theme.success, ..),
+                Some(Trend::Steady) => Text::new("→ steady"),
+                None => Text::new("-"),
+            };
+            commands_table = commands_table.add_styled_row(vec![
+                Text::new(&command.command_line).color_range(self.theme.command, ..),
+                Text::new(last),
+                Text::new(average),
+                trend,
+            ]);
+        }
+        print_table_with_coordinates(commands_table, 1, 3, Some(cols.saturating_sub(2)), None);
+
+        // most recent runs first, as many as fit above the help line
+        let runs_y_coords = 5 + self.commands_to_run.len();
+        let max_runs = rows.saturating_sub(runs_y_coords + 3);
+        let mut runs_table = Table::new().add_row(vec!["Finished", "Duration", "Success", "Failure"]);
+        for run in history.iter().rev().take(max_runs) {
+            let finished_ago = Duration::from_millis(now_ms().saturating_sub(run.finished_at_ms));
+            runs_table = runs_table.add_styled_row(vec![
+                Text::new(format!("{} ago", format_duration(finished_ago, 0))),
+                Text::new(format_duration(Duration::from_millis(run.duration_ms), self.duration_precision)),
+                Text::new(run.successful_command_count().to_string()).color_range(self.theme.success, ..),
+                Text::new(run.failed_command_count().to_string()).color_range(self.theme.failure, ..),
+            ]);
+        }
+        print_table_with_coordinates(runs_table, 1, runs_y_coords, Some(cols.saturating_sub(2)), None);
+
+        let help_text = "h - back to commands, g - filter by label";
+        print_text_with_coordinates(
+            Text::new(help_text).color_range(self.theme.key_hint, 0..1).color_range(self.theme.key_hint, 22..23),
+            1,
+            rows.saturating_sub(1),
+            None,
+            None,
+        );
+    }
+    fn handle_shell_history(&mut self, exit_code: Option<i32>, stdout: Vec<u8>, stderr: Vec<u8>, for_palette: bool) -> bool {
+        if for_palette && !std::mem::take(&mut self.shell_history_for_palette) {
+            // the palette closed in the meantime
+            return false;
+        }
+        if exit_code != Some(0) {
+            eprintln!("Failed to read shell history {}: {}", self.shell_history_file, String::from_utf8_lossy(&stderr));
+            if for_palette {
+                // the palette makes do with the other commands
+                return false;
+            }
+            self.finish_import();
+            return false;
+        }
+        let candidates = shell_history::parse_shell_history(&String::from_utf8_lossy(&stdout));
+        if for_palette {
+            if let Some(palette) = self.palette.as_mut() {
+                palette.add(candidates.iter().map(|c| c.as_str()), palette::Source::ShellHistory);
+            }
+            return self.palette.is_some();
+        }
+        self.import_candidates = Some(candidates);
+        self.import_selection.clear();
+        self.import_cursor = 0;
+        true
+    }
+    fn handle_import_key(&mut self, key: KeyWithModifier) -> bool {
+        let candidate_count = self.import_candidates.as_ref().map(|c| c.len()).unwrap_or(0);
+        if !key.has_no_modifiers() {
+            return false;
+        }
+        match key.bare_key {
+            BareKey::Down => {
+                self.import_cursor = (self.import_cursor + 1).min(candidate_count.saturating_sub(1));
+            }
+            BareKey::Up => {
+                self.import_cursor = self.import_cursor.saturating_sub(1);
+            }
+            BareKey::Char(' ') => {
+                if !self.import_selection.remove(&self.import_cursor) {
+                    self.import_selection.insert(self.import_cursor);
+                }
+            }
+            BareKey::Enter => {
+                self.import_selected_commands();
+                self.finish_import();
+            }
+            BareKey::Esc => {
+                self.import_candidates = None;
+                self.finish_import();
+            }
+            _ => return false,
+        }
+        true
+    }
+    // The wizard defers writing the project file until the imported commands are known
+    fn finish_import(&mut self) {
+        if self.write_project_file_after_import {
+            self.write_project_file_after_import = false;
+            self.write_project_file();
+        }
+        self.start_first_run_if_ready();
+    }
+    // Appends the selected history commands to the list, oldest first and each in its own stage
+    fn import_selected_commands(&mut self) {
+        let candidates = match self.import_candidates.take() {
+            Some(candidates) => candidates,
+            None => return,
+        };
+        let mut selection: Vec<usize> = self.import_selection.drain().collect();
+        selection.sort_unstable_by(|a, b| b.cmp(a));
+        let mut stage = self.commands_to_run.iter().map(|c| c.stage + 1).max().unwrap_or(0);
+        for i in &selection {
+            if let Some(command_line) = candidates.get(*i) {
+                let mut command = Command::new(command_line);
+                command.stage = stage;
+                self.commands_to_run.push(command);
+                stage += 1;
+            }
+        }
+        if !selection.is_empty() {
+            self.audit_trail.record(AuditEvent::Action(format!("imported {} commands from the shell history", selection.len())));
+        }
+    }
+    fn open_palette(&mut self) {
+        let history = self.history.iter().rev().flat_map(|run| run.commands.iter().map(|c| c.command_line.as_str()));
+        self.palette = Some(Palette::new(self.commands_to_run.iter().map(|c| c.command_line.as_str()), history));
+        if self.capabilities.contains(&Capability::RunCommands) {
+            // offered as well once it's read, see handle_shell_history
+            self.shell_history_for_palette = true;
+            shell_history::request_shell_history(&self.shell_history_file, shell_history::FOR_PALETTE);
+        }
+    }
+    fn handle_palette_key(&mut self, key: KeyWithModifier) -> bool {
+        let palette = match self.palette.as_mut() {
+            Some(palette) => palette,
+            None => return false,
+        };
+        match key.bare_key {
+            BareKey::Char(c) if key.has_no_modifiers() || key.has_modifiers(&[KeyModifier::Shift]) => palette.type_char(c),
+            BareKey::Backspace => palette.delete_char(),
+            BareKey::Down if key.has_no_modifiers() => palette.move_cursor(true),
+            BareKey::Up if key.has_no_modifiers() => palette.move_cursor(false),
+            BareKey::Enter => {
+                let chosen = palette.chosen();
+                self.palette = None;
+                self.shell_history_for_palette = false;
+                if let Some(command_line) = chosen {
+                    self.run_one_off(&command_line);
+                }
+            }
+            BareKey::Esc => {
+                self.palette = None;
+                self.shell_history_for_palette = false;
+            }
+            _ => return false,
+        }
+        true
+    }
+    // In a floating pane of its own, like the commands of the run but left alone by it. Running it
+    // again re-runs it in that pane if it's still open.
+    fn run_one_off(&mut self, command_line: &str) {
+        if !self.capabilities.contains(&Capability::RunCommands) {
+            return;
+        }
+        self.audit_trail.record(AuditEvent::Action(format!("run {} on its own", command_line)));
+        let index = match self.one_offs.iter().position(|o| o.command_line == command_line) {
+            Some(index) => index,
+            None => {
+                self.one_offs.push(OneOff::new(command_line));
+                self.one_offs.len() - 1
+            }
+        };
+        let launch_settings = self.launch_settings();
+        let one_off = &mut self.one_offs[index];
+        match one_off.pane_id {
+            Some(pane_id) if one_off.is_running() => focus_terminal_pane(pane_id, true),
+            Some(pane_id) => rerun_command_pane(pane_id),
+            None => {
+                let mut command_to_run = process::shell_command(&launch_settings.shell_invocation, command_line);
+                command_to_run.cwd = Some(PathBuf::from(&launch_settings.folder));
+                command_to_run = env_file::wrap_with_env(command_to_run, &launch_settings.env_variables);
+                let mut context = BTreeMap::new();
+                context.insert(palette::ONE_OFF_CONTEXT.to_owned(), index.to_string());
+                open_command_pane_floating(command_to_run, None, context);
+            }
+        }
+    }
+    fn handle_wizard_key(&mut self, key: KeyWithModifier) -> bool {
+        if !key.has_no_modifiers() {
+            return false;
+        }
+        if key.bare_key == BareKey::Esc {
+            // skip the rest of the wizard, nothing is written
+            self.wizard = None;
+            return true;
+        }
+        let done = self.wizard.as_mut().map(|w| w.handle_key(&key)).unwrap_or(false);
+        if done {
+            self.finish_wizard();
+        }
+        true
+    }
+    fn finish_wizard(&mut self) {
+        let wizard = match self.wizard.take() {
+            Some(wizard) => wizard,
+            None => return,
+        };
+        // the options of a profile (loaded later on) are applied over these, see apply_profile_options
+        for configuration in [&mut self.base_configuration, &mut self.userspace_configuration] {
+            configuration.insert("shell".to_owned(), wizard.shell.clone());
+            configuration.insert("folder".to_owned(), wizard.folder.clone());
+        }
+        self.shell = wizard.shell;
+        self.folder = wizard.folder;
+        if !self.userspace_configuration.contains_key("shell_history_file") {
+            self.shell_history_file = shell_history::default_history_file(&self.shell).to_owned();
+        }
+        self.audit_trail.record(AuditEvent::Action("first run wizard".to_owned()));
+        if let Some(template) = wizard.template.and_then(|t| templates::TEMPLATES.get(t)) {
+            self.parse_commands(template.commands);
+        }
+        if wizard.write_project_file {
+            self.config_file = Some(PathBuf::from(wizard::PROJECT_FILE));
+        }
+        if wizard.import_from_shell_history && self.capabilities.contains(&Capability::RunCommands) {
+            self.write_project_file_after_import = wizard.write_project_file;
+            shell_history::request_shell_history(&self.shell_history_file, "");
+            return;
+        }
+        if wizard.write_project_file {
+            self.write_project_file();
+        }
+        self.start_first_run_if_ready();
+    }
+    // The first run waits for the wizard (and the import that might follow it) to finish
+    fn start_first_run_if_ready(&mut self) {
+        let is_ready = self.current_run_index == 0 && !self.commands_to_run.is_empty() && self.capabilities.contains(&Capability::RunCommands);
+        if is_ready {
+            self.start_run();
+        }
+    }
+    fn write_project_file(&self) {
+        let commands = export::commands_to_kdl(&self.commands_to_run, &self.stage_names);
+        wizard::write_project_file(&self.shell, &self.folder, &commands);
+    }
+    // Keeps what was edited, added or reordered at runtime for the next time we're launched
+    fn save_commands_to_project_file(&mut self) {
+        let commands = export::commands_to_kdl(&self.commands_to_run, &self.stage_names);
+        wizard::save_commands_to_project_file(&self.shell, &self.folder, &commands);
+        self.audit_trail.record(AuditEvent::Action(format!("save the commands to {}", wizard::PROJECT_FILE)));
+        if self.userspace_configuration.get("config_file").map(|f| f.as_str()) != Some(wizard::PROJECT_FILE) {
+            // eg. the layout's own commands, the project file is then left aside
+            eprintln!("Saved the commands to {}, but they're configured elsewhere", wizard::PROJECT_FILE);
+        }
+    }
+    // Settings from the project file fill in whatever the layout doesn't configure, as long as
+    // the layout doesn't have its own commands
+    fn load_project_file(&mut self) {
+        if self.userspace_configuration.contains_key("commands") {
+            return;
+        }
+        if let Some((project_file, project_configuration)) = wizard::load_project_file() {
+            for (key, value) in project_configuration {
+                self.userspace_configuration.entry(key).or_insert(value);
+            }
+            self.userspace_configuration.entry("config_file".to_owned()).or_insert_with(|| project_file.to_owned());
+        }
+    }
+    // Without running commands or opening files, much of what we do can't be done. Once the user
+    // answered every request, what's missing and what still works is explained until they either
+    // ask for the permissions again or go on without them.
+    fn shows_permissions_screen(&self) -> bool {
+        let missing_essential_capability = self.denied_capabilities.contains(&Capability::RunCommands)
+            || self.denied_capabilities.contains(&Capability::OpenFiles);
+        missing_essential_capability && self.pending_capability_requests.is_empty() && !self.permissions_screen_dismissed && !self.observer
+    }
+    fn handle_permissions_screen_key(&mut self, key: KeyWithModifier) -> bool {
+        if !key.has_no_modifiers() {
+            return false;
+        }
+        match key.bare_key {
+            BareKey::Char('r') => {
+                self.pending_capability_requests = Capability::all().into_iter().filter(|c| self.denied_capabilities.contains(c)).collect();
+                self.denied_capabilities.clear();
+                self.request_next_capability();
+                self.audit_trail.record(AuditEvent::Action("ask for permissions again".to_owned()));
+            }
+            BareKey::Esc => self.permissions_screen_dismissed = true,
+            _ => return false,
+        }
+        true
+    }
+    fn render_permissions_screen(&self, rows: usize, cols: usize) {
+        let title = "Some permissions were denied";
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.failure, ..), 1, 1, None, None);
+        let mut y_coords = 3;
+        for capability in Capability::all().iter().filter(|c| self.denied_capabilities.contains(c)) {
+            let permissions: Vec<String> = capability.permissions().iter().map(|p| format!("{:?}", p)).collect();
+            let text = format!("Missing {}: no {}", permissions.join(", "), capability.description());
+            print_text_with_coordinates(Text::new(text).color_range(self.theme.failure, 0..7), 1, y_coords, Some(cols.saturating_sub(2)), None);
+            y_coords += 1;
+        }
+        let mut still_working = vec![
+            "Browsing the commands, read-only",
+            "D - a command's details, including what it would run (a dry-run)",
+        ];
+        if !self.denied_capabilities.contains(&Capability::RunCommands) {
+            still_working.push("Running the commands");
+        }
+        if !self.denied_capabilities.contains(&Capability::OpenFiles) {
+            still_working.push("e - editing the commands");
+        }
+        print_text_with_coordinates(Text::new("What still works:").color_range(self.theme.success, ..), 1, y_coords + 1, None, None);
+        let list = still_working.into_iter().map(NestedListItem::new).collect();
+        print_nested_list_with_coordinates(list, 1, y_coords + 2, Some(cols.saturating_sub(2)), None);
+        let help_text = "r - ask for them again, ESC - go on without them";
+        let help_text = Text::new(help_text).color_range(self.theme.key_hint, 0..1).color_range(self.theme.key_hint, 24..27);
+        print_text_with_coordinates(help_text, 1, rows.saturating_sub(1), Some(cols.saturating_sub(2)), None);
+    }
+    fn render_config_error(&self, rows: usize, cols: usize) {
+        let config_error = match &self.config_error {
+            Some(config_error) => config_error,
+            None => return,
+        };
+        let title = "Failed to parse the commands, nothing was run";
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.failure, ..), 1, 1, None, None);
+        print_text_with_coordinates(Text::new(&config_error.description), 1, 3, Some(cols.saturating_sub(2)), None);
+        print_text_with_coordinates(Text::new(&config_error.line_text).color_range(self.theme.command, ..), 1, 5, Some(cols.saturating_sub(2)), None);
+        let marker = format!("{}^", " ".repeat(config_error.column.saturating_sub(1)));
+        print_text_with_coordinates(Text::new(marker).color_range(self.theme.failure, ..), 1, 6, Some(cols.saturating_sub(2)), None);
+        let help_text = if self.edit_error.is_some() {
+            format!("Edit not applied: {} (e - fix it)", self.edit_error.as_deref().unwrap_or_default())
+        } else {
+            "e - fix it in the editor".to_owned()
+        };
+        let help_text_len = help_text.chars().count();
+        let help_text = if self.edit_error.is_some() {
+            Text::new(help_text).color_range(self.theme.failure, 0..17).color_range(self.theme.key_hint, help_text_len - 11..help_text_len - 10)
+        } else {
+            Text::new(help_text).color_range(self.theme.key_hint, 0..1)
+        };
+        print_text_with_coordinates(help_text, 1, rows.saturating_sub(1), Some(cols.saturating_sub(2)), None);
+    }
+    fn render_wizard(&self, rows: usize, cols: usize) {
+        let wizard = match &self.wizard {
+            Some(wizard) => wizard,
+            None => return,
+        };
+        let title = format!("Welcome to zlaunch (step {} of 4)", wizard.step_number());
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..18), 1, 1, None, None);
+        print_text_with_coordinates(Text::new(wizard.prompt()), 1, 3, None, None);
+        let choices = wizard.choices();
+        let help_text = if choices.is_empty() {
+            let input = format!("> {}_", wizard.folder);
+            print_text_with_coordinates(Text::new(input).color_range(self.theme.command, 2..), 1, 5, Some(cols.saturating_sub(2)), None);
+            Text::new("Type a folder, ENTER - next, ESC - skip setup")
+                .color_range(self.theme.key_hint, 15..20)
+                .color_range(self.theme.key_hint, 29..32)
+        } else {
+            let list = choices.iter().enumerate().map(|(i, choice)| {
+                let item = NestedListItem::new(choice).color_range(self.theme.command, ..);
+                if i == wizard.cursor { item.selected() } else { item }
+            }).collect();
+            print_nested_list_with_coordinates(list, 0, 5, Some(cols), None);
+            Text::new("↓↑ - choose, ENTER - next, ESC - skip setup")
+                .color_range(self.theme.key_hint, 0..2)
+                .color_range(self.theme.key_hint, 13..18)
+                .color_range(self.theme.key_hint, 27..30)
+        };
+        print_text_with_coordinates(help_text, 1, rows.saturating_sub(1), None, None);
+    }
+    fn render_import(&self, rows: usize, cols: usize) {
+        let candidates = self.import_candidates.as_deref().unwrap_or_default();
+        let title = format!("Import from {} ({} selected)", self.shell_history_file, self.import_selection.len());
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..6), 1, 1, None, None);
+        // keep the cursor in view
+        let max_candidates = rows.saturating_sub(5).max(1);
+        let first_candidate = self.import_cursor.saturating_sub(max_candidates - 1);
+        let mut list = vec![];
+        for (i, candidate) in candidates.iter().enumerate().skip(first_candidate).take(max_candidates) {
+            let marker = if self.import_selection.contains(&i) { "[x]" } else { "[ ]" };
+            let item = NestedListItem::new(format!("{} {}", marker, candidate)).color_range(self.theme.command, 4..);
+            list.push(if i == self.import_cursor { item.selected() } else { item });
+        }
+        print_nested_list_with_coordinates(list, 0, 3, Some(cols), None);
+        let help_text = "↓↑ - browse, SPACE - select, ENTER - add to the commands, ESC - cancel";
+        print_text_with_coordinates(
+            Text::new(help_text)
+                .color_range(self.theme.key_hint, 0..2)
+                .color_range(self.theme.key_hint, 13..18)
+                .color_range(self.theme.key_hint, 29..34)
+                .color_range(self.theme.key_hint, 58..61),
+            1,
+            rows.saturating_sub(1),
+            None,
+            None,
+        );
+    }
+    fn render_palette(&self, rows: usize, cols: usize) {
+        let palette = match &self.palette {
+            Some(palette) => palette,
+            None => return,
+        };
+        let title = format!("Run once: {}_", palette.query);
+        let query_len = palette.query.chars().count();
+        print_text_with_coordinates(
+            Text::new(title).color_range(self.theme.running, 0..9).color_range(self.theme.command, 10..10 + query_len),
+            1,
+            1,
+            None,
+            None,
+        );
+        let matches = palette.matches();
+        // keep the cursor in view
+        let max_matches = rows.saturating_sub(5).max(1);
+        let first_match = palette.cursor.saturating_sub(max_matches - 1);
+        let mut list = vec![];
+        for (i, entry) in matches.iter().enumerate().skip(first_match).take(max_matches) {
+            let command_len = entry.command_line.chars().count();
+            let item = NestedListItem::new(format!("{} ({})", entry.command_line, entry.source.label()))
+                .color_range(self.theme.command, 0..command_len);
+            list.push(if i == palette.cursor { item.selected() } else { item });
+        }
+        let typed = palette.query.trim();
+        if matches.is_empty() && !typed.is_empty() {
+            let typed_len = typed.chars().count();
+            list.push(NestedListItem::new(format!("{} (as typed)", typed)).color_range(self.theme.command, 0..typed_len).selected());
+        }
+        print_nested_list_with_coordinates(list, 0, 3, Some(cols), None);
+        let help_text = "↓↑ - browse, ENTER - run on its own, ESC - cancel";
+        print_text_with_coordinates(
+            Text::new(help_text)
+                .color_range(self.theme.key_hint, 0..2)
+                .color_range(self.theme.key_hint, 13..18)
+                .color_range(self.theme.key_hint, 37..40),
+            1,
+            rows.saturating_sub(1),
+            None,
+            None,
+        );
+    }
+    // The timing statistics of bench mode, slowest (on average) first
+    fn render_bench_results(&self, rows: usize, cols: usize) {
+        let title = format!("Bench results: every command ran {} times", self.bench_runs.unwrap_or(0));
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..13), 1, 1, None, None);
+        let mut results: Vec<(&Command, Option<bench::BenchStats>)> = self.commands_to_run.iter()
+            .map(|c| (c, bench::BenchStats::from_samples(&c.bench_samples)))
+            .collect();
+        results.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.map(|s| s.mean)));
+        let mut table = Table::new().add_row(vec!["Command", "Runs", "Min", "Mean", "Max", "Std dev"]);
+        for (command, stats) in results.iter().take(rows.saturating_sub(5)) {
+            let format = |duration: Duration| format_duration(duration, self.duration_precision);
+            let row = match stats {
+                Some(stats) => vec![
+                    Text::new(stats.runs.to_string()),
+                    Text::new(format(stats.min)),
+                    Text::new(format(stats.mean)).color_range(self.theme.running, ..),
+                    Text::new(format(stats.max)),
+                    Text::new(format(stats.stddev)),
+                ],
+                None => vec![Text::new("0"), Text::new("-"), Text::new("-"), Text::new("-"), Text::new("-")],
+            };
+            let mut styled_row = vec![Text::new(&command.command_line).color_range(self.theme.command, ..)];
+            styled_row.extend(row);
+            table = table.add_styled_row(styled_row);
+        }
+        print_table_with_coordinates(table, 1, 3, Some(cols.saturating_sub(2)), None);
+        let help_text = "ESC - back to commands, m - write the report";
+        print_text_with_coordinates(
+            Text::new(help_text).color_range(self.theme.key_hint, 0..3).color_range(self.theme.key_hint, 24..25),
+            1,
+            rows.saturating_sub(1),
+            None,
+            None,
+        );
+    }
+    // The commands of the finished run, slowest first
+    fn render_summary(&self, rows: usize, cols: usize) {
+        if self.bench_runs.is_some() {
+            self.render_bench_results(rows, cols);
+            return;
+        }
+        let title = format!(
+            "Run summary: {} succeeded, {} failed in {}",
+            self.successful_command_count(),
+            self.failed_command_count(),
+            self.total_run_time()
+        );
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..11), 1, 1, None, None);
+        let mut commands: Vec<&Command> = self.commands_to_run.iter().collect();
+        commands.sort_by_key(|c| std::cmp::Reverse(c.start_time.map(|start_time| c.end_time.unwrap_or_else(Instant::now).duration_since(start_time))));
+        let mut table = Table::new().add_row(vec!["Command", "Duration", "Exit code", "Attempts"]);
+        for command in commands.iter().take(rows.saturating_sub(5)) {
+            let duration = match (command.duration(), command.options.budget) {
+                (Some(duration), Some(budget)) if command.is_over_budget() => {
+                    let duration = format!("{} (budget {})", format_duration(duration, self.duration_precision), format_duration(budget, 0));
+                    Text::new(duration).color_range(self.theme.pending, ..)
+                }
+                (Some(duration), _) => Text::new(format_duration(duration, self.duration_precision)),
+                (None, _) => Text::new("-"),
+            };
+            let exit_code = match command.exit_status {
+                Some(exit_status) => {
+                    let color = if command.is_success_code(exit_status) { self.theme.success } else { self.theme.failure };
+                    Text::new(exit_status.to_string()).color_range(color, ..)
+                }
+                None if command.skipped => Text::new("skipped").color_range(self.theme.skipped, ..),
+                None => Text::new("-"),
+            };
+            table = table.add_styled_row(vec![
+                Text::new(&command.command_line).color_range(self.theme.command, ..),
+                duration,
+                exit_code,
+                Text::new(command.attempts.to_string()),
+            ]);
+        }
+        print_table_with_coordinates(table, 1, 3, Some(cols.saturating_sub(2)), None);
+        let help_text = "ESC - back to commands";
+        print_text_with_coordinates(Text::new(help_text).color_range(self.theme.key_hint, 0..3), 1, rows.saturating_sub(1), None, None);
+    }
+    // Once a run is over, its audit trail can be stepped through (starting from the end) to see
+    // how it unfolded. Stepping past the last entry goes back to the commands.
+    fn step_time_travel(&mut self, forward: bool) -> bool {
+        let run_is_over = self.running_command_index.is_none() && self.all_commands_exited();
+        let entry_count = self.audit_trail.current_run_entries().len();
+        if !run_is_over || entry_count == 0 {
+            return false;
+        }
+        self.time_travel = match (self.time_travel, forward) {
+            (None, false) => Some(entry_count - 1),
+            (None, true) => return false,
+            (Some(entry), false) => Some(entry.saturating_sub(1)),
+            (Some(entry), true) if entry + 1 < entry_count => Some(entry + 1),
+            (Some(_), true) => None,
+        };
+        true
+    }
+    fn render_time_travel(&self, rows: usize, cols: usize) {
+        let entries = self.audit_trail.current_run_entries();
+        let moment = match self.time_travel {
+            Some(moment) if moment < entries.len() => moment,
+            _ => return,
+        };
+        let started_at = entries[0].at;
+        let at = entries[moment].at;
+        let elapsed = format!("+{}", format_duration(at.duration_since(started_at), self.duration_precision));
+        let title = format!("Replay of run #{} at {} ({}/{})", self.current_run_index, elapsed, moment + 1, entries.len());
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..6), 1, 1, None, None);
+        print_text_with_coordinates(Text::new(entries[moment].event.to_string()).color_range(self.theme.pending, ..), 1, 2, Some(cols.saturating_sub(2)), None);
+        let statuses = audit::replay(&entries[..=moment], self.commands_to_run.len());
+        let mut list = vec![];
+        for (command, status) in self.commands_to_run.iter().zip(statuses) {
+            let (symbol, state, color) = match status {
+                ReplayedStatus::Pending => ("…", "pending".to_owned(), self.theme.pending),
+                ReplayedStatus::Running(since) => ("▶", format!("running for {}", format_duration(at.duration_since(since), self.duration_precision)), self.theme.running),
+                ReplayedStatus::Exited(Some(exit_code)) if command.is_success_code(exit_code) => ("✔", format!("exit code {}", exit_code), self.theme.success),
+                ReplayedStatus::Exited(Some(exit_code)) => ("✘", format!("exit code {}", exit_code), self.theme.failure),
+                ReplayedStatus::Exited(None) => ("✘", "exited".to_owned(), self.theme.failure),
+                ReplayedStatus::Skipped => ("-", "skipped".to_owned(), self.theme.skipped),
+                ReplayedStatus::SpawnFailed => ("✘", "failed to spawn".to_owned(), self.theme.failure),
+                ReplayedStatus::Killed => ("✘", "killed".to_owned(), self.theme.failure),
+                ReplayedStatus::PaneClosed => ("✘", "pane closed".to_owned(), self.theme.failure),
+            };
+            let command_len = command.command_line.chars().count();
+            list.push(
+                NestedListItem::new(format!("{} {} ({})", symbol, command.command_line, state))
+                    .color_range(color, 0..1)
+                    .color_range(self.theme.command, 2..2 + command_len)
+                    .color_range(color, 2 + command_len + 1..)
+            );
+        }
+        print_nested_list_with_coordinates(list, 0, 4, Some(cols), None);
+        let help_text = "←/→ - step through the run, ESC - back to commands";
+        print_text_with_coordinates(
+            Text::new(help_text).color_range(self.theme.key_hint, 0..3).color_range(self.theme.key_hint, 28..31),
+            1,
+            rows.saturating_sub(1),
+            None,
+            None,
+        );
+    }
+    fn handle_details_key(&mut self, key: KeyWithModifier) -> bool {
+        match key.bare_key {
+            BareKey::Esc | BareKey::Char('D') => self.show_details = false,
+            BareKey::Char('r') if key.has_no_modifiers() => return self.rerun_selected_command(),
+            BareKey::Char('c') if key.has_modifiers(&[KeyModifier::Ctrl]) => return self.kill_selected_command(),
+            BareKey::Char('e') if key.has_no_modifiers() => {
+                self.show_details = false;
+                self.open_editor();
+            }
+            BareKey::Up if key.has_no_modifiers() => self.move_selection_up(),
+            BareKey::Down if key.has_no_modifiers() => self.move_selection_down(),
+            _ => return false,
+        }
+        true
+    }
+    fn handle_marking_key(&mut self, key: KeyWithModifier) -> bool {
+        match key.bare_key {
+            BareKey::Esc | BareKey::Char('V') => {
+                self.marking = false;
+                self.marked.clear();
+            }
+            BareKey::Down if key.has_no_modifiers() => {
+                self.move_selection_down();
+                self.marked.extend(self.selected_index);
+            }
+            BareKey::Up if key.has_no_modifiers() => {
+                self.move_selection_up();
+                self.marked.extend(self.selected_index);
+            }
+            BareKey::Char(' ') if key.has_no_modifiers() => {
+                if let Some(selected_index) = self.selected_index {
+                    if !self.marked.remove(&selected_index) {
+                        self.marked.insert(selected_index);
+                    }
+                }
+            }
+            BareKey::Char('r') if key.has_no_modifiers() => {
+                self.apply_to_marked("re-run", Self::rerun_selected_command);
+            }
+            BareKey::Char('c') if key.has_modifiers(&[KeyModifier::Ctrl]) => {
+                self.apply_to_marked("kill", Self::kill_selected_command);
+            }
+            BareKey::Char('s') if key.has_no_modifiers() => {
+                self.apply_to_marked("skip", Self::toggle_skip_selected_command);
+            }
+            BareKey::Delete if key.has_no_modifiers() => {
+                self.delete_marked_commands();
+            }
+            _ => return false,
+        }
+        true
+    }
+    // Like the keys, except that a restart isn't confirmed: the prompt would be in a pane that
+    // might not even be visible
+    fn handle_keybinding_pipe(&mut self, pipe_name: &str) -> bool {
+        match pipe_name {
+            TOGGLE_PAUSE_PIPE_NAME => {
+                self.paused = !self.paused;
+                self.audit_trail.record(AuditEvent::Action(if self.paused { "pause" } else { "unpause" }.to_owned()));
+                if !self.paused && !self.all_commands_exited() {
+                    self.run_next_command();
+                }
+            }
+            RESTART_PIPE_NAME => self.restart_from_key(),
+            RERUN_FAILED_PIPE_NAME => {
+                let failed: Vec<usize> = self.commands_to_run.iter().enumerate()
+                    .filter(|(_, c)| c.exited && !c.succeeded() && !c.is_allowed_failure())
+                    .map(|(i, _)| i)
+                    .collect();
+                if failed.is_empty() {
+                    return false;
+                }
+                self.audit_trail.record(AuditEvent::Action("rerun failed".to_owned()));
+                // rerun_selected_command acts on the selection, which is restored afterwards
+                let selected_index = self.selected_index;
+                for index in failed {
+                    self.selected_index = Some(index);
+                    self.rerun_selected_command();
+                }
+                self.selected_index = selected_index;
+            }
+            _ => return false,
+        }
+        true
+    }
+    // Bulk actions reuse the ones on the selected command, the selection is restored afterwards.
+    // Marking ends with the action.
+    fn apply_to_marked(&mut self, action: &str, act_on_selected: fn(&mut Self) -> bool) {
+        let selected_index = self.selected_index;
+        let marked = std::mem::take(&mut self.marked);
+        let mut performed = 0;
+        for index in &marked {
+            self.selected_index = Some(*index);
+            if act_on_selected(self) {
+                performed += 1;
+            }
+        }
+        self.selected_index = selected_index;
+        self.marking = false;
+        if performed < marked.len() {
+            eprintln!("Couldn't {} {} of the {} marked commands", action, marked.len() - performed, marked.len());
+        }
+    }
+    // Like cloning, deleting shifts the commands below, so it isn't done above a running command
+    fn delete_marked_commands(&mut self) {
+        let first_marked = match self.marked.iter().next() {
+            Some(first_marked) => *first_marked,
+            None => return,
+        };
+        if self.has_panes_from(first_marked) {
+            eprintln!("Can't delete commands with or above a command that has a pane");
+            return;
+        }
+        if self.marked.iter().any(|i| self.commands_to_run.get(*i).map(|c| c.options.locked).unwrap_or(false)) {
+            eprintln!("Can't delete a locked command");
+            return;
+        }
+        let marked = std::mem::take(&mut self.marked);
+        for index in marked.iter().rev() {
+            if *index < self.commands_to_run.len() {
+                self.commands_to_run.remove(*index);
+                self.audit_trail.record(AuditEvent::Action(format!("delete #{}", index + 1)));
+            }
+        }
+        if let Some(running_command_index) = self.running_command_index {
+            // the last command the run got to, or the one before it if it was deleted
+            self.running_command_index = if marked.contains(&running_command_index) {
+                running_command_index.checked_sub(marked.range(..=running_command_index).count())
+            } else {
+                Some(running_command_index - marked.range(..running_command_index).count())
+            };
+        }
+        self.selected_index = None;
+        self.marking = false;
+    }
+    // Command panes (their context, pid file and output files) know their command by its index,
+    // so the commands from this index on can't be shifted while one of them has a pane
+    fn has_panes_from(&self, index: usize) -> bool {
+        self.commands_to_run.iter().skip(index).any(|c| c.pane_id.is_some())
+    }
+    // Closing the pane ends the command, the run goes on as if the user closed it
+    fn kill_selected_command(&mut self) -> bool {
+        let selected_index = match self.selected_index {
+            Some(selected_index) => selected_index,
+            None => return false,
+        };
+        match self.commands_to_run.get(selected_index) {
+            Some(command) if command.is_running() => {
+                if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
+                    self.terminate_panes(vec![(pane_id, true)]);
+                    self.audit_trail.record(AuditEvent::Killed(selected_index));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+    // Everything about the selected command: how it's launched and each of its attempts
+    fn render_details(&self, rows: usize, cols: usize) {
+        let selected_index = self.selected_index.unwrap_or(0);
+        let command = match self.commands_to_run.get(selected_index) {
+            Some(command) => command,
+            None => return,
+        };
+        let launch_settings = self.launch_settings();
+        let command_to_run = Self::command_to_run(command, &launch_settings);
+        let mut runs_as = vec![command_to_run.path.to_string_lossy().to_string()];
+        runs_as.extend(command_to_run.args.iter().map(|a| format!("{:?}", a)));
+        let mut environment = vec![format!("{}={}", run_tmp::RUN_TMP_ENV_VAR, self.host_folder.join(&launch_settings.run_tmp_dir).display())];
+        if !command.options.path_prepend.is_empty() {
+            environment.push(format!("PATH={}:$PATH", command.options.path_prepend.join(":")));
+        }
+        if let Some(env_file) = &self.env_file {
+            environment.extend(launch_settings.env_variables.iter().map(|(key, _)| format!("{} (from {})", key, env_file)));
+        }
+        let title = format!("Command #{}", selected_index + 1);
+        print_text_with_coordinates(Text::new(&title).color_range(self.theme.running, ..), 1, 1, None, None);
+        let mut list = vec![
+            NestedListItem::new(&command.command_line).color_range(self.theme.command, ..),
+        ];
+        if let Some(launch_inputs) = command.launch_inputs.as_ref().filter(|l| l.command_line != command.command_line) {
+            list.push(NestedListItem::new(format!("Resolved: {}", launch_inputs.command_line)).indent(1));
+        }
+        list.push(NestedListItem::new(format!("Runs as: {}", runs_as.join(" "))).indent(1));
+        list.push(NestedListItem::new(format!("In: {}", self.host_folder.join(&launch_settings.folder).display())).indent(1));
+        list.push(NestedListItem::new(format!("Environment: {}", environment.join(" "))).indent(1));
+        let stage = match self.stage_names.get(&command.stage) {
+            Some(stage_name) => format!("Stage: {}", stage_name),
+            None => format!("Stage: #{}", command.stage + 1),
+        };
+        list.push(NestedListItem::new(stage).indent(1));
+        if !command.options.tags.is_empty() {
+            list.push(NestedListItem::new(format!("Tags: {}", command.options.tags.join(" "))).indent(1));
+        }
+        if !command.options.matrix.is_empty() {
+            let values: Vec<String> = command.options.matrix.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            list.push(NestedListItem::new(format!("Matrix: {}", values.join(" "))).indent(1));
+        }
+        let entries = self.audit_trail.current_run_entries();
+        let started_at = entries.first().map(|e| e.at);
+        let attempts = audit::attempts(entries, selected_index);
+        list.push(NestedListItem::new(format!("Attempts ({})", attempts.len())).color_range(self.theme.running, ..));
+        for (i, attempt) in attempts.iter().enumerate() {
+            let start = started_at.map(|s| format_duration(attempt.started_at.duration_since(s), self.duration_precision)).unwrap_or_default();
+            let (outcome, color) = match attempt.end {
+                Some((ended_at, status)) => {
+                    let duration = format_duration(ended_at.duration_since(attempt.started_at), self.duration_precision);
+                    match status {
+                        ReplayedStatus::Exited(Some(exit_code)) if command.is_success_code(exit_code) => (format!("exit code {} after {}", exit_code, duration), self.theme.success),
+                        ReplayedStatus::Exited(Some(exit_code)) => (format!("exit code {} after {}", exit_code, duration), self.theme.failure),
+                        ReplayedStatus::SpawnFailed => ("failed to spawn".to_owned(), self.theme.failure),
+                        ReplayedStatus::Killed => (format!("killed after {}", duration), self.theme.failure),
+                        ReplayedStatus::PaneClosed => (format!("pane closed after {}", duration), self.theme.failure),
+                        _ => (format!("exited after {}", duration), self.theme.failure),
+                    }
+                }
+                None => ("running".to_owned(), self.theme.running),
+            };
+            let prefix = format!("#{} at +{}: ", i + 1, start);
+            let prefix_len = prefix.chars().count();
+            list.push(NestedListItem::new(format!("{}{}", prefix, outcome)).color_range(color, prefix_len..).indent(1));
+        }
+        print_nested_list_with_coordinates(list, 0, 3, Some(cols), None);
+        let help_text = "r - re-run, Ctrl c - kill, e - edit, ↓↑ - other commands, ESC - back";
+        let help = Text::new(help_text)
+            .color_range(self.theme.key_hint, 0..1)
+            .color_range(self.theme.key_hint, 12..18)
+            .color_range(self.theme.key_hint, 27..28)
+            .color_range(self.theme.key_hint, 37..39)
+            .color_range(self.theme.key_hint, 58..61);
+        print_text_with_coordinates(help, 1, rows.saturating_sub(1), Some(cols.saturating_sub(2)), None);
+    }
+    fn render_audit_trail(&self, rows: usize, _cols: usize) {
+        let entries = self.audit_trail.current_run_entries();
+        let title = format!("Audit trail ({} entries)", entries.len());
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..11), 1, 1, None, None);
+        // the most recent entries that fit above the help line
+        let max_entries = rows.saturating_sub(5);
+        let started_at = entries.first().map(|e| e.at);
+        let mut list = vec![];
+        for entry in entries.iter().skip(entries.len().saturating_sub(max_entries)) {
+            let elapsed = started_at.map(|s| entry.at.duration_since(s)).unwrap_or_default();
+            let elapsed = format!("+{}", format_duration(elapsed, self.duration_precision));
+            let elapsed_len = elapsed.chars().count();
+            let color = match entry.event {
+                AuditEvent::Exited(command_index, _) if self.commands_to_run.get(command_index).map(|c| c.exited && !c.succeeded()).unwrap_or(false) => self.theme.failure,
+                AuditEvent::Action(_) => self.theme.key_hint,
+                AuditEvent::RunStarted(_) => self.theme.running,
+                _ => self.theme.command,
+            };
+            list.push(
+                NestedListItem::new(format!("{} {}", elapsed, entry.event))
+                    .color_range(self.theme.pending, 0..elapsed_len)
+                    .color_range(color, elapsed_len + 1..)
+            );
+        }
+        print_nested_list_with_coordinates(list, 0, 3, None, None);
+        let help_text = "t - back to commands";
+        print_text_with_coordinates(Text::new(help_text).color_range(self.theme.key_hint, 0..1), 1, rows.saturating_sub(1), None, None);
+    }
+    // Every keybinding, in a panel centered in our pane
+    fn render_keybindings(&self, rows: usize, cols: usize) {
+        let key_width = KEYBINDINGS.iter().map(|(keys, _)| keys.chars().count()).max().unwrap_or(0);
+        let lines: Vec<String> = KEYBINDINGS.iter()
+            .map(|(keys, description)| format!("{}{} - {}", keys, " ".repeat(key_width - keys.chars().count()), description))
+            .collect();
+        let help_text = "ESC - back to commands";
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0).max(help_text.len());
+        let height = lines.len() + 4; // title, spacing and help
+        let x_coords = cols.saturating_sub(width) / 2;
+        let y_coords = rows.saturating_sub(height) / 2;
+        print_text_with_coordinates(Text::new("Keybindings").color_range(self.theme.running, ..), x_coords, y_coords, None, None);
+        // the bottom ones are cut in panes too small for all of them
+        for (i, line) in lines.into_iter().take(rows.saturating_sub(4)).enumerate() {
+            let text = Text::new(line).color_range(self.theme.key_hint, 0..key_width);
+            print_text_with_coordinates(text, x_coords, y_coords + 2 + i, Some(cols.saturating_sub(x_coords)), None);
+        }
+        let help_y_coords = (y_coords + height - 1).min(rows.saturating_sub(1));
+        print_text_with_coordinates(Text::new(help_text).color_range(self.theme.key_hint, 0..3), x_coords, help_y_coords, None, None);
+    }
+    fn render_help(&self, rows: usize, cols: usize) {
+        let y_coords = 8 + self.command_list_len();
+        if self.observer {
+            let observer_text = "Read-only observer (↓↑ to browse)";
+            print_text_with_coordinates(Text::new(observer_text).color_range(self.theme.key_hint, 0..18), 1, y_coords, None, None);
+            return;
+        }
+        if self.restart_prompt {
+            let text = "Restart run? The running commands will be killed (y/n)";
+            let text = Text::new(text).color_range(self.theme.pending, 0..12).color_range(self.theme.key_hint, 50..55);
+            print_text_with_coordinates(text, 1, y_coords, Some(cols.saturating_sub(2)), None);
+            return;
+        }
+        if self.resume_prompt {
+            let succeeded = self.successful_command_count();
+            let text = format!("Resume the interrupted run? The {} commands that succeeded won't run again (y - resume, n - start over)", succeeded);
+            let offset = succeeded.to_string().len();
+            let text = Text::new(text)
+                .color_range(self.theme.pending, 0..26)
+                .color_range(self.theme.key_hint, offset + 74..offset + 75)
+                .color_range(self.theme.key_hint, offset + 86..offset + 87);
+            print_text_with_coordinates(text, 1, y_coords, Some(cols.saturating_sub(2)), None);
+            return;
+        }
+        if self.marking {
+            let text = format!("{} marked: ↓↑/SPACE - mark, r - re-run, Ctrl c - kill, s - skip, DEL - delete, ESC - cancel", self.marked.len());
+            let offset = self.marked.len().to_string().len();
+            let text = Text::new(text)
+                .color_range(self.theme.selected, 0..offset + 7)
+                .color_range(self.theme.key_hint, offset + 9..offset + 17)
+                .color_range(self.theme.key_hint, offset + 26..offset + 27)
+                .color_range(self.theme.key_hint, offset + 38..offset + 44)
+                .color_range(self.theme.key_hint, offset + 53..offset + 54)
+                .color_range(self.theme.key_hint, offset + 63..offset + 66)
+                .color_range(self.theme.key_hint, offset + 77..offset + 80);
+            print_text_with_coordinates(text, 1, y_coords, Some(cols.saturating_sub(2)), None);
+            return;
+        }
+        if let Some(quick_add_input) = &self.quick_add_input {
+            let text = format!("Add command: {}_ (ENTER - add, ESC - cancel)", quick_add_input);
+            let input_len = quick_add_input.chars().count();
+            let text = Text::new(text)
+                .color_range(self.theme.key_hint, 0..12)
+                .color_range(self.theme.command, 13..13 + input_len)
+                .color_range(self.theme.key_hint, 16 + input_len..21 + input_len)
+                .color_range(self.theme.key_hint, 29 + input_len..32 + input_len);
+            print_text_with_coordinates(text, 1, y_coords, Some(cols.saturating_sub(2)), None);
+            return;
+        }
+        // (key, description, is_toggled_on)
+        let hints = [
+            ("?", "Keys", self.show_keybindings),
+            // with a selection, ENTER opens its action menu instead
+            ("ENTER", if self.selected_index.is_some() { "Actions" } else { "Restart" }, false),
+            ("SPACE", "Pause After Command", self.paused && !self.suspended),
+            ("z", "Suspend", self.suspended),
+            ("f", "Stop on Failure", self.stop_on_failure),
+            ("F", "Follow", self.focus_follow),
+            ("h", "History", self.show_history),
+            ("t", "Audit Trail", self.show_audit_trail),
+        ];
+        let mut x_coords = 1;
+        for (key, description, is_toggled_on) in hints {
+            let key_element = Text::new(key).color_range(self.theme.key_hint, ..);
+            let ribbon = if is_toggled_on {
+                Text::new(description).selected()
+            } else {
+                Text::new(description)
+            };
+            print_text_with_coordinates(key_element, x_coords, y_coords, None, None);
+            x_coords += key.chars().count() + 1;
+            print_ribbon_with_coordinates(ribbon, x_coords, y_coords, None, None);
+            x_coords += description.chars().count() + 5;
+        }
+        if !self.denied_capabilities.is_empty() {
+            let denied: Vec<&str> = Capability::all().iter()
+                .filter(|c| self.denied_capabilities.contains(c))
+                .map(|c| c.description())
+                .collect();
+            let denied_text = format!("Permission denied, disabled: {}", denied.join(", "));
+            print_text_with_coordinates(Text::new(denied_text).color_range(self.theme.failure, 0..17), 1, y_coords + 1, None, None);
+        }
+        let mut y_coords = if self.denied_capabilities.is_empty() { y_coords + 1 } else { y_coords + 2 };
+        if let Some(edit_error) = &self.edit_error {
+            let edit_error = format!("Edit not applied: {} (e - fix it)", edit_error);
+            let edit_error_len = edit_error.chars().count();
+            let text = Text::new(edit_error)
+                .color_range(self.theme.failure, 0..17)
+                .color_range(self.theme.key_hint, edit_error_len - 11..edit_error_len - 10);
+            print_text_with_coordinates(text, 1, y_coords, Some(cols.saturating_sub(2)), None);
+            y_coords += 1;
+        }
+        let refused_command = self.commands_to_run.iter().enumerate().find_map(|(i, c)| c.refused.map(|reason| (i, reason)));
+        if let Some((command_index, reason)) = refused_command {
+            let refused_text = format!("⚠ Skipped #{}: {} (allow_recursion=true to run it anyway)", command_index + 1, reason);
+            print_text_with_coordinates(Text::new(refused_text).color_range(self.theme.failure, ..), 1, y_coords, Some(cols.saturating_sub(2)), None);
+            y_coords += 1;
+        }
+        if let Some(contention_warning) = self.contention_warning() {
+            let contention_warning = format!("⚠ {}", contention_warning);
+            print_text_with_coordinates(Text::new(contention_warning).color_range(self.theme.pending, ..), 1, y_coords, Some(cols.saturating_sub(2)), None);
+        }
+    }
+    // Commands of a stage all run at once, so a stage larger than the capacity hint (or with
+    // several heavy commands) is likely to grind the machine to a halt. Only stages that haven't
+    // finished yet are considered.
+    fn contention_warning(&self) -> Option<String> {
+        let mut stages: Vec<usize> = self.commands_to_run.iter().map(|c| c.stage).collect();
+        stages.dedup();
+        for stage in stages {
+            let stage_commands: Vec<&Command> = self.commands_to_run.iter()
+                .filter(|c| c.stage == stage && !c.skipped && !c.skipped_by_user)
+                .collect();
+            if stage_commands.iter().all(|c| c.exited) {
+                continue;
+            }
+            let stage_name = match self.stage_names.get(&stage) {
+                Some(stage_name) => format!("Stage \"{}\"", stage_name),
+                None => format!("Stage {}", stage + 1),
+            };
+            if let Some(capacity_hint) = self.capacity_hint.filter(|c| stage_commands.len() > *c) {
+                return Some(format!(
+                    "{} runs {} commands at once, above the capacity hint of {}: consider splitting it into smaller stages",
+                    stage_name,
+                    stage_commands.len(),
+                    capacity_hint
+                ));
+            }
+            let heavy_commands = stage_commands.iter().filter(|c| c.options.heavy).count();
+            if heavy_commands > 1 {
+                return Some(format!(
+                    "{} runs {} heavy commands at once: consider moving some of them to their own stage",
+                    stage_name,
+                    heavy_commands
+                ));
+            }
+        }
+        None
+    }
+    // the decisions of the run are made by the scheduler, from these
+    fn command_statuses(&self) -> Vec<scheduler::CommandStatus> {
+        self.commands_to_run.iter().map(|c| c.status()).collect()
+    }
+    fn run_status(&self) -> scheduler::RunStatus {
+        scheduler::RunStatus {
+            running_command_index: self.running_command_index,
+            paused: self.paused,
+            waiting_for_setup: self.setup_running || self.checking_affected_paths,
+            stop_on_failure: self.stop_on_failure,
+        }
+    }
+    // None if the command at this index is in the first stage
+    fn previous_stage_succeeded(&self, command_index: usize) -> Option<bool> {
+        let launch_order = self.launch_order();
+        let (statuses, _) = self.launch_statuses(&launch_order);
+        let position = launch_order.iter().position(|i| *i == command_index)?;
+        scheduler::previous_stage_succeeded(&statuses, position)
+    }
+    fn running_stage(&self) -> Option<usize> {
+        self.running_command_index.and_then(|i| self.commands_to_run.get(i)).map(|c| c.stage)
+    }
+    fn current_stage_done(&self) -> bool {
+        match self.running_stage() {
+            Some(stage) => scheduler::stage_done(&self.command_statuses(), stage),
+            None => false,
+        }
+    }
+    // The indices of the commands in the order the run launches them, see apply_run_order
+    fn launch_order(&self) -> Vec<usize> {
+        let mut launch_order: Vec<usize> = (0..self.commands_to_run.len()).collect();
+        // commands added since the run started have no position, they come last
+        launch_order.sort_by_key(|i| (self.commands_to_run[*i].launch_position.unwrap_or(usize::MAX), *i));
+        launch_order
+    }
+    // The scheduler sees the commands in the order they're launched in
+    fn launch_statuses(&self, launch_order: &[usize]) -> (Vec<scheduler::CommandStatus>, scheduler::RunStatus) {
+        let statuses = launch_order.iter().map(|i| self.commands_to_run[*i].status()).collect();
+        let mut run_status = self.run_status();
+        run_status.running_command_index = self.running_command_index.and_then(|r| launch_order.iter().position(|i| *i == r));
+        (statuses, run_status)
+    }
+    fn run_next_command(&mut self) {
+        let launch_order = self.launch_order();
+        let (statuses, run_status) = self.launch_statuses(&launch_order);
+        match scheduler::next_action(&statuses, &run_status) {
+            scheduler::Action::Wait => {}
+            scheduler::Action::Halt => {
+                self.record_run_in_history();
+                self.handle_run_failed();
+                self.start_queued_rerun();
+            }
+            scheduler::Action::StartStage(positions) => {
+                // the commands of a stage are next to each other whatever the order, only
+                // commands alone in their stage move
+                let indices = &launch_order[positions];
+                let start = indices.iter().copied().min().unwrap_or(0);
+                self.start_stage(start..start + indices.len());
+            }
+            scheduler::Action::Finish { succeeded } => {
+                self.running_command_index = None;
+                self.record_run_in_history();
+                if succeeded {
+                    self.handle_run_end();
+                } else {
+                    // TODO: CONTINUE HERE - if the user fixed the exited command, we should
+                    // continue the run
+                    self.handle_run_failed();
+                }
+                self.start_queued_rerun();
+            }
+        }
+    }
+    // all the commands of the stage run concurrently
+    fn start_stage(&mut self, stage_commands: Range<usize>) {
+        let next_index = stage_commands.start;
+        let previous_stage_succeeded = self.previous_stage_succeeded(next_index);
+        let folder = PathBuf::from(&self.folder);
+        let launch_settings = self.launch_settings();
+        let captures_output = self.captures_output();
+        let mut launch_inputs: HashMap<usize, LaunchInputs> = stage_commands.clone()
+            .map(|index| (index, self.launch_inputs(index)))
+            .collect();
+        let next_commands = self.commands_to_run.iter_mut()
+            .enumerate()
+            .skip(next_index)
+            .take(stage_commands.len());
+        for (index, next_command) in next_commands {
+            if next_command.exited && next_command.succeeded() {
+                // already, in the interrupted run we resumed
+                continue;
+            }
+            if next_command.skipped_by_user {
+                next_command.skipped = true;
+                self.audit_trail.record(AuditEvent::Skipped(index, "by the user".to_owned()));
+                continue;
+            }
+            if let Some(reason) = self.run_tag_filter.excludes(&next_command.options.tags) {
+                next_command.skipped = true;
+                self.audit_trail.record(AuditEvent::Skipped(index, reason));
+                continue;
+            }
+            if let (false, Some(changed_paths)) = (next_command.options.paths.is_empty(), &self.changed_paths) {
+                if !affected::is_affected(&next_command.options.paths, changed_paths) {
+                    next_command.skipped = true;
+                    self.audit_trail.record(AuditEvent::Skipped(index, format!("none of its paths changed since {}", self.affected_base)));
+                    continue;
+                }
+            }
+            if let Some(when) = &next_command.options.when {
+                if !when.is_met(&folder, previous_stage_succeeded) {
+                    next_command.skipped = true;
+                    self.audit_trail.record(AuditEvent::Skipped(index, format!("{} not met", when)));
+                    continue;
+                }
+            }
+            if !next_command.options.allow_recursion.unwrap_or(self.allow_recursion) {
+                if let Some(reason) = recursion::recursion_reason(&next_command.command_line) {
+                    next_command.skipped = true;
+                    next_command.refused = Some(reason);
+                    self.audit_trail.record(AuditEvent::Skipped(index, reason.to_owned()));
+                    continue;
+                }
+            }
+            if let (true, Some(PaneId::Terminal(pane_id))) = (next_command.kept_pane, next_command.pane_id) {
+                // launched the same way as in the previous run, see restart_run
+                next_command.launch_inputs = launch_inputs.remove(&index);
+                rerun_command_pane(pane_id);
+                next_command.spawned_at = Some(Instant::now());
+                next_command.attempts += 1;
+                self.audit_trail.record(AuditEvent::Spawned(index, next_command.command_line.clone()));
+                continue;
+            }
+            let mut context = BTreeMap::new();
+            context.insert("command_index".to_owned(), index.to_string());
+            context.insert("current_run_index".to_owned(), self.current_run_index.to_string());
+            if captures_output {
+                next_command.captured_output = Some(capture::failure_output_path(index, &next_command.command_line));
+            }
+            if self.log_output {
+                next_command.log_file = Some(capture::log_file_path(index, &next_command.command_line));
+            }
+            next_command.launch_inputs = launch_inputs.remove(&index);
+            if let Some(e) = Self::exec_argv_error(next_command, &launch_settings) {
+                let reason = format!("can't split the command line: {}", e);
+                eprintln!("Command {} failed to spawn: {}", index + 1, reason);
+                next_command.spawn_failure = Some(reason.clone());
+                next_command.exited = true;
+                next_command.end_time = Some(Instant::now());
+                self.audit_trail.record(AuditEvent::SpawnFailed(index, reason));
+                continue;
+            }
+            Self::run_command(next_command, index, context, &launch_settings);
+            next_command.spawned_at = Some(Instant::now());
+            next_command.attempts += 1;
+            self.audit_trail.record(AuditEvent::Spawned(index, next_command.command_line.clone()));
+        }
+        self.running_command_index = Some(stage_commands.end - 1);
+        self.run_completion_hooks();
+        if self.current_stage_done() {
+            // every command of the stage was skipped, failed to spawn (or runs in the
+            // background)
+            self.fire_lifecycle_event(LifecycleEvent::StageEnd);
+            self.run_next_command();
+        }
+    }
+    // Commands are referred to by their name attribute, or by their position starting at 1
+    fn find_command(&self, reference: &str) -> Option<usize> {
+        self.commands_to_run.iter().position(|c| c.options.name.as_deref() == Some(reference))
+            .or_else(|| reference.parse::<usize>().ok().and_then(|position| position.checked_sub(1)))
+            .filter(|i| *i < self.commands_to_run.len())
+    }
+    // The output kept for a capture_output command, relative to the host folder
+    fn output_file_of(&self, reference: &str) -> Option<PathBuf> {
+        let command = self.commands_to_run.get(self.find_command(reference)?)?;
+        command.launch_inputs.as_ref()?.output_file.clone()
+    }
+    // eg. {{output:version}}, {{exit_code:build}}, {{duration:tests}} or {{status:lint}}
+    fn resolve_placeholder(&self, kind: &str, reference: &str) -> Option<String> {
+        if kind == "output" {
+            let output = fs::read_to_string(storage::host_path(self.output_file_of(reference)?)).ok()?;
+            return Some(output.trim_end_matches('\n').to_owned());
+        }
+        let command = self.commands_to_run.get(self.find_command(reference)?)?;
+        match kind {
+            "exit_code" => command.exit_status.map(|e| e.to_string()),
+            "duration" => command.duration().map(|d| format_duration(d, self.duration_precision)),
+            "status" => {
+                let status = if command.skipped {
+                    "skipped"
+                } else if command.succeeded() {
+                    "success"
+                } else if command.exited {
+                    "failure"
+                } else if command.is_running() {
+                    "running"
+                } else {
+                    "pending"
+                };
+                Some(status.to_owned())
+            }
+            _ => None,
+        }
+    }
+    fn launch_inputs(&self, command_index: usize) -> LaunchInputs {
+        let command = &self.commands_to_run[command_index];
+        let run_tmp_dir = run_tmp::run_tmp_dir(self.current_run_index);
+        let stdin_file = command.options.stdin_from.as_ref().and_then(|reference| {
+            let stdin_file = self.output_file_of(reference);
+            if stdin_file.is_none() {
+                eprintln!("stdin_from {}: no such command with capture_output=true", reference);
+            }
+            stdin_file
+        });
+        let command_line = placeholders::interpolate(&command.command_line, |kind, reference| self.resolve_placeholder(kind, reference));
+        // exec mode and scripts have no chains, the parts of a chain are run by the shell. Nor do
+        // remote commands, the results of the parts would be written on the remote host.
+        let is_chain = command.options.script.is_none()
+            && command.options.host.is_none()
+            && !command.options.exec.unwrap_or(self.exec)
+            && chain::is_supported_shell(&self.shell)
+            && chain::split(&command_line).len() > 1;
+        LaunchInputs {
+            chain_results_file: if is_chain { Some(run_tmp_dir.join(format!("chain-{}", command_index))) } else { None },
+            command_line,
+            output_file: if command.options.capture_output { Some(placeholders::output_file(&run_tmp_dir, command_index)) } else { None },
+            stdin_file,
+            script_file: command.options.script.as_ref().map(|_| run_tmp_dir.join(format!("script-{}", command_index))),
+        }
+    }
+    // The command as it is launched, before wrapping it eg. to capture its output
+    fn command_to_run(command: &Command, launch_settings: &LaunchSettings) -> CommandToRun {
+        let script_file = command.launch_inputs.as_ref().and_then(|l| l.script_file.as_ref());
+        let shell = launch_settings.shell_invocation.first().map(|s| s.as_str()).unwrap_or("sh");
+        let script_command_line = script_file.map(|script_file| {
+            let script_file = launch_settings.host_folder.join(script_file).to_string_lossy().replace('\'', "'\\''");
+            format!("{} '{}'", shell, script_file)
+        });
+        let command_line = match (&script_command_line, &command.options.host, &command.options.script) {
+            // the script file is on this machine, its content is run remotely instead
+            (_, Some(_), Some(script)) => script.as_str(),
+            (Some(script_command_line), _, _) => script_command_line.as_str(),
+            _ => command.launch_inputs.as_ref().map(|l| l.command_line.as_str()).unwrap_or(&command.command_line),
+        };
+        // scripts need a shell, as do remote commands
+        let is_exec = command.options.exec.unwrap_or(launch_settings.exec) && script_file.is_none() && command.options.host.is_none();
+        let mut command_to_run = if is_exec {
+            // no shell, so no rc files, aliases or shell syntax. Malformed quoting fails the command
+            // before it's launched (see run_next_command), this is only for showing it.
+            let argv = shell_words::split(command_line)
+                .unwrap_or_else(|_| command_line.split_whitespace().map(|w| w.to_owned()).collect());
+            let (program, args) = argv.split_first().map(|(p, a)| (p.as_str(), a.to_vec())).unwrap_or_default();
+            let mut command_to_run = CommandToRun::new_with_args(program, args);
+            if let Some(python_venv) = &command.options.python_venv {
+                // without a shell to activate it, the venv's executables are the next best thing
+                command_to_run = process::wrap_with_path_prepend(command_to_run, &[format!("{}/bin", python_venv)]);
+            }
+            if command.options.node_version.is_some() {
+                eprintln!("node_version needs a shell, ignoring it for {}", command.command_line);
+            }
+            command_to_run
+        } else {
+            let chain_results_file = command.launch_inputs.as_ref().and_then(|l| l.chain_results_file.as_ref());
+            let mut command_line = match chain_results_file {
+                Some(chain_results_file) => chain::wrap(&chain::split(command_line), &launch_settings.host_folder.join(chain_results_file)),
+                None => command_line.to_owned(),
+            };
+            if let Some(node_version) = &command.options.node_version {
+                match activation::node_version_prefix(shell, node_version) {
+                    Some(prefix) => command_line.insert_str(0, &prefix),
+                    None => eprintln!("Don't know how to switch node versions in {}, ignoring node_version", shell),
+                }
+            }
+            if let Some(python_venv) = &command.options.python_venv {
+                match activation::python_venv_prefix(shell, python_venv) {
+                    Some(prefix) => command_line.insert_str(0, &prefix),
+                    None => eprintln!("Don't know how to activate a venv in {}, ignoring python_venv", shell),
+                }
+            }
+            match &command.options.host {
+                // the env, captures and pid file wrap ssh, they stay on this machine
+                Some(host) => process::ssh_command(host, &launch_settings.folder, &command_line),
+                None => process::shell_command(&launch_settings.shell_invocation, &command_line),
+            }
+        };
+        command_to_run.cwd = Some(PathBuf::from(&launch_settings.folder));
+        command_to_run
+    }
+    // Without a shell, the command line is split by us
+    fn exec_argv_error(command: &Command, launch_settings: &LaunchSettings) -> Option<String> {
+        if !command.options.exec.unwrap_or(launch_settings.exec) || command.options.script.is_some() || command.options.host.is_some() {
+            return None;
+        }
+        let command_line = command.launch_inputs.as_ref().map(|l| l.command_line.as_str()).unwrap_or(&command.command_line);
+        shell_words::split(command_line).err()
+    }
+    fn run_command(command: &Command, command_index: usize, context: BTreeMap<String, String>, launch_settings: &LaunchSettings) {
+        if command.options.before.is_some() && !command.before_hook_passed {
+            // the command itself is run once the hook succeeds, see handle_command_hook_exited
+            Self::run_command_hook(command, command_index, "before", context, launch_settings);
+            return;
+        }
+        let host_folder = &launch_settings.host_folder;
+        if let (Some(script), Some(script_file)) = (&command.options.script, command.launch_inputs.as_ref().and_then(|l| l.script_file.as_ref())) {
+            if let Err(e) = storage::write_atomic(&storage::host_path(script_file), script) {
+                eprintln!("Failed to write the script of {}: {}", command.command_line, e);
+            }
+        }
+        let mut command_to_run = Self::command_to_run(command, launch_settings);
+        if let Some(stdin_file) = command.launch_inputs.as_ref().and_then(|l| l.stdin_file.as_ref()) {
+            command_to_run = capture::wrap_with_stdin(command_to_run, &host_folder.join(stdin_file));
+        }
+        if let Some(output_file) = command.launch_inputs.as_ref().and_then(|l| l.output_file.as_ref()) {
+            command_to_run = capture::wrap_with_stdout_capture(command_to_run, &host_folder.join(output_file));
+        }
+        command_to_run = run_tmp::wrap_with_run_tmp_dir(command_to_run, &host_folder.join(run_tmp::current_run_tmp_dir_file()));
+        if !command.options.path_prepend.is_empty() {
+            command_to_run = process::wrap_with_path_prepend(command_to_run, &command.options.path_prepend);
+        }
+        if let Some(captured_output) = &command.captured_output {
+            command_to_run = capture::wrap_with_capture(command_to_run, &host_folder.join(captured_output));
+        }
+        if let Some(log_file) = &command.log_file {
+            command_to_run = capture::wrap_with_capture(command_to_run, &host_folder.join(log_file));
+        }
+        command_to_run = env_file::wrap_with_env(command_to_run, &launch_settings.env_variables);
+        command_to_run = process::wrap_with_pid_file(command_to_run, &host_folder.join(process::pid_file_path(command_index)));
+        let is_quiet = command.is_quiet(launch_settings.quiet, launch_settings.fully_quiet);
+        if is_quiet || launch_settings.open_in_new_tab || launch_settings.stack_panes {
+            // panes for the dedicated tab are moved there once they open, stacked panes are
+            // shown one at a time once they open
+            open_command_pane_background(command_to_run, context);
+        } else {
+            open_command_pane_floating(command_to_run, None , context);
+        }
+    }
+    // The before and after hooks of a command (eg. starting and stopping a test database) run in
+    // hidden panes, in the folder and with the environment of the command
+    fn run_command_hook(command: &Command, command_index: usize, phase: &'static str, mut context: BTreeMap<String, String>, launch_settings: &LaunchSettings) {
+        let hook = match phase {
+            "before" => command.options.before.as_ref(),
+            _ => command.options.after.as_ref(),
+        };
+        let hook = match hook {
+            Some(hook) => hook,
+            None => return,
+        };
+        let mut command_to_run = process::shell_command(&launch_settings.shell_invocation, hook);
+        command_to_run.cwd = Some(PathBuf::from(&launch_settings.folder));
+        command_to_run = run_tmp::wrap_with_run_tmp_dir(command_to_run, &launch_settings.host_folder.join(run_tmp::current_run_tmp_dir_file()));
+        if !command.options.path_prepend.is_empty() {
+            command_to_run = process::wrap_with_path_prepend(command_to_run, &command.options.path_prepend);
+        }
+        command_to_run = env_file::wrap_with_env(command_to_run, &launch_settings.env_variables);
+        eprintln!("Running the {} hook of command {}: {}", phase, command_index + 1, hook);
+        context.insert(COMMAND_HOOK_CONTEXT.to_owned(), phase.to_owned());
+        open_command_pane_background(command_to_run, context);
+    }
+    // Runs the command again in the pane it ran in, with its hooks around it as the first time
+    fn rerun_command_in_pane(command: &mut Command, command_index: usize, pane_id: u32, current_run_index: usize, launch_settings: &LaunchSettings) {
+        command.before_hook_passed = false;
+        command.after_hook_done = false;
+        if command.options.before.is_some() {
+            // the pane re-runs the command once the hook succeeds, see handle_command_hook_exited
+            let mut context = BTreeMap::new();
+            context.insert("command_index".to_owned(), command_index.to_string());
+            context.insert("current_run_index".to_owned(), current_run_index.to_string());
+            context.insert(RERUN_PANE_CONTEXT.to_owned(), pane_id.to_string());
+            Self::run_command_hook(command, command_index, "before", context, launch_settings);
+            return;
+        }
+        rerun_command_pane(pane_id);
+    }
+    fn render_title(&self, rows: usize, cols: usize) -> Text {
+        let successful_commands = self.successful_command_count();
+        let successful_commands_indication = format!("{}", successful_commands);
+        let failed_commands = self.failed_command_count();
+        let failed_commands_indication = format!("{}", failed_commands);
+        let pending_commands = self.pending_command_count();
+        let pending_commands_indication = format!("{}", pending_commands);
+        // advisory failures (allow_failure) are counted on their own
+        let allowed_failures = match self.allowed_failure_count() {
+            0 => String::new(),
+            1 => " (1 allowed failure)".to_owned(),
+            allowed_failure_count => format!(" ({} allowed failures)", allowed_failure_count),
+        };
+        if let Some(running_command_index) = self.running_command_index.as_ref() {
+            let total_commands = successful_commands + failed_commands + pending_commands + self.allowed_failure_count();
+            let estimate = self.estimated_time_left().map(|left| format!(" ~{} left", format_estimate(left))).unwrap_or_default();
+            let title = format!("Running {}/{} commands (Success: {}, Failure: {}, Pending: {}){}{}", running_command_index + 1, total_commands, successful_commands_indication, failed_commands_indication, pending_commands_indication, allowed_failures, estimate);
+            let allowed_failures_start = title.chars().count() - allowed_failures.chars().count() - estimate.chars().count();
+            Text::new(title)
+                .color_range(self.theme.running, 0..20)
+                .color_range(self.theme.success, 31..31 + successful_commands_indication.chars().count())
+                .color_range(self.theme.failure, 42 + successful_commands_indication.chars().count()..42 + failed_commands_indication.chars().count() + 1)
+                .color_range(self.theme.pending, 54 + failed_commands_indication.chars().count()..54 + pending_commands_indication.chars().count() + 1)
+                .color_range(self.theme.pending, allowed_failures_start..)
+        } else if self.all_commands_exited() {
+            let title = format!("Done running commands. (Success: {}, Failure: {}, Pending: {}){}", successful_commands_indication, failed_commands_indication, pending_commands_indication, allowed_failures);
+            let allowed_failures_start = title.chars().count() - allowed_failures.chars().count();
+            Text::new(title)
+                .color_range(self.theme.running, 0..22)
+                .color_range(self.theme.success, 33..33 + successful_commands_indication.chars().count())
+                .color_range(self.theme.failure, 44 + successful_commands_indication.chars().count()..44 + failed_commands_indication.chars().count() + 1)
+                .color_range(self.theme.pending, 56 + failed_commands_indication.chars().count()..56 + pending_commands_indication.chars().count() + 1)
+                .color_range(self.theme.pending, allowed_failures_start..)
+        } else {
+            // let title = format!("Waiting to run commands... (Success: {}, Failure: {}, Pending: {})", successful_commands_indication, failed_commands_indication, pending_commands_indication);
+            let title = format!("Running commands. (Success: {}, Failure: {}, Pending: {}){}", successful_commands_indication, failed_commands_indication, pending_commands_indication, allowed_failures);
+            let allowed_failures_start = title.chars().count() - allowed_failures.chars().count();
+            Text::new(title)
+                .color_range(self.theme.running, 0..17)
+                .color_range(self.theme.success, 27 + successful_commands_indication.chars().count()..27 + successful_commands_indication.chars().count() + 1)
+                .color_range(self.theme.failure, 39 + successful_commands_indication.chars().count()..40 + failed_commands_indication.chars().count())
+                .color_range(self.theme.pending, 51 + failed_commands_indication.chars().count()..51 + pending_commands_indication.chars().count() + 1)
+                .color_range(self.theme.pending, allowed_failures_start..)
+        }
+    }
+    fn render_progress_bar(&self, cols: usize) -> Text {
+        let total = self.runnable_command_count();
+        let successful = self.successful_command_count();
+        let failed = self.failed_command_count();
+        let counts = format!(" {}/{}", successful + failed, total);
+        let width = cols.saturating_sub(counts.chars().count() + 4).min(PROGRESS_BAR_MAX_WIDTH);
+        let successful_width = (successful * width).checked_div(total).unwrap_or(0);
+        let failed_width = (failed * width).checked_div(total).unwrap_or(0);
+        let pending_width = width.saturating_sub(successful_width + failed_width);
+        let failed_cell = if self.accessible { "╳" } else { "█" };
+        let bar = format!(
+            "[{}{}{}]{}",
+            "█".repeat(successful_width),
+            failed_cell.repeat(failed_width),
+            "░".repeat(pending_width),
+            counts
+        );
+        Text::new(bar)
+            .color_range(self.theme.success, 1..1 + successful_width)
+            .color_range(self.theme.failure, 1 + successful_width..1 + successful_width + failed_width)
+    }
+    // Shows which commands occupied which concurrency slot over time, only once commands ran
+    // concurrently
+    fn render_lanes(&self, rows: usize, cols: usize) {
+        let commands: Vec<(usize, Instant, Option<Instant>)> = self.commands_to_run.iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.start_time.map(|start_time| (i, start_time, c.end_time)))
+            .collect();
+        let lanes = lanes::assign_lanes(&commands, Instant::now());
+        if lanes.len() < 2 {
+            return;
+        }
+        let y_coords = 10 + self.command_list_len();
+        if y_coords >= rows {
+            return;
+        }
+        print_text_with_coordinates(Text::new("Lanes").color_range(self.theme.running, ..), 1, y_coords, None, None);
+        let run_duration = lanes.iter().flatten().map(|s| s.end).max().unwrap_or_default();
+        let width = cols.saturating_sub(2).min(LANES_MAX_WIDTH);
+        for (lane_index, lane) in lanes.iter().enumerate() {
+            let lane_y_coords = y_coords + 1 + lane_index;
+            if lane_y_coords >= rows || width == 0 {
+                break;
+            }
+            let mut bar = String::new();
+            let mut cell_colors = vec![];
+            for cell in 0..width {
+                let at = run_duration.mul_f64((cell as f64 + 0.5) / width as f64);
+                match lanes::segment_at(lane, at) {
+                    Some(segment) => {
+                        let failed = self.commands_to_run.get(segment.command_index).map(|c| c.exited && !c.succeeded()).unwrap_or(false);
+                        if self.accessible && failed {
+                            bar.push('╳');
+                        } else {
+                            // alternate so that neighbouring commands can be told apart
+                            bar.push(if segment.command_index % 2 == 0 { '█' } else { '▓' });
+                        }
+                        cell_colors.push((cell, self.command_status_color(segment.command_index)));
+                    }
+                    None => bar.push('·'),
+                }
+            }
+            // the longest command of the lane is the one most likely to hold the others up
+            let label = lane.iter()
+                .max_by_key(|s| s.end.saturating_sub(s.start))
+                .and_then(|s| self.commands_to_run.get(s.command_index).map(|c| (c, s.end.saturating_sub(s.start))))
+                .map(|(command, duration)| format!(" {} ({})", command.command_line, format_duration(duration, self.duration_precision)))
+                .unwrap_or_default();
+            let mut text = Text::new(format!("{}{}", bar, label));
+            for (cell, color) in cell_colors {
+                text = text.color_range(color, cell..cell + 1);
+            }
+            print_text_with_coordinates(text, 1, lane_y_coords, Some(cols.saturating_sub(1)), None);
+        }
+    }
+    fn command_status_color(&self, command_index: usize) -> usize {
+        match self.commands_to_run.get(command_index) {
+            Some(command) if command.is_running() => self.theme.running,
+            Some(command) if command.succeeded() => self.theme.success,
+            Some(command) if command.is_allowed_failure() => self.theme.pending,
+            _ => self.theme.failure,
+        }
+    }
+    fn render_minimal(&self, rows: usize, cols: usize) {
+        let total = self.runnable_command_count();
+        let successful = self.successful_command_count();
+        let failed = self.failed_command_count();
+        let pending = self.pending_command_count();
+        let done = successful + failed;
+        let circle_frame = (done * (PROGRESS_CIRCLE_FRAMES.len() - 1)).checked_div(total).unwrap_or(0);
+        let ribbon_text = format!("{} {}/{}", PROGRESS_CIRCLE_FRAMES[circle_frame], done, total);
+        let ribbon_len = ribbon_text.chars().count();
+        print_ribbon_with_coordinates(Text::new(ribbon_text), 0, 0, None, None);
+        let successful_text = successful.to_string();
+        let failed_text = failed.to_string();
+        let counts = format!("✔ {} ✘ {} … {}", successful_text, failed_text, pending);
+        let successful_end = 2 + successful_text.chars().count();
+        let failed_end = successful_end + 3 + failed_text.chars().count();
+        let counts = Text::new(counts)
+            .color_range(self.theme.success, 0..successful_end)
+            .color_range(self.theme.failure, successful_end + 1..failed_end)
+            .color_range(self.theme.pending, failed_end + 1..);
+        print_text_with_coordinates(counts, ribbon_len + 5, 0, Some(cols.saturating_sub(ribbon_len + 5)), None);
+        if rows > 1 {
+            if let Some(running_command) = self.running_command_index.and_then(|i| self.commands_to_run.get(i)) {
+                let spinner = SPINNER_FRAMES[self.spinner_frame % SPINNER_FRAMES.len()];
+                let running_text = format!("{} {}", spinner, running_command.command_line);
+                print_text_with_coordinates(Text::new(running_text).color_range(self.theme.running, 0..1), 1, 1, Some(cols.saturating_sub(1)), None);
+            }
+        }
+    }
+    // The selected command, or the one running (the first of them when a stage runs at once)
+    fn live_tail_command(&self) -> Option<&Command> {
+        self.selected_index
+            .or_else(|| self.commands_to_run.iter().position(|c| c.is_running()))
+            .and_then(|i| self.commands_to_run.get(i))
+    }
+    // Read from the command's capture (or log), which is written as it runs
+    fn refresh_live_tail(&mut self) {
+        if !self.live_tail {
+            return;
+        }
+        let output_file = self.live_tail_command().and_then(|c| c.captured_output.as_ref().or(c.log_file.as_ref()));
+        self.live_tail_lines = match output_file {
+            Some(output_file) => match capture::read_end(&storage::host_path(output_file), LIVE_TAIL_MAX_BYTES) {
+                Ok(output) => collapse::tail(&output, LIVE_TAIL_MAX_LINES),
+                Err(_) => vec![], // not written yet
+            },
+            None => vec![],
+        };
+    }
+    fn render_live_tail(&self, y_coords: usize, rows: usize, cols: usize) {
+        let command = match self.live_tail_command() {
+            Some(command) => command,
+            None => {
+                print_text_with_coordinates(Text::new("Output: nothing running or selected").color_range(self.theme.pending, ..), 1, y_coords, Some(cols.saturating_sub(2)), None);
+                return;
+            }
+        };
+        let title = format!("Output: {}", command.command_line);
+        print_text_with_coordinates(Text::new(title).color_range(self.theme.running, 0..7), 1, y_coords, Some(cols.saturating_sub(2)), None);
+        if self.live_tail_lines.is_empty() {
+            let text = if command.start_time.is_some() { "(no output kept)" } else { "(not started yet)" };
+            print_text_with_coordinates(Text::new(text).color_range(self.theme.pending, ..), 1, y_coords + 1, Some(cols.saturating_sub(2)), None);
+            return;
+        }
+        let line_count = rows.saturating_sub(1);
+        let lines = &self.live_tail_lines[self.live_tail_lines.len().saturating_sub(line_count)..];
+        for (i, line) in lines.iter().enumerate() {
+            print_text_with_coordinates(Text::new(line), 1, y_coords + 1 + i, Some(cols.saturating_sub(2)), None);
+        }
+    }
+    // One line per command in the order they run, its status as a prefix, without colors or
+    // glyphs: for screen readers, and to be copy-pasted as it is
+    fn render_plain(&self, rows: usize, cols: usize) {
+        let summary = format!(
+            "{} OK, {} FAILED, {} PENDING{}",
+            self.successful_command_count(),
+            self.failed_command_count(),
+            self.pending_command_count(),
+            if self.paused { ", PAUSED" } else { "" },
+        );
+        print_text_with_coordinates(Text::new(summary), 0, 0, Some(cols), None);
+        let mut lines = vec![];
+        let mut previous_stage = None;
+        for (i, command) in self.commands_to_run.iter().enumerate() {
+            if let (true, Some(stage_name)) = (previous_stage != Some(command.stage), self.stage_names.get(&command.stage)) {
+                lines.push((None, format!("Stage: {}", stage_name)));
+            }
+            previous_stage = Some(command.stage);
+            let selected = if Some(i) == self.selected_index { "> " } else { "" };
+            let marked = if self.marked.contains(&i) { "* " } else { "" };
+            lines.push((Some(i), format!("{}{}{} {}", selected, marked, command.plain_status(self.duration_precision), command.command_line)));
+            if let (true, Some(action_menu)) = (Some(i) == self.selected_index, &self.action_menu) {
+                for (j, action) in action_menu.actions.iter().enumerate() {
+                    let chosen = if j == action_menu.selected { "> " } else { "  " };
+                    lines.push((None, format!("  {}{}", chosen, action.label())));
+                }
+                lines.push((None, "  UP/DOWN - choose, ENTER - run, ESC - close".to_owned()));
+            }
+        }
+        // the summary above and the help below
+        let available_rows = rows.saturating_sub(3).max(1);
+        let selected_line = lines.iter().position(|(i, _)| i.is_some() && *i == self.selected_index).unwrap_or(0);
+        // the action menu under it stays in view too
+        let selected_line = selected_line + self.action_menu.as_ref().map(|m| m.actions.len() + 1).unwrap_or(0);
+        let first_line = (selected_line + 1).saturating_sub(available_rows);
+        for (y, (_, line)) in lines.iter().skip(first_line).take(available_rows).enumerate() {
+            print_text_with_coordinates(Text::new(line), 0, y + 2, Some(cols), None);
+        }
+        print_text_with_coordinates(Text::new(self.plain_help()), 0, rows.saturating_sub(1), Some(cols), None);
+    }
+    // What render_help shows, as text
+    fn plain_help(&self) -> String {
+        if self.observer {
+            "Read-only observer".to_owned()
+        } else if self.restart_prompt {
+            "Restart run? The running commands will be killed (y/n)".to_owned()
+        } else if self.resume_prompt {
+            format!("Resume the interrupted run? The {} commands that succeeded won't run again (y - resume, n - start over)", self.successful_command_count())
+        } else if self.marking {
+            format!("{} marked: UP/DOWN/SPACE - mark, r - re-run, Ctrl c - kill, s - skip, DEL - delete, ESC - cancel", self.marked.len())
+        } else if let Some(quick_add_input) = &self.quick_add_input {
+            format!("Add command: {}_ (ENTER - add, ESC - cancel)", quick_add_input)
+        } else {
+            "ENTER - actions, ? - keybindings".to_owned()
+        }
+    }
+    // From the usual durations of the commands that aren't done yet, the stages one after the
+    // other and the commands of a stage at once. Those that never ran before are left out, None if
+    // none of them did.
+    fn estimated_time_left(&self) -> Option<Duration> {
+        let mut time_left = Duration::ZERO;
+        let mut stage_time_left: Option<(usize, Duration)> = None;
+        for command in &self.commands_to_run {
+            if command.is_done() {
+                continue;
+            }
+            let expected = match history::expected_duration(&self.history, &command.command_line) {
+                Some(expected) => expected,
+                None => continue,
+            };
+            let command_time_left = expected.saturating_sub(command.duration().unwrap_or_default());
+            stage_time_left = match stage_time_left {
+                Some((stage, stage_time_left)) if stage == command.stage => Some((stage, stage_time_left.max(command_time_left))),
+                Some((_, previous_stage_time_left)) => {
+                    time_left += previous_stage_time_left;
+                    Some((command.stage, command_time_left))
+                }
+                None => Some((command.stage, command_time_left)),
+            };
+        }
+        let (_, last_stage_time_left) = stage_time_left?;
+        Some(time_left + last_stage_time_left)
+    }
+    fn all_commands_exited(&self) -> bool {
+        self.commands_to_run.iter().all(|c| c.exited || c.pane_closed_by_user || c.skipped || c.aborted || c.options.background)
+    }
+    fn all_commands_exited_successfully(&self) -> bool {
+        scheduler::run_succeeded(&self.command_statuses())
+    }
+    fn successful_command_count(&self) -> usize {
+        self.commands_to_run.iter().filter(|c| c.succeeded()).count()
+    }
+    fn failed_command_count(&self) -> usize {
+        self.commands_to_run.iter().filter(|c| c.exited && !c.succeeded() && !c.options.allow_failure).count()
+    }
+    fn allowed_failure_count(&self) -> usize {
+        self.commands_to_run.iter().filter(|c| c.is_allowed_failure()).count()
+    }
+    fn pending_command_count(&self) -> usize {
+        self.commands_to_run.iter().filter(|c| !c.exited && !c.skipped && !c.aborted && !c.options.background).count()
+    }
+    // skipped commands are left out of the progress
+    fn runnable_command_count(&self) -> usize {
+        self.commands_to_run.iter().filter(|c| !c.skipped).count()
+    }
+    fn render_stage_header(&self, stage: usize, stage_name: &str) -> NestedListItem {
+        let stage_commands: Vec<&Command> = self.commands_to_run.iter().filter(|c| c.stage == stage).collect();
+        let done = stage_commands.iter().filter(|c| c.exited).count();
+        let header = format!("{} ({}/{})", stage_name, done, stage_commands.len());
+        if stage_commands.iter().any(|c| c.is_running()) {
+            let header = if self.accessible { format!("▶ {}", header) } else { header };
+            NestedListItem::new(header).color_range(self.theme.running, ..)
+        } else {
+            NestedListItem::new(header)
+        }
+    }
+    // the number of lines the command list takes when no command is selected, including the page
+    // summary when the list is paginated
+    fn command_list_len(&self) -> usize {
+        let page_summary_len = if self.page_size.is_some() { 1 } else { 0 };
+        self.command_lines_in(self.visible_commands()) + page_summary_len + self.one_off_lines()
+    }
+    // the one-off section, listed on every page
+    fn one_off_lines(&self) -> usize {
+        if self.one_offs.is_empty() {
+            0
+        } else {
+            self.one_offs.len() + 1
+        }
+    }
+    // the lines of the commands at these positions of the render order
+    fn command_lines_in(&self, commands: Range<usize>) -> usize {
+        let render_order = self.render_order();
+        let commands: Vec<&Command> = render_order[commands.start.min(render_order.len())..commands.end.min(render_order.len())]
+            .iter()
+            .map(|i| &self.commands_to_run[*i])
+            .collect();
+        let hidden_line_len = if commands.iter().any(|c| self.is_hidden(c)) { 1 } else { 0 };
+        let sort_line_len = if self.sort_mode != SortMode::Definition { 1 } else { 0 };
+        let tags_line_len = if self.tag_filter.is_empty() { 0 } else { 1 };
+        let commands: Vec<&Command> = commands.into_iter().filter(|c| !self.is_hidden(c)).collect();
+        let mut stages: Vec<usize> = commands.iter()
+            .map(|c| c.stage)
+            .filter(|s| self.stage_names.contains_key(s) && self.sort_mode == SortMode::Definition)
+            .collect();
+        stages.dedup();
+        let output_tail_len: usize = commands.iter().map(|c| c.output_tail.len() + c.hint.iter().count()).sum();
+        // those of the selected command only are left out like its other details
+        let description_len = if self.show_descriptions == DescriptionMode::Always {
+            commands.iter().filter(|c| c.options.desc.is_some()).count()
+        } else {
+            0
+        };
+        let services_header_len = if commands.iter().any(|c| c.options.background) { 1 } else { 0 };
+        commands.len() + stages.len() + output_tail_len + description_len + services_header_len + hidden_line_len + sort_line_len + tags_line_len
+    }
+    // The indices of the commands in the order they're listed in
+    fn render_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.commands_to_run.len()).collect();
+        match self.sort_mode {
+            SortMode::Definition => {}
+            SortMode::FailuresFirst => {
+                order.sort_by_key(|i| {
+                    let command = &self.commands_to_run[*i];
+                    std::cmp::Reverse(command.exited && !command.succeeded())
+                });
+            }
+            SortMode::SlowestFirst => {
+                // running commands by how long they've been running for
+                order.sort_by_key(|i| {
+                    let command = &self.commands_to_run[*i];
+                    std::cmp::Reverse(command.start_time.map(|start_time| command.end_time.unwrap_or_else(Instant::now).duration_since(start_time)))
+                });
+            }
+        }
+        order
+    }
+    fn render_position(&self, command_index: usize) -> Option<usize> {
+        self.render_order().iter().position(|i| *i == command_index)
+    }
+    fn is_hidden(&self, command: &Command) -> bool {
+        self.hide_succeeded && command.exit_status.is_some() && command.succeeded()
+    }
+    fn toggle_hide_succeeded(&mut self) {
+        self.hide_succeeded = !self.hide_succeeded;
+        if self.selected_index.and_then(|i| self.commands_to_run.get(i)).map(|c| self.is_hidden(c)).unwrap_or(false) {
+            self.selected_index = None;
+        }
+    }
+    // Resizes are done in steps, one per render until the command list fits. A step that
+    // overshoots (or that the pane can't take) ends the resizing until the list changes.
+    fn fit_pane_to_commands(&mut self, rows: usize) {
+        let wanted_rows = self.command_lines_in(0..self.commands_to_run.len()) + PAGINATION_RESERVED_ROWS;
+        let resize = if rows < wanted_rows {
+            Resize::Increase
+        } else if rows > wanted_rows + AUTO_RESIZE_SLACK {
+            Resize::Decrease
+        } else {
+            self.last_auto_resize = None;
+            return;
+        };
+        if let Some((last_resize, last_wanted_rows, last_rows)) = self.last_auto_resize {
+            let overshot = last_resize != resize;
+            let had_no_effect = last_rows == rows;
+            if last_wanted_rows == wanted_rows && (overshot || had_no_effect) {
+                return;
+            }
+        }
+        self.last_auto_resize = Some((resize, wanted_rows, rows));
+        resize_pane_with_id(ResizeStrategy::new(resize, None), PaneId::Plugin(get_plugin_ids().plugin_id));
+    }
+    // commands are paged rather than overflowing the pane when they don't all fit
+    fn update_pagination(&mut self, rows: usize) {
+        let command_count = self.commands_to_run.len();
+        let reserved_rows = PAGINATION_RESERVED_ROWS + self.one_off_lines();
+        if self.command_lines_in(0..command_count) + reserved_rows <= rows {
+            self.page_size = None;
+            self.page = 0;
+            return;
+        }
+        let page_size = rows.saturating_sub(reserved_rows).max(1);
+        self.page_size = Some(page_size);
+        if let Some(position) = self.selected_index.and_then(|i| self.render_position(i)) {
+            self.page = position / page_size;
+        }
+        self.page = self.page.min(self.page_count().saturating_sub(1));
+    }
+    fn page_count(&self) -> usize {
+        match self.page_size {
+            Some(page_size) => self.commands_to_run.len().div_ceil(page_size),
+            None => 1,
+        }
+    }
+    fn visible_commands(&self) -> Range<usize> {
+        match self.page_size {
+            Some(page_size) => {
+                let start = self.page * page_size;
+                start..(start + page_size).min(self.commands_to_run.len())
+            }
+            None => 0..self.commands_to_run.len(),
+        }
+    }
+    fn move_page(&mut self, offset: isize) -> bool {
+        let page_size = match self.page_size {
+            Some(page_size) => page_size,
+            None => return false,
+        };
+        let page = self.page.saturating_add_signed(offset).min(self.page_count().saturating_sub(1));
+        if page == self.page {
+            return false;
+        }
+        self.page = page;
+        if self.selected_index.is_some() {
+            // the selection follows the page, otherwise the page would follow the selection
+            self.selected_index = self.render_order().get(page * page_size).copied();
+        }
+        true
+    }
+    // eg. "Page 2/5 (11-20): 3 ✔ 1 ✘ 6 …", for the commands of the current page
+    fn render_page_summary(&self) -> Option<Text> {
+        self.page_size?;
+        let visible_commands = self.visible_commands();
+        let render_order = self.render_order();
+        let commands: Vec<&Command> = render_order[visible_commands.clone()].iter().map(|i| &self.commands_to_run[*i]).collect();
+        let succeeded = commands.iter().filter(|c| c.exited && c.succeeded()).count().to_string();
+        let failed = commands.iter().filter(|c| c.exited && !c.succeeded()).count().to_string();
+        let pending = commands.iter().filter(|c| !c.exited && !c.skipped).count().to_string();
+        let prefix = format!("Page {}/{} ({}-{}): ", self.page + 1, self.page_count(), visible_commands.start + 1, visible_commands.end);
+        let succeeded_start = prefix.chars().count();
+        let failed_start = succeeded_start + succeeded.chars().count() + 3;
+        let pending_start = failed_start + failed.chars().count() + 3;
+        let text = format!("{}{} ✔ {} ✘ {} … (PgUp/PgDn)", prefix, succeeded, failed, pending);
+        let hint_start = pending_start + pending.chars().count() + 3;
+        Some(
+            Text::new(text)
+                .color_range(self.theme.running, 0..4)
+                .color_range(self.theme.success, succeeded_start..failed_start - 1)
+                .color_range(self.theme.failure, failed_start..pending_start - 1)
+                .color_range(self.theme.pending, pending_start..hint_start - 1)
+                .color_range(self.theme.key_hint, hint_start..)
+        )
+    }
+    // eg. ▶ cargo check (Running for 3s), ✔ cargo check (3s) or ✘ cargo check [EXIT CODE: 101]
+    fn render_one_off(&self, one_off: &OneOff) -> NestedListItem {
+        let duration = one_off.duration().map(|d| format_duration(d, self.duration_precision)).unwrap_or_default();
+        let (icon, status, color) = match one_off.exit_status {
+            _ if one_off.is_running() => (&self.status_icons.running, format!("(Running for {})", duration), self.theme.running),
+            Some(0) => (&self.status_icons.success, format!("({})", duration), self.theme.success),
+            Some(exit_status) => (&self.status_icons.failure, format!("[EXIT CODE: {}]", exit_status), self.theme.failure),
+            None if one_off.end_time.is_some() => (&self.status_icons.failure, "(Stopped)".to_owned(), self.theme.failure),
+            None => (&self.status_icons.pending, "(Starting)".to_owned(), self.theme.pending),
+        };
+        let icon_len = icon.chars().count();
+        let command_len = one_off.command_line.chars().count();
+        NestedListItem::new(format!("{} {} {}", icon, one_off.command_line, status))
+            .indent(1)
+            .color_range(color, 0..icon_len)
+            .color_range(self.theme.command, icon_len + 1..icon_len + 1 + command_len)
+            .color_range(color, icon_len + 2 + command_len..)
+    }
+    fn render_command(&self, command: &Command, is_running: bool, is_selected: bool, is_marked: bool, indent: usize) -> Vec<NestedListItem> {
+        let command_color = if is_selected || is_marked { self.theme.selected } else { self.theme.command };
+        let mut command_text = if command.options.locked {
+            format!("🔒 {}", command.command_line)
+        } else {
+            command.command_line.clone()
+        };
+        if let Some(host) = &command.options.host {
+            command_text = format!("[{}] {}", host, command_text);
+        }
+        if is_marked {
+            command_text = format!("◆ {}", command_text);
+        }
+        if self.list_status_icons {
+            command_text = format!("{} {}", command.status_icon(&self.status_icons), command_text);
+        } else if let (true, Some(exit_status)) = (self.accessible, command.exit_status) {
+            // the exit code alone doesn't tell, eg. with success_codes
+            let mark = if command.is_success_code(exit_status) { "✔" } else { "✘" };
+            command_text = format!("{} {}", mark, command_text);
+        }
+        let item_title = if is_running && self.suspended {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("⏸ {} (Suspended)", &command_text))
+                .color_range(self.theme.pending, 0..1)
+                .color_range(command_color, 2..2 + command_len + 1)
+                .color_range(self.theme.pending, 2 + command_len + 1..)
+        } else if is_running && command.options.background {
+            let command_len = command_text.chars().count();
+            let (icon, state, color) = if command.is_ready() { ("●", "Up", self.theme.running) } else { ("◌", "Starting", self.theme.pending) };
+            NestedListItem::new(format!("{} {} ({} for {})", icon, &command_text, state, format_duration(command.start_time.unwrap_or_else(Instant::now).elapsed(), self.duration_precision)))
+                .color_range(color, 0..1)
+                .color_range(command_color, 2..2 + command_len + 1)
+                .color_range(self.theme.running, 2 + command_len + 1..)
+        } else if is_running && command.options.interactive {
+            // the run goes on once the user is done with it and its pane exits
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("⌨ {} (Waiting for user input)", &command_text))
+                .color_range(self.theme.pending, 0..1)
+                .color_range(command_color, 2..2 + command_len + 1)
+                .color_range(self.theme.pending, 2 + command_len + 1..)
+        } else if is_running {
+            let spinner = SPINNER_FRAMES[self.spinner_frame % SPINNER_FRAMES.len()];
+            let command_len = command_text.chars().count();
+            let attempt = if command.attempts > 1 { format!(", attempt {}", command.attempts) } else { String::new() };
+            // probably hanging, so it stands out from the other running commands
+            let (usually, running_color) = match self.slow_command_expectation(command) {
+                Some(expected) => (format!(", usually {}", format_duration(expected, 0)), self.theme.warning),
+                None => (String::new(), self.theme.running),
+            };
+            NestedListItem::new(format!("{} {} (Running for {}{}{})", spinner, &command_text, format_duration(command.start_time.unwrap_or_else(|| Instant::now()).elapsed(), self.duration_precision), usually, attempt))
+                .color_range(running_color, 0..1)
+                .color_range(command_color, 2..2 + command_len + 1)
+                .color_range(running_color, 2 + command_len + 1..)
+        } else if let Some(next_attempt) = command.next_attempt {
+            let command_len = command_text.chars().count();
+            let next_attempt_in = next_attempt.saturating_duration_since(Instant::now());
+            NestedListItem::new(format!("⟳ {} (Attempt {} failed, next in {})", &command_text, command.attempts, format_duration(next_attempt_in, 0)))
+                .color_range(self.theme.pending, 0..1)
+                .color_range(command_color, 2..2 + command_len + 1)
+                .color_range(self.theme.pending, 2 + command_len + 1..)
+        } else if let Some(exit_status) = command.exit_status {
+            let exit_status_color = if command.is_success_code(exit_status) {
+                self.theme.success
+            } else if command.options.allow_failure {
+                self.theme.pending
+            } else {
+                self.theme.failure
+            };
+            let command_len = command_text.chars().count();
+            let exit_status_len = exit_status.to_string().chars().count();
+            let over_budget = if command.is_over_budget() { " [OVER BUDGET]" } else { "" };
+            let attempt = if command.attempts > 1 { format!(" (attempt {})", command.attempts) } else { String::new() };
+            // passed in the previous run, so this is probably what the change broke
+            let mut regression = if self.is_regression(command) { " [NEW FAILURE]" } else { "" }.to_owned();
+            if let Some(hook) = command.hook_failure {
+                regression.push_str(&format!(" [{} HOOK FAILED]", hook.to_uppercase()));
+            }
+            let regression_len = regression.chars().count();
+            let fallback = match self.fallback_that_ran(command) {
+                Some(fallback) => format!(" [FALLBACK: {}]", fallback),
+                None => String::new(),
+            };
+            NestedListItem::new(format!("{} [EXIT CODE: {}]{}{}{}{}", command_text, exit_status, regression, fallback, over_budget, attempt))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(exit_status_color, command_len + 13..command_len + 13 + exit_status_len)
+                .color_range(self.theme.failure, command_len + 15 + exit_status_len..command_len + 14 + exit_status_len + regression_len)
+                .color_range(self.theme.pending, command_len + 15 + exit_status_len + regression_len..)
+        } else if let Some(spawn_failure) = &command.spawn_failure {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [FAILED TO SPAWN: {}]", command_text, spawn_failure))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.failure, command_len + 2..)
+        } else if command.stopped {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [STOPPED]", command_text))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.skipped, command_len + 2..command_len + 9)
+        } else if command.aborted {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [ABORTED]", command_text))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.failure, command_len + 2..command_len + 9)
+        } else if command.exited {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [EXITED]", command_text))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.failure, command_len + 2..command_len + 8)
+        } else if command.skipped {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [SKIPPED]", command_text))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.skipped, command_len + 2..command_len + 9)
+        } else if command.pane_lost {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [PANE GONE]", command_text))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.failure, command_len + 2..command_len + 11)
+        } else if command.pane_closed_by_user {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(format!("{} [CLOSED]", command_text))
+                .color_range(command_color, 0..command_len + 1)
+                .color_range(self.theme.failure, command_len + 2..command_len + 8)
+        } else {
+            let command_len = command_text.chars().count();
+            NestedListItem::new(&command_text)
+                .color_range(command_color, 0..command_len + 1)
+        };
+        if is_selected {
+            let start_time = command.start_time.unwrap_or_else(|| Instant::now());
+            let end_time = command.end_time.unwrap_or_else(|| Instant::now());
+            let running_line = if is_running {
+                NestedListItem::new(format!("Running for: {}", format_duration(end_time.duration_since(start_time), self.duration_precision))).indent(indent + 1).selected()
+            } else {
+                NestedListItem::new(format!("Done after: {}", format_duration(end_time.duration_since(start_time), self.duration_precision))).indent(indent + 1).selected()
+            };
+            let has_pane_id = command.pane_id.is_some();
+            // TODO: Also add <Ctrl c> - delete command and close terminal
+            let rerun_or_open = if has_pane_id { 
+                NestedListItem::new("<TAB> - open terminal").color_range(self.theme.key_hint, 0..5).indent(indent + 1).selected()
+            } else {
+                NestedListItem::new("<TAB> - re-run in new terminal").color_range(self.theme.key_hint, 0..5).indent(indent + 1).selected()
+            };
+            let mut lines = vec![item_title.indent(indent).selected()];
+            if let Some(desc) = &command.options.desc {
+                lines.push(NestedListItem::new(desc).indent(indent + 1).selected());
+            }
+            if let Some(action_menu) = &self.action_menu {
+                // in place of the details, until an action is picked
+                for (i, action) in action_menu.actions.iter().enumerate() {
+                    let item = if i == action_menu.selected {
+                        NestedListItem::new(format!("> {}", action.label())).color_range(self.theme.key_hint, 0..1)
+                    } else {
+                        NestedListItem::new(format!("  {}", action.label()))
+                    };
+                    lines.push(item.indent(indent + 1).selected());
+                }
+                lines.push(NestedListItem::new("↓↑ - choose, ENTER - run, ESC - close")
+                    .color_range(self.theme.key_hint, 0..2)
+                    .color_range(self.theme.key_hint, 13..18)
+                    .color_range(self.theme.key_hint, 26..29)
+                    .indent(indent + 1)
+                    .selected());
+                return lines;
+            }
+            lines.push(running_line);
+            lines.push(rerun_or_open);
+            if !command.succeeded() && !command.skipped {
+                let babysit = if command.babysitting { "<b> - stop babysitting" } else { "<b> - babysit (re-run until it succeeds)" };
+                lines.push(NestedListItem::new(babysit).color_range(self.theme.key_hint, 0..3).indent(indent + 1).selected());
+            }
+            if !command.chain_results.is_empty() {
+                let parts = chain::split(&command.command_line);
+                let ran: Vec<String> = command.chain_results.iter()
+                    .map(|r| format!("{} (exit code {})", parts.get(r.part).map(|p| p.command_line.as_str()).unwrap_or("?"), r.exit_code))
+                    .collect();
+                lines.push(NestedListItem::new(format!("Ran: {}", ran.join(", "))).indent(indent + 1).selected());
+            }
+            if let (true, Some(captured_output)) = (command.exited, &command.captured_output) {
+                lines.push(NestedListItem::new(format!("Output saved to: {}", captured_output.display())).indent(indent + 1).selected());
+            }
+            if let Some(log_file) = &command.log_file {
+                lines.push(NestedListItem::new(format!("Log: {}", log_file.display())).indent(indent + 1).selected());
+            }
+            lines.append(&mut self.render_output_tail(command, indent));
+            lines
+        } else {
+            let mut lines = vec![item_title.indent(indent)];
+            if let (DescriptionMode::Always, Some(desc)) = (self.show_descriptions, &command.options.desc) {
+                // uncolored, so that it reads as secondary to the command line
+                lines.push(NestedListItem::new(desc).indent(indent + 1));
+            }
+            lines.append(&mut self.render_output_tail(command, indent));
+            lines
+        }
+    }
+    // How long the command usually takes, if it's been running for slow_command_factor times
+    // longer than that
+    fn slow_command_expectation(&self, command: &Command) -> Option<Duration> {
+        if self.slow_command_factor <= 0.0 || command.options.background || command.options.interactive || !command.is_running() {
+            return None;
+        }
+        let expected = history::expected_duration(&self.history, &command.command_line)?;
+        let elapsed = command.duration()?;
+        if elapsed.as_secs_f64() > expected.as_secs_f64() * self.slow_command_factor {
+            Some(expected)
+        } else {
+            None
+        }
+    }
+    // Runs the on_slow hook (eg. a desktop notification) once for each attempt of a command that
+    // takes far longer than usual
+    fn warn_about_slow_commands(&mut self) {
+        let slow_commands: Vec<usize> = self.commands_to_run.iter().enumerate()
+            .filter(|(_, c)| !c.slow_warned && self.slow_command_expectation(c).is_some())
+            .map(|(i, _)| i)
+            .collect();
+        for command_index in slow_commands {
+            let command = &mut self.commands_to_run[command_index];
+            command.slow_warned = true;
+            eprintln!("Command {} is taking far longer than usual: {}", command_index + 1, command.command_line);
+            let command_env = vec![("ZLAUNCH_SLOW_COMMAND".to_owned(), command.command_line.clone())];
+            self.spawn_hook_commands("on_slow", &command_env);
+        }
+    }
+    // The command line of the || fallback the exit code of the command came from, if any
+    fn fallback_that_ran(&self, command: &Command) -> Option<String> {
+        let last_result = command.chain_results.last()?;
+        let part = chain::split(&command.command_line).into_iter().nth(last_result.part)?;
+        if part.operator == Some(chain::ChainOperator::Fallback) {
+            Some(part.command_line)
+        } else {
+            None
+        }
+    }
+    fn is_regression(&self, command: &Command) -> bool {
+        command.exited && !command.succeeded() && self.previous_results.get(&command.command_line) == Some(&true)
+    }
+    fn render_output_tail(&self, command: &Command, indent: usize) -> Vec<NestedListItem> {
+        let mut lines: Vec<NestedListItem> = command.output_tail.iter()
+            .map(|line| NestedListItem::new(format!("│ {}", line)).color_range(self.theme.failure, 0..1).indent(indent + 1))
+            .collect();
+        if let Some(hint) = &command.hint {
+            let message_len = hint.message.chars().count();
+            let hint_line = match &hint.remediation {
+                Some(remediation) => NestedListItem::new(format!("Hint: {} (d - run: {})", hint.message, remediation))
+                    .color_range(self.theme.key_hint, 8 + message_len..9 + message_len),
+                None => NestedListItem::new(format!("Hint: {}", hint.message)),
+            };
+            lines.push(hint_line.color_range(self.theme.pending, 0..5).indent(indent + 1));
+        }
+        lines
+    }
+    // Runs the suggested fix of the selected command (or of the first command that has one) in
+    // a floating pane, where the user can see what it does
+    fn run_remediation(&mut self) -> bool {
+        let remediation = self.selected_index
+            .and_then(|i| self.commands_to_run.get(i))
+            .and_then(|c| c.hint.as_ref())
+            .or_else(|| self.commands_to_run.iter().find_map(|c| c.hint.as_ref().filter(|h| h.remediation.is_some())))
+            .and_then(|h| h.remediation.clone());
+        let remediation = match remediation {
+            Some(remediation) => remediation,
+            None => return false,
+        };
+        if !self.capabilities.contains(&Capability::RunCommands) {
+            return false;
+        }
+        self.audit_trail.record(AuditEvent::Action(format!("remediation {}", remediation)));
+        let mut command_to_run = process::shell_command(&self.shell_invocation(), &remediation);
+        command_to_run.cwd = Some(PathBuf::from(&self.folder));
+        let command_to_run = env_file::wrap_with_env(command_to_run, &self.env_variables);
+        let mut context = BTreeMap::new();
+        context.insert("remediation".to_owned(), String::new());
+        open_command_pane_floating(command_to_run, None, context);
+        true
+    }
+    // A babysat command is re-run every babysit_interval until it succeeds, the run continues
+    // once it does. Babysitting a command that already failed re-runs it right away.
+    fn toggle_babysit_selected_command(&mut self) -> bool {
+        let selected_index = match self.selected_index {
+            Some(selected_index) => selected_index,
+            None => return false,
+        };
+        let command = match self.commands_to_run.get_mut(selected_index) {
+            Some(command) if !command.skipped && !command.succeeded() => command,
+            _ => return false,
+        };
+        if command.babysitting {
+            command.babysitting = false;
+            if command.next_attempt.take().is_some() {
+                // the failure it was waiting on stands
+                command.exited = true;
+                self.audit_trail.record(AuditEvent::Action(format!("stop babysitting #{}", selected_index + 1)));
+                if self.current_stage_done() || self.running_command_index.is_none() {
+                    self.run_next_command();
+                }
+                return true;
+            }
+        } else {
+            command.babysitting = true;
+            if command.exited {
+                command.exited = false;
+                command.next_attempt = Some(Instant::now());
+            }
+        }
+        let action = if command.babysitting { "babysit" } else { "stop babysitting" };
+        self.audit_trail.record(AuditEvent::Action(format!("{} #{}", action, selected_index + 1)));
+        true
+    }
+    fn retry_babysat_commands(&mut self) {
+        let now = Instant::now();
+        let current_run_index = self.current_run_index;
+        let launch_settings = self.launch_settings();
+        for (index, command) in self.commands_to_run.iter_mut().enumerate() {
+            if !command.next_attempt.map(|n| n <= now).unwrap_or(false) {
+                continue;
+            }
+            command.next_attempt = None;
+            command.exit_status = None;
+            command.attempts += 1;
+            match command.pane_id {
+                Some(PaneId::Terminal(pane_id)) => Self::rerun_command_in_pane(command, index, pane_id, current_run_index, &launch_settings),
+                _ => {
+                    let mut context = BTreeMap::new();
+                    context.insert("command_index".to_owned(), index.to_string());
+                    context.insert("current_run_index".to_owned(), current_run_index.to_string());
+                    Self::run_command(command, index, context, &launch_settings);
+                    command.spawned_at = Some(Instant::now());
+                }
+            }
+            self.audit_trail.record(AuditEvent::Retried(index));
+        }
+    }
+    // Moves the selected command down (or up) among the commands the run hasn't reached yet.
+    // Within its stage it trades places with its neighbor. At the edge of its stage it leaves it
+    // for a stage of its own, and a command alone in its stage moves past the next stage.
+    fn move_selected_command(&mut self, down: bool) -> bool {
+        let selected_index = match self.selected_index {
+            Some(selected_index) if selected_index < self.commands_to_run.len() => selected_index,
+            _ => return false,
+        };
+        if self.sort_mode != SortMode::Definition {
+            // the move would be in an order that isn't the one shown
+            return false;
+        }
+        let neighbor_index = if down { selected_index + 1 } else { selected_index.wrapping_sub(1) };
+        if neighbor_index >= self.commands_to_run.len() {
+            return false;
+        }
+        let stage = self.commands_to_run[selected_index].stage;
+        let neighbor_stage = self.commands_to_run[neighbor_index].stage;
+        let is_alone_in_stage = self.commands_to_run.iter().filter(|c| c.stage == stage).count() == 1;
+        // the commands that change places
+        let moved = if stage == neighbor_stage {
+            neighbor_index.min(selected_index)..neighbor_index.max(selected_index) + 1
+        } else if !is_alone_in_stage {
+            selected_index..selected_index + 1
+        } else if down {
+            let neighbor_stage_len = self.commands_to_run[neighbor_index..].iter().take_while(|c| c.stage == neighbor_stage).count();
+            selected_index..neighbor_index + neighbor_stage_len
+        } else {
+            let neighbor_stage_len = self.commands_to_run[..=neighbor_index].iter().rev().take_while(|c| c.stage == neighbor_stage).count();
+            neighbor_index + 1 - neighbor_stage_len..selected_index + 1
+        };
+        let is_ahead_of_run = self.running_command_index.map(|r| moved.start > r).unwrap_or(true);
+        let can_move = self.commands_to_run[moved.clone()].iter().all(|c| c.start_time.is_none() && !c.exited && !c.options.locked);
+        if !is_ahead_of_run || !can_move {
+            return false;
+        }
+        // the launch positions stay where they are, the commands are launched in their new places
+        let launch_positions: Vec<Option<usize>> = self.commands_to_run.iter().map(|c| c.launch_position).collect();
+        let new_index = if stage == neighbor_stage {
+            self.commands_to_run.swap(selected_index, neighbor_index);
+            neighbor_index
+        } else if !is_alone_in_stage {
+            // it stays where it is, between its former stage and the next one
+            self.commands_to_run[selected_index].stage = self.commands_to_run.iter().map(|c| c.stage + 1).max().unwrap_or(0);
+            selected_index
+        } else if down {
+            self.commands_to_run[moved.clone()].rotate_left(1);
+            moved.end - 1
+        } else {
+            self.commands_to_run[moved.clone()].rotate_right(1);
+            moved.start
+        };
+        for (command, launch_position) in self.commands_to_run.iter_mut().zip(launch_positions) {
+            command.launch_position = launch_position;
+        }
+        self.selected_index = Some(new_index);
+        self.audit_trail.record(AuditEvent::Action(format!("move #{} to #{}", selected_index + 1, new_index + 1)));
+        true
+    }
+    // Only commands the run hasn't reached yet can be skipped or un-skipped
+    fn toggle_skip_selected_command(&mut self) -> bool {
+        let selected_index = match self.selected_index {
+            Some(selected_index) => selected_index,
+            None => return false,
+        };
+        let is_ahead_of_run = self.running_command_index.map(|r| selected_index > r).unwrap_or(true);
+        match self.commands_to_run.get_mut(selected_index) {
+            Some(command) if command.options.locked => {
+                eprintln!("Can't skip a locked command");
+                false
+            }
+            Some(command) if is_ahead_of_run && command.start_time.is_none() && !command.exited => {
+                command.skipped_by_user = !command.skipped_by_user;
+                command.skipped = command.skipped_by_user;
+                let action = if command.skipped_by_user { "skip" } else { "un-skip" };
+                self.audit_trail.record(AuditEvent::Action(format!("{} #{}", action, selected_index + 1)));
+                true
+            }
+            _ => false,
+        }
+    }
+    fn handle_quick_add_key(&mut self, key: KeyWithModifier) -> bool {
+        let quick_add_input = match self.quick_add_input.as_mut() {
+            Some(quick_add_input) => quick_add_input,
+            None => return false,
+        };
+        match key.bare_key {
+            BareKey::Char(c) if key.has_no_modifiers() || key.has_modifiers(&[KeyModifier::Shift]) => quick_add_input.push(c),
+            BareKey::Backspace => {
+                quick_add_input.pop();
+            }
+            BareKey::Enter => {
+                match self.quick_add_input.take() {
+                    // a run shared by a teammate, pasted in
+                    Some(input) if share::is_share_blob(&input) => {
+                        self.import_shared_run(&input);
+                    }
+                    Some(command_line) => self.quick_add_command(command_line.trim()),
+                    None => {}
+                }
+            }
+            BareKey::Esc => self.quick_add_input = None,
+            _ => return false,
+        }
+        true
+    }
+    // The command is appended in its own stage. If the run is already over it's started right
+    // away, otherwise it runs when the run gets to it.
+    fn quick_add_command(&mut self, command_line: &str) {
+        if command_line.is_empty() {
+            return;
+        }
+        let run_is_over = self.running_command_index.is_none() && self.all_commands_exited();
+        let mut command = Command::new(command_line);
+        command.stage = self.commands_to_run.iter().map(|c| c.stage + 1).max().unwrap_or(0);
+        self.commands_to_run.push(command);
+        self.audit_trail.record(AuditEvent::Action(format!("add {}", command_line)));
+        if !run_is_over || !self.capabilities.contains(&Capability::RunCommands) {
+            return;
+        }
+        if self.current_run_index == 0 {
+            // eg. the first command added after the wizard
+            self.start_run();
+        } else {
+            // continue from the last command, rather than starting the run over
+            // (the new command has no launch position, it comes last, see launch_order)
+            self.running_command_index = self.launch_order().iter().rev().nth(1).copied();
+            self.run_next_command();
+        }
+    }
+    // Inserts a copy of the selected command (including its attributes) right after it
+    fn clone_selected_command(&mut self) -> bool {
+        let selected_index = match self.selected_index {
+            Some(selected_index) => selected_index,
+            None => return false,
+        };
+        if self.has_panes_from(selected_index + 1) {
+            eprintln!("Can't clone a command above a command that has a pane");
+            return false;
+        }
+        // the clone would never be started by a stage that's already running
+        if self.running_stage().is_some() && self.running_stage() == self.commands_to_run.get(selected_index).map(|c| c.stage) && !self.current_stage_done() {
+            eprintln!("Can't clone a command into a running stage");
+            return false;
+        }
+        let cloned = match self.commands_to_run.get(selected_index) {
+            Some(command) => {
+                let mut cloned = Command::with_options(&command.command_line, command.options.clone());
+                cloned.stage = command.stage;
+                cloned.skipped_by_user = command.skipped_by_user;
+                cloned.skipped = command.skipped_by_user;
+                cloned
+            }
+            None => return false,
+        };
+        self.commands_to_run.insert(selected_index + 1, cloned);
+        self.audit_trail.record(AuditEvent::Action(format!("clone #{}", selected_index + 1)));
+        if let Some(running_command_index) = self.running_command_index.as_mut() {
+            if *running_command_index > selected_index {
+                *running_command_index += 1;
+            }
+        }
+        self.selected_index = Some(selected_index + 1);
+        true
+    }
+    // collapsed commands are skipped, possibly all the way to no selection
+    fn move_selection_down(&mut self) {
+        self.step_selection_down();
+        while self.selected_index.and_then(|i| self.commands_to_run.get(i)).map(|c| self.is_hidden(c)).unwrap_or(false) {
+            self.step_selection_down();
+        }
+    }
+    fn move_selection_up(&mut self) {
+        self.step_selection_up();
+        while self.selected_index.and_then(|i| self.commands_to_run.get(i)).map(|c| self.is_hidden(c)).unwrap_or(false) {
+            self.step_selection_up();
+        }
+    }
+    // the selection moves through the commands in the order they're listed in
+    fn step_selection_down(&mut self) {
+        let render_order = self.render_order();
+        self.selected_index = match self.selected_index.and_then(|i| self.render_position(i)) {
+            None => render_order.first().copied(),
+            Some(position) => render_order.get(position + 1).copied(),
+        };
+    }
+    fn step_selection_up(&mut self) {
+        let render_order = self.render_order();
+        self.selected_index = match self.selected_index.and_then(|i| self.render_position(i)) {
+            None => render_order.last().copied(),
+            Some(position) => position.checked_sub(1).and_then(|p| render_order.get(p)).copied(),
+        };
+    }
+    fn focus_selected_terminal(&mut self) {
+        let selected_index = self.selected_index;
+        if let (true, Some(selected_index)) = (self.stack_panes, selected_index) {
+            if self.commands_to_run.get(selected_index).map(|c| c.pane_id.is_some()).unwrap_or(false) {
+                self.show_stack_member(selected_index);
+            }
+        }
+        let launch_inputs = selected_index.filter(|i| *i < self.commands_to_run.len()).map(|i| self.launch_inputs(i));
+        let current_run_index = self.current_run_index;
+        let launch_settings = self.launch_settings();
+        let captures_output = self.captures_output();
+        let log_output = self.log_output;
+        if let Some(focused_command) = self.get_focused_command() {
+            match focused_command.pane_id {
+                Some(PaneId::Terminal(pane_id)) => {
+                    let should_float_if_hidden = true;
+                    focus_terminal_pane(pane_id, should_float_if_hidden)
+                },
+                _ => {
+                    if let Some(selected_index) = selected_index {
+                        let mut context = BTreeMap::new();
+                        context.insert("command_index".to_owned(), selected_index.to_string());
+                        context.insert("current_run_index".to_owned(), current_run_index.to_string());
+                        focused_command.reset();
+                        if captures_output {
+                            focused_command.captured_output = Some(capture::failure_output_path(selected_index, &focused_command.command_line));
+                        }
+                        if log_output {
+                            focused_command.log_file = Some(capture::log_file_path(selected_index, &focused_command.command_line));
+                        }
+                        focused_command.launch_inputs = launch_inputs;
+                        Self::run_command(&focused_command, selected_index, context, &launch_settings);
+                        focused_command.spawned_at = Some(Instant::now());
+                        focused_command.attempts += 1;
+                        self.audit_trail.record(AuditEvent::Retried(selected_index));
+                    }
+                }
+            }
+        }
+    }
+    // With stack_panes, shows the pane of this command in place of the one that was visible
+    fn show_stack_member(&mut self, command_index: usize) {
+        let pane_id = match self.commands_to_run.get(command_index).and_then(|c| c.pane_id) {
+            Some(pane_id) => pane_id,
+            None => return,
+        };
+        let previous_pane_id = self.visible_stack_member
+            .filter(|i| *i != command_index)
+            .and_then(|i| self.commands_to_run.get(i))
+            .and_then(|c| c.pane_id);
+        if let Some(previous_pane_id) = previous_pane_id {
+            hide_pane_with_id(previous_pane_id);
+        }
+        show_pane_with_id(pane_id, true);
+        self.visible_stack_member = Some(command_index);
+    }
+    // Shows the pane of the next command that has one, wrapping around
+    fn cycle_stack(&mut self) -> bool {
+        let command_count = self.commands_to_run.len();
+        let start = self.visible_stack_member.map(|i| i + 1).unwrap_or(0);
+        let next_member = (0..command_count)
+            .map(|offset| (start + offset) % command_count)
+            .find(|i| self.commands_to_run[*i].pane_id.is_some());
+        match next_member {
+            Some(next_member) => {
+                self.show_stack_member(next_member);
+                true
+            }
+            None => false,
+        }
+    }
+    fn get_focused_command(&mut self) -> Option<&mut Command> {
+        match self.selected_index {
+            Some(selected_index) => {
+                self.commands_to_run.get_mut(selected_index)
+            },
+            None => None
+        }
+    }
+    fn parse_profiles_from_configuration(&mut self) {
+        if let Some(profiles) = self.userspace_configuration.get("profiles") {
+            match profiles.parse::<KdlDocument>() {
+                // eg.
+                // deploy {
+                //     "cargo build --release"
+                //     "./deploy.sh"
+                // }
+                //
+                // properties of a profile override the rest of the configuration, eg.
+                // release stop_on_failure=true shell="zsh" { ... }
+                Ok(doc) => {
+                    for node in doc.nodes() {
+                        let name = node.name().value().to_owned();
+                        let commands = node.children().map(|c| c.to_string()).unwrap_or_default();
+                        let options = node.entries().iter()
+                            .filter_map(|e| {
+                                let key = e.name()?.value().to_owned();
+                                let value = e.value().as_string().map(|v| v.to_owned()).unwrap_or_else(|| e.value().to_string());
+                                Some((key, value))
+                            })
+                            .collect();
+                        self.profiles.insert(name.clone(), commands);
+                        self.profile_options.insert(name, options);
+                    }
+                }
+                Err(e) => eprintln!("Failed to parse profiles: {}", e),
+            }
+        }
+        self.base_configuration = self.userspace_configuration.clone();
+        self.active_profile = self.userspace_configuration.get("profile").cloned();
+        self.on_success_run_profile = self.userspace_configuration.get("on_success_run_profile").cloned();
+        self.apply_profile_options();
+    }
+    fn apply_profile_options(&mut self) {
+        self.userspace_configuration = self.base_configuration.clone();
+        let profile_options = self.active_profile.as_ref().and_then(|p| self.profile_options.get(p));
+        if let Some(profile_options) = profile_options {
+            self.userspace_configuration.extend(profile_options.clone());
+        }
+    }
+    fn parse_commands_from_configuration(&mut self) {
+        let profile_commands = self.active_profile.as_ref().and_then(|p| self.profiles.get(p)).cloned();
+        if let Some(commands) = profile_commands.or_else(|| self.userspace_configuration.get("commands").cloned()) {
+            self.parse_commands(&commands);
+        } else if let Some(template) = self.userspace_configuration.get("template") {
+            match templates::find_template(template) {
+                Some(template) => self.parse_commands(template.commands),
+                None => eprintln!("Unknown template: {}", template),
+            }
+        }
+    }
+    fn parse_commands(&mut self, commands: &str) {
+        let kdl_doc = commands.parse::<KdlDocument>();
+        // TOML and YAML commands are mapped to KDL
+        let doc = kdl_doc.as_ref().ok().cloned()
+            .or_else(|| formats::commands_to_kdl(commands).and_then(|kdl| kdl.parse::<KdlDocument>().ok()));
+        if let Some(doc) = doc {
+            let (commands, stage_names) = commands_from_kdl(&doc);
+            self.commands_to_run.extend(commands);
+            self.stage_names.extend(stage_names);
+        } else if let (Err(e), true) = (&kdl_doc, looks_like_kdl(commands)) {
+            let config_error = ConfigError::from_kdl_error(commands, e);
+            eprintln!("Failed to parse commands: {}", config_error.description);
+            self.config_error = Some(config_error);
+        } else {
+            for (stage, command) in commands.split("&&").enumerate() {
+                let mut command = Command::new(command.trim());
+                command.stage = stage;
+                self.commands_to_run.push(command);
+            }
+        }
+    }
+    fn parse_panes_to_run_on_completion_from_configuration(&mut self) {
+        if let Some(commands) = self.userspace_configuration.get("panes_to_run_on_completion") {
+            if let Ok(doc) = commands.parse::<KdlDocument>() {
+                // these are in kdl format
+                for node in doc.nodes() {
+                    // eg. "my pane title" on="run_start first_failure"
+                    let triggers = node.get("on")
+                        .and_then(|e| e.value().as_string())
+                        .map(|on| on.split_whitespace().filter_map(LifecycleEvent::from_str).collect())
+                        .unwrap_or_else(|| vec![LifecycleEvent::RunEnd]);
+                    if let Some(target) = CompletionTarget::from_kdl_node(node) {
+                        let pane_ids = match &target {
+                            CompletionTarget::Pane(pane_id) => vec![*pane_id],
+                            _ => vec![],
+                        };
+                        self.panes_to_run_on_completion.push(CompletionPane { target, pane_ids, triggers });
+                    }
+                }
+            }
+        }
+    }
+    fn parse_other_configuration(&mut self) {
+        self.shell = self.userspace_configuration.get("shell").map(|s| s.to_string()).unwrap_or_else(|| "bash".to_string());
+        self.exec = self.userspace_configuration.get("exec").map(|s| s == "true").unwrap_or(false);
+        self.fully_quiet = self.userspace_configuration.get("quiet").map(|s| s == "full").unwrap_or(false);
+        self.quiet = self.fully_quiet || self.userspace_configuration.get("quiet").map(|s| s == "true").unwrap_or(false);
+        self.open_in_new_tab = self.userspace_configuration.get("open_in_new_tab").map(|s| s == "true").unwrap_or(false);
+        self.stack_panes = self.userspace_configuration.get("stack_panes").map(|s| s == "true").unwrap_or(false);
+        self.tile_failed_panes = self.userspace_configuration.get("tile_failed_panes").map(|s| s == "true").unwrap_or(false);
+        self.slow_command_factor = self.userspace_configuration.get("slow_command_factor")
+            .and_then(|s| {
+                let factor = s.parse::<f64>().ok().filter(|f| *f >= 0.0);
+                if factor.is_none() {
+                    eprintln!("Invalid slow_command_factor: {}", s);
+                }
+                factor
+            })
+            .unwrap_or(DEFAULT_SLOW_COMMAND_FACTOR);
+        self.auto_resize = self.userspace_configuration.get("auto_resize").map(|s| s == "true").unwrap_or(false);
+        self.shell_args = self.userspace_configuration.get("shell_args")
+            .map(|s| s.split_whitespace().map(|s| s.to_owned()).collect())
+            .unwrap_or_else(|| process::DEFAULT_SHELL_ARGS.iter().map(|s| s.to_string()).collect());
+        self.folder = self.userspace_configuration.get("folder").map(|s| s.to_string()).unwrap_or_else(|| ".".to_string());
+        self.stop_on_failure = self.userspace_configuration.get("stop_on_failure").map(|s| s == "true").unwrap_or(false);
+        self.focus_follow = self.userspace_configuration.get("focus_follow").map(|s| s == "true").unwrap_or(false);
+        self.minimal_mode = self.userspace_configuration.get("minimal_mode").map(|s| s == "true").unwrap_or(false);
+        self.plain_ui = self.userspace_configuration.get("plain_ui").map(|s| s == "true").unwrap_or(false);
+        let color_blind = self.userspace_configuration.get("color_blind").map(|s| s == "true").unwrap_or(false);
+        // colors alone can't be relied on, whichever they are
+        self.accessible = color_blind || self.userspace_configuration.get("accessible").map(|s| s == "true").unwrap_or(false);
+        let high_contrast = self.userspace_configuration.get("high_contrast").map(|s| s == "true").unwrap_or(false);
+        let base_theme = if color_blind {
+            Theme::color_blind()
+        } else if high_contrast {
+            Theme::high_contrast()
+        } else {
+            Theme::default()
+        };
+        self.theme = Theme::from_configuration(self.userspace_configuration.get("theme"), base_theme);
+        let status_icons = self.userspace_configuration.get("status_icons");
+        self.list_status_icons = status_icons.is_some();
+        self.status_icons = status_icons.map(|s| StatusIcons::from_configuration(s)).unwrap_or_default();
+        self.duration_precision = self.userspace_configuration.get("duration_precision").and_then(|p| p.parse().ok()).unwrap_or(DEFAULT_DURATION_PRECISION);
+        self.failure_screenshots = self.userspace_configuration.get("failure_screenshots").map(|s| s == "true").unwrap_or(false);
+        self.dump_failed_panes = self.userspace_configuration.get("dump_failed_panes").map(|s| s == "true").unwrap_or(false);
+        self.failure_tail_lines = self.userspace_configuration.get("failure_tail_lines").and_then(|s| s.parse().ok()).unwrap_or(0);
+        self.live_tail = self.userspace_configuration.get("live_tail").map(|s| s == "true").unwrap_or(false);
+        self.shell_history_file = self.userspace_configuration.get("shell_history_file")
+            .cloned()
+            .unwrap_or_else(|| shell_history::DEFAULT_SHELL_HISTORY_FILE.to_owned());
+        self.labels = self.userspace_configuration.get("labels").map(|l| history::parse_labels(l)).unwrap_or_default();
+        self.env_file = self.userspace_configuration.get("env_file").cloned();
+        self.load_env_file();
+        self.report_file = self.userspace_configuration.get("report_file")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(report::DEFAULT_REPORT_FILE));
+        self.plugin_location = self.userspace_configuration.get("plugin_location")
+            .cloned()
+            .unwrap_or_else(|| export::DEFAULT_PLUGIN_LOCATION.to_owned());
+        self.install_key = self.userspace_configuration.get("install_key")
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_INSTALL_KEY.to_owned());
+        self.report_on_run_end = self.userspace_configuration.get("report_on_run_end").map(|s| s == "true").unwrap_or(false);
+        self.bench_runs = self.userspace_configuration.get("bench").and_then(|b| b.parse().ok()).filter(|b| *b > 0);
+        self.loop_mode = self.userspace_configuration.get("loop").and_then(|l| {
+            let loop_mode = LoopMode::from_str(l);
+            if loop_mode.is_none() {
+                eprintln!("Invalid loop: {}, expected a number or \"until-failure\"", l);
+            }
+            loop_mode
+        });
+        self.reset_loop();
+        self.repeat_every = self.userspace_configuration.get("repeat_every_secs")
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|s| *s > 0.0)
+            .map(Duration::from_secs_f64);
+        self.termination_grace_period = self.userspace_configuration.get("termination_grace_period")
+            .and_then(|s| parse_duration(s))
+            .unwrap_or(DEFAULT_TERMINATION_GRACE_PERIOD);
+        self.spawn_timeout = self.userspace_configuration.get("spawn_timeout_secs")
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|s| *s > 0.0)
+            .map(Duration::from_secs_f64)
+            .unwrap_or(DEFAULT_SPAWN_TIMEOUT);
+        self.allow_recursion = self.userspace_configuration.get("allow_recursion").map(|s| s == "true").unwrap_or(false);
+        self.capacity_hint = self.userspace_configuration.get("capacity_hint").and_then(|s| s.parse().ok()).filter(|c| *c > 0);
+        self.babysit_interval = self.userspace_configuration.get("babysit_interval")
+            .and_then(|s| parse_duration(s))
+            .unwrap_or(DEFAULT_BABYSIT_INTERVAL);
+        self.confirm_restart = self.userspace_configuration.get("confirm_restart").map(|s| s != "false").unwrap_or(true);
+        self.rerun_in_same_pane = self.userspace_configuration.get("rerun_in_same_pane").map(|s| s != "false").unwrap_or(true);
+        self.summary_screen = self.userspace_configuration.get("summary_screen").map(|s| s == "true").unwrap_or(false);
+        self.summary_pane = self.userspace_configuration.get("summary_pane").map(|s| s == "true").unwrap_or(false);
+        self.pipe_run_status = self.userspace_configuration.get("pipe_run_status").map(|s| s == "true").unwrap_or(false);
+        // with nothing on screen, the output of every command is kept for inspection
+        self.log_output = self.fully_quiet || self.userspace_configuration.get("log_output").map(|s| s == "true").unwrap_or(false);
+        self.collapse_patterns = collapse::parse_patterns(self.userspace_configuration.get("collapse_patterns"));
+        self.host_folder = get_plugin_ids().initial_cwd;
+        self.watch = self.userspace_configuration.get("watch").map(|s| s == "true").unwrap_or(false);
+        self.watch_idle_period = self.userspace_configuration.get("watch_idle_seconds")
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|s| *s > 0.0)
+            .map(Duration::from_secs_f64);
+        self.watch_ignore = self.userspace_configuration.get("watch_ignore")
+            .map(|s| s.split_whitespace().map(|s| s.to_owned()).collect())
+            .unwrap_or_else(|| vec!["target".to_owned(), ".git".to_owned(), "node_modules".to_owned(), ".zlaunch".to_owned(), ".zlaunch-history.json".to_owned(), ".zlaunch-status".to_owned()]);
+        self.config_file = self.userspace_configuration.get("config_file").map(PathBuf::from);
+        self.teardown_commands = parse_command_lines(self.userspace_configuration.get("teardown"));
+        self.setup_commands = parse_command_lines(self.userspace_configuration.get("setup"));
+        self.affected_base = self.userspace_configuration.get("affected_base").cloned().unwrap_or_else(|| affected::DEFAULT_AFFECTED_BASE.to_owned());
+        self.hook_commands.clear();
+        for hook in ["on_failure", "on_success", "on_slow"] {
+            self.hook_commands.insert(hook.to_owned(), parse_command_lines(self.userspace_configuration.get(hook)));
+        }
+        self.hook_commands.extend(parse_completion_hooks(self.userspace_configuration.get("completion_hooks")));
+        self.on_retrigger = self.userspace_configuration.get("on_retrigger").and_then(|s| RetriggerPolicy::from_str(s)).unwrap_or_default();
+        self.run_completion_panes_on = self.userspace_configuration.get("run_completion_panes_on").and_then(|s| RunEndOutcome::from_str(s)).unwrap_or_default();
+        self.show_descriptions = self.userspace_configuration.get("show_descriptions").and_then(|s| DescriptionMode::from_str(s)).unwrap_or_default();
+        self.configured_tag_filter = TagFilter::new(
+            self.userspace_configuration.get("run_tags").map(|s| s.as_str()),
+            self.userspace_configuration.get("skip_tags").map(|s| s.as_str()),
+        );
+        self.tag_filter = self.configured_tag_filter.clone();
+        self.run_order = self.userspace_configuration.get("order").and_then(|s| scheduler::RunOrder::from_str(s)).unwrap_or_default();
+        self.on_sibling_instance = self.userspace_configuration.get("on_sibling_instance").and_then(|s| SiblingPolicy::from_str(s)).unwrap_or_default();
+        if self.captures_output() {
+            if let Err(e) = fs::create_dir_all(PathBuf::from("/host").join(capture::FAILURES_FOLDER)) {
+                eprintln!("Failed to create failures folder: {}", e);
+            }
+        }
+        if self.log_output {
+            if let Err(e) = fs::create_dir_all(PathBuf::from("/host").join(capture::LOGS_FOLDER)) {
+                eprintln!("Failed to create logs folder: {}", e);
+            }
+        }
+    }
+    fn log_pane_ids_as_needed(&mut self, panes: PaneManifest) {
+        let own_plugin_id = get_plugin_ids().plugin_id;
+        let command_pane_ids: HashSet<PaneId> = self.commands_to_run.iter().filter_map(|c| c.pane_id).collect();
+        for completion_pane in self.panes_to_run_on_completion.iter_mut() {
+            if let CompletionTarget::Pane(_) = completion_pane.target {
+                continue;
+            }
+            completion_pane.pane_ids.clear();
+            for (tab_position, panes) in &panes.panes {
+                let tab = self.tabs.iter().find(|t| t.position == *tab_position);
+                for pane in panes {
+                    let pane_id = if pane.is_plugin { PaneId::Plugin(pane.id) } else { PaneId::Terminal(pane.id) };
+                    // we never re-run ourselves or the commands of the run
+                    if pane_id == PaneId::Plugin(own_plugin_id) || command_pane_ids.contains(&pane_id) || !pane.is_selectable {
+                        continue;
+                    }
+                    let is_match = match &completion_pane.target {
+                        CompletionTarget::Title(title) => pane.title == *title,
+                        CompletionTarget::TitleRegex(regex) => regex.is_match(&pane.title),
+                        CompletionTarget::Tab(tab_name_or_position) => {
+                            tab.map(|t| t.name == *tab_name_or_position).unwrap_or(false)
+                                || (tab_position + 1).to_string() == *tab_name_or_position
+                        }
+                        CompletionTarget::Pane(_) => false,
+                    };
+                    if is_match {
+                        completion_pane.pane_ids.push(pane_id);
+                    }
+                }
+            }
+        }
+    }
+    // A pane kept from a previous run (see restart_run) still has the context it was opened with,
+    // its events are about the command re-run in it
+    fn adopt_kept_pane(&self, terminal_pane_id: u32, context: &mut BTreeMap<String, String>) {
+        let command_index = self.commands_to_run.iter()
+            .position(|c| c.kept_pane && c.pane_id == Some(PaneId::Terminal(terminal_pane_id)));
+        if let Some(command_index) = command_index {
+            context.insert("command_index".to_owned(), command_index.to_string());
+            context.insert("current_run_index".to_owned(), self.current_run_index.to_string());
+        }
+    }
+    fn handle_command_pane_opened(&mut self, terminal_pane_id: u32, context: BTreeMap<String, String>) -> bool {
+        let mut should_render = false;
+        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        let broadcasts_events = self.broadcasts_events();
+        match (command_index, current_run_index) {
+            (Some(command_index), Some(current_run_index)) => {
+                if current_run_index == self.current_run_index {
+                    if let Some(command) = self.commands_to_run.get_mut(command_index) {
+                        if command.spawn_failure.is_some() {
+                            // too late, the command was already given up on
+                            close_terminal_pane(terminal_pane_id);
+                            return false;
+                        }
+                        command.spawned_at = None;
+                        let is_new_pane = command.pane_id != Some(PaneId::Terminal(terminal_pane_id));
+                        let is_quiet = command.is_quiet(self.quiet, self.fully_quiet);
+                        let is_interactive = command.options.interactive;
+                        let mut due = Instant::now();
+                        command.scheduled_input = command.options.input.iter()
+                            .map(|input| {
+                                due += input.delay;
+                                (due, input.text.clone())
+                            })
+                            .collect();
+                        command.pane_id = Some(PaneId::Terminal(terminal_pane_id));
+                        command.start_time = Some(Instant::now());
+                        command.end_time = None; // in case this is a re-run
+                        rename_terminal_pane(terminal_pane_id, command.pane_title(self.duration_precision, &self.status_icons));
+                        if broadcasts_events {
+                            let started = CommandStartedMessage::new(get_plugin_ids().plugin_id, current_run_index, command_index, command);
+                            broadcast_event(status::COMMAND_STARTED_PIPE_NAME, &started);
+                        }
+                        should_render = true;
+                        if self.open_in_new_tab && is_new_pane && !is_quiet {
+                            self.move_to_command_tab(PaneId::Terminal(terminal_pane_id));
+                        } else if self.stack_panes && !is_quiet {
+                            // the latest command comes to the top of the stack
+                            self.show_stack_member(command_index);
+                        }
+                        if (self.focus_follow || is_interactive) && !is_quiet {
+                            focus_terminal_pane(terminal_pane_id, true);
+                        }
+                    }
+                } else {
+                    eprintln!("Received a message from a previous run, ignoring");
+                }
+            }
+            _ => {}
+        }
+        should_render
+    }
+    fn move_to_command_tab(&mut self, pane_id: PaneId) {
+        match self.tabs.iter().find(|t| t.name == COMMAND_TAB_NAME) {
+            Some(tab) => break_panes_to_tab_with_index(&[pane_id], tab.position, false),
+            None if !self.command_tab_requested => {
+                self.command_tab_requested = true;
+                break_panes_to_new_tab(&[pane_id], Some(COMMAND_TAB_NAME.to_owned()), false);
+            }
+            None => self.panes_waiting_for_command_tab.push(pane_id),
+        }
+    }
+    // panes that opened while the dedicated tab was being created
+    fn move_panes_waiting_for_command_tab(&mut self) {
+        let command_tab_position = match self.tabs.iter().find(|t| t.name == COMMAND_TAB_NAME) {
+            Some(tab) => tab.position,
+            None => return,
+        };
+        self.command_tab_requested = false;
+        if !self.panes_waiting_for_command_tab.is_empty() {
+            let pane_ids: Vec<PaneId> = self.panes_waiting_for_command_tab.drain(..).collect();
+            break_panes_to_tab_with_index(&pane_ids, command_tab_position, false);
+        }
+    }
+    fn handle_command_pane_exited(&mut self, exit_code: Option<i32>, context: BTreeMap<String, String>) {
+        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        let babysit_interval = self.babysit_interval;
+        let broadcasts_events = self.broadcasts_events();
+        let bench_runs = self.bench_runs;
+        match (command_index, current_run_index) {
+            (Some(command_index), Some(current_run_index)) => {
+                if current_run_index == self.current_run_index {
+                    if let Some(command) = self.commands_to_run.get_mut(command_index) {
+                        if command.stopped || command.aborted {
+                            return;
+                        }
+                        self.audit_trail.record(AuditEvent::Exited(command_index, exit_code));
+                        command.exit_status = exit_code;
+                        command.end_time = Some(Instant::now());
+                        if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
+                            rename_terminal_pane(pane_id, command.pane_title(self.duration_precision, &self.status_icons));
+                        }
+                        if broadcasts_events {
+                            let finished = CommandFinishedMessage::new(get_plugin_ids().plugin_id, current_run_index, command_index, command);
+                            broadcast_event(status::COMMAND_FINISHED_PIPE_NAME, &finished);
+                        }
+                        if bench_runs.is_some() && command.succeeded() {
+                            // the next sample is taken once its after hook ran, see
+                            // finish_or_take_next_bench_sample
+                            command.bench_samples.extend(command.duration());
+                        }
+                        if scheduler::is_retried(&command.status()) {
+                            // not done yet as far as the run is concerned, it's re-run on a timer
+                            command.next_attempt = Some(Instant::now() + babysit_interval);
+                            return;
+                        }
+                        if command.options.after.is_some() && !command.after_hook_done {
+                            // eg. stopping a test database, the command is only done once its
+                            // after hook is, see handle_command_hook_exited
+                            let launch_settings = self.launch_settings();
+                            if let Some(command) = self.commands_to_run.get(command_index) {
+                                Self::run_command_hook(command, command_index, "after", context, &launch_settings);
+                            }
+                            return;
+                        }
+                        self.finish_or_take_next_bench_sample(command_index, exit_code);
+                    }
+                } else {
+                    eprintln!("Received a message from a previous run, ignoring");
+                }
+            },
+            _ => {}
+        }
+    }
+    fn handle_one_off_opened(&mut self, terminal_pane_id: u32, context: BTreeMap<String, String>) -> bool {
+        let index = context.get(palette::ONE_OFF_CONTEXT).and_then(|i| i.parse::<usize>().ok());
+        let one_off = match index.and_then(|i| self.one_offs.get_mut(i)) {
+            Some(one_off) => one_off,
+            None => return false,
+        };
+        one_off.pane_id = Some(terminal_pane_id);
+        one_off.start_time = Some(Instant::now());
+        one_off.end_time = None; // in case this is a re-run
+        one_off.exit_status = None;
+        true
+    }
+    fn handle_one_off_exited(&mut self, exit_code: Option<i32>, context: BTreeMap<String, String>) -> bool {
+        let index = context.get(palette::ONE_OFF_CONTEXT).and_then(|i| i.parse::<usize>().ok());
+        let one_off = match index.and_then(|i| self.one_offs.get_mut(i)) {
+            Some(one_off) => one_off,
+            None => return false,
+        };
+        one_off.exit_status = exit_code;
+        one_off.end_time = Some(Instant::now());
+        true
+    }
+    fn handle_command_hook_opened(&mut self, terminal_pane_id: u32, context: BTreeMap<String, String>) {
+        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        if current_run_index != Some(self.current_run_index) {
+            return;
+        }
+        if let Some(command) = command_index.and_then(|i| self.commands_to_run.get_mut(i)) {
+            // the hook did spawn, the command's own pane is only asked for once it succeeds
+            command.spawned_at = None;
+            command.hook_pane_id = Some(terminal_pane_id);
+        }
+    }
+    fn handle_command_hook_exited(&mut self, terminal_pane_id: u32, exit_code: Option<i32>, mut context: BTreeMap<String, String>) {
+        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        let phase = context.remove(COMMAND_HOOK_CONTEXT);
+        let command_index = match (command_index, current_run_index) {
+            (Some(command_index), Some(current_run_index)) if current_run_index == self.current_run_index => command_index,
+            _ => {
+                eprintln!("Received a message from a previous run, ignoring");
+                return;
+            }
+        };
+        let launch_settings = self.launch_settings();
+        let command = match self.commands_to_run.get_mut(command_index) {
+            Some(command) if !command.stopped && !command.aborted => command,
+            _ => return,
+        };
+        command.hook_pane_id = None;
+        let hook_succeeded = exit_code == Some(0);
+        if hook_succeeded {
+            // failed hooks are kept around (hidden) to be looked into
+            close_terminal_pane(terminal_pane_id);
+        }
+        match phase.as_deref() {
+            Some("before") if hook_succeeded => {
+                command.before_hook_passed = true;
+                match context.remove(RERUN_PANE_CONTEXT).and_then(|p| p.parse::<u32>().ok()) {
+                    Some(pane_id) => rerun_command_pane(pane_id),
+                    None => {
+                        Self::run_command(command, command_index, context, &launch_settings);
+                        command.spawned_at = Some(Instant::now());
+                    }
+                }
+            }
+            Some("before") => {
+                // the command itself never ran
+                self.audit_trail.record(AuditEvent::Exited(command_index, exit_code));
+                command.hook_failure = Some("before");
+                command.exit_status = exit_code;
+                command.captured_output = None;
+                command.end_time = Some(Instant::now());
+                self.finish_command_exit(command_index, exit_code);
+            }
+            Some("after") => {
+                command.after_hook_done = true;
+                if !hook_succeeded && command.succeeded() {
+                    command.hook_failure = Some("after");
+                    command.exit_status = exit_code;
+                }
+                let exit_status = command.exit_status;
+                self.finish_or_take_next_bench_sample(command_index, exit_status);
+            }
+            _ => {}
+        }
+    }
+    // In bench mode, a command that succeeded is re-run in its pane until it has all its samples
+    fn finish_or_take_next_bench_sample(&mut self, command_index: usize, exit_code: Option<i32>) {
+        let current_run_index = self.current_run_index;
+        let launch_settings = self.launch_settings();
+        if let (Some(bench_runs), Some(command)) = (self.bench_runs, self.commands_to_run.get_mut(command_index)) {
+            if let (true, true, Some(PaneId::Terminal(pane_id))) = (command.succeeded(), command.bench_samples.len() < bench_runs, command.pane_id) {
+                command.attempts += 1;
+                Self::rerun_command_in_pane(command, command_index, pane_id, current_run_index, &launch_settings);
+                return;
+            }
+        }
+        self.finish_command_exit(command_index, exit_code);
+    }
+    fn finish_command_exit(&mut self, command_index: usize, exit_code: Option<i32>) {
+        let command = match self.commands_to_run.get_mut(command_index) {
+            Some(command) => command,
+            None => return,
+        };
+        let command_stage = command.stage;
+        command.babysitting = false;
+        command.exited = true;
+        // (commands failed by their before hook never ran)
+        let chain_results_file = command.launch_inputs.as_ref().and_then(|l| l.chain_results_file.as_ref()).filter(|_| command.start_time.is_some());
+        if let Some(chain_results_file) = chain_results_file {
+            match fs::read_to_string(storage::host_path(chain_results_file)) {
+                Ok(results) => command.chain_results = chain::parse_results(&results),
+                Err(e) => eprintln!("Failed to read which parts of {} ran: {}", command.command_line, e),
+            }
+        }
+        let command_failed = !command.succeeded();
+        let is_background = command.options.background;
+        if !command_failed {
+            // we only keep the output of failed commands
+            if let Some(captured_output) = command.captured_output.take() {
+                let _ = fs::remove_file(PathBuf::from("/host").join(captured_output));
+            }
+        } else if let Some(captured_output) = &command.captured_output {
+            let captured_output = PathBuf::from("/host").join(captured_output);
+            let collapse_patterns = &self.collapse_patterns;
+            let collapsed = fs::read_to_string(&captured_output)
+                .map(|output| collapse::collapse_noise(&output, collapse_patterns));
+            match collapsed {
+                Ok(output) => {
+                    command.hint = doctor::diagnose(exit_code, &output);
+                    command.output_tail = collapse::tail(&output, self.failure_tail_lines);
+                    if let Err(e) = storage::write_atomic(&captured_output, output) {
+                        eprintln!("Failed to collapse captured output: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to collapse captured output: {}", e),
+            }
+        }
+        if command_failed && command.hint.is_none() {
+            command.hint = doctor::diagnose(exit_code, "");
+        }
+        if let Some(_pane_id) = command.pane_id {
+            // TODO: toggle this
+            // hide_pane_with_id(pane_id);
+        }
+        let is_quiet = command.is_quiet(self.quiet, self.fully_quiet);
+        if let (true, Some(pane_id)) = (command_failed && is_quiet, command.pane_id) {
+            // quiet panes are only surfaced when they fail
+            show_pane_with_id(pane_id, true);
+        }
+        if command_failed && !command.options.allow_failure && !self.first_failure_reported {
+            self.first_failure_reported = true;
+            self.fire_lifecycle_event(LifecycleEvent::FirstFailure);
+        }
+        if is_background && self.running_stage() != Some(command_stage) {
+            // the run already moved on without it
+            return;
+        }
+        self.continue_run_after_exit(command_stage);
+    }
+    fn continue_run_after_exit(&mut self, command_stage: usize) {
+        self.run_completion_hooks();
+        let (statuses, run_status) = self.launch_statuses(&self.launch_order());
+        match scheduler::after_exit(&statuses, &run_status, command_stage) {
+            AfterExit::Wait => {}
+            AfterExit::NextStage => {
+                self.fire_lifecycle_event(LifecycleEvent::StageEnd);
+                self.run_next_command();
+            }
+            AfterExit::RunEnded => {
+                self.record_run_in_history();
+                self.handle_run_end();
+                self.start_queued_rerun();
+            }
+        }
+    }
+    // A pane that doesn't open (eg. a pane limit or a host error) would otherwise hang the run
+    // forever. The command is failed instead, and the run goes on as it does after any failure.
+    fn fail_commands_that_did_not_spawn(&mut self) -> bool {
+        let spawn_timeout = self.spawn_timeout;
+        let mut failed_stages = vec![];
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            let timed_out = command.start_time.is_none() && command.spawned_at.map(|s| s.elapsed() >= spawn_timeout).unwrap_or(false);
+            if !timed_out {
+                continue;
+            }
+            let reason = format!("no pane opened within {}", format_duration(spawn_timeout, 0));
+            eprintln!("Command {} failed to spawn: {}", i + 1, reason);
+            command.spawned_at = None;
+            command.spawn_failure = Some(reason.clone());
+            command.exited = true;
+            command.babysitting = false;
+            command.end_time = Some(Instant::now());
+            self.audit_trail.record(AuditEvent::SpawnFailed(i, reason));
+            failed_stages.push(command.stage);
+        }
+        if failed_stages.is_empty() {
+            return false;
+        }
+        if !self.first_failure_reported {
+            self.first_failure_reported = true;
+            self.fire_lifecycle_event(LifecycleEvent::FirstFailure);
+        }
+        failed_stages.dedup();
+        for stage in failed_stages {
+            self.continue_run_after_exit(stage);
+        }
+        true
+    }
+    fn handle_pane_closed(&mut self, pane_id: PaneId) -> bool {
+        let mut should_render = false;
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            if command.pane_id == Some(pane_id) {
+                self.audit_trail.record(AuditEvent::PaneClosed(i));
+                command.reset();
+                command.pane_closed_by_user = true;
+                should_render = true;
+                break;
+            }
+        }
+        if let PaneId::Terminal(terminal_pane_id) = pane_id {
+            for one_off in self.one_offs.iter_mut().filter(|o| o.pane_id == Some(terminal_pane_id)) {
+                // its result stays listed, running it again opens a new pane
+                one_off.pane_id = None;
+                if one_off.is_running() {
+                    one_off.end_time = Some(Instant::now());
+                }
+                should_render = true;
+            }
+            if self.active_edit_pane_ids.contains(&terminal_pane_id) {
+                self.active_edit_pane_ids.retain(|p| *p != terminal_pane_id);
+                self.handle_editor_closed();
+            }
+        }
+        should_render
+    }
+    fn broadcast_run_finished(&self) {
+        if self.broadcasts_events() {
+            let finished = RunFinishedMessage::new(get_plugin_ids().plugin_id, self.current_run_index, &self.commands_to_run, self.total_run_duration());
+            broadcast_event(status::RUN_FINISHED_PIPE_NAME, &finished);
+        }
+    }
+    // The .zlaunch-status marker, also the answer of the CLI pipes that sent the commands
+    fn write_run_status(&mut self, succeeded: bool) {
+        let first_failure = self.commands_to_run.iter()
+            .find(|c| c.exited && !c.succeeded() && !c.options.allow_failure)
+            .map(|c| (c.command_line.as_str(), c.exit_status));
+        let marker = run_status::status_marker(succeeded, first_failure);
+        run_status::write(&marker);
+        self.release_pipes_awaiting_run_status(&marker);
+    }
+    // The CLI pipes waiting on a run get their answer when it ends, or a failure when it's
+    // killed, replaced by another run or closed with us
+    fn release_pipes_awaiting_run_status(&mut self, answer: &str) {
+        for pipe_id in self.pipes_awaiting_run_status.drain(..) {
+            cli_pipe_output(&pipe_id, answer);
+            unblock_cli_pipe_input(&pipe_id);
+        }
+    }
+    // The timer ticks every second (for the elapsed times, spinners, retries, timeouts and
+    // schedules) only while there's something to tick for, it's re-armed once there is again
+    fn arm_timer_if_needed(&mut self) {
+        if self.timer_armed || !self.needs_timer() {
+            return;
+        }
+        self.timer_armed = true;
+        set_timeout(1.0);
+    }
+    fn needs_timer(&self) -> bool {
+        self.commands_to_run.iter().any(|c| c.is_running() || c.spawned_at.is_some() || c.next_attempt.is_some())
+            || !self.terminating_panes.is_empty()
+            || self.start_at.is_some()
+            || self.repeat_every.is_some()
+            || self.last_deferred_change.is_some()
+            || self.sibling_check_until.is_some()
+            || self.one_offs.iter().any(|o| o.is_running())
+    }
+    // The position each command is launched at in this run. Only commands marked independent are
+    // moved, by how long they usually take (the history changes with every run), and only among
+    // the consecutive ones: a stage of its own, not passing output along, without a condition nor
+    // placeholders. The commands stay in their definition order, in the list and in the
+    // configuration.
+    fn apply_run_order(&mut self) {
+        for command in self.commands_to_run.iter_mut() {
+            command.launch_position = None;
+        }
+        if self.run_order == scheduler::RunOrder::Definition {
+            return;
+        }
+        let movable: Vec<bool> = self.commands_to_run.iter()
+            .map(|c| {
+                let stage_len = self.commands_to_run.iter().filter(|other| other.stage == c.stage).count();
+                c.options.independent
+                    && stage_len == 1
+                    && !self.stage_names.contains_key(&c.stage)
+                    && !c.options.background
+                    && !c.options.capture_output
+                    && c.options.stdin_from.is_none()
+                    && c.options.when.is_none()
+                    && !c.command_line.contains("{{")
+            })
+            .collect();
+        let expected_durations: Vec<Option<Duration>> = self.commands_to_run.iter()
+            .map(|c| history::expected_duration(&self.history, &c.command_line))
+            .collect();
+        let order = scheduler::run_order(&movable, &expected_durations, self.run_order);
+        for (position, i) in order.iter().enumerate() {
+            self.commands_to_run[*i].launch_position = Some(position);
+        }
+    }
+    fn cycle_tag_filter(&mut self) {
+        let mut all_tags: Vec<String> = vec![];
+        for tag in self.commands_to_run.iter().flat_map(|c| c.options.tags.iter()) {
+            if !all_tags.contains(tag) {
+                all_tags.push(tag.clone());
+            }
+        }
+        let filters = tags::filters_to_cycle(&self.configured_tag_filter, &all_tags);
+        let next = filters.iter().position(|f| *f == self.tag_filter).map(|i| (i + 1) % filters.len()).unwrap_or(0);
+        self.tag_filter = filters[next].clone();
+        self.audit_trail.record(AuditEvent::Action(format!("run tags {}", self.tag_filter.description())));
+    }
+    fn instance_key(&self) -> String {
+        sibling::instance_key(&self.host_folder.join(&self.folder), self.commands_to_run.iter().map(|c| c.command_line.as_str()))
+    }
+    fn query_siblings(&mut self) {
+        pipe_message_to_plugin(MessageToPlugin::new(sibling::SIBLING_QUERY_PIPE_NAME).with_payload(self.instance_key()));
+        self.sibling_check_until = Some(Instant::now() + sibling::SIBLING_ANSWER_TIMEOUT);
+    }
+    // Nobody runs our commands
+    fn finish_sibling_check_if_unanswered(&mut self) {
+        match self.sibling_check_until {
+            Some(sibling_check_until) if sibling_check_until <= Instant::now() => {
+                self.sibling_check_until = None;
+                if self.start_after_sibling_check {
+                    self.start_after_sibling_check = false;
+                    self.start_run();
+                }
+            }
+            _ => {}
+        }
+    }
+    fn answer_sibling_query(&self, source: PipeSource, payload: Option<String>) {
+        let source_plugin_id = match source {
+            PipeSource::Plugin(plugin_id) => plugin_id,
+            _ => return,
+        };
+        let own_plugin_id = get_plugin_ids().plugin_id;
+        if source_plugin_id == own_plugin_id || payload.as_deref() != Some(self.instance_key().as_str()) {
+            return;
+        }
+        let is_running = self.running_command_index.is_some() && (!self.all_commands_exited() || self.watch);
+        // when both instances are only about to start, the one loaded first runs the commands
+        let starts_first = self.sibling_check_until.is_some() && own_plugin_id < source_plugin_id;
+        if is_running || starts_first {
+            let message = MessageToPlugin::new(sibling::SIBLING_HERE_PIPE_NAME).with_payload(self.instance_key());
+            pipe_message_to_plugin(message.with_destination_plugin_id(source_plugin_id));
+        }
+    }
+    fn handle_sibling_here(&mut self, source: PipeSource, payload: Option<String>) -> bool {
+        let sibling_plugin_id = match source {
+            PipeSource::Plugin(plugin_id) => plugin_id,
+            _ => return false,
+        };
+        if self.sibling_check_until.is_none() || payload.as_deref() != Some(self.instance_key().as_str()) {
+            return false;
+        }
+        self.sibling_check_until = None;
+        self.start_after_sibling_check = false;
+        self.sibling_plugin_id = Some(sibling_plugin_id);
+        match self.on_sibling_instance {
+            SiblingPolicy::Observe => {
+                self.audit_trail.record(AuditEvent::Action(format!("observe the run of plugin {}", sibling_plugin_id)));
+                self.observe_sibling(sibling_plugin_id);
+            }
+            SiblingPolicy::Refuse => {
+                // ENTER starts it anyway
+                self.waiting_to_start = true;
+            }
+            SiblingPolicy::TakeOver => {
+                self.audit_trail.record(AuditEvent::Action(format!("take over the run of plugin {}", sibling_plugin_id)));
+                let message = MessageToPlugin::new(sibling::TAKE_OVER_PIPE_NAME).with_payload(self.instance_key());
+                pipe_message_to_plugin(message.with_destination_plugin_id(sibling_plugin_id));
+                self.start_run();
+            }
+            SiblingPolicy::Ignore => self.start_run(),
+        }
+        true
+    }
+    fn handle_take_over(&mut self, source: PipeSource, payload: Option<String>) -> bool {
+        let plugin_id = match source {
+            PipeSource::Plugin(plugin_id) => plugin_id,
+            _ => return false,
+        };
+        if payload.as_deref() != Some(self.instance_key().as_str()) {
+            return false;
+        }
+        self.abort_run();
+        self.audit_trail.record(AuditEvent::Action(format!("taken over by plugin {}", plugin_id)));
+        self.observe_sibling(plugin_id);
+        true
+    }
+    // From now on, we only mirror the run of the sibling
+    fn observe_sibling(&mut self, plugin_id: u32) {
+        self.observer = true;
+        self.observed_plugin_id = Some(plugin_id);
+        pipe_message_to_plugin(MessageToPlugin::new("zlaunch_state_request").with_destination_plugin_id(plugin_id));
+        // observers keep their timer armed, see update_observer
+        if !self.timer_armed {
+            self.timer_armed = true;
+            set_timeout(1.0);
+        }
+    }
+    // The input of the commands that's due, in order
+    fn write_scheduled_input(&mut self) {
+        let now = Instant::now();
+        for command in self.commands_to_run.iter_mut().filter(|c| c.is_running() && !c.scheduled_input.is_empty()) {
+            let pane_id = match command.pane_id {
+                Some(pane_id) => pane_id,
+                None => continue,
+            };
+            let due_count = command.scheduled_input.iter().take_while(|(due, _)| *due <= now).count();
+            for (_, text) in command.scheduled_input.drain(..due_count) {
+                write_chars_to_pane_id(&text, pane_id);
+            }
+        }
+    }
+    fn update_running_pane_titles(&self) {
+        for command in self.commands_to_run.iter().filter(|c| c.is_running()) {
+            if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
+                rename_terminal_pane(pane_id, command.pane_title(self.duration_precision, &self.status_icons));
+            }
+        }
+    }
+    // Background commands with a readiness check are probed every tick until they're up
+    fn probe_starting_services(&mut self) {
+        let folder = PathBuf::from(&self.folder);
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            if !command.options.background || command.ready || command.probing || !command.is_running() {
+                continue;
+            }
+            if let Some(ready_when) = &command.options.ready_when {
+                ready_when.probe(&folder, i, self.current_run_index);
+                command.probing = true;
+            }
+        }
+    }
+    fn handle_readiness_probe(&mut self, exit_code: Option<i32>, context: BTreeMap<String, String>) -> bool {
+        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
+        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
+        if current_run_index != Some(self.current_run_index) {
+            return false;
+        }
+        let command = match command_index.and_then(|i| self.commands_to_run.get_mut(i)) {
+            Some(command) => command,
+            None => return false,
+        };
+        command.probing = false;
+        if exit_code != Some(0) || !command.is_running() {
+            return false;
+        }
+        command.ready = true;
+        let command_stage = command.stage;
+        if self.running_stage() == Some(command_stage) {
+            self.continue_run_after_exit(command_stage);
+        }
+        true
+    }
+    // Kills the background commands still up, the run they were started for is over
+    fn stop_services(&mut self) {
+        let mut panes = vec![];
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            if !command.options.background || !command.is_running() {
+                continue;
+            }
+            if let Some(PaneId::Terminal(pane_id)) = command.pane_id.take() {
+                panes.push((pane_id, true));
+            }
+            command.stopped = true;
+            command.end_time = Some(Instant::now());
+            self.audit_trail.record(AuditEvent::Killed(i));
+        }
+        self.terminate_panes(panes);
+    }
+    // Unlike a pause, this ends the run right away. The results so far stay on screen until the
+    // next run.
+    fn abort_run(&mut self) -> bool {
+        if self.running_command_index.is_none() || self.all_commands_exited() {
+            return false;
+        }
+        self.audit_trail.record(AuditEvent::Action("abort".to_owned()));
+        self.forget_persisted_state();
+        self.write_run_status(false);
+        self.stop_services();
+        let mut panes = vec![];
+        for (i, command) in self.commands_to_run.iter_mut().enumerate() {
+            // taken so that the pane closing doesn't reset the command
+            if let Some(PaneId::Terminal(pane_id)) = command.pane_id.take() {
+                panes.push((pane_id, command.is_running()));
+            }
+            if let Some(hook_pane_id) = command.hook_pane_id.take() {
+                panes.push((hook_pane_id, true));
+            }
+            if command.is_running() {
+                command.end_time = Some(Instant::now());
+                command.aborted = true;
+                self.audit_trail.record(AuditEvent::Killed(i));
+            } else if !command.is_done() {
+                command.aborted = true;
+            }
+            command.babysitting = false;
+            command.next_attempt = None;
+        }
+        self.terminate_panes(panes);
+        self.suspended = false;
+        self.next_repeat_at = None;
+        self.broadcast_run_finished();
+        self.run_teardown_commands();
+        true
+    }
+    fn handle_run_end(&mut self) {
+        self.forget_persisted_state();
+        self.stop_services();
+        self.broadcast_run_finished();
+        self.write_run_status(true);
+        self.run_teardown_commands();
+        self.fire_run_end(true);
+        self.run_hook_commands("on_success");
+        if self.start_next_loop_iteration() {
+            return;
+        }
+        if let Some(linked_profile) = self.on_success_run_profile.clone() {
+            if self.profiles.contains_key(&linked_profile) {
+                self.load_profile(&linked_profile);
+                return;
+            }
+            // another zlaunch instance might have this profile
+            if self.capabilities.contains(&Capability::Messaging) {
+                pipe_message_to_plugin(MessageToPlugin::new("zlaunch_run_profile").with_payload(linked_profile));
+            }
+        }
+        if self.summary_screen || self.bench_runs.is_some() {
+            self.show_summary = true;
+        }
+        if self.summary_pane {
+            self.open_summary_pane();
+        }
+        if self.watch || self.summary_screen || self.bench_runs.is_some() || self.repeat_every.is_some() || self.loop_mode.is_some() {
+            // in watch mode (or when repeating) we stick around for the next trigger, and the
+            // summary (or the loop's results) stay until the user closes us
+            return;
+        }
+        self.dump_failed_panes();
+        let panes = self.commands_to_run.iter()
+            .filter_map(|c| match c.pane_id {
+                Some(PaneId::Terminal(pane_id)) => Some((pane_id, c.is_running())),
+                _ => None,
+            })
+            .collect();
+        self.terminate_panes(panes);
+        if self.terminating_panes.is_empty() {
+            self.close_once_worker_is_done();
+        } else {
+            // the services we stopped are given their grace period
+            self.close_after_termination = true;
+        }
+    }
+    // Called when the plugin is about to close (eg. because the session is exiting), before the
+    // command panes vanish. This is best-effort: zellij does not wait for the teardown to finish.
+    fn run_teardown(&mut self) {
+        self.release_pipes_awaiting_run_status(&run_status::status_marker(false, None));
+        run_tmp::remove_run_tmp_dir(&run_tmp::run_tmp_dir(self.current_run_index));
+        run_tmp::forget_current_run_tmp_dir();
+        for command in &self.commands_to_run {
+            let is_running = command.start_time.is_some() && command.end_time.is_none();
+            if let (true, Some(PaneId::Terminal(pane_id))) = (is_running, command.pane_id) {
                 close_terminal_pane(pane_id);
             }
-            *command = Command::new(&command.command_line);
         }
-        self.current_run_index += 1;
-        self.run_next_command();
+        self.run_teardown_commands();
     }
-    fn render_status(&self, rows: usize, cols: usize) {
-        let y_coords = 6 + self.commands_to_run.len();
-        let shell_text = self.shell.to_string();
-        let folder_text = self.folder.to_string();
-        let total_run_time = self.total_run_time();
-        let text = format!("Elapsed: {}s Shell: {} Folder: {}", total_run_time, shell_text, folder_text);
-        let text = Text::new(text)
-            .color_range(1, 9..10 + total_run_time.chars().count())
-            .color_range(1, 18 + total_run_time.chars().count()..19 + total_run_time.chars().count() + shell_text.chars().count())
-            .color_range(1, 26 + total_run_time.chars().count() + shell_text.chars().count()..27 + total_run_time.chars().count() + shell_text.chars().count() + folder_text.chars().count());
-        print_text_with_coordinates(text, 1, y_coords, None, None);
+    // The teardown commands run once per run: when it ends (whether it succeeded, failed, was
+    // halted by stop_on_failure or aborted) or when we close in the middle of it
+    fn run_teardown_commands(&mut self) {
+        if self.teardown_commands.is_empty() || self.teardown_ran_for_run == Some(self.current_run_index) {
+            return;
+        }
+        self.teardown_ran_for_run = Some(self.current_run_index);
+        // run in the background and in order, regardless of whether a teardown command fails
+        let teardown_script = self.teardown_commands.join("; ");
+        let mut context = BTreeMap::new();
+        context.insert("teardown".to_owned(), String::new());
+        let teardown_command = process::shell_command(&self.shell_invocation(), &teardown_script);
+        let mut argv = vec![teardown_command.path.to_string_lossy().to_string()];
+        argv.extend(teardown_command.args);
+        let argv: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+        run_command_with_env_variables_and_cwd(
+            &argv,
+            self.env_variables.iter().cloned().collect(),
+            PathBuf::from(&self.folder),
+            context,
+        );
     }
-    fn total_run_time(&self) -> String {
-        let start_time = self.commands_to_run.iter().next().and_then(|c| c.start_time.clone()).unwrap_or_else(|| Instant::now());
-        let end_time = self.commands_to_run.iter().rev().next().and_then(|c| c.end_time.clone()).unwrap_or_else(|| Instant::now());
-        end_time.duration_since(start_time).as_secs().to_string()
+    fn handle_filesystem_change(&mut self, paths: Vec<(PathBuf, Option<FileMetadata>)>) -> bool {
+        let has_relevant_changes = paths.iter().any(|(path, _metadata)| !self.is_ignored_by_watch(path));
+        if has_relevant_changes && self.watch_idle_period.is_some() {
+            // every change restarts the wait, the run is triggered from the timer
+            self.last_deferred_change = Some(Instant::now());
+            return true;
+        }
+        let triggered_recently = self.last_watch_trigger.map(|t| t.elapsed() < Duration::from_secs(1)).unwrap_or(false);
+        if !has_relevant_changes || triggered_recently {
+            return false;
+        }
+        self.trigger_watch_run();
+        true
     }
-    fn render_help(&self, rows: usize, cols: usize) {
-        let y_coords = 8 + self.commands_to_run.len();
-        let enter_text = "ENTER";
-        let enter_element = Text::new(enter_text).color_range(2, ..);
-        let enter_ribbon_text = "Restart";
-        let enter_ribbon = Text::new(enter_ribbon_text);
-        let space_text = "SPACE";
-        let space_element = Text::new(space_text).color_range(2, ..);
-        let space_ribbon_text = "Pause After Command";
-        let space_ribbon = if self.paused {
-            Text::new(space_ribbon_text).selected()
-        } else {
-            Text::new(space_ribbon_text)
-        };
-        let f_text = "f";
-        let f_element = Text::new(f_text).color_range(2, ..);
-        let f_ribbon_text = "Stop on Failure";
-        let f_ribbon = if self.stop_on_failure {
-            Text::new(f_ribbon_text).selected()
-        } else {
-            Text::new(f_ribbon_text)
+    fn trigger_deferred_watch_run(&mut self) {
+        let is_idle = match (self.last_deferred_change, self.watch_idle_period) {
+            (Some(last_deferred_change), Some(watch_idle_period)) => last_deferred_change.elapsed() >= watch_idle_period,
+            _ => false,
         };
-        let enter_text_x_coords = 1;
-        let enter_ribbon_x_coords = enter_text_x_coords + enter_text.chars().count() + 1;
-
-        let space_text_x_coords = enter_ribbon_x_coords + enter_ribbon_text.chars().count() + 5;
-        let space_ribbon_x_coords = space_text_x_coords + space_text.chars().count() + 1;
-
-        let f_text_x_coords = space_ribbon_x_coords + space_ribbon_text.chars().count() + 5;
-        let f_ribbon_x_coords = f_text_x_coords + f_text.chars().count() + 1;
-
-        print_text_with_coordinates(enter_element, enter_text_x_coords, y_coords, None, None);
-        print_ribbon_with_coordinates(enter_ribbon, enter_ribbon_x_coords, y_coords, None, None);
-
-        print_text_with_coordinates(space_element, space_text_x_coords, y_coords, None, None);
-        print_ribbon_with_coordinates(space_ribbon, space_ribbon_x_coords, y_coords, None, None);
-
-        print_text_with_coordinates(f_element, f_text_x_coords, y_coords, None, None);
-        print_ribbon_with_coordinates(f_ribbon, f_ribbon_x_coords, y_coords, None, None);
+        if is_idle {
+            self.last_deferred_change = None;
+            self.trigger_watch_run();
+        }
+    }
+    // The commands still running decide: any of them asking not to be interrupted is enough,
+    // queuing the next run over skipping it
+    fn trigger_watch_run(&mut self) {
+        self.last_watch_trigger = Some(Instant::now());
+        let policies: Vec<RetriggerPolicy> = self.commands_to_run.iter()
+            .filter(|c| c.is_running())
+            .map(|c| c.options.on_retrigger.unwrap_or(self.on_retrigger))
+            .collect();
+        if policies.contains(&RetriggerPolicy::Queue) {
+            self.rerun_queued = true;
+        } else if !policies.contains(&RetriggerPolicy::Skip) {
+            self.restart_run();
+        }
     }
-    fn current_command_failed(&self) -> bool {
-        self.running_command_index.and_then(|i| self.commands_to_run.get(i)).map(|c| !(c.exited && c.exit_status == Some(0))).unwrap_or(false)
+    fn is_ignored_by_watch(&self, path: &Path) -> bool {
+        path.components().any(|component| {
+            let component = component.as_os_str().to_string_lossy();
+            self.watch_ignore.iter().any(|ignored| component == ignored.as_str())
+        })
     }
-    fn run_next_command(&mut self) {
-        if self.paused {
+    // The delayed first run (start_delay_secs) and the periodic ones (repeat_every_secs). A
+    // periodic run is skipped if the previous one is still active.
+    fn start_scheduled_runs(&mut self) {
+        let now = Instant::now();
+        if !self.capabilities.contains(&Capability::RunCommands) || self.wizard.is_some() {
             return;
         }
-        if self.current_command_failed() && self.stop_on_failure {
-            self.show_failed_commands();
+        if let Some(start_at) = self.start_at {
+            if start_at <= now {
+                self.start_at = None;
+                if !self.restored_from_persisted_state {
+                    self.start_run();
+                }
+            }
             return;
         }
-        let next_index = self.running_command_index.map(|i| i + 1).unwrap_or(0);
-        match self.commands_to_run.get_mut(next_index) {
-            Some(next_command) => {
-                let mut context = BTreeMap::new();
-                context.insert("command_index".to_owned(), next_index.to_string());
-                context.insert("current_run_index".to_owned(), self.current_run_index.to_string());
-                Self::run_command(&next_command, context, &self.shell, &self.folder);
-                self.running_command_index = Some(next_index);
-            },
-            None => {
-                self.running_command_index = None;
-                if self.all_commands_exited_successfully() {
-                    self.handle_run_end();
-                } else {
-                    // TODO: CONTINUE HERE - if the user fixed the exited command, we should
-                    // continue the run
-                    self.show_failed_commands();
-                }
+        let (repeat_every, next_repeat_at) = match (self.repeat_every, self.next_repeat_at) {
+            (Some(repeat_every), Some(next_repeat_at)) if next_repeat_at <= now => (repeat_every, next_repeat_at),
+            (Some(repeat_every), None) => {
+                self.next_repeat_at = Some(now + repeat_every);
+                return;
             }
+            _ => return,
+        };
+        // catch up without firing for every missed interval (eg. after the machine slept)
+        let mut next = next_repeat_at + repeat_every;
+        while next <= now {
+            next += repeat_every;
         }
-    }
-    fn run_command(command: &Command, context: BTreeMap<String, String>, shell: &str, folder: &str) {
-        let mut command_line = vec![ "-ic" ];
-        command_line.push(&command.command_line);
-        let mut command_to_run = CommandToRun::new_with_args(shell, command_line);
-        command_to_run.cwd = Some(PathBuf::from(folder));
-        open_command_pane_floating(command_to_run, None , context);
-    }
-    fn render_title(&self, rows: usize, cols: usize) -> Text {
-        let successful_commands = self.successful_command_count();
-        let successful_commands_indication = format!("{}", successful_commands);
-        let failed_commands = self.failed_command_count();
-        let failed_commands_indication = format!("{}", failed_commands);
-        let pending_commands = self.pending_command_count();
-        let pending_commands_indication = format!("{}", pending_commands);
-        if let Some(running_command_index) = self.running_command_index.as_ref() {
-            let total_commands = successful_commands + failed_commands + pending_commands;
-            let title = format!("Running {}/{} commands (Success: {}, Failure: {}, Pending: {})", running_command_index + 1, total_commands, successful_commands_indication, failed_commands_indication, pending_commands_indication);
-            Text::new(title)
-                .color_range(1, 0..20)
-                .color_range(2, 31..31 + successful_commands_indication.chars().count())
-                .color_range(3, 42 + successful_commands_indication.chars().count()..42 + failed_commands_indication.chars().count() + 1)
-                .color_range(1, 54 + failed_commands_indication.chars().count()..54 + pending_commands_indication.chars().count() + 1)
-        } else if self.all_commands_exited() {
-            let title = format!("Done running commands. (Success: {}, Failure: {}, Pending: {})", successful_commands_indication, failed_commands_indication, pending_commands_indication);
-            Text::new(title)
-                .color_range(1, 0..22)
-                .color_range(2, 33..33 + successful_commands_indication.chars().count())
-                .color_range(3, 44 + successful_commands_indication.chars().count()..44 + failed_commands_indication.chars().count() + 1)
-                .color_range(1, 56 + failed_commands_indication.chars().count()..56 + pending_commands_indication.chars().count() + 1)
+        self.next_repeat_at = Some(next);
+        let run_is_active = self.commands_to_run.iter().any(|c| c.is_running() || c.next_attempt.is_some());
+        if run_is_active {
+            self.audit_trail.record(AuditEvent::Action("skipped a scheduled run, the previous one is still active".to_owned()));
         } else {
-            // let title = format!("Waiting to run commands... (Success: {}, Failure: {}, Pending: {})", successful_commands_indication, failed_commands_indication, pending_commands_indication);
-            let title = format!("Running commands. (Success: {}, Failure: {}, Pending: {})", successful_commands_indication, failed_commands_indication, pending_commands_indication);
-            Text::new(title)
-                .color_range(1, 0..17)
-                .color_range(2, 27 + successful_commands_indication.chars().count()..27 + successful_commands_indication.chars().count() + 1)
-                .color_range(3, 39 + successful_commands_indication.chars().count()..40 + failed_commands_indication.chars().count())
-                .color_range(1, 51 + failed_commands_indication.chars().count()..51 + pending_commands_indication.chars().count() + 1)
+            self.audit_trail.record(AuditEvent::Action("scheduled run".to_owned()));
+            self.restart_run();
         }
     }
-    fn all_commands_exited(&self) -> bool {
-        self.commands_to_run.iter().all(|c| c.exited || c.pane_closed_by_user)
+    fn reset_loop(&mut self) {
+        self.loop_iteration = 1;
+        self.failed_loop_iterations = 0;
+        self.first_failed_loop_iteration = None;
     }
-    fn all_commands_exited_successfully(&self) -> bool {
-        self.commands_to_run.iter().all(|c| c.exit_status == Some(0))
+    // Returns false once the loop is over (or if there's no loop)
+    fn start_next_loop_iteration(&mut self) -> bool {
+        let has_next_iteration = match self.loop_mode {
+            Some(LoopMode::Times(times)) => self.loop_iteration < times,
+            Some(LoopMode::UntilFailure) => self.first_failed_loop_iteration.is_none(),
+            None => false,
+        };
+        if has_next_iteration {
+            self.loop_iteration += 1;
+            self.audit_trail.record(AuditEvent::Action(format!("loop iteration {}", self.loop_iteration)));
+            self.restart_run();
+        }
+        has_next_iteration
     }
-    fn successful_command_count(&self) -> usize {
-        self.commands_to_run.iter().filter(|c| c.exit_status == Some(0)).count()
+    fn loop_text(&self) -> Option<String> {
+        let iteration = match self.loop_mode? {
+            LoopMode::Times(times) => format!("Loop: iteration {}/{}", self.loop_iteration, times),
+            LoopMode::UntilFailure => format!("Loop until failure: iteration {}", self.loop_iteration),
+        };
+        match self.first_failed_loop_iteration {
+            Some(first_failed) => Some(format!("{} ({} failed, first at iteration {})", iteration, self.failed_loop_iterations, first_failed)),
+            None => Some(iteration),
+        }
     }
-    fn failed_command_count(&self) -> usize {
-        self.commands_to_run.iter().filter(|c| c.exited && c.exit_status != Some(0)).count()
+    fn start_queued_rerun(&mut self) {
+        if self.rerun_queued {
+            self.rerun_queued = false;
+            self.restart_run();
+        }
     }
-    fn pending_command_count(&self) -> usize {
-        self.commands_to_run.iter().filter(|c| !c.exited).count()
+    fn update_observer(&mut self, event: Event) -> bool {
+        let mut should_render = false;
+        match event {
+            Event::PermissionRequestResult(_) => {
+                pipe_message_to_plugin(MessageToPlugin::new("zlaunch_state_request"));
+            }
+            Event::Timer(_elapsed) => {
+                set_timeout(1.0);
+                self.spinner_frame = self.spinner_frame.wrapping_add(1);
+                // the panes of a run that was taken over
+                self.close_terminated_panes();
+                should_render = true;
+            }
+            Event::Key(key) => {
+                if key.bare_key == BareKey::Down && key.has_no_modifiers() {
+                    self.move_selection_down();
+                    should_render = true;
+                } else if key.bare_key == BareKey::Up && key.has_no_modifiers() {
+                    self.move_selection_up();
+                    should_render = true;
+                } else if key.bare_key == BareKey::PageDown && key.has_no_modifiers() {
+                    should_render = self.move_page(1);
+                } else if key.bare_key == BareKey::PageUp && key.has_no_modifiers() {
+                    should_render = self.move_page(-1);
+                }
+            }
+            _ => (),
+        }
+        should_render
     }
-    fn render_command(&self, command: &Command, is_running: bool, is_selected: bool) -> Vec<NestedListItem> {
-        let item_title = if is_running {
-            NestedListItem::new(format!("{} (Running for {}s)", &command.command_line, &command.start_time.unwrap_or_else(|| Instant::now()).elapsed().as_secs()))
-                .color_range(0, 0..command.command_line.chars().count() + 1)
-                .color_range(1, command.command_line.chars().count() + 1..)
-        } else if let Some(exit_status) = command.exit_status {
-            let exit_status_color = if exit_status == 0 { 2 } else { 3 };
-            let command_len = command.command_line.chars().count();
-            NestedListItem::new(format!("{} [EXIT CODE: {}]", command.command_line, exit_status))
-                .color_range(0, 0..command_len + 1)
-                .color_range(exit_status_color, command_len + 13..command_len + 14)
-        } else if command.exited {
-            let command_len = command.command_line.chars().count();
-            NestedListItem::new(format!("{} [EXITED]", command.command_line))
-                .color_range(0, 0..command_len + 1)
-                .color_range(3, command_len + 2..command_len + 8)
-        } else if command.pane_closed_by_user {
-            let command_len = command.command_line.chars().count();
-            NestedListItem::new(format!("{} [CLOSED]", command.command_line))
-                .color_range(0, 0..command_len + 1)
-                .color_range(3, command_len + 2..command_len + 8)
-        } else {
-            let command_len = command.command_line.chars().count();
-            NestedListItem::new(&command.command_line)
-                .color_range(0, 0..command_len + 1)
+    fn handle_state_broadcast(&mut self, source: PipeSource, payload: Option<String>) -> bool {
+        let source_plugin_id = match source {
+            PipeSource::Plugin(plugin_id) => plugin_id,
+            _ => return false,
         };
-        if is_selected {
-            let start_time = command.start_time.unwrap_or_else(|| Instant::now());
-            let end_time = command.end_time.unwrap_or_else(|| Instant::now());
-            let running_line = if is_running {
-                NestedListItem::new(format!("Running for: {}s", end_time.duration_since(start_time).as_secs())).indent(1).selected()
-            } else {
-                NestedListItem::new(format!("Done after: {}s", end_time.duration_since(start_time).as_secs())).indent(1).selected()
-            };
-            let has_pane_id = command.pane_id.is_some();
-            // TODO: Also add <Ctrl c> - delete command and close terminal
-            let rerun_or_open = if has_pane_id { 
-                NestedListItem::new("<TAB> - open terminal").color_range(2, 0..5).indent(1).selected()
-            } else {
-                NestedListItem::new("<TAB> - re-run in new terminal").color_range(2, 0..5).indent(1).selected()
-            };
-            vec![
-                item_title.selected(),
-                running_line,
-                rerun_or_open,
-            ]
-        } else {
-            vec![item_title]
+        if self.observed_plugin_id.map(|p| p != source_plugin_id).unwrap_or(false) {
+            // we lock on to the first instance we hear from
+            return false;
         }
-    }
-    fn move_selection_down(&mut self) {
-        let max_selected_index = self.commands_to_run.len().saturating_sub(1);
-        match self.selected_index.as_mut() {
-            None if !self.commands_to_run.is_empty() => {
-                self.selected_index = Some(0);
-            },
-            Some(current_index) if *current_index < max_selected_index => {
-                *current_index += 1;
+        match payload.as_ref().map(|p| serde_json::from_str::<RunSnapshot>(p)) {
+            Some(Ok(snapshot)) => {
+                self.observed_plugin_id = Some(source_plugin_id);
+                let age = snapshot.age();
+                self.current_run_index = snapshot.current_run_index;
+                self.running_command_index = snapshot.running_command_index;
+                self.stopwatch = snapshot.stopwatch(age);
+                self.stage_names = snapshot.stage_names;
+                self.paused = snapshot.paused;
+                self.stop_on_failure = snapshot.stop_on_failure;
+                self.shell = snapshot.shell;
+                self.folder = snapshot.folder;
+                self.commands_to_run = snapshot.commands.iter().map(|c| c.to_command(age)).collect();
+                if self.selected_index.map(|s| s >= self.commands_to_run.len()).unwrap_or(false) {
+                    self.selected_index = None;
+                }
+                true
             }
-            _ => {
-                self.selected_index = None;
+            Some(Err(e)) => {
+                eprintln!("Failed to parse state broadcast: {}", e);
+                false
             }
+            None => false
         }
     }
-    fn move_selection_up(&mut self) {
-        let max_selected_index = self.commands_to_run.len().saturating_sub(1);
-        match self.selected_index.as_mut() {
-            None if !self.commands_to_run.is_empty() => {
-                self.selected_index = Some(max_selected_index);
-            },
-            Some(current_index) if *current_index > 0 => {
-                *current_index -= 1;
+    fn snapshot(&self) -> RunSnapshot {
+        RunSnapshot {
+            taken_at_ms: now_ms(),
+            current_run_index: self.current_run_index,
+            running_command_index: self.running_command_index,
+            stage_names: self.stage_names.clone(),
+            paused: self.paused,
+            stop_on_failure: self.stop_on_failure,
+            shell: self.shell.clone(),
+            folder: self.folder.clone(),
+            commands: self.commands_to_run.iter().map(CommandSnapshot::from_command).collect(),
+            elapsed_ms: Some(self.stopwatch.elapsed().as_millis() as u64),
+            stopwatch_running: self.stopwatch.is_running(),
+        }
+    }
+    fn broadcast_state(&self) {
+        if !self.observer && !self.capabilities.contains(&Capability::Messaging) {
+            return;
+        }
+        match serde_json::to_string(&self.snapshot()) {
+            Ok(payload) => {
+                pipe_message_to_plugin(MessageToPlugin::new("zlaunch_state").with_payload(payload));
             }
-            _ => {
-                self.selected_index = None;
+            Err(e) => {
+                eprintln!("Failed to serialize state: {}", e);
             }
         }
     }
-    fn focus_selected_terminal(&mut self) {
-        let selected_index = self.selected_index;
-        let current_run_index = self.current_run_index;
-        let shell = self.shell.clone();
-        let folder = self.folder.clone();
-        if let Some(focused_command) = self.get_focused_command() {
-            match focused_command.pane_id {
-                Some(PaneId::Terminal(pane_id)) => {
-                    let should_float_if_hidden = true;
-                    focus_terminal_pane(pane_id, should_float_if_hidden)
-                },
-                _ => {
-                    let mut context = BTreeMap::new();
-                    if let Some(selected_index) = selected_index {
-                        context.insert("command_index".to_owned(), selected_index.to_string());
-                    }
-                    context.insert("current_run_index".to_owned(), current_run_index.to_string());
-                    focused_command.reset();
-                    Self::run_command(&focused_command, context, &shell, &folder);
-                }
+    // The panes of the failed commands are about to be closed, what they printed (captured as
+    // they ran, plugins can't read the scrollback of a pane) is kept under their name
+    fn dump_failed_panes(&self) {
+        if !self.dump_failed_panes {
+            return;
+        }
+        for command in &self.commands_to_run {
+            if command.pane_id.is_none() || !command.exited || command.succeeded() {
+                continue;
+            }
+            let captured_output = match &command.captured_output {
+                Some(captured_output) => captured_output,
+                None => continue,
+            };
+            let dump_path = capture::failure_dump_path(command.options.name.as_deref(), &command.command_line);
+            let dumped = fs::read(storage::host_path(captured_output))
+                .and_then(|output| storage::write_atomic(&storage::host_path(&dump_path), output));
+            match dumped {
+                Ok(()) => eprintln!("Saved the output of {} to {}", command.command_line, dump_path.display()),
+                Err(e) => eprintln!("Failed to save the output of {}: {}", command.command_line, e),
             }
         }
     }
-    fn get_focused_command(&mut self) -> Option<&mut Command> {
-        match self.selected_index {
-            Some(selected_index) => {
-                self.commands_to_run.get_mut(selected_index)
-            },
-            None => None
+    // the output of commands is captured to keep it for failures, or to show the end of it
+    fn captures_output(&self) -> bool {
+        self.failure_screenshots || self.dump_failed_panes || self.failure_tail_lines > 0 || self.live_tail
+    }
+    fn run_state(&self) -> RunState {
+        let is_halted_by_failure = self.stop_on_failure && self.failed_command_count() > 0;
+        if self.suspended {
+            RunState::Suspended
+        } else if self.commands_to_run.iter().any(|c| c.is_running()) {
+            RunState::Running
+        } else if !self.commands_to_run.is_empty() && self.all_commands_exited() {
+            if self.all_commands_exited_successfully() { RunState::Succeeded } else { RunState::Failed }
+        } else if is_halted_by_failure {
+            RunState::Failed
+        } else if self.paused {
+            RunState::Paused
+        } else if self.running_command_index.is_some() {
+            RunState::Running
+        } else {
+            RunState::Idle
         }
     }
-    fn parse_commands_from_configuration(&mut self) {
-        if let Some(commands) = self.userspace_configuration.get("commands") {
-            if let Ok(doc) = commands.parse::<KdlDocument>() {
-                // commands are in kdl format
-                for node in doc.nodes() {
-                    self.commands_to_run.push(Command::new(node.name().value().trim()));
-                }
-            } else {
-                for command in commands.split("&&") {
-                    self.commands_to_run.push(Command::new(command.trim()));
+    // Sends the stable status format (see status.rs) to all plugins, or only to the one that asked
+    fn broadcast_status(&self, destination_plugin_id: Option<u32>) {
+        if self.observer || !self.capabilities.contains(&Capability::Messaging) {
+            return;
+        }
+        let status = StatusMessage::new(get_plugin_ids().plugin_id, self.run_state(), self.current_run_index, &self.commands_to_run);
+        match serde_json::to_string(&status) {
+            Ok(payload) => {
+                let message = MessageToPlugin::new(status::STATUS_PIPE_NAME).with_payload(payload);
+                match destination_plugin_id {
+                    Some(plugin_id) => pipe_message_to_plugin(message.with_destination_plugin_id(plugin_id)),
+                    None => pipe_message_to_plugin(message),
                 }
             }
+            Err(e) => {
+                eprintln!("Failed to serialize status: {}", e);
+            }
         }
     }
-    fn parse_panes_to_run_on_completion_from_configuration(&mut self) {
-        if let Some(commands) = self.userspace_configuration.get("panes_to_run_on_completion") {
-            if let Ok(doc) = commands.parse::<KdlDocument>() {
-                // these are in kdl format
-                for node in doc.nodes() {
-                    self.panes_to_run_on_completion.insert(node.name().value().trim().to_owned(), None);
-                }
-            }
+    fn broadcasts_events(&self) -> bool {
+        !self.observer && self.capabilities.contains(&Capability::Messaging)
+    }
+    // One per instance, instances sharing a project folder each resume their own run
+    fn state_file() -> PathBuf {
+        storage::host_path(".zlaunch").join(format!("{}.json", storage::instance_file_name("state")))
+    }
+    fn persist_state(&self) {
+        if self.run_ended {
+            // there's nothing to resume
+            return;
+        }
+        let result = serde_json::to_string(&self.snapshot())
+            .map_err(|e| e.to_string())
+            .and_then(|state| storage::write_atomic(&Self::state_file(), state).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Failed to persist state: {}", e);
         }
     }
-    fn parse_other_configuration(&mut self) {
-        self.shell = self.userspace_configuration.get("shell").map(|s| s.to_string()).unwrap_or_else(|| "bash".to_string());
-        self.folder = self.userspace_configuration.get("folder").map(|s| s.to_string()).unwrap_or_else(|| ".".to_string());
-        self.stop_on_failure = self.userspace_configuration.get("stop_on_failure").map(|s| s == "true").unwrap_or(false);
+    // Whatever way a run ends (even when we stick around for the next one), it's not resumed on
+    // the next launch
+    fn forget_persisted_state(&mut self) {
+        self.run_ended = true;
+        match fs::remove_file(Self::state_file()) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Failed to remove {}: {}", Self::state_file().display(), e),
+        }
     }
-    fn log_pane_ids_as_needed(&mut self, panes: PaneManifest) {
-        for (_tab, panes) in panes.panes {
-            for pane in panes {
-                if self.panes_to_run_on_completion.contains_key(&pane.title) {
-                    self.panes_to_run_on_completion.get_mut(&pane.title).map(|p| *p = Some(PaneId::Terminal(pane.id)));
+    // The state file is removed when the run ends, so before closing ourselves we keep the final
+    // state of the run around for inspection
+    fn persist_final_state(&self) {
+        let result = serde_json::to_string_pretty(&self.snapshot())
+            .map_err(|e| e.to_string())
+            .and_then(|state| storage::write_atomic(Path::new(FINAL_STATE_FILE), state).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Failed to persist final state: {}", e);
+        }
+    }
+    fn restore_persisted_state(&mut self) {
+        let snapshot = match fs::read_to_string(Self::state_file()).ok().and_then(|s| serde_json::from_str::<RunSnapshot>(&s).ok()) {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        let was_interrupted = snapshot.commands.iter().any(|c| c.started_ms_ago.is_some())
+            && snapshot.commands.iter().any(|c| !c.exited && !c.skipped);
+        if !was_interrupted {
+            // a run that ended (or never started) before we were closed
+            self.forget_persisted_state();
+            return;
+        }
+        let has_same_commands = snapshot.commands.len() == self.commands_to_run.len()
+            && snapshot.commands.iter().zip(self.commands_to_run.iter()).all(|(s, c)| s.command_line == c.command_line);
+        if !has_same_commands {
+            // the configuration changed since the state was persisted
+            return;
+        }
+        let age = snapshot.age();
+        for (command, command_snapshot) in self.commands_to_run.iter_mut().zip(snapshot.commands.iter()) {
+            let options = command.options.clone();
+            let stage = command.stage;
+            *command = command_snapshot.to_command(age);
+            command.options = options;
+            command.stage = stage;
+        }
+        // we keep the same run index so that events from panes that are still open are recognized
+        self.current_run_index = snapshot.current_run_index;
+        self.running_command_index = snapshot.running_command_index;
+        self.paused = snapshot.paused;
+        self.stop_on_failure = snapshot.stop_on_failure;
+        self.stopwatch = snapshot.stopwatch(age);
+        self.restored_from_persisted_state = true;
+        self.should_verify_restored_panes = true;
+    }
+    fn verify_restored_panes(&mut self, panes: &PaneManifest) -> bool {
+        let was_interrupted = !self.all_commands_exited();
+        let existing_pane_ids: HashSet<PaneId> = panes.panes.values().flatten().map(|p| {
+            if p.is_plugin { PaneId::Plugin(p.id) } else { PaneId::Terminal(p.id) }
+        }).collect();
+        for command in self.commands_to_run.iter_mut() {
+            if let Some(pane_id) = command.pane_id {
+                if !existing_pane_ids.contains(&pane_id) {
+                    command.pane_id = None;
+                    command.pane_lost = true;
+                    command.pane_closed_by_user = true;
                 }
             }
         }
+        let some_panes_lost = self.commands_to_run.iter().any(|c| c.pane_lost);
+        if was_interrupted && some_panes_lost && self.commands_to_run.iter().any(|c| c.succeeded()) {
+            // the run can't go on as it was, but what already succeeded needn't run again
+            self.resume_prompt = true;
+            return true;
+        }
+        if self.current_stage_done() {
+            self.run_next_command();
+        }
+        some_panes_lost
     }
-    fn handle_command_pane_opened(&mut self, terminal_pane_id: u32, context: BTreeMap<String, String>) -> bool {
-        let mut should_render = false;
-        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
-        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
-        match (command_index, current_run_index) {
-            (Some(command_index), Some(current_run_index)) => {
-                if current_run_index == self.current_run_index {
-                    if let Some(command) = self.commands_to_run.get_mut(command_index) {
-                        command.pane_id = Some(PaneId::Terminal(terminal_pane_id));
-                        command.start_time = Some(Instant::now());
-                        command.end_time = None; // in case this is a re-run
-                        should_render = true;
-                    }
-                } else {
-                    eprintln!("Received a message from a previous run, ignoring");
-                }
+    fn handle_run_failed(&mut self) {
+        self.forget_persisted_state();
+        self.stop_services();
+        self.broadcast_run_finished();
+        self.write_run_status(false);
+        self.run_teardown_commands();
+        self.fire_run_end(false);
+        if self.loop_mode.is_some() {
+            self.failed_loop_iterations += 1;
+            self.first_failed_loop_iteration.get_or_insert(self.loop_iteration);
+            if self.start_next_loop_iteration() {
+                return;
             }
-            _ => {}
         }
-        should_render
+        self.show_summary = self.summary_screen;
+        if self.summary_pane {
+            self.open_summary_pane();
+        }
+        self.show_failed_commands();
+        self.run_hook_commands("on_failure");
     }
-    fn handle_command_pane_exited(&mut self, exit_code: Option<i32>, context: BTreeMap<String, String>) {
-        let command_index = context.get("command_index").and_then(|i| i.parse::<usize>().ok());
-        let current_run_index = context.get("current_run_index").and_then(|i| i.parse::<usize>().ok());
-        match (command_index, current_run_index) {
-            (Some(command_index), Some(current_run_index)) => {
-                if current_run_index == self.current_run_index {
-                    if let Some(command) = self.commands_to_run.get_mut(command_index) {
-                        command.exit_status = exit_code;
-                        command.exited = true;
-                        command.end_time = Some(Instant::now());
-                        if let Some(_pane_id) = command.pane_id {
-                            // TODO: toggle this
-                            // hide_pane_with_id(pane_id);
-                        }
-                        if self.running_command_index == Some(command_index) {
-                            self.run_next_command();
-                        } else if self.all_commands_exited_successfully() {
-                            self.handle_run_end();
-                        }
-                    }
-                } else {
-                    eprintln!("Received a message from a previous run, ignoring");
-                }
-            },
-            _ => {}
+    fn launch_settings(&self) -> LaunchSettings {
+        LaunchSettings {
+            shell_invocation: self.shell_invocation(),
+            exec: self.exec,
+            quiet: self.quiet,
+            fully_quiet: self.fully_quiet,
+            open_in_new_tab: self.open_in_new_tab,
+            stack_panes: self.stack_panes,
+            folder: self.folder.clone(),
+            host_folder: self.host_folder.clone(),
+            run_tmp_dir: run_tmp::run_tmp_dir(self.current_run_index),
+            env_variables: self.env_variables.clone(),
         }
     }
-    fn handle_pane_closed(&mut self, pane_id: PaneId) -> bool {
-        let mut should_render = false;
-        for command in self.commands_to_run.iter_mut() {
-            if command.pane_id == Some(pane_id) {
-                *command = Command::new(&command.command_line);
-                command.pane_closed_by_user = true;
-                should_render = true;
-                break;
+    fn load_env_file(&mut self) {
+        self.env_variables = match &self.env_file {
+            Some(env_file) => env_file::load(env_file),
+            None => vec![],
+        };
+    }
+    // the shell followed by its argument template, eg. ["bash", "-ic", "{cmd}"]
+    fn shell_invocation(&self) -> Vec<String> {
+        let mut shell_invocation = vec![self.shell.clone()];
+        shell_invocation.extend(self.shell_args.iter().cloned());
+        shell_invocation
+    }
+    // Runs the hooks of the stages (and tags) whose commands are all done, see
+    // parse_completion_hooks
+    fn run_completion_hooks(&mut self) {
+        let mut stages: Vec<usize> = self.commands_to_run.iter().map(|c| c.stage).collect();
+        stages.dedup();
+        let mut completed = vec![];
+        for hook in self.hook_commands.keys() {
+            let commands: Vec<&Command> = if let Some(stage) = hook.strip_prefix("stage:") {
+                let position = stage.parse::<usize>().ok().and_then(|p| p.checked_sub(1)).and_then(|p| stages.get(p));
+                self.commands_to_run.iter()
+                    .filter(|c| self.stage_names.get(&c.stage).map(|n| n == stage).unwrap_or(false) || Some(&c.stage) == position)
+                    .collect()
+            } else if let Some(tag) = hook.strip_prefix("tag:") {
+                self.commands_to_run.iter().filter(|c| c.options.tags.iter().any(|t| t == tag)).collect()
+            } else {
+                continue;
+            };
+            // a stage that hasn't started yet has no skipped commands either
+            if !commands.is_empty() && commands.iter().all(|c| c.is_done()) {
+                completed.push(hook.clone());
             }
         }
-        if let PaneId::Terminal(terminal_pane_id) = pane_id {
-            if self.active_edit_pane_ids.contains(&terminal_pane_id) {
-                self.active_edit_pane_ids.retain(|p| *p != terminal_pane_id);
-                self.handle_editor_closed();
-            }
+        for hook in completed {
+            self.run_hook_commands(&hook);
         }
-        should_render
     }
-    fn handle_run_end(&self) {
-        for (_name, pane_id) in &self.panes_to_run_on_completion {
-            match pane_id {
-                Some(PaneId::Terminal(terminal_pane_id)) => {
-                    rerun_command_pane(*terminal_pane_id);
-                }
-                _ => {}
-            }
+    // Hook commands run in background panes, they are not part of the run. Each hook runs at
+    // most once per run
+    fn run_hook_commands(&mut self, hook: &str) {
+        if self.hooks_ran_for_run.get(hook) == Some(&self.current_run_index) {
+            return;
         }
-        for command in &self.commands_to_run {
-            if let Some(PaneId::Terminal(pane_id)) = command.pane_id {
-                close_terminal_pane(pane_id);
-            }
+        self.hooks_ran_for_run.insert(hook.to_owned(), self.current_run_index);
+        self.spawn_hook_commands(hook, &[]);
+    }
+    fn spawn_hook_commands(&self, hook: &str, hook_env: &[(String, String)]) {
+        for command_line in self.hook_commands.get(hook).into_iter().flatten() {
+            // eg. notify-send "build exited with {{exit_code:build}} after {{duration:build}}"
+            let command_line = placeholders::interpolate(command_line, |kind, reference| self.resolve_placeholder(kind, reference));
+            let mut command_to_run = process::shell_command(&self.shell_invocation(), &command_line);
+            command_to_run.cwd = Some(PathBuf::from(&self.folder));
+            let env_variables: Vec<(String, String)> = self.env_variables.iter().chain(hook_env).cloned().collect();
+            let command_to_run = env_file::wrap_with_env(command_to_run, &env_variables);
+            let mut context = BTreeMap::new();
+            context.insert("hook".to_owned(), hook.to_owned());
+            open_command_pane_background(command_to_run, context);
         }
-        close_self();
     }
     fn show_failed_commands(&self) {
+        let mut failed_pane_ids = vec![];
         for command in &self.commands_to_run {
             if let Some(pane_id) = command.pane_id {
-                if let Some(exit_status) = command.exit_status {
-                    if exit_status != 0 {
-                        show_pane_with_id(pane_id, true);
-                        continue;
-                    }
+                if command.exit_status.is_some() && !command.succeeded() {
+                    failed_pane_ids.push(pane_id);
+                    continue;
                 }
                 hide_pane_with_id(pane_id);
             }
         }
+        if self.tile_failed_panes && !failed_pane_ids.is_empty() {
+            // side by side in a tab of their own, so that they can all be looked at at once
+            match self.tabs.iter().find(|t| t.name == FAILURES_TAB_NAME) {
+                Some(tab) => break_panes_to_tab_with_index(&failed_pane_ids, tab.position, true),
+                None => break_panes_to_new_tab(&failed_pane_ids, Some(FAILURES_TAB_NAME.to_owned()), true),
+            }
+            return;
+        }
+        for pane_id in failed_pane_ids {
+            show_pane_with_id(pane_id, true);
+        }
     }
 }
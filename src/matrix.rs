@@ -0,0 +1,67 @@
+use kdl::KdlNode;
+
+use crate::placeholders;
+
+// A command with a matrix block is expanded into one command per combination of its parameters,
+// like a CI matrix. The parameters are referred to with {{matrix:<name>}}, eg.
+// "cargo +{{matrix:rust_version}} test --features {{matrix:features}}" {
+//     matrix {
+//         rust_version "stable" "beta"
+//         features "a" "b"
+//     }
+// }
+// runs cargo +stable test --features a, cargo +stable test --features b, cargo +beta test...
+
+// The parameters and their values, in the order they're defined
+pub fn parse_matrix(node: &KdlNode) -> Vec<(String, Vec<String>)> {
+    let matrix = match node.children().and_then(|c| c.get("matrix")).and_then(|m| m.children()) {
+        Some(matrix) => matrix,
+        None => return vec![],
+    };
+    matrix
+        .nodes()
+        .iter()
+        .filter_map(|parameter| {
+            let values: Vec<String> = parameter
+                .entries()
+                .iter()
+                .filter(|e| e.name().is_none())
+                .map(|e| e.value().as_string().map(|v| v.to_owned()).unwrap_or_else(|| e.value().to_string()))
+                .collect();
+            if values.is_empty() {
+                eprintln!("Matrix parameter {} has no values, ignoring it", parameter.name().value());
+                return None;
+            }
+            Some((parameter.name().value().to_owned(), values))
+        })
+        .collect()
+}
+
+// Every combination of one value per parameter, the last parameter changing the fastest
+pub fn combinations(matrix: &[(String, Vec<String>)]) -> Vec<Vec<(String, String)>> {
+    let mut combinations = vec![vec![]];
+    for (name, values) in matrix {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination: Vec<(String, String)>| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((name.clone(), value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+// Replaces the {{matrix:<name>}} placeholders, the others are resolved when the command is
+// launched
+pub fn substitute(text: &str, combination: &[(String, String)]) -> String {
+    placeholders::interpolate(text, |kind, reference| {
+        if kind != "matrix" {
+            return Some(format!("{{{{{}:{}}}}}", kind, reference));
+        }
+        combination.iter().find(|(name, _)| name == reference).map(|(_, value)| value.clone())
+    })
+}
@@ -0,0 +1,138 @@
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+// p opens a palette to run a single command on its own, outside of the run (eg. a quick cargo
+// check while the tests run). It offers the defined commands, those of the previous runs and
+// those of the shell history, narrowed down by typing some of their characters. The commands
+// it ran are listed in a one-off section of their own, under the run.
+pub const ONE_OFF_CONTEXT: &str = "one_off"; // the position of the one-off, for its pane's events
+const MAX_MATCHES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Defined,
+    History, // the commands of the previous runs
+    ShellHistory,
+}
+
+impl Source {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Source::Defined => "defined",
+            Source::History => "history",
+            Source::ShellHistory => "shell history",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub command_line: String,
+    pub source: Source,
+}
+
+#[derive(Debug, Default)]
+pub struct Palette {
+    pub query: String,
+    pub cursor: usize, // among the matches
+    entries: Vec<Entry>,
+}
+
+impl Palette {
+    // The shell history is added once it's read, see add
+    pub fn new<'a>(defined: impl Iterator<Item = &'a str>, history: impl Iterator<Item = &'a str>) -> Self {
+        let mut palette = Palette::default();
+        palette.add(defined, Source::Defined);
+        palette.add(history, Source::History);
+        palette
+    }
+    // A command line already offered keeps its first source
+    pub fn add<'a>(&mut self, command_lines: impl Iterator<Item = &'a str>, source: Source) {
+        for command_line in command_lines {
+            if !self.entries.iter().any(|e| e.command_line == command_line) {
+                self.entries.push(Entry { command_line: command_line.to_owned(), source });
+            }
+        }
+    }
+    // The entries matching the query, best first. Without a query, in the order they were added.
+    pub fn matches(&self) -> Vec<&Entry> {
+        let mut matches: Vec<(i64, usize, &Entry)> = self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| fuzzy_score(&self.query, &entry.command_line).map(|score| (score, i, entry)))
+            .collect();
+        matches.sort_by_key(|(score, i, _)| (Reverse(*score), *i));
+        matches.into_iter().take(MAX_MATCHES).map(|(_, _, entry)| entry).collect()
+    }
+    // The command to run: the match under the cursor, or what was typed when nothing matches
+    pub fn chosen(&self) -> Option<String> {
+        match self.matches().get(self.cursor) {
+            Some(entry) => Some(entry.command_line.clone()),
+            None => Some(self.query.trim().to_owned()).filter(|q| !q.is_empty()),
+        }
+    }
+    pub fn type_char(&mut self, c: char) {
+        self.query.push(c);
+        self.cursor = 0;
+    }
+    pub fn delete_char(&mut self) {
+        self.query.pop();
+        self.cursor = 0;
+    }
+    pub fn move_cursor(&mut self, down: bool) {
+        if down {
+            self.cursor = (self.cursor + 1).min(self.matches().len().saturating_sub(1));
+        } else {
+            self.cursor = self.cursor.saturating_sub(1);
+        }
+    }
+}
+
+// The characters of the query appear in the candidate in order, not necessarily next to each
+// other, ignoring case. Runs of consecutive characters and characters starting a word score
+// higher, and so do shorter candidates.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let candidate: Vec<char> = candidate.chars().flat_map(|c| c.to_lowercase()).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for query_char in query.chars().flat_map(|c| c.to_lowercase()) {
+        let found = (position..candidate.len()).find(|i| candidate[*i] == query_char)?;
+        score += 1;
+        if previous_match.map(|p| p + 1 == found).unwrap_or(false) {
+            score += 5;
+        }
+        if found == 0 || matches!(candidate[found - 1], ' ' | '-' | '_' | '/' | ':' | '.') {
+            score += 3;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    Some(score * 100 - candidate.len() as i64)
+}
+
+// A command run from the palette. It's not part of the run: it doesn't count towards its result,
+// and isn't stopped or restarted with it.
+#[derive(Debug, Clone)]
+pub struct OneOff {
+    pub command_line: String,
+    pub pane_id: Option<u32>,
+    pub start_time: Option<Instant>,
+    pub end_time: Option<Instant>,
+    pub exit_status: Option<i32>,
+}
+
+impl OneOff {
+    pub fn new(command_line: &str) -> Self {
+        OneOff { command_line: command_line.to_owned(), pane_id: None, start_time: None, end_time: None, exit_status: None }
+    }
+    pub fn is_running(&self) -> bool {
+        self.start_time.is_some() && self.end_time.is_none()
+    }
+    pub fn duration(&self) -> Option<Duration> {
+        self.start_time.map(|start_time| self.end_time.unwrap_or_else(Instant::now).duration_since(start_time))
+    }
+}
@@ -0,0 +1,32 @@
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
+
+// Later commands (and the on_success/on_failure hooks) can use what earlier ones did through
+// placeholders resolved when they are launched, eg. echo "{{output:version}}" or
+// {{exit_code:build}}. Commands are referred to by name (the name attribute) or by position,
+// starting at 1.
+const PLACEHOLDER_PATTERN: &str = r"\{\{\s*(\w+):\s*([^}]+?)\s*\}\}";
+
+// Where the stdout of a capture_output command is kept, inside the run's temp folder
+pub fn output_file(run_tmp_dir: &Path, command_index: usize) -> PathBuf {
+    run_tmp_dir.join(format!("output-{}", command_index))
+}
+
+// Placeholders that can't be resolved are left as they are
+pub fn interpolate<F>(command_line: &str, resolve: F) -> String
+    where F: Fn(&str, &str) -> Option<String>,
+{
+    let regex = match Regex::new(PLACEHOLDER_PATTERN) {
+        Ok(regex) => regex,
+        Err(_) => return command_line.to_owned(),
+    };
+    regex.replace_all(command_line, |captures: &Captures| {
+        match resolve(&captures[1], &captures[2]) {
+            Some(value) => value,
+            None => {
+                eprintln!("Failed to resolve {} in {}", &captures[0], command_line);
+                captures[0].to_owned()
+            }
+        }
+    }).to_string()
+}
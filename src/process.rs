@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zellij_tile::prelude::{run_command, CommandToRun};
+
+use crate::storage;
+
+pub const PIDS_FOLDER: &str = ".zlaunch/pids";
+pub const DEFAULT_SHELL_ARGS: &[&str] = &["-ic", "{cmd}"];
+const COMMAND_PLACEHOLDER: &str = "{cmd}";
+
+// Runs the command line through the shell invocation (eg. ["nu", "-c", "{cmd}"]), "{cmd}" being
+// replaced by the command line. If the template has no "{cmd}", the command line is appended.
+pub fn shell_command(shell_invocation: &[String], command_line: &str) -> CommandToRun {
+    let (shell, template) = match shell_invocation.split_first() {
+        Some((shell, template)) => (shell.as_str(), template),
+        None => ("sh", &[][..]),
+    };
+    let mut args: Vec<String> = template.iter().map(|arg| arg.replace(COMMAND_PLACEHOLDER, command_line)).collect();
+    if !template.iter().any(|arg| arg.contains(COMMAND_PLACEHOLDER)) {
+        args.push(command_line.to_owned());
+    }
+    CommandToRun::new_with_args(shell, args)
+}
+
+// Runs the command line on another machine, in the folder if it's not the current one (relative
+// folders being relative to the remote home). -t gives it a terminal, so that it behaves as in the
+// pane and that it's stopped along with ssh. ssh exits with the exit code of the command.
+pub fn ssh_command(host: &str, folder: &str, command_line: &str) -> CommandToRun {
+    let remote_command_line = if folder == "." || folder.is_empty() {
+        command_line.to_owned()
+    } else {
+        format!("cd '{}' && {}", folder.replace('\'', "'\\''"), command_line)
+    };
+    CommandToRun::new_with_args("ssh", vec!["-t".to_owned(), host.to_owned(), remote_command_line])
+}
+
+// Wraps a command so that the pid of its pane's process is written to `pid_path` (a path on the
+// host). The command is exec'd, so this pid is also the id of the session every process in the
+// pane belongs to.
+pub fn wrap_with_pid_file(command_to_run: CommandToRun, pid_path: &Path) -> CommandToRun {
+    let script = r#"echo $$ > "$0"; exec "$@""#;
+    let mut args = vec![
+        "-c".to_owned(),
+        script.to_owned(),
+        pid_path.to_string_lossy().to_string(),
+        command_to_run.path.to_string_lossy().to_string(),
+    ];
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("sh", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
+
+// Wraps a command so that the given directories come first in its PATH, in the order given.
+// Relative directories are relative to the command's working directory and ~/ is expanded.
+pub fn wrap_with_path_prepend(command_to_run: CommandToRun, directories: &[String]) -> CommandToRun {
+    let script = r#"while [ "$1" != "--" ]; do case "$1" in /*) dir="$1";; "~/"*) dir="$HOME/${1#\~/}";; *) dir="$PWD/$1";; esac; PATH="$dir:$PATH"; shift; done; shift; export PATH; exec "$@""#;
+    let mut args = vec!["-c".to_owned(), script.to_owned(), "zlaunch-path".to_owned()];
+    // each directory is prepended in turn, so the first one has to come last
+    args.extend(directories.iter().rev().cloned());
+    args.push("--".to_owned());
+    args.push(command_to_run.path.to_string_lossy().to_string());
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("sh", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
+
+// Named after the instance too, instances sharing a project folder have their own command #1
+pub fn pid_file_path(command_index: usize) -> PathBuf {
+    PathBuf::from(PIDS_FOLDER).join(format!("{}-{}", storage::instance_file_name("pid"), command_index))
+}
+
+// Sends a signal (eg. "STOP" or "CONT") to every process in the pane of the command
+pub fn signal_command(command_index: usize, signal: &str) {
+    let pid_file = Path::new("/host").join(pid_file_path(command_index));
+    match fs::read_to_string(&pid_file).map(|pid| pid.trim().to_owned()) {
+        Ok(pid) if !pid.is_empty() => {
+            let mut context = BTreeMap::new();
+            context.insert("signal".to_owned(), signal.to_owned());
+            run_command(&["pkill", &format!("-{}", signal), "-s", &pid], context);
+        }
+        _ => eprintln!("No pid recorded for command {}, can't send it {}", command_index, signal),
+    }
+}
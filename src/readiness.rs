@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use zellij_tile::prelude::run_command_with_env_variables_and_cwd;
+
+// Probes run on the host until a background command is up, their result arrives as a
+// RunCommandResult with this context
+pub const READINESS_PROBE_CONTEXT: &str = "readiness_probe";
+
+// When a background command is considered up, eg. ready_when_port=5432 or
+// ready_when_cmd="curl -sf localhost:8080/health". Later stages wait for it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadyWhen {
+    Port(u16), // something listens on this port of localhost
+    Command(String), // this command exits successfully, run in the folder of the commands
+}
+
+impl ReadyWhen {
+    // Probes once, the command index and run index are passed back in the context
+    pub fn probe(&self, folder: &Path, command_index: usize, current_run_index: usize) {
+        let mut context = BTreeMap::new();
+        context.insert(READINESS_PROBE_CONTEXT.to_owned(), String::new());
+        context.insert("command_index".to_owned(), command_index.to_string());
+        context.insert("current_run_index".to_owned(), current_run_index.to_string());
+        let port;
+        let probe: Vec<&str> = match self {
+            // nc isn't always installed, bash's /dev/tcp is the fallback
+            ReadyWhen::Port(p) => {
+                port = p.to_string();
+                vec![
+                    "sh",
+                    "-c",
+                    r#"nc -z 127.0.0.1 "$0" 2>/dev/null || bash -c 'exec 3<>"/dev/tcp/127.0.0.1/$0"' "$0" 2>/dev/null"#,
+                    &port,
+                ]
+            }
+            ReadyWhen::Command(command) => vec!["sh", "-c", command],
+        };
+        run_command_with_env_variables_and_cwd(&probe, BTreeMap::new(), folder.to_path_buf(), context);
+    }
+}
+
+impl std::fmt::Display for ReadyWhen {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadyWhen::Port(port) => write!(f, "port {}", port),
+            ReadyWhen::Command(command) => write!(f, "{}", command),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Commands run inside the session, so a command starting zellij (or zlaunch) again nests sessions
+// or spawns zlaunch instances that spawn their own commands, and so on. Commands like this are
+// refused unless recursion is explicitly allowed.
+
+// zellij subcommands that work on the current session rather than starting a new one
+const SESSION_SAFE_SUBCOMMANDS: &[&str] = &[
+    "action", "run", "edit", "pipe", "plugin", "list-sessions", "ls", "kill-session", "k",
+    "kill-all-sessions", "delete-session", "d", "delete-all-sessions", "setup", "convert-config",
+    "convert-layout", "convert-theme", "--help", "-h", "--version", "-V",
+];
+
+// Why the command line would recurse, if it would
+pub fn recursion_reason(command_line: &str) -> Option<&'static str> {
+    let words: Vec<&str> = command_line
+        .split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')' | '`'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        let program = word.rsplit('/').next().unwrap_or(word);
+        if program != "zellij" {
+            continue;
+        }
+        let launches_zlaunch = words[i + 1..].iter().any(|w| w.contains("zlaunch"));
+        match words.get(i + 1) {
+            _ if launches_zlaunch => return Some("it would launch zlaunch"),
+            Some(subcommand) if SESSION_SAFE_SUBCOMMANDS.contains(subcommand) => {}
+            _ => return Some("it would start a nested zellij session"),
+        }
+    }
+    None
+}
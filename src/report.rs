@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::bench;
+use crate::format::{format_duration, format_time_of_day};
+use crate::snapshot::now_ms;
+use crate::Command;
+
+pub const DEFAULT_REPORT_FILE: &str = ".zlaunch/report.md"; // relative to the host folder
+
+// What the report says about a command. Taken from the command by the plugin, the worker turns
+// the rows into Markdown (see worker::WriteReport).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReportRow {
+    pub command_line: String,
+    pub status: String,
+    pub duration: Option<Duration>,
+    pub exceeded_budget: Option<Duration>,
+    pub attempts: usize,
+    pub started_at_ms: Option<u64>, // since the unix epoch
+    pub allowed_failure: bool,
+    pub bench_samples: Vec<Duration>,
+}
+
+pub fn report_rows(commands: &[Command]) -> Vec<ReportRow> {
+    let now = Instant::now();
+    let now_ms = now_ms();
+    commands.iter().map(|command| {
+        let status = match command.exit_status {
+            Some(_) if command.succeeded() => "✔ success".to_owned(),
+            Some(exit_status) if command.hook_failure.is_some() => format!("✘ {} hook exit code {}", command.hook_failure.unwrap_or_default(), exit_status),
+            Some(exit_status) if command.options.allow_failure => format!("⚠ exit code {} (allowed)", exit_status),
+            Some(exit_status) => format!("✘ exit code {}", exit_status),
+            None if command.spawn_failure.is_some() => "✘ failed to spawn".to_owned(),
+            None if command.skipped => "skipped".to_owned(),
+            None if command.is_running() => "running".to_owned(),
+            None => "pending".to_owned(),
+        };
+        ReportRow {
+            command_line: command.command_line.clone(),
+            status,
+            duration: command.duration(),
+            exceeded_budget: command.options.budget.filter(|_| command.is_over_budget()),
+            attempts: command.attempts,
+            started_at_ms: command.start_time.map(|start_time| now_ms.saturating_sub(now.duration_since(start_time).as_millis() as u64)),
+            allowed_failure: command.is_allowed_failure(),
+            bench_samples: command.bench_samples.clone(),
+        }
+    }).collect()
+}
+
+// A Markdown table of the run, meant to be pasted eg. into a PR description
+pub fn markdown_report(rows: &[ReportRow], total_duration: Duration, precision: usize) -> String {
+    let mut report = String::new();
+    let allowed_failures = rows.iter().filter(|r| r.allowed_failure).count();
+    match allowed_failures {
+        0 => report.push_str(&format!("Ran {} commands in {}\n\n", rows.len(), format_duration(total_duration, precision))),
+        1 => report.push_str(&format!("Ran {} commands in {} (1 allowed failure)\n\n", rows.len(), format_duration(total_duration, precision))),
+        _ => report.push_str(&format!("Ran {} commands in {} ({} allowed failures)\n\n", rows.len(), format_duration(total_duration, precision), allowed_failures)),
+    }
+    report.push_str("| Command | Status | Duration | Attempts | Started at |\n");
+    report.push_str("| --- | --- | --- | --- | --- |\n");
+    for row in rows {
+        let mut duration = row.duration
+            .map(|duration| format_duration(duration, precision))
+            .unwrap_or_else(|| "-".to_owned());
+        if let Some(budget) = row.exceeded_budget {
+            duration.push_str(&format!(" ⚠ over budget ({})", format_duration(budget, 0)));
+        }
+        let started_at = row.started_at_ms
+            .map(format_time_of_day)
+            .unwrap_or_else(|| "-".to_owned());
+        report.push_str(&format!(
+            "| `{}` | {} | {} | {} | {} |\n",
+            row.command_line.replace('|', "\\|").replace('`', "'"),
+            row.status,
+            duration,
+            row.attempts,
+            started_at
+        ));
+    }
+    if rows.iter().any(|r| !r.bench_samples.is_empty()) {
+        report.push('\n');
+        report.push_str(&bench::markdown_bench_table(rows, precision));
+    }
+    report
+}
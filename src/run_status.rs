@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::Path;
+
+use crate::storage;
+
+// Scripts wrapping a run (zlaunch && deploy.sh) branch on its result with the marker written in
+// the host folder when it ends, eg.
+//   failure
+//   command=cargo test
+//   exit_code=101
+// or just success. The first line is enough for [ "$(head -n 1 .zlaunch-status)" = success ].
+pub const RUN_STATUS_FILE: &str = "/host/.zlaunch-status";
+
+// The command line and exit code of the first command that failed the run, the exit code is
+// unknown when it was stopped or failed to spawn
+pub fn status_marker(succeeded: bool, first_failure: Option<(&str, Option<i32>)>) -> String {
+    if succeeded {
+        return "success\n".to_owned();
+    }
+    let mut marker = "failure\n".to_owned();
+    if let Some((command_line, exit_code)) = first_failure {
+        marker.push_str(&format!("command={}\n", command_line.replace('\n', " ")));
+        if let Some(exit_code) = exit_code {
+            marker.push_str(&format!("exit_code={}\n", exit_code));
+        }
+    }
+    marker
+}
+
+pub fn write(marker: &str) {
+    if let Err(e) = storage::write_atomic(Path::new(RUN_STATUS_FILE), marker) {
+        eprintln!("Failed to write the run status to {}: {}", RUN_STATUS_FILE, e);
+    }
+}
+
+// A script shouldn't mistake the result of the previous run for the one of this run
+pub fn clear() {
+    match fs::remove_file(RUN_STATUS_FILE) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to remove {}: {}", RUN_STATUS_FILE, e),
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use zellij_tile::prelude::CommandToRun;
+
+use crate::storage;
+
+pub const RUN_TMP_FOLDER: &str = ".zlaunch/tmp";
+pub const RUN_TMP_ENV_VAR: &str = "ZLAUNCH_TMPDIR";
+
+// Every run gets its own scratch folder, named after the instance and the run so that runs of
+// several instances sharing a project never collide
+pub fn run_tmp_dir(run_index: usize) -> PathBuf {
+    PathBuf::from(RUN_TMP_FOLDER).join(format!("{}-{}", storage::instance_file_name("run"), run_index))
+}
+
+// Holds the path (on the host) of the current run's temp folder. Commands read it when they
+// start, so that a pane re-running its command in a later run gets the folder of that run
+pub fn current_run_tmp_dir_file() -> PathBuf {
+    PathBuf::from(RUN_TMP_FOLDER).join(storage::instance_file_name("current"))
+}
+
+pub fn set_current_run_tmp_dir(host_run_tmp_dir: &Path) {
+    if let Err(e) = storage::write_atomic(&storage::host_path(current_run_tmp_dir_file()), host_run_tmp_dir.to_string_lossy().as_bytes()) {
+        eprintln!("Failed to record the current run temp folder: {}", e);
+    }
+}
+
+pub fn forget_current_run_tmp_dir() {
+    match fs::remove_file(storage::host_path(current_run_tmp_dir_file())) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to remove the current run temp folder record: {}", e),
+    }
+}
+
+pub fn create_run_tmp_dir(run_tmp_dir: &Path) {
+    if let Err(e) = fs::create_dir_all(storage::host_path(run_tmp_dir)) {
+        eprintln!("Failed to create run temp folder {}: {}", run_tmp_dir.display(), e);
+    }
+}
+
+pub fn remove_run_tmp_dir(run_tmp_dir: &Path) {
+    match fs::remove_dir_all(storage::host_path(run_tmp_dir)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to remove run temp folder {}: {}", run_tmp_dir.display(), e),
+    }
+}
+
+// Wraps a command so that it sees the current run's temp folder (a path on the host, read from
+// current_run_tmp_dir_file) as $ZLAUNCH_TMPDIR. The folder is created again if needed, eg. when
+// it was deleted in the meantime.
+pub fn wrap_with_run_tmp_dir(command_to_run: CommandToRun, current_run_tmp_dir_file: &Path) -> CommandToRun {
+    let script = format!(r#"export {0}="$(cat "$0")"; mkdir -p "${0}"; exec "$@""#, RUN_TMP_ENV_VAR);
+    let mut args = vec![
+        "-c".to_owned(),
+        script,
+        current_run_tmp_dir_file.to_string_lossy().to_string(),
+        command_to_run.path.to_string_lossy().to_string(),
+    ];
+    args.extend(command_to_run.args);
+    let mut wrapped = CommandToRun::new_with_args("sh", args);
+    wrapped.cwd = command_to_run.cwd;
+    wrapped
+}
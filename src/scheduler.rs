@@ -0,0 +1,237 @@
+use std::ops::Range;
+use std::time::Duration;
+
+// The decisions of a run (which stage starts next, when a stage is over, when stop_on_failure
+// halts the run, when a failed command is retried and when the run is over) made from the state
+// of the commands alone. The plugin carries them out with panes, events and timers, so the control
+// flow doesn't depend on a Zellij host.
+
+// What the scheduler needs to know about a command
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandStatus {
+    pub stage: usize,
+    pub exited: bool,
+    pub succeeded: bool,
+    pub skipped: bool,
+    pub aborted: bool,
+    pub allowed_failure: bool, // failed, but with allow_failure
+    pub background: bool,
+    pub done: bool, // as far as its stage is concerned, see Command::is_done
+    pub babysitting: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunStatus {
+    pub running_command_index: Option<usize>, // the last command of the running stage
+    pub paused: bool,
+    pub waiting_for_setup: bool,
+    pub stop_on_failure: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Wait, // for the running stage, or for the user (paused, aborted)
+    Halt, // a command of the stage failed and stop_on_failure is on
+    StartStage(Range<usize>), // the indices of the commands of the next stage, run concurrently
+    Finish { succeeded: bool },
+}
+
+// Once a command exited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AfterExit {
+    Wait, // for the rest of its stage
+    NextStage,
+    RunEnded,
+}
+
+pub fn next_action(commands: &[CommandStatus], run: &RunStatus) -> Action {
+    if run.paused || run.waiting_for_setup || commands.iter().any(|c| c.aborted) {
+        return Action::Wait;
+    }
+    if let Some(stage) = running_stage(commands, run.running_command_index) {
+        if !stage_done(commands, stage) {
+            // the next stage starts once all the commands of this one are done
+            return Action::Wait;
+        }
+        if stage_failed(commands, stage) && run.stop_on_failure {
+            return Action::Halt;
+        }
+    }
+    let next_index = run.running_command_index.map(|i| i + 1).unwrap_or(0);
+    match commands.get(next_index) {
+        Some(next_command) => {
+            let stage_len = commands[next_index..].iter().take_while(|c| c.stage == next_command.stage).count();
+            Action::StartStage(next_index..next_index + stage_len)
+        }
+        None => Action::Finish { succeeded: run_succeeded(commands) },
+    }
+}
+
+pub fn after_exit(commands: &[CommandStatus], run: &RunStatus, command_stage: usize) -> AfterExit {
+    if running_stage(commands, run.running_command_index) == Some(command_stage) {
+        if stage_done(commands, command_stage) {
+            AfterExit::NextStage
+        } else {
+            AfterExit::Wait
+        }
+    } else if run_succeeded(commands) {
+        AfterExit::RunEnded
+    } else {
+        AfterExit::Wait
+    }
+}
+
+// babysat commands are re-run until they succeed, they're not done until then
+pub fn is_retried(command: &CommandStatus) -> bool {
+    command.babysitting && !command.succeeded
+}
+
+pub fn running_stage(commands: &[CommandStatus], running_command_index: Option<usize>) -> Option<usize> {
+    running_command_index.and_then(|i| commands.get(i)).map(|c| c.stage)
+}
+
+pub fn stage_done(commands: &[CommandStatus], stage: usize) -> bool {
+    commands.iter().filter(|c| c.stage == stage).all(|c| c.done)
+}
+
+pub fn stage_failed(commands: &[CommandStatus], stage: usize) -> bool {
+    commands.iter().filter(|c| c.stage == stage).any(|c| !(c.skipped || c.background && !c.exited || c.exited && c.succeeded || c.allowed_failure))
+}
+
+// None if the command at this index is in the first stage
+pub fn previous_stage_succeeded(commands: &[CommandStatus], command_index: usize) -> Option<bool> {
+    let previous_stage = commands.get(command_index.checked_sub(1)?)?.stage;
+    Some(commands.iter().filter(|c| c.stage == previous_stage).all(|c| c.succeeded || c.skipped || c.allowed_failure))
+}
+
+// services that are still up don't hold the run back
+pub fn run_succeeded(commands: &[CommandStatus]) -> bool {
+    commands.iter().all(|c| c.succeeded || c.skipped || c.allowed_failure || c.background && !c.exited)
+}
+
+// The order independent commands run in, from how long they usually take
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RunOrder {
+    #[default]
+    Definition,
+    FastestFirst, // quick failures surface early
+    SlowestFirst,
+}
+
+impl RunOrder {
+    pub fn from_str(order: &str) -> Option<Self> {
+        match order {
+            "definition" => Some(RunOrder::Definition),
+            "fastest-first" => Some(RunOrder::FastestFirst),
+            "slowest-first" => Some(RunOrder::SlowestFirst),
+            _ => None,
+        }
+    }
+}
+
+// The indices of the commands in the order they should run. Consecutive movable commands (each
+// a stage of its own) are sorted by their expected duration, the others stay in place and the
+// commands never move across them. Those without an expected duration come last, in their order.
+pub fn run_order(movable: &[bool], expected_durations: &[Option<Duration>], order: RunOrder) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..movable.len()).collect();
+    if order == RunOrder::Definition {
+        return indices;
+    }
+    let mut segment_start = 0;
+    while segment_start < indices.len() {
+        if !movable[segment_start] {
+            segment_start += 1;
+            continue;
+        }
+        let segment_len = movable[segment_start..].iter().take_while(|m| **m).count();
+        let segment = &mut indices[segment_start..segment_start + segment_len];
+        // sort_by_key is stable, so ties keep their order
+        segment.sort_by_key(|i| match (expected_durations[*i], order) {
+            (None, _) => (1, Duration::ZERO),
+            (Some(duration), RunOrder::SlowestFirst) => (0, Duration::MAX - duration),
+            (Some(duration), _) => (0, duration),
+        });
+        segment_start += segment_len;
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(stage: usize) -> CommandStatus {
+        CommandStatus { stage, ..Default::default() }
+    }
+
+    fn exited(stage: usize, succeeded: bool) -> CommandStatus {
+        CommandStatus { stage, exited: true, succeeded, done: true, ..Default::default() }
+    }
+
+    fn running(running_command_index: usize) -> RunStatus {
+        RunStatus { running_command_index: Some(running_command_index), ..Default::default() }
+    }
+
+    #[test]
+    fn starts_the_commands_of_the_next_stage_together() {
+        let commands = [pending(0), pending(0), pending(1)];
+        assert_eq!(next_action(&commands, &RunStatus::default()), Action::StartStage(0..2));
+        let commands = [exited(0, true), exited(0, true), pending(1)];
+        assert_eq!(next_action(&commands, &running(1)), Action::StartStage(2..3));
+    }
+
+    #[test]
+    fn waits_for_the_running_stage_and_for_the_user() {
+        let commands = [exited(0, true), pending(0), pending(1)];
+        assert_eq!(next_action(&commands, &running(1)), Action::Wait);
+        let paused = RunStatus { paused: true, ..Default::default() };
+        assert_eq!(next_action(&[pending(0)], &paused), Action::Wait);
+        let aborted = CommandStatus { aborted: true, ..pending(0) };
+        assert_eq!(next_action(&[aborted], &RunStatus::default()), Action::Wait);
+    }
+
+    #[test]
+    fn halts_on_a_failure_only_with_stop_on_failure() {
+        let commands = [exited(0, false), pending(1)];
+        let stop_on_failure = RunStatus { stop_on_failure: true, ..running(0) };
+        assert_eq!(next_action(&commands, &stop_on_failure), Action::Halt);
+        assert_eq!(next_action(&commands, &running(0)), Action::StartStage(1..2));
+        let allowed_failure = CommandStatus { allowed_failure: true, ..exited(0, false) };
+        assert_eq!(next_action(&[allowed_failure, pending(1)], &stop_on_failure), Action::StartStage(1..2));
+    }
+
+    #[test]
+    fn finishes_once_every_stage_ran() {
+        assert_eq!(next_action(&[exited(0, true), exited(1, true)], &running(1)), Action::Finish { succeeded: true });
+        assert_eq!(next_action(&[exited(0, false), exited(1, true)], &running(1)), Action::Finish { succeeded: false });
+    }
+
+    #[test]
+    fn moves_on_once_the_stage_of_the_command_is_done() {
+        assert_eq!(after_exit(&[exited(0, true), pending(0), pending(1)], &running(1), 0), AfterExit::Wait);
+        assert_eq!(after_exit(&[exited(0, true), exited(0, false), pending(1)], &running(1), 0), AfterExit::NextStage);
+        // eg. a re-run of a command of a previous stage once the run is over
+        assert_eq!(after_exit(&[exited(0, true), exited(1, true)], &running(1), 0), AfterExit::RunEnded);
+        assert_eq!(after_exit(&[exited(0, true), exited(1, false)], &running(1), 0), AfterExit::Wait);
+    }
+
+    #[test]
+    fn services_that_are_up_dont_fail_the_run() {
+        let service = CommandStatus { background: true, ..pending(0) };
+        assert!(run_succeeded(&[service, exited(1, true)]));
+        let crashed_service = CommandStatus { background: true, ..exited(0, false) };
+        assert!(!run_succeeded(&[crashed_service, exited(1, true)]));
+        let skipped = CommandStatus { skipped: true, ..pending(0) };
+        assert!(run_succeeded(&[skipped, exited(1, true)]));
+    }
+
+    #[test]
+    fn sorts_consecutive_movable_commands_by_their_usual_duration() {
+        let secs = |s| Some(Duration::from_secs(s));
+        let movable = [true, true, true, false, true, true];
+        let expected_durations = [secs(30), None, secs(10), secs(1), secs(5), secs(50)];
+        assert_eq!(run_order(&movable, &expected_durations, RunOrder::Definition), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(run_order(&movable, &expected_durations, RunOrder::FastestFirst), vec![2, 0, 1, 3, 4, 5]);
+        assert_eq!(run_order(&movable, &expected_durations, RunOrder::SlowestFirst), vec![0, 2, 1, 3, 5, 4]);
+    }
+}
@@ -0,0 +1,665 @@
+use serde::Serialize;
+
+// The configuration zlaunch understands, returned through the `schema` pipe so that external
+// tools can validate configurations. This must be kept in sync with the parsing in main.rs
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct OptionSchema {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct Schema {
+    pub version: u32,
+    pub options: &'static [OptionSchema],
+    pub command_attributes: &'static [OptionSchema],
+}
+
+pub const OPTIONS: &[OptionSchema] = &[
+    OptionSchema {
+        name: "commands",
+        kind: "kdl",
+        default: None,
+        description: "The commands to run, either as KDL nodes (one per command, stage nodes group commands that run concurrently), as a TOML or YAML commands list with the same attributes, or separated by &&",
+    },
+    OptionSchema {
+        name: "profiles",
+        kind: "kdl",
+        default: None,
+        description: "Named command sets, one KDL node per profile with the commands as its children and properties overriding other options (eg. release stop_on_failure=true { ... }), cycled with n",
+    },
+    OptionSchema {
+        name: "profile",
+        kind: "string",
+        default: None,
+        description: "The profile whose commands are run instead of the commands option",
+    },
+    OptionSchema {
+        name: "on_success_run_profile",
+        kind: "string",
+        default: None,
+        description: "A profile started when all commands succeed, in this instance if it has the profile or otherwise through a zlaunch_run_profile pipe message",
+    },
+    OptionSchema {
+        name: "panes_to_run_on_completion",
+        kind: "kdl",
+        default: None,
+        description: "Panes to re-run (or reload, for plugins) by title, regex \"pattern\", terminal <id>, plugin <id> or tab \"name or position\", with an optional on=\"run_start first_failure stage_end run_end\"",
+    },
+    OptionSchema {
+        name: "show_descriptions",
+        kind: "always|selected",
+        default: Some("always"),
+        description: "Whether the desc of every command is shown under it in the list, or only that of the selected command",
+    },
+    OptionSchema {
+        name: "run_completion_panes_on",
+        kind: "success|failure|always",
+        default: Some("success"),
+        description: "Which run endings trigger the run_end completion panes: success, failure (including a halt by stop_on_failure) or always",
+    },
+    OptionSchema {
+        name: "config_file",
+        kind: "path",
+        default: None,
+        description: "The file the commands are defined in (eg. .zlaunch.kdl), opened with o at the selected command",
+    },
+    OptionSchema {
+        name: "shell_history_file",
+        kind: "path",
+        default: Some("~/.bash_history"),
+        description: "The shell history commands are imported from with i (bash, zsh and fish formats)",
+    },
+    OptionSchema {
+        name: "on_success",
+        kind: "kdl",
+        default: None,
+        description: "Commands run in background panes when all commands exit successfully, either as KDL nodes or separated by &&",
+    },
+    OptionSchema {
+        name: "on_failure",
+        kind: "kdl",
+        default: None,
+        description: "Commands run in background panes when the run stops due to a failure, either as KDL nodes or separated by &&",
+    },
+    OptionSchema {
+        name: "on_slow",
+        kind: "kdl",
+        default: None,
+        description: "Commands run in background panes when a command runs slow_command_factor times longer than usual, with the command line in ZLAUNCH_SLOW_COMMAND, either as KDL nodes or separated by &&",
+    },
+    OptionSchema {
+        name: "slow_command_factor",
+        kind: "number",
+        default: Some("2"),
+        description: "Running commands that take this many times longer than their average in the history are shown in the pending color and trigger on_slow, 0 to turn this off",
+    },
+    OptionSchema {
+        name: "completion_hooks",
+        kind: "kdl",
+        default: None,
+        description: "Commands run in background panes once a stage or all the commands with a tag are done, eg. stage \"checks\" \"notify-send done\" or tag \"docker\" \"docker compose down\" (stages by name or position)",
+    },
+    OptionSchema {
+        name: "affected_base",
+        kind: "string",
+        default: Some("HEAD"),
+        description: "The git revision the paths of the commands are compared to before every run (git diff --name-only, plus the untracked files), eg. origin/main",
+    },
+    OptionSchema {
+        name: "teardown",
+        kind: "kdl",
+        default: None,
+        description: "Commands run in order in the background after every run (whether it succeeded, failed, was halted by stop_on_failure or aborted) or when zlaunch closes in the middle of one, either as KDL nodes or separated by &&",
+    },
+    OptionSchema {
+        name: "setup",
+        kind: "kdl",
+        default: None,
+        description: "Commands run in order in the background before the first command of every run, either as KDL nodes or separated by &&. If one fails, the run fails without running any command",
+    },
+    OptionSchema {
+        name: "shell",
+        kind: "string",
+        default: Some("bash"),
+        description: "The shell used to run the commands",
+    },
+    OptionSchema {
+        name: "shell_args",
+        kind: "string",
+        default: Some("-ic {cmd}"),
+        description: "Space separated shell arguments, {cmd} standing for the command line (eg. \"-l -c {cmd}\")",
+    },
+    OptionSchema {
+        name: "exec",
+        kind: "bool",
+        default: Some("false"),
+        description: "Run the commands directly, split on whitespace, rather than through the interactive shell",
+    },
+    OptionSchema {
+        name: "quiet",
+        kind: "bool|full",
+        default: Some("false"),
+        description: "Open the command panes in the background, only showing those that fail. With \"full\", no command gets a visible pane (whatever its own quiet attribute) until it fails and the output of every command is kept in a log file under .zlaunch/logs",
+    },
+    OptionSchema {
+        name: "open_in_new_tab",
+        kind: "bool",
+        default: Some("false"),
+        description: "Open the command panes tiled in a dedicated \"zlaunch\" tab, created if needed",
+    },
+    OptionSchema {
+        name: "stack_panes",
+        kind: "bool",
+        default: Some("false"),
+        description: "Show one command pane at a time (the latest to start) instead of one floating pane per command, cycled with v",
+    },
+    OptionSchema {
+        name: "tile_failed_panes",
+        kind: "bool",
+        default: Some("false"),
+        description: "When the run fails, move the panes of the failed commands side by side into a \"zlaunch failures\" tab instead of floating them on top of each other",
+    },
+    OptionSchema {
+        name: "auto_resize",
+        kind: "bool",
+        default: Some("false"),
+        description: "Grow or shrink the zlaunch pane to fit the command list as commands are added or removed",
+    },
+    OptionSchema {
+        name: "folder",
+        kind: "path",
+        default: Some("."),
+        description: "The folder in which the commands are run",
+    },
+    OptionSchema {
+        name: "stop_on_failure",
+        kind: "bool",
+        default: Some("false"),
+        description: "Stop the run when a command fails",
+    },
+    OptionSchema {
+        name: "focus_follow",
+        kind: "bool",
+        default: Some("false"),
+        description: "Focus the pane of the running command (floating it if it's hidden) as the run goes, toggled with F",
+    },
+    OptionSchema {
+        name: "observer",
+        kind: "bool",
+        default: Some("false"),
+        description: "Mirror the state of another zlaunch instance without being able to trigger actions",
+    },
+    OptionSchema {
+        name: "failure_screenshots",
+        kind: "bool",
+        default: Some("false"),
+        description: "Save the output of failed commands to .zlaunch/failures",
+    },
+    OptionSchema {
+        name: "dump_failed_panes",
+        kind: "bool",
+        default: Some("false"),
+        description: "Before the panes are closed at the end of a run or on a restart, save the output of each failed command to .zlaunch/failures/<name>.log (by its name, or its command line)",
+    },
+    OptionSchema {
+        name: "failure_tail_lines",
+        kind: "number",
+        default: Some("0"),
+        description: "Show this many of the last lines a failed command printed under it in the list",
+    },
+    OptionSchema {
+        name: "live_tail",
+        kind: "bool",
+        default: Some("false"),
+        description: "Keep the bottom half of the pane for the latest output of the selected command (or the running one), so that the command panes can stay hidden. The output is captured as the commands run",
+    },
+    OptionSchema {
+        name: "log_output",
+        kind: "bool",
+        default: Some("false"),
+        description: "Keep the output of every command in timestamped log files under .zlaunch/logs",
+    },
+    OptionSchema {
+        name: "collapse_patterns",
+        kind: "kdl",
+        default: None,
+        description: "Regular expressions (one per KDL node or line) for noise lines collapsed in captured output",
+    },
+    OptionSchema {
+        name: "duration_precision",
+        kind: "integer",
+        default: Some("1"),
+        description: "Decimal places shown for durations under an hour",
+    },
+    OptionSchema {
+        name: "watch",
+        kind: "bool",
+        default: Some("false"),
+        description: "Re-run when files in the project change",
+    },
+    OptionSchema {
+        name: "watch_idle_seconds",
+        kind: "number",
+        default: None,
+        description: "In watch mode, wait until the files stopped changing for this many seconds before starting a run",
+    },
+    OptionSchema {
+        name: "watch_ignore",
+        kind: "string",
+        default: Some("target .git node_modules .zlaunch .zlaunch-history.json .zlaunch-status"),
+        description: "Space separated path components ignored by watch mode",
+    },
+    OptionSchema {
+        name: "order",
+        kind: "definition|fastest-first|slowest-first",
+        default: Some("definition"),
+        description: "The order consecutive commands marked independent=true run in, from their durations in the history (those without any run last). The other commands stay in place, as do independent ones in stages with several or named commands, services, and those with capture_output, stdin_from, placeholders or a condition. The list and the configuration keep the definition order",
+    },
+    OptionSchema {
+        name: "run_tags",
+        kind: "string",
+        default: None,
+        description: "Space separated tags, only the commands with one of them run (eg. fast for a quick check), the others are skipped. T changes it at runtime",
+    },
+    OptionSchema {
+        name: "skip_tags",
+        kind: "string",
+        default: None,
+        description: "Space separated tags, the commands with one of them are skipped",
+    },
+    OptionSchema {
+        name: "on_sibling_instance",
+        kind: "observe|refuse|take_over|ignore",
+        default: Some("observe"),
+        description: "What to do when another zlaunch instance is already running the same commands in the same folder: mirror its run read-only, wait for ENTER to start anyway, abort its run and run here, or run anyway",
+    },
+    OptionSchema {
+        name: "on_retrigger",
+        kind: "queue|restart|skip",
+        default: Some("queue"),
+        description: "What to do when watch mode triggers while a command is still running",
+    },
+    OptionSchema {
+        name: "theme",
+        kind: "kdl",
+        default: None,
+        description: "Palette indices for the running, success, failure, pending, command, selected, key_hint, skipped and warning roles",
+    },
+    OptionSchema {
+        name: "status_icons",
+        kind: "kdl",
+        default: None,
+        description: "Prefixes of the commands in the list and in their pane titles: glyphs (✔ ✘ ▶ ⏳ ⏭), ascii ([OK] [FAIL] [RUN] [WAIT] [SKIP]) or success, failure, running, pending and skipped nodes overriding the glyphs. Pane titles use the glyphs when unset, the list no prefixes",
+    },
+    OptionSchema {
+        name: "accessible",
+        kind: "bool",
+        default: Some("false"),
+        description: "Always convey status with symbols or text (eg. ✔/✘ marks, ╳ for failures in bars) rather than by color alone",
+    },
+    OptionSchema {
+        name: "color_blind",
+        kind: "bool",
+        default: Some("false"),
+        description: "Start from a theme where success and failure aren't green and red, and turn on accessible, the theme option still overrides its roles",
+    },
+    OptionSchema {
+        name: "high_contrast",
+        kind: "bool",
+        default: Some("false"),
+        description: "Start from a theme where every status has its own color, the theme option still overrides its roles",
+    },
+    OptionSchema {
+        name: "plain_ui",
+        kind: "bool",
+        default: Some("false"),
+        description: "Show the commands as plain lines of text prefixed by their status (eg. [RUNNING], [OK 3s], [FAIL 1]) in the order they run, without colors or glyphs: friendlier to screen readers and to copy-paste",
+    },
+    OptionSchema {
+        name: "minimal_mode",
+        kind: "bool",
+        default: Some("false"),
+        description: "Only render a progress ribbon and counts (always on in panes of 3 rows or less)",
+    },
+    OptionSchema {
+        name: "confirm_restart",
+        kind: "bool",
+        default: Some("true"),
+        description: "Ask for confirmation before ENTER restarts a run that isn't over",
+    },
+    OptionSchema {
+        name: "rerun_in_same_pane",
+        kind: "bool",
+        default: Some("true"),
+        description: "When a run restarts, re-run the commands that exited in the pane they ran in (keeping its scrollback) instead of closing it and opening a new one",
+    },
+    OptionSchema {
+        name: "summary_screen",
+        kind: "bool",
+        default: Some("false"),
+        description: "When the run ends, show the commands sorted by duration instead of closing the plugin",
+    },
+    OptionSchema {
+        name: "labels",
+        kind: "string",
+        default: None,
+        description: "Labels attached to the runs in the history (eg. \"branch=feature-x\"), which it can be filtered by with g. Also set through a zlaunch_label pipe message",
+    },
+    OptionSchema {
+        name: "summary_pane",
+        kind: "bool",
+        default: Some("false"),
+        description: "Open a pane with the report of the run when it ends, which stays around after the command panes are closed",
+    },
+    OptionSchema {
+        name: "pipe_run_status",
+        kind: "bool",
+        default: Some("false"),
+        description: "Keep a zellij pipe from the CLI that sent commands open until their run ends, and answer it with the run status also written to .zlaunch-status",
+    },
+    OptionSchema {
+        name: "env_file",
+        kind: "path",
+        default: None,
+        description: "A dotenv file (eg. \".env\", relative to the folder zlaunch was started in) whose variables are passed to every command, read again on restart",
+    },
+    OptionSchema {
+        name: "report_file",
+        kind: "path",
+        default: Some(".zlaunch/report.md"),
+        description: "Where the Markdown timing report of the run is written, with m or when the run ends",
+    },
+    OptionSchema {
+        name: "plugin_location",
+        kind: "string",
+        default: Some("file:~/.config/zellij/plugins/zlaunch.wasm"),
+        description: "Where zlaunch is installed, for the layouts (l) and the install files (L) it writes",
+    },
+    OptionSchema {
+        name: "install_key",
+        kind: "string",
+        default: Some("Alt l"),
+        description: "The key bound to launch zlaunch in the keybinding written with L, next to a layout, in .zlaunch/install",
+    },
+    OptionSchema {
+        name: "report_on_run_end",
+        kind: "bool",
+        default: Some("false"),
+        description: "Write the Markdown timing report every time a run ends",
+    },
+    OptionSchema {
+        name: "babysit_interval",
+        kind: "duration",
+        default: Some("5s"),
+        description: "How long a babysat command (b) waits before it is re-run after failing",
+    },
+    OptionSchema {
+        name: "capacity_hint",
+        kind: "integer",
+        default: None,
+        description: "How many commands the machine can comfortably run at once, a warning is shown for stages with more commands",
+    },
+    OptionSchema {
+        name: "template",
+        kind: "rust|node|python|go|make",
+        default: None,
+        description: "A built-in pipeline to run when no commands are configured",
+    },
+    OptionSchema {
+        name: "allow_recursion",
+        kind: "bool",
+        default: Some("false"),
+        description: "Run commands that would start a nested zellij session or launch zlaunch, which are skipped otherwise",
+    },
+    OptionSchema {
+        name: "start_delay_secs",
+        kind: "number",
+        default: None,
+        description: "Start the first run this many seconds after zlaunch loads",
+    },
+    OptionSchema {
+        name: "start_paused",
+        kind: "bool",
+        default: Some("false"),
+        description: "Show the commands without running them until ENTER is pressed, eg. to skip or reorder some first",
+    },
+    OptionSchema {
+        name: "repeat_every_secs",
+        kind: "number",
+        default: None,
+        description: "Start a new run this often, skipped while the previous run is still active",
+    },
+    OptionSchema {
+        name: "termination_grace_period",
+        kind: "duration",
+        default: Some("3s"),
+        description: "How long a running command has to exit after the ctrl-c it gets on restart, abort or when the run ends, before its pane is closed (0 closes it right away)",
+    },
+    OptionSchema {
+        name: "spawn_timeout_secs",
+        kind: "number",
+        default: Some("10"),
+        description: "How long a command pane has to open before the command is failed (eg. because of a pane limit or a host error)",
+    },
+    OptionSchema {
+        name: "loop",
+        kind: "integer|until-failure",
+        default: None,
+        description: "Chain runs automatically: this many times (failures don't stop the loop), or until a run fails",
+    },
+    OptionSchema {
+        name: "bench",
+        kind: "integer",
+        default: None,
+        description: "Run every command this many times in a row and show its min/mean/max/std dev timings when the run ends, also in the report",
+    },
+    OptionSchema {
+        name: "first_run_wizard",
+        kind: "bool",
+        default: Some("true"),
+        description: "Walk through a short setup writing .zlaunch.kdl when there are no commands and no .zlaunch.kdl yet",
+    },
+];
+
+pub const COMMAND_ATTRIBUTES: &[OptionSchema] = &[
+    OptionSchema {
+        name: "on_retrigger",
+        kind: "queue|restart|skip",
+        default: None,
+        description: "Overrides the global on_retrigger policy for this command",
+    },
+    OptionSchema {
+        name: "name",
+        kind: "string",
+        default: None,
+        description: "How later commands and hooks refer to this one in placeholders ({{output:<name>}}, {{exit_code:<name>}}, {{duration:<name>}}, {{status:<name>}}) and stdin_from (otherwise by position, starting at 1)",
+    },
+    OptionSchema {
+        name: "interactive",
+        kind: "bool",
+        default: Some("false"),
+        description: "The command waits for the user (eg. git rebase -i): its pane is opened focused even when quiet, and it's shown as waiting for user input until it exits",
+    },
+    OptionSchema {
+        name: "matrix",
+        kind: "child node",
+        default: None,
+        description: "Parameters and their values (eg. matrix { rust_version \"stable\" \"beta\"; }), the command is expanded into one command per combination, running at once, with {{matrix:<name>}} replaced by the values in its command line, script and desc",
+    },
+    OptionSchema {
+        name: "host",
+        kind: "string",
+        default: None,
+        description: "Run the command on another machine through ssh -t (eg. user@server), in the folder if it's set (relative to the remote home). Also on a stage, for the commands in it without their own. The env, captures and logs stay on this machine",
+    },
+    OptionSchema {
+        name: "desc",
+        kind: "string",
+        default: None,
+        description: "What the command does, shown on a line of its own under it in the list (see show_descriptions)",
+    },
+    OptionSchema {
+        name: "tags",
+        kind: "string",
+        default: None,
+        description: "Space separated tags, the completion_hooks of a tag run once all the commands with it are done, and run_tags/skip_tags choose which commands run by them",
+    },
+    OptionSchema {
+        name: "before",
+        kind: "string",
+        default: None,
+        description: "Run in a hidden pane before the command (eg. starting a test database), which is failed without running if it fails",
+    },
+    OptionSchema {
+        name: "after",
+        kind: "string",
+        default: None,
+        description: "Run in a hidden pane once the command exited, whatever its exit code (eg. stopping a test database), the command only succeeds if it does too",
+    },
+    OptionSchema {
+        name: "capture_output",
+        kind: "bool",
+        default: Some("false"),
+        description: "Keep the stdout of the command for later commands, through {{output:<name>}} in their command line or stdin_from",
+    },
+    OptionSchema {
+        name: "stdin_from",
+        kind: "string",
+        default: None,
+        description: "Feed the output of an earlier capture_output command (by name or position) to this command's stdin",
+    },
+    OptionSchema {
+        name: "locked",
+        kind: "bool",
+        default: Some("false"),
+        description: "Prevents the command from being edited, skipped or removed at runtime",
+    },
+    OptionSchema {
+        name: "success_codes",
+        kind: "string",
+        default: Some("0"),
+        description: "Space separated exit codes considered successful for this command",
+    },
+    OptionSchema {
+        name: "budget",
+        kind: "duration",
+        default: None,
+        description: "The expected duration of the command (eg. \"30s\" or \"2m\"), longer runs are marked as over budget without being stopped",
+    },
+    OptionSchema {
+        name: "warmup",
+        kind: "bool",
+        default: Some("false"),
+        description: "A warm-up command (eg. cache priming), whose durations are left out of the history statistics",
+    },
+    OptionSchema {
+        name: "heavy",
+        kind: "bool",
+        default: Some("false"),
+        description: "A resource hungry command, a warning is shown when several of them are in the same stage",
+    },
+    OptionSchema {
+        name: "allow_failure",
+        kind: "bool",
+        default: Some("false"),
+        description: "An advisory command (eg. a lint step), its failure doesn't stop the run on failure nor fail it, and is counted on its own",
+    },
+    OptionSchema {
+        name: "independent",
+        kind: "bool",
+        default: Some("false"),
+        description: "The command doesn't need the ones before it, so the order option can run it earlier or later among the other independent commands next to it",
+    },
+    OptionSchema {
+        name: "background",
+        kind: "bool",
+        default: Some("false"),
+        description: "A service (eg. a dev server) that the run doesn't wait for, listed under Services and killed when the run ends or restarts",
+    },
+    OptionSchema {
+        name: "ready_when_port",
+        kind: "integer",
+        default: None,
+        description: "For a background command: later stages wait until something listens on this port of localhost",
+    },
+    OptionSchema {
+        name: "ready_when_cmd",
+        kind: "string",
+        default: None,
+        description: "For a background command: later stages wait until this command succeeds (eg. \"curl -sf localhost:8080/health\")",
+    },
+    OptionSchema {
+        name: "allow_recursion",
+        kind: "bool",
+        default: None,
+        description: "Overrides the global allow_recursion option for this command",
+    },
+    OptionSchema {
+        name: "path_prepend",
+        kind: "child node",
+        default: None,
+        description: "Directories put in front of PATH for this command, as a child node (eg. \"npm test\" { path_prepend \"./node_modules/.bin\"; })",
+    },
+    OptionSchema {
+        name: "script",
+        kind: "string",
+        default: None,
+        description: "A multi-line script run by the shell instead of the command line (which is then only a label), as a child node (eg. \"migrate db\" { script r#\"...\"#; })",
+    },
+    OptionSchema {
+        name: "paths",
+        kind: "child node",
+        default: None,
+        description: "Globs (* within a directory, ** across them) or directories the command depends on, it's skipped when none of them changed since affected_base (eg. \"cargo test\" { paths \"src/**\" \"Cargo.toml\"; })",
+    },
+    OptionSchema {
+        name: "input",
+        kind: "child node",
+        default: None,
+        description: "Characters written to the pane of the command once it opens, in order, each input node optionally waiting a delay first (eg. \"npx prisma migrate reset\" { input \"y\\n\"; input \"yes\\n\" delay=\"2s\"; })",
+    },
+    OptionSchema {
+        name: "python_venv",
+        kind: "child node",
+        default: None,
+        description: "A virtualenv activated (for the configured shell) before the command, eg. \"pytest\" { python_venv \".venv\"; }",
+    },
+    OptionSchema {
+        name: "node_version",
+        kind: "child node",
+        default: None,
+        description: "A node version switched to with nvm before the command, eg. \"npm test\" { node_version \"20\"; }",
+    },
+    OptionSchema {
+        name: "exec",
+        kind: "bool",
+        default: None,
+        description: "Overrides the global exec option for this command",
+    },
+    OptionSchema {
+        name: "quiet",
+        kind: "bool",
+        default: None,
+        description: "Overrides the global quiet option for this command",
+    },
+    OptionSchema {
+        name: "when",
+        kind: "condition",
+        default: None,
+        description: "Only run the command if the condition is met, otherwise skip it: file_exists(path), prev_success or prev_failure, optionally negated with !",
+    },
+];
+
+pub fn schema() -> Schema {
+    Schema {
+        version: SCHEMA_VERSION,
+        options: OPTIONS,
+        command_attributes: COMMAND_ATTRIBUTES,
+    }
+}
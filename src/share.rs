@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// A run definition (the commands and the options they run with) as a single line of text that
+// can be pasted through chat, eg. zlaunch1:eyJ2IjoxLC...
+// The payload is JSON, base64 encoded with the URL safe alphabet so that it survives chat
+// clients and shells without quoting.
+pub const SHARE_PREFIX: &str = "zlaunch1:";
+pub const SHARE_FILE: &str = ".zlaunch/share.txt"; // relative to the host folder
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// Options that are either part of the blob already or only make sense on this machine
+const UNSHARED_OPTIONS: &[&str] = &["commands", "profiles", "profile", "config_file", "shell_history_file", "report_file"];
+
+#[derive(Serialize, Deserialize)]
+pub struct SharedRun {
+    pub commands: String, // KDL, as in the commands option
+    pub options: BTreeMap<String, String>,
+}
+
+impl SharedRun {
+    pub fn new(commands: String, configuration: &BTreeMap<String, String>) -> Self {
+        let options = configuration.iter()
+            .filter(|(key, _)| !UNSHARED_OPTIONS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        SharedRun { commands, options }
+    }
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        format!("{}{}", SHARE_PREFIX, encode_base64(json.as_bytes()))
+    }
+    // Whitespace (eg. line breaks added by a chat client) is ignored
+    pub fn decode(blob: &str) -> Result<Self, String> {
+        let blob: String = blob.split_whitespace().collect();
+        let encoded = blob.strip_prefix(SHARE_PREFIX).ok_or_else(|| format!("not a zlaunch blob (should start with {})", SHARE_PREFIX))?;
+        let json = decode_base64(encoded).ok_or_else(|| "the blob is damaged".to_owned())?;
+        serde_json::from_slice(&json).map_err(|e| format!("the blob is damaged: {}", e))
+    }
+}
+
+pub fn is_share_blob(text: &str) -> bool {
+    text.trim_start().starts_with(SHARE_PREFIX)
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (i, byte)| triple | (*byte as u32) << (16 - 8 * i));
+        for i in 0..chunk.len() + 1 {
+            encoded.push(ALPHABET[(triple >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let sextets: Vec<u32> = encoded.trim_end_matches('=').bytes()
+        .map(|c| ALPHABET.iter().position(|a| *a == c).map(|p| p as u32))
+        .collect::<Option<_>>()?;
+    for chunk in sextets.chunks(4) {
+        if chunk.len() < 2 {
+            return None;
+        }
+        let quad = chunk.iter().enumerate().fold(0u32, |quad, (i, sextet)| quad | sextet << (18 - 6 * i));
+        for i in 0..chunk.len() - 1 {
+            bytes.push((quad >> (16 - 8 * i) & 0xff) as u8);
+        }
+    }
+    Some(bytes)
+}
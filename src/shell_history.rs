@@ -0,0 +1,58 @@
+use std::collections::{BTreeMap, HashSet};
+use zellij_tile::prelude::run_command;
+
+pub const DEFAULT_SHELL_HISTORY_FILE: &str = "~/.bash_history";
+pub const SHELL_HISTORY_CONTEXT: &str = "shell_history";
+pub const FOR_PALETTE: &str = "palette"; // the value of SHELL_HISTORY_CONTEXT when the palette asked
+const MAX_IMPORT_CANDIDATES: usize = 100;
+const HISTORY_LINES_TO_READ: usize = 1000;
+
+// Where the given shell keeps its history by default
+pub fn default_history_file(shell: &str) -> &'static str {
+    match shell {
+        "zsh" => "~/.zsh_history",
+        "fish" => "~/.local/share/fish/fish_history",
+        _ => DEFAULT_SHELL_HISTORY_FILE,
+    }
+}
+
+// The history file is usually outside of the host folder, so it's read on the host rather than
+// through the plugin's filesystem. The result arrives as a RunCommandResult with this context,
+// `purpose` being its value.
+pub fn request_shell_history(history_file: &str, purpose: &str) {
+    let script = format!(
+        r#"file="$0"; case "$file" in "~/"*) file="$HOME/${{file#\~/}}";; esac; tail -n {} "$file""#,
+        HISTORY_LINES_TO_READ
+    );
+    let mut context = BTreeMap::new();
+    context.insert(SHELL_HISTORY_CONTEXT.to_owned(), purpose.to_owned());
+    run_command(&["sh", "-c", &script, history_file], context);
+}
+
+// The most recent distinct commands of the history, most recent first. Understands the plain
+// (bash), extended (zsh, ": <timestamp>:<duration>;<command>") and fish ("- cmd: <command>")
+// formats.
+pub fn parse_shell_history(history: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    history
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            let line = line.trim();
+            if let Some(command) = line.strip_prefix("- cmd: ") {
+                return Some(command);
+            }
+            if line.starts_with(": ") {
+                return line.split_once(';').map(|(_, command)| command);
+            }
+            if line.starts_with('#') || line.starts_with("when: ") || line.starts_with("paths:") {
+                // bash timestamps and fish metadata
+                return None;
+            }
+            Some(line)
+        })
+        .map(|command| command.trim().to_owned())
+        .filter(|command| !command.is_empty() && seen.insert(command.clone()))
+        .take(MAX_IMPORT_CANDIDATES)
+        .collect()
+}
@@ -0,0 +1,133 @@
+// Splits a command line into its program and arguments the way a POSIX shell would, for the
+// commands that are run without one (exec mode). Quotes and backslashes are honored, but there's
+// no expansion of any kind, eg. echo "a b" 'c d' e\ f is ["echo", "a b", "c d", "e f"].
+pub fn split(command_line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command_line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_owned()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // only these are escaped between double quotes, the backslash stays otherwise
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '\\' | '$' | '`')) => word.push(escaped),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_owned()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_owned()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // a line continuation
+                Some('\n') => {}
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => return Err("trailing backslash".to_owned()),
+            },
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// The word as the shell would read it back, eg. to put text from a command's output in a command
+// line. Words made of harmless characters only are left as they are, the others single quoted.
+pub fn quote(word: &str) -> String {
+    let is_harmless = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '-' | '+' | ',' | ':' | '=' | '@' | '%');
+    if !word.is_empty() && word.chars().all(is_harmless) {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command_line: &str) -> Vec<String> {
+        split(command_line).unwrap()
+    }
+
+    #[test]
+    fn splits_at_blanks_and_honors_quotes() {
+        assert_eq!(words("  cargo   test --  "), vec!["cargo", "test", "--"]);
+        assert_eq!(words(r#"echo "a b" 'c d' e"f"'g'"#), vec!["echo", "a b", "c d", "efg"]);
+        assert_eq!(words(r#"echo '$HOME "x"' "it's""#), vec!["echo", "$HOME \"x\"", "it's"]);
+    }
+
+    #[test]
+    fn escapes_with_backslashes_outside_quotes() {
+        assert_eq!(words(r"echo e\ f \'g\' \\"), vec!["echo", "e f", "'g'", "\\"]);
+    }
+
+    #[test]
+    fn only_escapes_some_characters_between_double_quotes() {
+        assert_eq!(words(r#"echo "\"\\\$\`" "\n\a""#), vec!["echo", "\"\\$`", "\\n\\a"]);
+        assert_eq!(words(r"echo 'a\b'"), vec!["echo", "a\\b"]);
+    }
+
+    #[test]
+    fn keeps_empty_quoted_words() {
+        assert_eq!(words(r#"printf '' """#), vec!["printf", "", ""]);
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        assert_eq!(words("cargo test \\\n  --release"), vec!["cargo", "test", "--release"]);
+        assert_eq!(words("echo \"a\\\nb\""), vec!["echo", "ab"]);
+    }
+
+    #[test]
+    fn reports_unterminated_quotes_and_trailing_backslashes() {
+        assert_eq!(split("echo 'a b"), Err("unterminated single quote".to_owned()));
+        assert_eq!(split("echo \"a b"), Err("unterminated double quote".to_owned()));
+        assert_eq!(split("echo \"a b\\"), Err("unterminated double quote".to_owned()));
+        assert_eq!(split("echo a\\"), Err("trailing backslash".to_owned()));
+    }
+
+    #[test]
+    fn quoted_words_are_split_back_as_they_were() {
+        let originals = ["plain", "a/b-c_d.e", "", "two words", "it's", "$HOME", "a\"b\\c", "line\nbreak", "*"];
+        for original in originals {
+            assert_eq!(words(&quote(original)), vec![original], "{}", quote(original));
+        }
+        assert_eq!(quote("src/main.rs"), "src/main.rs");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
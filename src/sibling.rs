@@ -0,0 +1,46 @@
+use std::path::Path;
+use std::time::Duration;
+
+// Two zlaunch instances loaded with the same commands in the same folder (eg. the layout opened
+// twice) would run everything twice. Before its first run, an instance asks the others with a
+// SIBLING_QUERY_PIPE_NAME message whose payload is its instance key. One that's already running
+// these commands answers with SIBLING_HERE_PIPE_NAME, and what happens then is up to the
+// on_sibling_instance policy.
+pub const SIBLING_QUERY_PIPE_NAME: &str = "zlaunch_sibling_query";
+pub const SIBLING_HERE_PIPE_NAME: &str = "zlaunch_sibling_here";
+pub const TAKE_OVER_PIPE_NAME: &str = "zlaunch_take_over"; // the sibling aborts its run and observes ours
+
+// The first run starts if nobody answered by then
+pub const SIBLING_ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SiblingPolicy {
+    #[default]
+    Observe, // become a read-only observer of the sibling's run
+    Refuse, // don't start until the user says so
+    TakeOver, // abort the sibling's run and run the commands here
+    Ignore, // run anyway, without asking
+}
+
+impl SiblingPolicy {
+    pub fn from_str(policy: &str) -> Option<Self> {
+        match policy {
+            "observe" => Some(SiblingPolicy::Observe),
+            "refuse" => Some(SiblingPolicy::Refuse),
+            "take_over" => Some(SiblingPolicy::TakeOver),
+            "ignore" => Some(SiblingPolicy::Ignore),
+            _ => None,
+        }
+    }
+}
+
+// Instances are siblings when they run the same commands in the same folder, two instances with
+// different commands in a project (eg. a build and a dev server) are not
+pub fn instance_key<'a>(folder: &Path, command_lines: impl Iterator<Item = &'a str>) -> String {
+    let mut key = folder.to_string_lossy().to_string();
+    for command_line in command_lines {
+        key.push('\n');
+        key.push_str(command_line);
+    }
+    key
+}
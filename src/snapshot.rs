@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use zellij_tile::prelude::PaneId;
+
+use crate::stopwatch::Stopwatch;
+use crate::Command;
+
+// A serializable view of the run, used to mirror the state of a zlaunch instance elsewhere.
+// Instants can't be serialized, so times are stored as milliseconds before the snapshot was taken.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RunSnapshot {
+    #[serde(default)]
+    pub taken_at_ms: u64, // since the unix epoch
+    pub current_run_index: usize,
+    pub running_command_index: Option<usize>,
+    #[serde(default)]
+    pub stage_names: HashMap<usize, String>,
+    pub paused: bool,
+    pub stop_on_failure: bool,
+    pub shell: String,
+    pub folder: String,
+    pub commands: Vec<CommandSnapshot>,
+    #[serde(default)]
+    pub elapsed_ms: Option<u64>, // the active time of the run, None in snapshots from older versions
+    #[serde(default)]
+    pub stopwatch_running: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CommandSnapshot {
+    pub command_line: String,
+    pub started_ms_ago: Option<u64>,
+    pub ended_ms_ago: Option<u64>,
+    pub exit_status: Option<i32>,
+    pub exited: bool,
+    pub pane_closed_by_user: bool,
+    pub captured_output: Option<PathBuf>,
+    #[serde(default)]
+    pub log_file: Option<PathBuf>,
+    #[serde(default)]
+    pub output_tail: Vec<String>,
+    #[serde(default)]
+    pub budget_ms: Option<u64>,
+    #[serde(default)]
+    pub pane_id: Option<PaneId>,
+    #[serde(default)]
+    pub pane_lost: bool,
+    #[serde(default)]
+    pub stage: usize,
+    #[serde(default)]
+    pub skipped: bool,
+    #[serde(default)]
+    pub skipped_by_user: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub success_codes: Option<Vec<i32>>,
+    #[serde(default)]
+    pub launch_position: Option<usize>,
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+impl RunSnapshot {
+    // How long ago this snapshot was taken
+    pub fn age(&self) -> Duration {
+        Duration::from_millis(now_ms().saturating_sub(self.taken_at_ms))
+    }
+    // the run's stopwatch as it is now, age being how long ago the snapshot was taken
+    pub fn stopwatch(&self, age: Duration) -> Stopwatch {
+        let elapsed = Duration::from_millis(self.elapsed_ms.unwrap_or(0));
+        if self.stopwatch_running {
+            Stopwatch::resumed_from(elapsed + age, true)
+        } else {
+            Stopwatch::resumed_from(elapsed, false)
+        }
+    }
+}
+
+impl CommandSnapshot {
+    pub fn from_command(command: &Command) -> Self {
+        CommandSnapshot {
+            command_line: command.command_line.clone(),
+            started_ms_ago: command.start_time.map(|t| t.elapsed().as_millis() as u64),
+            ended_ms_ago: command.end_time.map(|t| t.elapsed().as_millis() as u64),
+            exit_status: command.exit_status,
+            exited: command.exited,
+            pane_closed_by_user: command.pane_closed_by_user,
+            captured_output: command.captured_output.clone(),
+            log_file: command.log_file.clone(),
+            output_tail: command.output_tail.clone(),
+            budget_ms: command.options.budget.map(|b| b.as_millis() as u64),
+            pane_id: command.pane_id,
+            pane_lost: command.pane_lost,
+            stage: command.stage,
+            skipped: command.skipped,
+            skipped_by_user: command.skipped_by_user,
+            locked: command.options.locked,
+            success_codes: command.options.success_codes.clone(),
+            launch_position: command.launch_position,
+        }
+    }
+    // age is how long ago the snapshot was taken, so that durations keep counting from then
+    pub fn to_command(&self, age: Duration) -> Command {
+        let now = Instant::now();
+        let mut command = Command::new(&self.command_line);
+        command.start_time = self.started_ms_ago.and_then(|ms| now.checked_sub(Duration::from_millis(ms) + age));
+        command.end_time = self.ended_ms_ago.and_then(|ms| now.checked_sub(Duration::from_millis(ms) + age));
+        command.exit_status = self.exit_status;
+        command.exited = self.exited;
+        command.pane_closed_by_user = self.pane_closed_by_user;
+        command.captured_output = self.captured_output.clone();
+        command.log_file = self.log_file.clone();
+        command.output_tail = self.output_tail.clone();
+        command.options.budget = self.budget_ms.map(Duration::from_millis);
+        command.pane_id = self.pane_id;
+        command.pane_lost = self.pane_lost;
+        command.stage = self.stage;
+        command.skipped = self.skipped;
+        command.skipped_by_user = self.skipped_by_user;
+        command.options.locked = self.locked;
+        command.options.success_codes = self.success_codes.clone();
+        command.launch_position = self.launch_position;
+        command
+    }
+}
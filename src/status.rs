@@ -0,0 +1,224 @@
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::Command;
+
+// The status zlaunch broadcasts for other plugins (eg. status bar widgets). Unlike the state
+// broadcast to observers, this format is stable: fields are only ever added, anything else bumps
+// STATUS_FORMAT_VERSION.
+//
+// Every time the run changes, a pipe message named "zlaunch:status" is sent to all plugins, with a
+// JSON payload:
+//
+//   {
+//     "version": 1,
+//     "plugin_id": 3,           // the zlaunch instance, several can run in the same session
+//     "state": "running",       // idle, running, paused, suspended, succeeded or failed
+//     "run_index": 2,           // increases on every restart
+//     "total": 5,               // commands that are part of the run (skipped ones are not)
+//     "succeeded": 2,
+//     "failed": 1,
+//     "pending": 2,             // not yet exited, including the running ones
+//     "skipped": 0,
+//     "running": ["cargo test"] // the command lines currently running
+//   }
+//
+// A plugin that just loaded can ask for the current status by sending a "zlaunch:status_request"
+// pipe message, zlaunch replies with a "zlaunch:status" message to that plugin only.
+//
+// Plugins that rather follow individual events get these, in the same versioned format:
+//
+//   "zlaunch:command_started"  {"version": 1, "plugin_id": 3, "run_index": 2, "command_index": 0,
+//                               "command_line": "cargo test", "attempt": 1}
+//   "zlaunch:command_finished" {"version": 1, "plugin_id": 3, "run_index": 2, "command_index": 0,
+//                               "command_line": "cargo test", "exit_code": 0, "succeeded": true,
+//                               "duration_ms": 5312}
+//   "zlaunch:run_finished"     {"version": 1, "plugin_id": 3, "run_index": 2, "state": "succeeded",
+//                               "succeeded": 4, "failed": 0, "skipped": 1, "duration_ms": 31200}
+//
+// exit_code is null when the command exited without one (eg. it was killed).
+//
+// Scripts can query the status with a "zlaunch::status" pipe message, eg.
+// zellij pipe --name zlaunch::status, which is answered (on the CLI's output, or with a
+// "zlaunch:status" message to a plugin) with the status above plus the commands themselves:
+//
+//   "commands": [{"index": 0, "command_line": "cargo test", "state": "succeeded", "exit_code": 0,
+//                 "duration_ms": 5312}]
+//
+// where state is pending, running, succeeded, failed, skipped, stopped or aborted.
+pub const STATUS_FORMAT_VERSION: u32 = 1;
+pub const STATUS_PIPE_NAME: &str = "zlaunch:status";
+pub const STATUS_REQUEST_PIPE_NAME: &str = "zlaunch:status_request";
+pub const STATUS_QUERY_PIPE_NAME: &str = "zlaunch::status";
+pub const COMMAND_STARTED_PIPE_NAME: &str = "zlaunch:command_started";
+pub const COMMAND_FINISHED_PIPE_NAME: &str = "zlaunch:command_finished";
+pub const RUN_FINISHED_PIPE_NAME: &str = "zlaunch:run_finished";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Idle,
+    Running,
+    Paused,
+    Suspended,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StatusMessage {
+    pub version: u32,
+    pub plugin_id: u32,
+    pub state: RunState,
+    pub run_index: usize,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub pending: usize,
+    pub skipped: usize,
+    pub running: Vec<String>,
+}
+
+impl StatusMessage {
+    pub fn new(plugin_id: u32, state: RunState, run_index: usize, commands: &[Command]) -> Self {
+        StatusMessage {
+            version: STATUS_FORMAT_VERSION,
+            plugin_id,
+            state,
+            run_index,
+            total: commands.iter().filter(|c| !c.skipped).count(),
+            succeeded: commands.iter().filter(|c| c.succeeded()).count(),
+            failed: commands.iter().filter(|c| c.exited && !c.succeeded()).count(),
+            pending: commands.iter().filter(|c| !c.exited && !c.skipped).count(),
+            skipped: commands.iter().filter(|c| c.skipped).count(),
+            running: commands.iter().filter(|c| c.is_running()).map(|c| c.command_line.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct StatusReport {
+    #[serde(flatten)]
+    pub status: StatusMessage,
+    pub commands: Vec<CommandStatusEntry>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommandStatusEntry {
+    pub index: usize,
+    pub command_line: String,
+    pub state: &'static str,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+}
+
+impl StatusReport {
+    pub fn new(plugin_id: u32, state: RunState, run_index: usize, commands: &[Command]) -> Self {
+        StatusReport {
+            status: StatusMessage::new(plugin_id, state, run_index, commands),
+            commands: commands.iter().enumerate().map(|(index, command)| CommandStatusEntry {
+                index,
+                command_line: command.command_line.clone(),
+                state: command_state(command),
+                exit_code: command.exit_status,
+                duration_ms: command.duration().map(|d| d.as_millis() as u64),
+            }).collect(),
+        }
+    }
+}
+
+fn command_state(command: &Command) -> &'static str {
+    if command.is_running() {
+        "running"
+    } else if command.succeeded() {
+        "succeeded"
+    } else if command.stopped {
+        "stopped"
+    } else if command.aborted {
+        "aborted"
+    } else if command.exited {
+        "failed"
+    } else if command.skipped {
+        "skipped"
+    } else {
+        "pending"
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommandStartedMessage {
+    pub version: u32,
+    pub plugin_id: u32,
+    pub run_index: usize,
+    pub command_index: usize,
+    pub command_line: String,
+    pub attempt: usize,
+}
+
+impl CommandStartedMessage {
+    pub fn new(plugin_id: u32, run_index: usize, command_index: usize, command: &Command) -> Self {
+        CommandStartedMessage {
+            version: STATUS_FORMAT_VERSION,
+            plugin_id,
+            run_index,
+            command_index,
+            command_line: command.command_line.clone(),
+            attempt: command.attempts,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommandFinishedMessage {
+    pub version: u32,
+    pub plugin_id: u32,
+    pub run_index: usize,
+    pub command_index: usize,
+    pub command_line: String,
+    pub exit_code: Option<i32>,
+    pub succeeded: bool,
+    pub duration_ms: u64,
+}
+
+impl CommandFinishedMessage {
+    pub fn new(plugin_id: u32, run_index: usize, command_index: usize, command: &Command) -> Self {
+        CommandFinishedMessage {
+            version: STATUS_FORMAT_VERSION,
+            plugin_id,
+            run_index,
+            command_index,
+            command_line: command.command_line.clone(),
+            exit_code: command.exit_status,
+            succeeded: command.succeeded(),
+            duration_ms: command.duration().map(|d| d.as_millis() as u64).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RunFinishedMessage {
+    pub version: u32,
+    pub plugin_id: u32,
+    pub run_index: usize,
+    pub state: RunState,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub duration_ms: u64,
+}
+
+impl RunFinishedMessage {
+    pub fn new(plugin_id: u32, run_index: usize, commands: &[Command], duration: Duration) -> Self {
+        let failed = commands.iter().filter(|c| c.exited && !c.succeeded()).count();
+        RunFinishedMessage {
+            version: STATUS_FORMAT_VERSION,
+            plugin_id,
+            run_index,
+            state: if failed > 0 { RunState::Failed } else { RunState::Succeeded },
+            succeeded: commands.iter().filter(|c| c.succeeded()).count(),
+            failed,
+            skipped: commands.iter().filter(|c| c.skipped).count(),
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::{Duration, Instant};
+
+// Accumulates the time the run was active, so that pauses, suspensions and the time between a
+// run ending and a re-run don't count towards its duration
+#[derive(Debug, Default, Clone)]
+pub struct Stopwatch {
+    accumulated: Duration,
+    running_since: Option<Instant>,
+}
+
+impl Stopwatch {
+    // eg. when restoring a persisted run
+    pub fn resumed_from(elapsed: Duration, is_running: bool) -> Self {
+        Stopwatch {
+            accumulated: elapsed,
+            running_since: if is_running { Some(Instant::now()) } else { None },
+        }
+    }
+    pub fn set_running(&mut self, is_running: bool) {
+        match (is_running, self.running_since) {
+            (true, None) => self.running_since = Some(Instant::now()),
+            (false, Some(running_since)) => {
+                self.accumulated += running_since.elapsed();
+                self.running_since = None;
+            }
+            _ => {}
+        }
+    }
+    pub fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+    pub fn reset(&mut self) {
+        *self = Stopwatch::default();
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.accumulated + self.running_since.map(|r| r.elapsed()).unwrap_or_default()
+    }
+}
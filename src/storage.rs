@@ -0,0 +1,54 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Every file zlaunch writes goes through here. Several zlaunch instances can share a project
+// folder, so files are written atomically (a reader never sees a partial file) and files that
+// belong to one instance are named after it.
+
+static INSTANCE_ID: AtomicU32 = AtomicU32::new(0);
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Called once on load with our plugin id
+pub fn set_instance_id(plugin_id: u32) {
+    INSTANCE_ID.store(plugin_id, Ordering::Relaxed);
+}
+
+// A path relative to the folder zlaunch was started in, as seen by the plugin
+pub fn host_path<P: AsRef<Path>>(relative_path: P) -> PathBuf {
+    Path::new("/host").join(relative_path)
+}
+
+// A file name only this instance uses, eg. "editing-commands-3"
+pub fn instance_file_name(name: &str) -> String {
+    format!("{}-{}", name, INSTANCE_ID.load(Ordering::Relaxed))
+}
+
+// Writes to a uniquely named temporary file next to the destination and renames it into place,
+// creating the parent folders as needed
+pub fn write_atomic<C: AsRef<[u8]>>(path: &Path, contents: C) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = temp_path_for(path);
+    if let Err(e) = fs::write(&temp_path, contents).and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let file_name = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    let temp_file_name = format!(
+        ".{}.{}-{}-{}.tmp",
+        file_name,
+        INSTANCE_ID.load(Ordering::Relaxed),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+        nanos
+    );
+    path.with_file_name(temp_file_name)
+}
@@ -0,0 +1,61 @@
+// Which commands run, by their tags (tags="fast unit"), so that the same project file serves eg.
+// a quick check (run_tags "fast") and the full pipeline. Configured with run_tags and skip_tags
+// (space separated), and changed at runtime with T.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagFilter {
+    pub run_tags: Vec<String>, // only the commands with one of these run, all of them if empty
+    pub skip_tags: Vec<String>, // the commands with one of these never run
+}
+
+impl TagFilter {
+    pub fn new(run_tags: Option<&str>, skip_tags: Option<&str>) -> Self {
+        let split = |tags: Option<&str>| tags.map(|t| t.split_whitespace().map(|t| t.to_owned()).collect()).unwrap_or_default();
+        TagFilter { run_tags: split(run_tags), skip_tags: split(skip_tags) }
+    }
+    pub fn only(tag: &str) -> Self {
+        TagFilter { run_tags: vec![tag.to_owned()], skip_tags: vec![] }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.run_tags.is_empty() && self.skip_tags.is_empty()
+    }
+    // Why a command with these tags doesn't run, None if it does
+    pub fn excludes(&self, tags: &[String]) -> Option<String> {
+        if let Some(tag) = tags.iter().find(|t| self.skip_tags.contains(t)) {
+            return Some(format!("tagged {}", tag));
+        }
+        if !self.run_tags.is_empty() && !tags.iter().any(|t| self.run_tags.contains(t)) {
+            return Some(format!("not tagged {}", self.run_tags.join(" or ")));
+        }
+        None
+    }
+    // eg. fast unit, not slow
+    pub fn description(&self) -> String {
+        let mut description = vec![];
+        if !self.run_tags.is_empty() {
+            description.push(self.run_tags.join(" "));
+        }
+        if !self.skip_tags.is_empty() {
+            description.push(format!("not {}", self.skip_tags.join(" ")));
+        }
+        if description.is_empty() {
+            "all".to_owned()
+        } else {
+            description.join(", ")
+        }
+    }
+}
+
+// What T cycles through: the configured filter, each tag on its own, then no filter at all
+pub fn filters_to_cycle(configured: &TagFilter, tags: &[String]) -> Vec<TagFilter> {
+    let mut filters = vec![configured.clone()];
+    for tag in tags {
+        let filter = TagFilter::only(tag);
+        if !filters.contains(&filter) {
+            filters.push(filter);
+        }
+    }
+    if !filters.contains(&TagFilter::default()) {
+        filters.push(TagFilter::default());
+    }
+    filters
+}
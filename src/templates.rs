@@ -0,0 +1,59 @@
+// Built-in pipelines for common stacks, offered by the first run wizard. They seed the project
+// file, so they're meant to be customized from there.
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub commands: &'static str, // in the KDL format of the commands configuration
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "rust",
+        description: "fmt → clippy → test → build",
+        commands: r#""cargo fmt --check"
+"cargo clippy --all-targets -- -D warnings"
+"cargo test"
+"cargo build --release"
+"#,
+    },
+    Template {
+        name: "node",
+        description: "install → lint → test → build",
+        commands: r#""npm install"
+"npm run lint"
+"npm test"
+"npm run build"
+"#,
+    },
+    Template {
+        name: "python",
+        description: "install → lint and type check → test",
+        commands: r#""pip install -r requirements.txt"
+stage "checks" {
+    "ruff check ."
+    "mypy ."
+}
+"pytest"
+"#,
+    },
+    Template {
+        name: "go",
+        description: "download → vet → test → build",
+        commands: r#""go mod download"
+"go vet ./..."
+"go test ./..."
+"go build ./..."
+"#,
+    },
+    Template {
+        name: "make",
+        description: "build → test",
+        commands: r#""make"
+"make test"
+"#,
+    },
+];
+
+pub fn find_template(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
@@ -0,0 +1,102 @@
+use kdl::KdlDocument;
+
+// Maps the semantic roles of the UI to color indices of the zellij palette
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub running: usize,
+    pub success: usize,
+    pub failure: usize,
+    pub pending: usize,
+    pub command: usize,
+    pub selected: usize,
+    pub key_hint: usize,
+    pub skipped: usize,
+    pub warning: usize, // eg. a command running for much longer than usual
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            running: 1,
+            success: 2,
+            failure: 3,
+            pending: 1,
+            command: 0,
+            selected: 0,
+            key_hint: 2,
+            skipped: 0,
+            warning: 0,
+        }
+    }
+}
+
+impl Theme {
+    // Every status gets its own color, and neither the pending nor the skipped commands share
+    // the running or the key hint colors
+    pub fn high_contrast() -> Self {
+        Theme {
+            running: 1,
+            success: 2,
+            failure: 3,
+            pending: 0,
+            command: 0,
+            selected: 0,
+            key_hint: 1,
+            skipped: 0,
+            warning: 0,
+        }
+    }
+    // Success and failure are told apart without green and red (cyan and magenta in the default
+    // zellij palette), for red-green color-blind users
+    pub fn color_blind() -> Self {
+        Theme {
+            running: 2,
+            success: 1,
+            failure: 3,
+            pending: 2,
+            command: 0,
+            selected: 0,
+            key_hint: 2,
+            skipped: 0,
+            warning: 0,
+        }
+    }
+    // The roles in the configuration override those of the base theme, eg.
+    // theme {
+    //     running 4
+    //     key_hint 5
+    // }
+    pub fn from_configuration(theme: Option<&String>, base: Theme) -> Self {
+        let mut parsed = base;
+        let doc = match theme.map(|t| t.parse::<KdlDocument>()) {
+            Some(Ok(doc)) => doc,
+            Some(Err(e)) => {
+                eprintln!("Failed to parse theme: {}", e);
+                return parsed;
+            }
+            None => return parsed,
+        };
+        for node in doc.nodes() {
+            let color_index = match node.entries().first().and_then(|e| e.value().as_i64()) {
+                Some(color_index) if color_index >= 0 => color_index as usize,
+                _ => {
+                    eprintln!("Invalid color index for theme role: {}", node.name().value());
+                    continue;
+                }
+            };
+            match node.name().value() {
+                "running" => parsed.running = color_index,
+                "success" => parsed.success = color_index,
+                "failure" => parsed.failure = color_index,
+                "pending" => parsed.pending = color_index,
+                "command" => parsed.command = color_index,
+                "selected" => parsed.selected = color_index,
+                "key_hint" | "key-hint" => parsed.key_hint = color_index,
+                "skipped" => parsed.skipped = color_index,
+                "warning" => parsed.warning = color_index,
+                role => eprintln!("Unknown theme role: {}", role),
+            }
+        }
+        parsed
+    }
+}
@@ -0,0 +1,205 @@
+use kdl::{KdlDocument, KdlNode};
+use std::collections::BTreeMap;
+use std::fs;
+use zellij_tile::prelude::{BareKey, KeyWithModifier};
+
+use crate::formats;
+use crate::storage;
+use crate::templates::TEMPLATES;
+
+// The project configuration written by the wizard, relative to the host folder. Its top level
+// nodes are configuration keys, eg. shell "zsh" or commands { "cargo build"; }
+pub const PROJECT_FILE: &str = ".zlaunch.kdl";
+
+const SHELLS: &[&str] = &["bash", "zsh", "fish", "sh", "nu"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WizardStep {
+    Shell,
+    Folder,
+    ConfigFile,
+    ImportSource,
+    Template,
+}
+
+// Walks a new user through the basic configuration when zlaunch is launched without commands in
+// a project that has no PROJECT_FILE
+#[derive(Debug, Clone)]
+pub struct Wizard {
+    pub step: WizardStep,
+    pub cursor: usize,
+    pub shell: String,
+    pub folder: String,
+    pub write_project_file: bool,
+    pub import_from_shell_history: bool,
+    pub template: Option<usize>, // index in TEMPLATES
+}
+
+impl Wizard {
+    pub fn new() -> Self {
+        Wizard {
+            step: WizardStep::Shell,
+            cursor: 0,
+            shell: SHELLS[0].to_owned(),
+            folder: ".".to_owned(),
+            write_project_file: true,
+            import_from_shell_history: true,
+            template: None,
+        }
+    }
+    // choosing a template is part of the import step
+    pub fn step_number(&self) -> usize {
+        match self.step {
+            WizardStep::Shell => 1,
+            WizardStep::Folder => 2,
+            WizardStep::ConfigFile => 3,
+            WizardStep::ImportSource | WizardStep::Template => 4,
+        }
+    }
+    pub fn prompt(&self) -> &'static str {
+        match self.step {
+            WizardStep::Shell => "Which shell should run the commands?",
+            WizardStep::Folder => "Which folder should the commands run in? (relative to this one)",
+            WizardStep::ConfigFile => "Save this configuration?",
+            WizardStep::ImportSource => "Where should the first commands come from?",
+            WizardStep::Template => "Which pipeline should we start from? (it can be customized later)",
+        }
+    }
+    // The choices of the current step, empty for the text input of the folder step
+    pub fn choices(&self) -> Vec<String> {
+        match self.step {
+            WizardStep::Shell => SHELLS.iter().map(|s| s.to_string()).collect(),
+            WizardStep::Folder => vec![],
+            WizardStep::ConfigFile => vec!["Yes, write .zlaunch.kdl".to_owned(), "No, only for this session".to_owned()],
+            WizardStep::ImportSource => vec![
+                "Pick from my shell history".to_owned(),
+                "Start from a template".to_owned(),
+                "Start with no commands".to_owned(),
+            ],
+            WizardStep::Template => TEMPLATES.iter().map(|t| format!("{} ({})", t.name, t.description)).collect(),
+        }
+    }
+    // Returns true once the last step is done
+    pub fn handle_key(&mut self, key: &KeyWithModifier) -> bool {
+        let choice_count = self.choices().len();
+        match key.bare_key {
+            BareKey::Down if choice_count > 0 => self.cursor = (self.cursor + 1) % choice_count,
+            BareKey::Up if choice_count > 0 => self.cursor = (self.cursor + choice_count - 1) % choice_count,
+            BareKey::Char(c) if self.step == WizardStep::Folder => self.folder.push(c),
+            BareKey::Backspace if self.step == WizardStep::Folder => {
+                self.folder.pop();
+            }
+            BareKey::Enter => return self.next_step(),
+            _ => {}
+        }
+        false
+    }
+    fn next_step(&mut self) -> bool {
+        let next_step = match self.step {
+            WizardStep::Shell => {
+                self.shell = SHELLS.get(self.cursor).unwrap_or(&SHELLS[0]).to_string();
+                WizardStep::Folder
+            }
+            WizardStep::Folder => {
+                if self.folder.trim().is_empty() {
+                    self.folder = ".".to_owned();
+                }
+                WizardStep::ConfigFile
+            }
+            WizardStep::ConfigFile => {
+                self.write_project_file = self.cursor == 0;
+                WizardStep::ImportSource
+            }
+            WizardStep::ImportSource => {
+                self.import_from_shell_history = self.cursor == 0;
+                if self.cursor != 1 {
+                    return true;
+                }
+                WizardStep::Template
+            }
+            WizardStep::Template => {
+                self.template = Some(self.cursor);
+                return true;
+            }
+        };
+        self.step = next_step;
+        self.cursor = 0;
+        false
+    }
+}
+
+// The project file and its configuration, if there is one. Nodes with children (eg. commands)
+// are kept as KDL, like they are in layouts. Without a PROJECT_FILE, a TOML or YAML one is looked
+// for.
+pub fn load_project_file() -> Option<(&'static str, BTreeMap<String, String>)> {
+    let contents = match fs::read_to_string(storage::host_path(PROJECT_FILE)) {
+        Ok(contents) => contents,
+        Err(_) => {
+            return formats::PROJECT_FILES.iter().find_map(|file_name| {
+                let contents = fs::read_to_string(storage::host_path(file_name)).ok()?;
+                Some((*file_name, formats::load_configuration(file_name, &contents)?))
+            });
+        }
+    };
+    let doc = match contents.parse::<KdlDocument>() {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", PROJECT_FILE, e);
+            return None;
+        }
+    };
+    let mut configuration = BTreeMap::new();
+    for node in doc.nodes() {
+        let value = match (node.children(), node.entries().first()) {
+            (Some(children), _) => children.to_string(),
+            (None, Some(entry)) => match entry.value().as_string() {
+                Some(value) => value.to_owned(),
+                None => entry.value().to_string(),
+            },
+            (None, None) => continue,
+        };
+        configuration.insert(node.name().value().to_owned(), value);
+    }
+    Some((PROJECT_FILE, configuration))
+}
+
+// Replaces the commands of the project file, keeping the rest of its configuration (eg. hooks or
+// profiles). It's written from scratch if there's none yet or if it doesn't parse.
+pub fn save_commands_to_project_file(shell: &str, folder: &str, commands_kdl: &str) {
+    let existing = fs::read_to_string(storage::host_path(PROJECT_FILE)).ok().and_then(|contents| contents.parse::<KdlDocument>().ok());
+    let mut doc = match existing {
+        Some(doc) => doc,
+        None => return write_project_file(shell, folder, commands_kdl),
+    };
+    // indented like write_project_file does it
+    let indented_commands: String = commands_kdl.lines().map(|line| format!("    {}\n", line)).collect();
+    let commands = match format!("\n{}", indented_commands).parse::<KdlDocument>() {
+        Ok(commands) => commands,
+        Err(e) => {
+            eprintln!("Failed to save the commands to {}: {}", PROJECT_FILE, e);
+            return;
+        }
+    };
+    match doc.get_mut("commands") {
+        Some(commands_node) => commands_node.set_children(commands),
+        None => {
+            let mut commands_node = KdlNode::new("commands");
+            commands_node.set_children(commands);
+            doc.nodes_mut().push(commands_node);
+        }
+    }
+    if let Err(e) = storage::write_atomic(&storage::host_path(PROJECT_FILE), doc.to_string()) {
+        eprintln!("Failed to write {}: {}", PROJECT_FILE, e);
+    }
+}
+
+pub fn write_project_file(shell: &str, folder: &str, commands_kdl: &str) {
+    let mut contents = format!("shell {:?}\nfolder {:?}\ncommands {{\n", shell, folder);
+    for line in commands_kdl.lines() {
+        contents.push_str(&format!("    {}\n", line));
+    }
+    contents.push_str("}\n");
+    if let Err(e) = storage::write_atomic(&storage::host_path(PROJECT_FILE), contents) {
+        eprintln!("Failed to write {}: {}", PROJECT_FILE, e);
+    }
+}
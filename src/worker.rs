@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use zellij_tile::prelude::*;
+
+use crate::history::{self, RunRecord};
+use crate::report::{self, ReportRow};
+use crate::storage;
+
+// Turning the run into the Markdown report and writing it, and recording the run in the history
+// (a file read, parsed and written back whole, growing with every run) are done by a worker, so
+// that they don't hold up update() and rendering. The worker answers with a CustomMessage once
+// it's done. The project file is still parsed by the plugin, the commands it yields live there.
+pub const WORKER_NAME: &str = "zlaunch"; // registered as zlaunch_worker
+pub const WRITE_REPORT_MESSAGE: &str = "write_report";
+pub const RECORD_RUN_MESSAGE: &str = "record_run";
+pub const REPORT_WRITTEN_MESSAGE: &str = "zlaunch_report_written";
+pub const RUN_RECORDED_MESSAGE: &str = "zlaunch_run_recorded";
+
+// What the plugin does with the report once it's written
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFollowUp {
+    Nothing,
+    OpenFile,
+    SummaryPane,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WriteReport {
+    pub path: PathBuf, // in the plugin's filesystem
+    pub rows: Vec<ReportRow>,
+    pub total_duration: Duration,
+    pub precision: usize,
+    pub then: ReportFollowUp,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JobDone {
+    pub then: Option<ReportFollowUp>,
+    pub error: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ZlaunchWorker {}
+
+impl<'de> ZellijWorker<'de> for ZlaunchWorker {
+    fn on_message(&mut self, message: String, payload: String) {
+        match message.as_str() {
+            WRITE_REPORT_MESSAGE => {
+                let (then, result) = match serde_json::from_str::<WriteReport>(&payload) {
+                    Ok(write_report) => {
+                        let markdown = report::markdown_report(&write_report.rows, write_report.total_duration, write_report.precision);
+                        (Some(write_report.then), storage::write_atomic(&write_report.path, markdown).map_err(|e| e.to_string()))
+                    }
+                    Err(e) => (None, Err(e.to_string())),
+                };
+                post_job_done(REPORT_WRITTEN_MESSAGE, then, result);
+            }
+            RECORD_RUN_MESSAGE => {
+                let result = serde_json::from_str::<RunRecord>(&payload).map(|run| {
+                    // the history on file is the one that counts, see record_run
+                    history::record_run(&mut vec![], run);
+                });
+                post_job_done(RUN_RECORDED_MESSAGE, None, result.map_err(|e| e.to_string()));
+            }
+            _ => eprintln!("Unknown worker message: {}", message),
+        }
+    }
+}
+
+fn post_job_done(message: &str, then: Option<ReportFollowUp>, result: Result<(), String>) {
+    let job_done = JobDone { then, error: result.err() };
+    match serde_json::to_string(&job_done) {
+        Ok(payload) => post_message_to_plugin(PluginMessage::new_to_plugin(message, &payload)),
+        Err(e) => eprintln!("Failed to serialize the worker's answer: {}", e),
+    }
+}
+
+// Whether the job was sent, the plugin then waits for its answer
+pub fn post_to_worker<T: Serialize>(message: &str, payload: &T) -> bool {
+    match serde_json::to_string(payload) {
+        Ok(payload) => {
+            post_message_to(PluginMessage::new_to_worker(WORKER_NAME, message, &payload));
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to serialize the {} job: {}", message, e);
+            false
+        }
+    }
+}